@@ -0,0 +1,172 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Carry-less multiplication for GF(2^n) arithmetic, using the x86
+//! `PCLMULQDQ` or ARM `PMULL` instructions when the CPU supports them.
+//!
+//! This is a separate crate so that the SIMD intrinsics (which require unsafe
+//! code) are the only unsafe code in paperback, and paperback-core can keep
+//! forbidding it.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(test)]
+extern crate quickcheck;
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck_macros;
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Implementation used to multiply `GfElem`s.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GfBackend {
+    /// Portable bit-by-bit multiplication.
+    Portable,
+    /// Carry-less multiplication using the x86 `PCLMULQDQ` instruction.
+    Pclmulqdq,
+    /// Polynomial multiplication using the ARM `PMULL` instruction.
+    Pmull,
+}
+
+// The detected backend, cached because feature detection is comparatively
+// expensive and we need it for every multiplication.
+static BACKEND: AtomicU8 = AtomicU8::new(GfBackend::UNKNOWN);
+
+impl GfBackend {
+    const UNKNOWN: u8 = 0;
+
+    /// Returns the backend used for `GfElem` multiplication on this CPU, which
+    /// is detected at runtime.
+    pub fn detect() -> Self {
+        match BACKEND.load(Ordering::Relaxed) {
+            Self::UNKNOWN => {
+                let backend = Self::detect_uncached();
+                BACKEND.store(backend.to_u8(), Ordering::Relaxed);
+                backend
+            }
+            backend => Self::from_u8(backend),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn detect_uncached() -> Self {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("pclmulqdq") {
+                return GfBackend::Pclmulqdq;
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("pmull") {
+                return GfBackend::Pmull;
+            }
+        }
+        GfBackend::Portable
+    }
+
+    // Runtime feature detection requires std, so without it we can only use
+    // the instructions if the target is known to support them.
+    #[cfg(not(feature = "std"))]
+    fn detect_uncached() -> Self {
+        if cfg!(all(target_arch = "x86_64", target_feature = "pclmulqdq")) {
+            GfBackend::Pclmulqdq
+        } else if cfg!(all(target_arch = "aarch64", target_feature = "aes")) {
+            GfBackend::Pmull
+        } else {
+            GfBackend::Portable
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            GfBackend::Portable => 1,
+            GfBackend::Pclmulqdq => 2,
+            GfBackend::Pmull => 3,
+        }
+    }
+
+    fn from_u8(v: u8) -> Self {
+        match v {
+            2 => GfBackend::Pclmulqdq,
+            3 => GfBackend::Pmull,
+            _ => GfBackend::Portable,
+        }
+    }
+
+    /// Carry-less multiplication of `a` and `b`, or `None` if this backend has
+    /// no hardware support for it (or isn't the backend detected for this
+    /// CPU).
+    pub fn clmul(self, a: u64, b: u64) -> Option<u128> {
+        // Anyone can construct a GfBackend, so make sure the CPU actually
+        // supports it before using the instructions.
+        if self != Self::detect() {
+            return None;
+        }
+        match self {
+            GfBackend::Portable => None,
+            // SAFETY: The backend is only detected if the CPU supports the
+            //         necessary instructions.
+            #[cfg(target_arch = "x86_64")]
+            GfBackend::Pclmulqdq => Some(unsafe { clmul_pclmulqdq(a, b) }),
+            #[cfg(target_arch = "aarch64")]
+            GfBackend::Pmull => Some(unsafe { clmul_pmull(a, b) }),
+            _ => unreachable!("gf backend {:?} not supported on this platform", self),
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "pclmulqdq")]
+unsafe fn clmul_pclmulqdq(a: u64, b: u64) -> u128 {
+    use core::arch::x86_64::{
+        _mm_clmulepi64_si128, _mm_cvtsi128_si64, _mm_set_epi64x, _mm_unpackhi_epi64,
+    };
+
+    let product = _mm_clmulepi64_si128(_mm_set_epi64x(0, a as i64), _mm_set_epi64x(0, b as i64), 0);
+    let lo = _mm_cvtsi128_si64(product) as u64;
+    let hi = _mm_cvtsi128_si64(_mm_unpackhi_epi64(product, product)) as u64;
+    (u128::from(hi) << 64) | u128::from(lo)
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon,aes")]
+unsafe fn clmul_pmull(a: u64, b: u64) -> u128 {
+    core::arch::aarch64::vmull_p64(a, b)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Portable carry-less multiplication, to compare against.
+    fn clmul_portable(a: u64, b: u64) -> u128 {
+        (0..64)
+            .filter(|i| (b >> i) & 1 == 1)
+            .fold(0u128, |acc, i| acc ^ (u128::from(a) << i))
+    }
+
+    #[quickcheck]
+    fn clmul_matches_portable(a: u64, b: u64) -> bool {
+        match GfBackend::detect().clmul(a, b) {
+            Some(product) => product == clmul_portable(a, b),
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,313 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The payload is the wire format of the object, encoded as z-base-32 and
+//! split into lines of `LINE_LENGTH` characters. It is followed by a line
+//! containing `=` and the z-base-32 encoded CRC-32C of the payload:
+//!
+//! ```text
+//! -----BEGIN PAPERBACK SHARD-----
+//! 6n9hq4d1ybrbonoyyrbo...
+//! =ojxs8cy
+//! -----END PAPERBACK SHARD-----
+//! ```
+//!
+//! When decoding, any text before the `BEGIN` line (and after the `END` line)
+//! is ignored, as is whitespace around each line, so armored objects can be
+//! pasted into emails and other documents.
+
+use crate::{
+    shamir::encoding,
+    v0::{FromWire, ToWire, WireError},
+};
+
+use std::{error, fmt};
+
+/// Label for armored `Shard`s.
+pub const SHARD_LABEL: &str = "PAPERBACK SHARD";
+
+/// Label for armored `MainDocument`s.
+pub const MAIN_DOCUMENT_LABEL: &str = "PAPERBACK MAIN DOCUMENT";
+
+/// Label for armored `EncryptedKeyShard`s.
+pub const KEY_SHARD_LABEL: &str = "PAPERBACK KEY SHARD";
+
+/// Label for armored `DetachedSignature`s.
+pub const SIGNATURE_LABEL: &str = "PAPERBACK SIGNATURE";
+
+/// Label for armored `SupersededNotice`s.
+pub const SUPERSEDED_NOTICE_LABEL: &str = "PAPERBACK SUPERSEDED NOTICE";
+
+/// Label for armored `ExpansionGrant`s.
+pub const EXPANSION_GRANT_LABEL: &str = "PAPERBACK EXPANSION GRANT";
+
+/// Label for armored `RevocationList`s.
+pub const REVOCATION_LIST_LABEL: &str = "PAPERBACK REVOCATION LIST";
+
+/// Label for armored `PossessionVerifier`s.
+pub const POSSESSION_VERIFIER_LABEL: &str = "PAPERBACK POSSESSION VERIFIER";
+
+/// Label for armored `RecoveryRecord`s.
+pub const RECOVERY_RECORD_LABEL: &str = "PAPERBACK RECOVERY RECORD";
+
+/// Label for armored `MainDocumentPage`s.
+pub const MAIN_DOCUMENT_PAGE_LABEL: &str = "PAPERBACK MAIN DOCUMENT PAGE";
+
+/// Number of payload characters on each line of armored output.
+pub const LINE_LENGTH: usize = 64;
+
+const BEGIN_PREFIX: &str = "-----BEGIN ";
+const END_PREFIX: &str = "-----END ";
+const DELIMITER_SUFFIX: &str = "-----";
+const CHECKSUM_PREFIX: char = '=';
+
+/// Reasons why armored text could not be decoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ArmorError {
+    /// There is no `-----BEGIN <label>-----` line.
+    MissingHeader,
+    /// There is no `-----END <label>-----` line after the header.
+    MissingFooter,
+    /// The label of the armor is not the one expected (or the labels of the
+    /// header and footer differ).
+    LabelMismatch { expected: String, found: String },
+    /// There is no checksum line before the footer.
+    MissingChecksum,
+    /// The payload (or checksum) is not validly z-base-32 encoded, or there is
+    /// text between the checksum and the footer.
+    InvalidEncoding,
+    /// The checksum does not match the payload.
+    ChecksumMismatch,
+    /// The payload could not be deserialised.
+    Wire(WireError),
+}
+
+impl fmt::Display for ArmorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArmorError::MissingHeader => write!(f, "missing armor header line"),
+            ArmorError::MissingFooter => write!(f, "missing armor footer line"),
+            ArmorError::LabelMismatch { expected, found } => write!(
+                f,
+                "armor is labelled {:?} but expected {:?}",
+                found, expected
+            ),
+            ArmorError::MissingChecksum => write!(f, "missing armor checksum line"),
+            ArmorError::InvalidEncoding => write!(f, "armor payload is not valid z-base-32"),
+            ArmorError::ChecksumMismatch => write!(f, "armor checksum mismatch"),
+            ArmorError::Wire(err) => write!(f, "failed to deserialise armor payload: {}", err),
+        }
+    }
+}
+
+impl error::Error for ArmorError {}
+
+impl From<WireError> for ArmorError {
+    fn from(err: WireError) -> Self {
+        ArmorError::Wire(err)
+    }
+}
+
+fn checksum(payload: &[u8]) -> String {
+    zbase32::encode_full_bytes(&encoding::crc32c(payload).to_be_bytes())
+}
+
+/// Wrap the wire format of `object` in armor with the given label (such as
+/// `SHARD_LABEL`).
+///
+/// The label should only contain uppercase ASCII letters, digits and spaces.
+pub fn armor<T: ToWire + ?Sized>(label: &str, object: &T) -> String {
+    armor_bytes(label, &object.to_wire())
+}
+
+/// Wrap an arbitrary payload in armor with the given label.
+pub fn armor_bytes(label: &str, payload: &[u8]) -> String {
+    debug_assert!(label
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == ' '));
+
+    let encoded = zbase32::encode_full_bytes(payload);
+    let mut armored = format!("{}{}{}\n", BEGIN_PREFIX, label, DELIMITER_SUFFIX);
+    // z-base-32 is pure ASCII, so splitting at any byte is safe.
+    for line in encoded.as_bytes().chunks(LINE_LENGTH) {
+        armored.push_str(std::str::from_utf8(line).expect("z-base-32 is ASCII"));
+        armored.push('\n');
+    }
+    armored.push(CHECKSUM_PREFIX);
+    armored.push_str(&checksum(payload));
+    armored.push('\n');
+    armored.push_str(&format!("{}{}{}\n", END_PREFIX, label, DELIMITER_SUFFIX));
+    armored
+}
+
+/// Decode an object from the first armored block in `armored`, which must have
+/// the given label.
+pub fn dearmor<T: FromWire>(label: &str, armored: &str) -> Result<T, ArmorError> {
+    match dearmor_bytes(armored)? {
+        (found, payload) if found == label => Ok(T::from_wire(payload)?),
+        (found, _) => Err(ArmorError::LabelMismatch {
+            expected: label.into(),
+            found,
+        }),
+    }
+}
+
+/// Decode the first armored block in `armored`, returning its label and
+/// payload.
+pub fn dearmor_bytes(armored: &str) -> Result<(String, Vec<u8>), ArmorError> {
+    let delimited = |line: &str, prefix: &str| {
+        line.strip_prefix(prefix)
+            .and_then(|line| line.strip_suffix(DELIMITER_SUFFIX))
+            .map(String::from)
+    };
+
+    let mut lines = armored.lines().map(str::trim);
+    let label = lines
+        .find_map(|line| delimited(line, BEGIN_PREFIX))
+        .ok_or(ArmorError::MissingHeader)?;
+
+    let (mut encoded, mut encoded_checksum) = (String::new(), None);
+    loop {
+        let line = lines.next().ok_or(ArmorError::MissingFooter)?;
+        if let Some(found) = delimited(line, END_PREFIX) {
+            if found != label {
+                return Err(ArmorError::LabelMismatch {
+                    expected: label,
+                    found,
+                });
+            }
+            break;
+        }
+        match (line.strip_prefix(CHECKSUM_PREFIX), &encoded_checksum) {
+            _ if line.is_empty() => (),
+            (Some(checksum), None) => encoded_checksum = Some(checksum),
+            (None, None) => encoded.push_str(line),
+            // Nothing but the footer may follow the checksum.
+            (_, Some(_)) => return Err(ArmorError::InvalidEncoding),
+        }
+    }
+    let encoded_checksum = encoded_checksum.ok_or(ArmorError::MissingChecksum)?;
+
+    let payload =
+        zbase32::decode_full_bytes_str(&encoded).map_err(|_| ArmorError::InvalidEncoding)?;
+    // Only accept the canonical encoding of the payload.
+    if zbase32::encode_full_bytes(&payload) != encoded {
+        return Err(ArmorError::InvalidEncoding);
+    }
+    if checksum(&payload) != encoded_checksum {
+        return Err(ArmorError::ChecksumMismatch);
+    }
+    Ok((label, payload))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{shamir::Shard, v0::MainDocument};
+
+    #[quickcheck]
+    fn armor_shard_roundtrip(shard: Shard) -> bool {
+        let armored = armor(SHARD_LABEL, &shard);
+        armored
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .all(|line| line.len() <= LINE_LENGTH)
+            && dearmor::<Shard>(SHARD_LABEL, &armored).as_ref() == Ok(&shard)
+    }
+
+    #[quickcheck]
+    fn armor_main_document_roundtrip(main: MainDocument) -> bool {
+        let armored = armor(MAIN_DOCUMENT_LABEL, &main);
+        dearmor::<MainDocument>(MAIN_DOCUMENT_LABEL, &armored).as_ref() == Ok(&main)
+    }
+
+    #[quickcheck]
+    fn armor_bytes_roundtrip(payload: Vec<u8>) -> bool {
+        let armored = armor_bytes("TEST", &payload);
+        dearmor_bytes(&armored) == Ok(("TEST".into(), payload))
+    }
+
+    #[quickcheck]
+    fn armor_surrounding_text(shard: Shard) -> bool {
+        // Quoted and indented in an email, with a signature afterwards.
+        let email = format!(
+            "Hi,\n\nHere is your shard:\n\n{}\n-- \nAlice\n",
+            armor(SHARD_LABEL, &shard)
+                .lines()
+                .map(|line| format!("    {}\r\n", line))
+                .collect::<String>()
+        );
+        dearmor::<Shard>(SHARD_LABEL, &email).as_ref() == Ok(&shard)
+    }
+
+    #[quickcheck]
+    fn armor_corrupted(payload: Vec<u8>, pos: usize, delta: u8) -> bool {
+        if payload.is_empty() {
+            return true;
+        }
+        let armored = armor_bytes("TEST", &payload);
+        let mut lines = armored.lines().map(String::from).collect::<Vec<_>>();
+
+        // Change one character of the payload.
+        let line = &mut lines[1];
+        let pos = pos % line.len();
+        let alphabet = "ybndrfg8ejkmcpqxot1uwisza345h769";
+        let old = alphabet.find(&line[pos..=pos]).unwrap_or(0);
+        let new = alphabet.as_bytes()[(old + 1 + usize::from(delta) % 31) % 32] as char;
+        line.replace_range(pos..=pos, &new.to_string());
+
+        matches!(
+            dearmor_bytes(&lines.join("\n")),
+            Err(ArmorError::ChecksumMismatch) | Err(ArmorError::InvalidEncoding)
+        )
+    }
+
+    #[test]
+    fn armor_errors() {
+        let armored = armor_bytes("TEST", b"paperback");
+        let lines = armored.lines().collect::<Vec<_>>();
+        let without = |n: usize| {
+            let mut lines = lines.clone();
+            lines.remove(n);
+            lines.join("\n")
+        };
+
+        assert_eq!(dearmor_bytes(&without(0)), Err(ArmorError::MissingHeader));
+        assert_eq!(dearmor_bytes(&without(3)), Err(ArmorError::MissingFooter));
+        assert_eq!(dearmor_bytes(&without(2)), Err(ArmorError::MissingChecksum));
+        assert_eq!(
+            dearmor_bytes(&armored.replace("END TEST", "END OTHER")),
+            Err(ArmorError::LabelMismatch {
+                expected: "TEST".into(),
+                found: "OTHER".into()
+            })
+        );
+        assert_eq!(
+            dearmor::<Shard>(SHARD_LABEL, &armored),
+            Err(ArmorError::LabelMismatch {
+                expected: SHARD_LABEL.into(),
+                found: "TEST".into()
+            })
+        );
+        assert!(matches!(
+            dearmor::<Shard>("TEST", &armored),
+            Err(ArmorError::Wire(_))
+        ));
+    }
+}
@@ -0,0 +1,360 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Strings are encoded as described in [BIP-350][bip350], except that there is
+//! no limit on their length (paperback's objects are far longer than the 90
+//! characters permitted by BIP-173). The checksum is guaranteed to detect up
+//! to four errors in strings of up to 89 characters, and detects errors in
+//! longer strings with overwhelming probability.
+//!
+//! If a string contains a single mistyped character, `decode` reports its
+//! position.
+//!
+//! [bip350]: https://github.com/bitcoin/bips/blob/master/bip-0350.mediawiki
+
+use crate::v0::{FromWire, ToWire, WireError};
+
+use std::{error, fmt};
+
+/// Human-readable part for `Shard`s.
+pub const SHARD_HRP: &str = "pbshard";
+
+/// Human-readable part for `MainDocument`s.
+pub const MAIN_DOCUMENT_HRP: &str = "pbdoc";
+
+/// Human-readable part for `EncryptedKeyShard`s.
+pub const KEY_SHARD_HRP: &str = "pbkey";
+
+/// Human-readable part for `DetachedSignature`s.
+pub const SIGNATURE_HRP: &str = "pbsig";
+
+/// Human-readable part for `SupersededNotice`s.
+pub const SUPERSEDED_NOTICE_HRP: &str = "pbnotice";
+
+/// Human-readable part for `ExpansionGrant`s.
+pub const EXPANSION_GRANT_HRP: &str = "pbgrant";
+
+/// Human-readable part for `RevocationList`s.
+pub const REVOCATION_LIST_HRP: &str = "pbrevoke";
+
+/// Human-readable part for `PossessionVerifier`s.
+pub const POSSESSION_VERIFIER_HRP: &str = "pbverify";
+
+/// Human-readable part for `RecoveryRecord`s.
+pub const RECOVERY_RECORD_HRP: &str = "pbaudit";
+
+/// Human-readable part for `MainDocumentPage`s.
+pub const MAIN_DOCUMENT_PAGE_HRP: &str = "pbpage";
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATORS: [u32; 5] = [
+    0x3b6a_57b2,
+    0x2650_8e6d,
+    0x1ea1_19fa,
+    0x3d42_33dd,
+    0x2a14_62b3,
+];
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+const CHECKSUM_LENGTH: usize = 6;
+const SEPARATOR: char = '1';
+
+/// Reasons why a Bech32m string could not be decoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Bech32Error {
+    /// There is no `1` separating the human-readable part from the data, or
+    /// there is not enough data for the checksum.
+    MissingSeparator,
+    /// The human-readable part is empty or contains invalid characters.
+    InvalidHrp,
+    /// The human-readable part is not the one expected.
+    HrpMismatch { expected: String, found: String },
+    /// The string contains both uppercase and lowercase characters.
+    MixedCase,
+    /// The character at `position` is not in the Bech32 character set.
+    InvalidCharacter { position: usize },
+    /// The checksum does not match. If the string can be corrected by changing
+    /// a single character, `position` is the position of that character.
+    ChecksumMismatch { position: Option<usize> },
+    /// The data has invalid padding bits.
+    InvalidPadding,
+    /// The data could not be deserialised.
+    Wire(WireError),
+}
+
+impl fmt::Display for Bech32Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Bech32Error::MissingSeparator => write!(f, "missing bech32 separator"),
+            Bech32Error::InvalidHrp => write!(f, "invalid bech32 human-readable part"),
+            Bech32Error::HrpMismatch { expected, found } => write!(
+                f,
+                "bech32 human-readable part is {:?} but expected {:?}",
+                found, expected
+            ),
+            Bech32Error::MixedCase => write!(f, "bech32 string has mixed case"),
+            Bech32Error::InvalidCharacter { position } => {
+                write!(f, "invalid bech32 character at position {}", position)
+            }
+            Bech32Error::ChecksumMismatch { position: None } => {
+                write!(f, "bech32 checksum mismatch")
+            }
+            Bech32Error::ChecksumMismatch {
+                position: Some(position),
+            } => write!(
+                f,
+                "bech32 checksum mismatch (character {} was probably mistyped)",
+                position
+            ),
+            Bech32Error::InvalidPadding => write!(f, "invalid bech32 padding"),
+            Bech32Error::Wire(err) => write!(f, "failed to deserialise data: {}", err),
+        }
+    }
+}
+
+impl error::Error for Bech32Error {}
+
+impl From<WireError> for Bech32Error {
+    fn from(err: WireError) -> Self {
+        Bech32Error::Wire(err)
+    }
+}
+
+fn polymod_step(chk: u32, value: u8) -> u32 {
+    let top = chk >> 25;
+    GENERATORS
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| (top >> idx) & 1 == 1)
+        .fold(
+            ((chk & 0x1ff_ffff) << 5) ^ u32::from(value),
+            |chk, (_, g)| chk ^ g,
+        )
+}
+
+fn polymod(hrp: &str, values: &[u8]) -> u32 {
+    hrp.bytes()
+        .map(|c| c >> 5)
+        .chain(std::iter::once(0))
+        .chain(hrp.bytes().map(|c| c & 0x1f))
+        .chain(values.iter().copied())
+        .fold(1, polymod_step)
+}
+
+// Find the position (within values) of the only single-character change that
+// would make the checksum valid, if there is exactly one.
+fn locate_error(hrp: &str, values: &[u8]) -> Option<usize> {
+    // The checksum is linear, so the residue is the contribution of the error
+    // alone, which is the error value followed by as many zeros as there are
+    // values after it.
+    let residue = polymod(hrp, values) ^ BECH32M_CONST;
+    let mut states = (1..32u8).map(|e| polymod_step(0, e)).collect::<Vec<_>>();
+    let mut found = None;
+    for distance in 0..values.len() {
+        for state in states.iter_mut() {
+            if *state == residue {
+                match found {
+                    None => found = Some(values.len() - 1 - distance),
+                    Some(_) => return None,
+                }
+            }
+            *state = polymod_step(*state, 0);
+        }
+    }
+    found
+}
+
+// Regroup bits from `from` bits per value to `to` bits per value.
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let (mut acc, mut bits) = (0u32, 0u32);
+    let mut converted = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+    let max = (1u32 << to) - 1;
+    for value in data {
+        acc = (acc << from) | u32::from(*value);
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            converted.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            converted.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || (acc << (to - bits)) & max != 0 {
+        return None;
+    }
+    Some(converted)
+}
+
+/// Encode `data` as a Bech32m string with the given human-readable part (such
+/// as `SHARD_HRP`), which must be lowercase ASCII.
+pub fn encode<B: AsRef<[u8]>>(hrp: &str, data: B) -> String {
+    debug_assert!(hrp
+        .bytes()
+        .all(|c| (33..=126).contains(&c) && !c.is_ascii_uppercase()));
+
+    let mut values = convert_bits(data.as_ref(), 8, 5, true).expect("padding is always valid");
+    let checksum = polymod(hrp, &[&values[..], &[0; CHECKSUM_LENGTH]].concat()) ^ BECH32M_CONST;
+    values.extend((0..CHECKSUM_LENGTH).map(|idx| ((checksum >> (5 * (5 - idx))) & 0x1f) as u8));
+
+    let mut encoded = String::with_capacity(hrp.len() + 1 + values.len());
+    encoded.push_str(hrp);
+    encoded.push(SEPARATOR);
+    encoded.extend(values.iter().map(|v| CHARSET[usize::from(*v)] as char));
+    encoded
+}
+
+/// Encode the wire format of `object` as a Bech32m string with the given
+/// human-readable part.
+pub fn encode_wire<T: ToWire + ?Sized>(hrp: &str, object: &T) -> String {
+    encode(hrp, object.to_wire())
+}
+
+/// Decode a Bech32m string, returning its (lowercase) human-readable part and
+/// data.
+pub fn decode<S: AsRef<str>>(input: S) -> Result<(String, Vec<u8>), Bech32Error> {
+    let input = input.as_ref();
+    let has_lower = input.bytes().any(|c| c.is_ascii_lowercase());
+    if has_lower && input.bytes().any(|c| c.is_ascii_uppercase()) {
+        return Err(Bech32Error::MixedCase);
+    }
+    let input = input.to_ascii_lowercase();
+
+    let split = input
+        .rfind(SEPARATOR)
+        .ok_or(Bech32Error::MissingSeparator)?;
+    let (hrp, data) = (&input[..split], &input[split + 1..]);
+    if hrp.is_empty() || !hrp.bytes().all(|c| (33..=126).contains(&c)) {
+        return Err(Bech32Error::InvalidHrp);
+    }
+    if data.len() < CHECKSUM_LENGTH {
+        return Err(Bech32Error::MissingSeparator);
+    }
+
+    let values = data
+        .bytes()
+        .enumerate()
+        .map(|(idx, c)| {
+            CHARSET.iter().position(|a| *a == c).map(|v| v as u8).ok_or(
+                Bech32Error::InvalidCharacter {
+                    position: split + 1 + idx,
+                },
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if polymod(hrp, &values) != BECH32M_CONST {
+        return Err(Bech32Error::ChecksumMismatch {
+            position: locate_error(hrp, &values).map(|idx| split + 1 + idx),
+        });
+    }
+    let data = convert_bits(&values[..values.len() - CHECKSUM_LENGTH], 5, 8, false)
+        .ok_or(Bech32Error::InvalidPadding)?;
+    Ok((hrp.into(), data))
+}
+
+/// Decode an object from a Bech32m string produced by `encode_wire`, which
+/// must have the given human-readable part.
+pub fn decode_wire<T: FromWire, S: AsRef<str>>(hrp: &str, input: S) -> Result<T, Bech32Error> {
+    match decode(input)? {
+        (found, data) if found == hrp => Ok(T::from_wire(data)?),
+        (found, _) => Err(Bech32Error::HrpMismatch {
+            expected: hrp.into(),
+            found,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::shamir::Shard;
+
+    #[test]
+    fn bech32m_vectors() {
+        // Valid test vectors from BIP-350.
+        for vector in &[
+            "A1LQFN3A",
+            "a1lqfn3a",
+            "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx",
+            "split1checkupstagehandshakeupstreamerranterredcaperredlc445v",
+            "?1v759aa",
+        ] {
+            assert!(decode(vector).is_ok(), "{} should be valid", vector);
+        }
+        assert_eq!(
+            decode("abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx"),
+            Ok((
+                "abcdef".into(),
+                convert_bits(&(0..32).rev().collect::<Vec<_>>()[..], 5, 8, false).unwrap()
+            ))
+        );
+
+        // Invalid test vectors from BIP-350.
+        assert_eq!(decode("1xj0phk"), Err(Bech32Error::InvalidHrp));
+        assert_eq!(decode("pzry9x0s0muk"), Err(Bech32Error::MissingSeparator));
+        assert!(matches!(
+            decode("M1VUXWEZ"),
+            Err(Bech32Error::ChecksumMismatch { .. })
+        ));
+        assert_eq!(decode("li1dgmt3"), Err(Bech32Error::MissingSeparator));
+        assert_eq!(
+            decode("x1b4n0q5v"),
+            Err(Bech32Error::InvalidCharacter { position: 2 })
+        );
+        assert_eq!(decode("A1g7SGD8"), Err(Bech32Error::MixedCase));
+    }
+
+    #[quickcheck]
+    fn bech32m_roundtrip(data: Vec<u8>) -> bool {
+        let encoded = encode("test", &data);
+        decode(&encoded) == Ok(("test".into(), data.clone()))
+            && decode(encoded.to_ascii_uppercase()) == Ok(("test".into(), data))
+    }
+
+    #[quickcheck]
+    fn bech32m_shard_roundtrip(shard: Shard) -> bool {
+        let encoded = encode_wire(SHARD_HRP, &shard);
+        decode_wire::<Shard, _>(SHARD_HRP, &encoded).as_ref() == Ok(&shard)
+            && matches!(
+                decode_wire::<Shard, _>(KEY_SHARD_HRP, &encoded),
+                Err(Bech32Error::HrpMismatch { .. })
+            )
+    }
+
+    #[quickcheck]
+    fn bech32m_locate_typo(data: Vec<u8>, pos: usize, delta: usize) -> bool {
+        let encoded = encode("test", &data);
+
+        // Replace one character after the separator with a different one.
+        let pos = "test1".len() + pos % (encoded.len() - "test1".len());
+        let old = CHARSET
+            .iter()
+            .position(|c| *c == encoded.as_bytes()[pos])
+            .unwrap();
+        let new = CHARSET[(old + 1 + delta % 31) % 32] as char;
+        let mut typo = encoded.clone();
+        typo.replace_range(pos..=pos, &new.to_string());
+
+        decode(&typo)
+            == Err(Bech32Error::ChecksumMismatch {
+                position: Some(pos),
+            })
+    }
+}
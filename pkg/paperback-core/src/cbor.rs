@@ -0,0 +1,357 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Only the subset of CBOR needed by paperback is supported: unsigned
+//! integers, byte strings, text strings, arrays and maps with text keys.
+//! Everything is encoded deterministically (RFC 8949, section 4.2.1)
+//! -- the shortest form of every length and integer is used, lengths are
+//! always definite, and map keys are sorted by their encoded form. The decoder
+//! rejects any input which is not encoded this way, so every object has
+//! exactly one valid encoding.
+
+use crate::shamir::WireError;
+
+use std::convert::TryFrom;
+
+/// Serialise an object as deterministic CBOR.
+pub trait ToCbor {
+    fn to_cbor(&self) -> Vec<u8>;
+}
+
+/// Deserialise an object from deterministic CBOR.
+pub trait FromCbor: Sized {
+    fn from_cbor<B: AsRef<[u8]>>(input: B) -> Result<Self, WireError>;
+}
+
+// Maximum nesting of arrays and maps. None of paperback's types nest deeper
+// than two levels.
+const MAX_DEPTH: usize = 8;
+
+const MAJOR_UINT: u8 = 0;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+
+/// A decoded CBOR data item.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Value {
+    Uint(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+fn encode_head(major: u8, n: u64, bytes: &mut Vec<u8>) {
+    let major = major << 5;
+    match n {
+        0..=23 => bytes.push(major | n as u8),
+        24..=0xff => bytes.extend_from_slice(&[major | 24, n as u8]),
+        0x100..=0xffff => {
+            bytes.push(major | 25);
+            bytes.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            bytes.push(major | 26);
+            bytes.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+        _ => {
+            bytes.push(major | 27);
+            bytes.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+}
+
+impl Value {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.encode_into(&mut bytes);
+        bytes
+    }
+
+    fn encode_into(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Value::Uint(n) => encode_head(MAJOR_UINT, *n, bytes),
+            Value::Bytes(b) => {
+                encode_head(MAJOR_BYTES, b.len() as u64, bytes);
+                bytes.extend_from_slice(b);
+            }
+            Value::Text(s) => {
+                encode_head(MAJOR_TEXT, s.len() as u64, bytes);
+                bytes.extend_from_slice(s.as_bytes());
+            }
+            Value::Array(items) => {
+                encode_head(MAJOR_ARRAY, items.len() as u64, bytes);
+                items.iter().for_each(|item| item.encode_into(bytes));
+            }
+            Value::Map(entries) => {
+                // Keys must be sorted by their encoded form.
+                let mut entries = entries
+                    .iter()
+                    .map(|(key, value)| (Value::Text(key.clone()).encode(), value))
+                    .collect::<Vec<_>>();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+                encode_head(MAJOR_MAP, entries.len() as u64, bytes);
+                for (key, value) in entries {
+                    bytes.extend_from_slice(&key);
+                    value.encode_into(bytes);
+                }
+            }
+        }
+    }
+
+    /// Decode a single data item, which must make up the entire input.
+    pub(crate) fn decode(input: &[u8]) -> Result<Value, WireError> {
+        match Value::decode_partial(input, 0)? {
+            (value, []) => Ok(value),
+            (_, remain) => Err(WireError::TrailingData {
+                length: remain.len(),
+            }),
+        }
+    }
+
+    fn decode_partial(input: &[u8], depth: usize) -> Result<(Value, &[u8]), WireError> {
+        if depth > MAX_DEPTH {
+            return Err(String::from("cbor items are nested too deeply").into());
+        }
+
+        let (major, n, mut input) = decode_head(input)?;
+        match major {
+            MAJOR_UINT => Ok((Value::Uint(n), input)),
+            MAJOR_BYTES | MAJOR_TEXT => {
+                let len = usize::try_from(n)
+                    .ok()
+                    .filter(|len| *len <= input.len())
+                    .ok_or(WireError::UnexpectedEof)?;
+                let (data, input) = input.split_at(len);
+                let value = match major {
+                    MAJOR_BYTES => Value::Bytes(data.to_vec()),
+                    _ => Value::Text(
+                        String::from_utf8(data.to_vec())
+                            .map_err(|_| String::from("cbor text is not valid utf-8"))?,
+                    ),
+                };
+                Ok((value, input))
+            }
+            MAJOR_ARRAY | MAJOR_MAP => {
+                // Every item is at least one byte long, so only allocate as
+                // much as could possibly be in the input.
+                let count = usize::try_from(n)
+                    .ok()
+                    .filter(|count| *count <= input.len())
+                    .ok_or(WireError::UnexpectedEof)?;
+                if major == MAJOR_ARRAY {
+                    let mut items = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let (item, remain) = Value::decode_partial(input, depth + 1)?;
+                        items.push(item);
+                        input = remain;
+                    }
+                    return Ok((Value::Array(items), input));
+                }
+
+                let mut entries = Vec::with_capacity(count);
+                let mut last_key: Option<&[u8]> = None;
+                for _ in 0..count {
+                    let (key, remain) = Value::decode_partial(input, depth + 1)?;
+                    let key_bytes = &input[..input.len() - remain.len()];
+                    let key = match key {
+                        Value::Text(key) => key,
+                        _ => return Err(String::from("cbor map keys must be text").into()),
+                    };
+                    if last_key.is_some_and(|last| last >= key_bytes) {
+                        return Err(
+                            String::from("cbor map keys are duplicated or not sorted").into()
+                        );
+                    }
+                    last_key = Some(key_bytes);
+
+                    let (value, remain) = Value::decode_partial(remain, depth + 1)?;
+                    entries.push((key, value));
+                    input = remain;
+                }
+                Ok((Value::Map(entries), input))
+            }
+            _ => Err(String::from("unsupported cbor data item").into()),
+        }
+    }
+}
+
+// Decode the head of a data item, returning its major type and argument. The
+// argument must be encoded in its shortest form.
+fn decode_head(input: &[u8]) -> Result<(u8, u64, &[u8]), WireError> {
+    let (&initial, input) = input.split_first().ok_or(WireError::UnexpectedEof)?;
+    let (major, info) = (initial >> 5, initial & 0x1f);
+    let (len, min) = match info {
+        0..=23 => return Ok((major, u64::from(info), input)),
+        24 => (1, 24),
+        25 => (2, 0x100),
+        26 => (4, 0x1_0000),
+        27 => (8, 0x1_0000_0000),
+        // Reserved values and indefinite lengths.
+        _ => return Err(String::from("cbor item has an invalid or indefinite length").into()),
+    };
+    if input.len() < len {
+        return Err(WireError::UnexpectedEof);
+    }
+    let (arg, input) = input.split_at(len);
+    let n = arg.iter().fold(0u64, |n, b| (n << 8) | u64::from(*b));
+    if n < min {
+        return Err(String::from("cbor item is not in its shortest form").into());
+    }
+    Ok((major, n, input))
+}
+
+// Helper to extract the fields of a decoded map.
+pub(crate) struct MapReader {
+    entries: Vec<(String, Value)>,
+}
+
+impl MapReader {
+    pub(crate) fn new(value: Value) -> Result<Self, WireError> {
+        match value {
+            Value::Map(entries) => Ok(MapReader { entries }),
+            _ => Err(String::from("expected a cbor map").into()),
+        }
+    }
+
+    /// Remove the named field, if it is present.
+    pub(crate) fn take_optional(&mut self, field: &'static str) -> Option<Value> {
+        let idx = self.entries.iter().position(|(key, _)| key == field)?;
+        Some(self.entries.remove(idx).1)
+    }
+
+    /// Remove the named field.
+    pub(crate) fn take(&mut self, field: &'static str) -> Result<Value, WireError> {
+        self.take_optional(field)
+            .ok_or_else(|| format!("cbor map is missing field '{}'", field).into())
+    }
+
+    pub(crate) fn take_uint(&mut self, field: &'static str) -> Result<u64, WireError> {
+        match self.take(field)? {
+            Value::Uint(n) => Ok(n),
+            _ => Err(format!("cbor field '{}' must be an integer", field).into()),
+        }
+    }
+
+    pub(crate) fn take_u32(&mut self, field: &'static str) -> Result<u32, WireError> {
+        u32::try_from(self.take_uint(field)?)
+            .map_err(|_| format!("cbor field '{}' is too large", field).into())
+    }
+
+    pub(crate) fn take_usize(&mut self, field: &'static str) -> Result<usize, WireError> {
+        usize::try_from(self.take_uint(field)?)
+            .map_err(|_| format!("cbor field '{}' is too large", field).into())
+    }
+
+    pub(crate) fn take_bytes(&mut self, field: &'static str) -> Result<Vec<u8>, WireError> {
+        match self.take(field)? {
+            Value::Bytes(b) => Ok(b),
+            _ => Err(format!("cbor field '{}' must be a byte string", field).into()),
+        }
+    }
+
+    /// Check that every field of the map has been used.
+    pub(crate) fn finish(self) -> Result<(), WireError> {
+        match self.entries.first() {
+            None => Ok(()),
+            Some((key, _)) => Err(format!("unknown cbor field '{}'", key).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cbor_known_encodings() {
+        // Examples from RFC 8949, Appendix A.
+        assert_eq!(Value::Uint(0).encode(), [0x00]);
+        assert_eq!(Value::Uint(23).encode(), [0x17]);
+        assert_eq!(Value::Uint(24).encode(), [0x18, 0x18]);
+        assert_eq!(Value::Uint(1000).encode(), [0x19, 0x03, 0xe8]);
+        assert_eq!(
+            Value::Uint(1_000_000_000_000).encode(),
+            [0x1b, 0x00, 0x00, 0x00, 0xe8, 0xd4, 0xa5, 0x10, 0x00]
+        );
+        assert_eq!(
+            Value::Bytes(vec![1, 2, 3, 4]).encode(),
+            [0x44, 0x01, 0x02, 0x03, 0x04]
+        );
+        assert_eq!(Value::Text("IETF".into()).encode(), b"\x64IETF");
+        assert_eq!(
+            Value::Array(vec![Value::Uint(1), Value::Uint(2)]).encode(),
+            [0x82, 0x01, 0x02]
+        );
+        // Keys are sorted by length first, then bytewise.
+        assert_eq!(
+            Value::Map(vec![
+                ("bb".into(), Value::Uint(2)),
+                ("a".into(), Value::Uint(1)),
+            ])
+            .encode(),
+            b"\xa2\x61a\x01\x62bb\x02"
+        );
+    }
+
+    #[test]
+    fn cbor_rejects_nondeterministic() {
+        // Non-shortest integer.
+        assert!(Value::decode(&[0x18, 0x17]).is_err());
+        // Indefinite-length byte string.
+        assert!(Value::decode(&[0x5f, 0x41, 0x00, 0xff]).is_err());
+        // Unsorted map keys.
+        assert!(Value::decode(b"\xa2\x62bb\x02\x61a\x01").is_err());
+        // Duplicate map keys.
+        assert!(Value::decode(b"\xa2\x61a\x01\x61a\x01").is_err());
+        // Trailing data.
+        assert_eq!(
+            Value::decode(&[0x01, 0x02]),
+            Err(WireError::TrailingData { length: 1 })
+        );
+        // Huge declared length.
+        assert_eq!(
+            Value::decode(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Err(WireError::UnexpectedEof)
+        );
+    }
+
+    #[quickcheck]
+    fn cbor_roundtrip(n: u64, bytes: Vec<u8>, text: String, nested: Vec<u32>) -> bool {
+        let value = Value::Map(vec![
+            ("n".into(), Value::Uint(n)),
+            ("bytes".into(), Value::Bytes(bytes)),
+            ("text".into(), Value::Text(text)),
+            (
+                "nested".into(),
+                Value::Array(nested.into_iter().map(u64::from).map(Value::Uint).collect()),
+            ),
+        ]);
+        let bytes = value.encode();
+
+        // Decoding gives the map in sorted order, which must re-encode to the
+        // same bytes.
+        match Value::decode(&bytes) {
+            Ok(decoded @ Value::Map(_)) => decoded.encode() == bytes,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,275 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A `.paperback` file holds the wire format of a single object, for users who
+//! keep digital copies of their documents (on USB sticks, in password managers
+//! and so on) alongside the printed ones. The layout is:
+//!
+//! ```text
+//! magic    \x89 "PAPERBACK" \r \n \x1a \n
+//! version  varint (FILE_VERSION)
+//! kind     varint (type tag of the object, see `shamir::DocumentKind`)
+//! length   varint
+//! payload  <length> bytes (wire format of the object)
+//! checksum Blake2b-256 of everything before it
+//! ```
+//!
+//! As with PNG, the magic contains a non-ASCII byte and both kinds of line
+//! ending, so files mangled by text-mode transfers are rejected outright. The
+//! layout will only ever be extended by bumping the version, and every
+//! version will remain readable.
+
+use crate::{
+    shamir::{encoding, header, DocumentKind},
+    v0::{FromWire, ToWire, WireError},
+};
+
+use std::{
+    error, fmt,
+    io::{self, Read, Write},
+};
+
+/// Conventional extension for `.paperback` files.
+pub const FILE_EXTENSION: &str = "paperback";
+
+/// Magic bytes at the start of every `.paperback` file.
+pub const FILE_MAGIC: [u8; 14] = *b"\x89PAPERBACK\r\n\x1a\n";
+
+/// Version of the `.paperback` file layout written by `encode_file`.
+pub const FILE_VERSION: u32 = 1;
+
+/// Length (in bytes) of the checksum at the end of a `.paperback` file.
+const CHECKSUM_LENGTH: usize = 32;
+
+const CHECKSUM_PERSONAL: &[u8] = b"paperback-file";
+
+/// Reasons why a `.paperback` file could not be decoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FileError {
+    /// The file does not start with `FILE_MAGIC`.
+    MissingMagic,
+    /// The file was written by a newer (unsupported) version of paperback.
+    UnsupportedVersion(u32),
+    /// The file ended before the declared payload and checksum.
+    Truncated,
+    /// There are bytes after the checksum.
+    TrailingData,
+    /// The checksum does not match the contents of the file.
+    ChecksumMismatch,
+    /// The file holds a different kind of object to the one expected.
+    KindMismatch {
+        expected: DocumentKind,
+        found: DocumentKind,
+    },
+    /// The payload could not be deserialised.
+    Wire(WireError),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileError::MissingMagic => write!(f, "not a paperback file"),
+            FileError::UnsupportedVersion(version) => {
+                write!(f, "unsupported paperback file version {}", version)
+            }
+            FileError::Truncated => write!(f, "paperback file is truncated"),
+            FileError::TrailingData => write!(f, "unexpected data after paperback file"),
+            FileError::ChecksumMismatch => write!(f, "paperback file checksum mismatch"),
+            FileError::KindMismatch { expected, found } => write!(
+                f,
+                "paperback file holds a {} but expected a {}",
+                found, expected
+            ),
+            FileError::Wire(err) => write!(f, "failed to deserialise file payload: {}", err),
+        }
+    }
+}
+
+impl error::Error for FileError {}
+
+impl From<WireError> for FileError {
+    fn from(err: WireError) -> Self {
+        FileError::Wire(err)
+    }
+}
+
+fn checksum(contents: &[u8]) -> blake2b_simd::Hash {
+    blake2b_simd::Params::new()
+        .hash_length(CHECKSUM_LENGTH)
+        .personal(CHECKSUM_PERSONAL)
+        .hash(contents)
+}
+
+/// Serialise `object` as the contents of a `.paperback` file.
+pub fn encode_file<T: ToWire + ?Sized>(object: &T) -> Vec<u8> {
+    let payload = object.to_wire();
+    // Objects without a type tag are stored with the (reserved) tag of
+    // DocumentKind::Unknown.
+    let kind = header::detect(&payload);
+
+    let mut bytes = Vec::with_capacity(
+        FILE_MAGIC.len()
+            + encoding::varint_len(FILE_VERSION.into())
+            + encoding::varint_len(kind.tag())
+            + encoding::varint_len(payload.len() as u64)
+            + payload.len()
+            + CHECKSUM_LENGTH,
+    );
+    bytes.extend_from_slice(&FILE_MAGIC);
+    encoding::varint(FILE_VERSION.into(), &mut bytes);
+    encoding::varint(kind.tag(), &mut bytes);
+    encoding::varint(payload.len() as u64, &mut bytes);
+    bytes.extend_from_slice(&payload);
+    let checksum = checksum(&bytes);
+    bytes.extend_from_slice(checksum.as_bytes());
+    bytes
+}
+
+/// Split the contents of a `.paperback` file into the kind of object it holds
+/// and the object's wire format, verifying the checksum of the file (but not
+/// deserialising the object).
+pub fn decode_file_bytes(input: &[u8]) -> Result<(DocumentKind, &[u8]), FileError> {
+    use crate::nom_helpers;
+    use nom::combinator::complete;
+
+    let varint = |input| {
+        WireError::nom(complete(nom_helpers::u64)(input)).map_err(|err| match err {
+            WireError::UnexpectedEof => FileError::Truncated,
+            err => FileError::Wire(err),
+        })
+    };
+
+    let body = match input.strip_prefix(&FILE_MAGIC[..]) {
+        Some(body) => body,
+        None if FILE_MAGIC.starts_with(input) => return Err(FileError::Truncated),
+        None => return Err(FileError::MissingMagic),
+    };
+    let (body, version) = varint(body)?;
+    if version != FILE_VERSION.into() {
+        return Err(FileError::UnsupportedVersion(version as u32));
+    }
+    let (body, tag) = varint(body)?;
+    let (body, length) = varint(body)?;
+    if (body.len() as u64) < length + CHECKSUM_LENGTH as u64 {
+        return Err(FileError::Truncated);
+    }
+    let (payload, body) = body.split_at(length as usize);
+    let (expected, trailing) = body.split_at(CHECKSUM_LENGTH);
+    if !trailing.is_empty() {
+        return Err(FileError::TrailingData);
+    }
+    // blake2b_simd::Hash comparisons are constant-time.
+    if checksum(&input[..input.len() - body.len()]) != *expected {
+        return Err(FileError::ChecksumMismatch);
+    }
+    Ok((DocumentKind::from_tag(tag), payload))
+}
+
+/// Decode an object from the contents of a `.paperback` file.
+pub fn decode_file<T: FromWire>(input: &[u8]) -> Result<T, FileError> {
+    let (_, payload) = decode_file_bytes(input)?;
+    Ok(T::from_wire(payload)?)
+}
+
+/// Like `decode_file`, but the file must hold the given kind of object.
+pub fn decode_file_kind<T: FromWire>(kind: DocumentKind, input: &[u8]) -> Result<T, FileError> {
+    match decode_file_bytes(input)? {
+        (found, payload) if found == kind => Ok(T::from_wire(payload)?),
+        (found, _) => Err(FileError::KindMismatch {
+            expected: kind,
+            found,
+        }),
+    }
+}
+
+/// Write `object` to `writer` as a `.paperback` file.
+pub fn write_file<T, W>(object: &T, writer: &mut W) -> io::Result<()>
+where
+    T: ToWire + ?Sized,
+    W: Write + ?Sized,
+{
+    writer.write_all(&encode_file(object))
+}
+
+/// Read an object from a `.paperback` file read from `reader` (which is read
+/// until EOF).
+///
+/// If the file cannot be decoded, an `io::ErrorKind::InvalidData` error is
+/// returned (wrapping the `FileError`).
+pub fn read_file<T, R>(reader: &mut R) -> io::Result<T>
+where
+    T: FromWire,
+    R: Read + ?Sized,
+{
+    let mut input = vec![];
+    reader.read_to_end(&mut input)?;
+    decode_file(&input).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{shamir::Dealer, v0::Backup};
+
+    #[quickcheck]
+    fn file_roundtrip(secret: Vec<u8>) -> bool {
+        let backup = Backup::new(2, &secret).unwrap();
+        let main = backup.main_document();
+        let contents = encode_file(main);
+
+        let mut written = vec![];
+        write_file(main, &mut written).unwrap();
+
+        contents.starts_with(&FILE_MAGIC)
+            && written == contents
+            && decode_file_bytes(&contents).map(|(kind, _)| kind) == Ok(DocumentKind::MainDocument)
+            && decode_file(&contents).as_ref() == Ok(main)
+            && read_file(&mut &contents[..]).ok().as_ref() == Some(main)
+            && decode_file_kind(DocumentKind::MainDocument, &contents).as_ref() == Ok(main)
+    }
+
+    #[quickcheck]
+    fn file_damaged(secret: Vec<u8>, flip: usize) -> bool {
+        let mut dealer = Dealer::new(2, &secret);
+        let shard = dealer.next_shard();
+        let contents = encode_file(&shard);
+
+        let mut corrupted = contents.clone();
+        let flip = FILE_MAGIC.len() + flip % (corrupted.len() - FILE_MAGIC.len());
+        corrupted[flip] ^= 0x40;
+
+        let mut trailing = contents.clone();
+        trailing.push(0);
+
+        let mut mangled = contents.clone();
+        mangled.remove(FILE_MAGIC.len() - 3);
+
+        decode_file_bytes(&corrupted).is_err()
+            && decode_file_bytes(&contents[..contents.len() - 1]) == Err(FileError::Truncated)
+            && decode_file_bytes(&contents[..5]) == Err(FileError::Truncated)
+            && decode_file_bytes(&trailing) == Err(FileError::TrailingData)
+            && decode_file_bytes(&mangled) == Err(FileError::MissingMagic)
+            && decode_file_bytes(&shard.to_wire()) == Err(FileError::MissingMagic)
+            && decode_file_kind::<crate::v0::MainDocument>(DocumentKind::MainDocument, &contents)
+                == Err(FileError::KindMismatch {
+                    expected: DocumentKind::MainDocument,
+                    found: DocumentKind::Shard,
+                })
+    }
+}
@@ -16,18 +16,42 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![forbid(unsafe_code)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
 extern crate aead;
+#[cfg(feature = "std")]
+extern crate aes;
+#[cfg(feature = "std")]
+extern crate aes_gcm;
+#[cfg(feature = "std")]
+extern crate argon2;
+#[cfg(feature = "std")]
 extern crate bip39;
+extern crate blake2b_simd;
+#[cfg(feature = "std")]
 extern crate chacha20poly1305;
+#[cfg(feature = "std")]
 extern crate ed25519_dalek;
 extern crate itertools;
+#[cfg(feature = "std")]
+extern crate miniz_oxide;
+#[cfg(feature = "std")]
 extern crate nom;
+extern crate paperback_clmul;
 extern crate rand;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "serde")]
 extern crate serde;
+#[cfg(feature = "std")]
 extern crate unsigned_varint;
+#[cfg(feature = "std")]
 extern crate zbase32;
+extern crate zeroize;
 
 #[cfg(test)]
 extern crate quickcheck;
@@ -36,16 +60,71 @@ extern crate quickcheck;
 extern crate quickcheck_macros;
 
 /// Implementation of Shamir Secret Sharing.
-mod shamir;
+pub mod shamir;
+
+/// ASCII armor for serialised objects, so they can be pasted into emails and
+/// other text documents.
+#[cfg(feature = "std")]
+pub mod armor;
+
+/// Line-oriented z-base-32 encoding with per-line checksums, for typing
+/// serialised objects in by hand.
+#[cfg(feature = "std")]
+pub mod lines;
+
+/// Multibase-prefixed text encodings, so that the base a string was encoded
+/// in can be detected when decoding.
+#[cfg(feature = "std")]
+pub mod multibase;
+
+/// Encoding as a list of dictionary words, for hand-copying small objects
+/// (such as key shards).
+#[cfg(feature = "std")]
+pub mod words;
+
+/// Compatibility with SLIP-0039 Shamir mnemonic shares.
+#[cfg(feature = "std")]
+pub mod slip39;
+
+/// Bech32m encoding, whose checksum can locate mistyped characters.
+#[cfg(feature = "std")]
+pub mod bech32;
+
+/// Detection of which of the above text encodings was used for a string, so
+/// that it can be decoded without being told.
+#[cfg(feature = "std")]
+pub mod text;
+
+/// Framing for streams holding several serialised objects.
+#[cfg(feature = "std")]
+pub mod records;
+
+/// Stable container format for `.paperback` files, holding digital copies of
+/// serialised objects.
+#[cfg(feature = "std")]
+pub mod file;
+
+/// Decoding of every historical version of the wire format, and migration of
+/// old objects to the current version.
+#[cfg(feature = "std")]
+pub mod migrate;
+
+/// Deterministic CBOR encoding of paperback documents and shards, as an
+/// alternative to the wire format.
+#[cfg(feature = "cbor")]
+pub mod cbor;
 
 /// `nom` helpers which haven't been upstreamed to the relevant projects.
+#[cfg(feature = "std")]
 mod nom_helpers;
 
 /// Initial version of paperback wire format types.
 ///
 /// This module also includes all of the necessary code to serialise and
 /// interact with the relevant structures.
+#[cfg(feature = "std")]
 pub mod v0;
 
 /// Re-export of the newest paperback wire format types.
+#[cfg(feature = "std")]
 pub use v0 as latest;
@@ -0,0 +1,297 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The data is encoded as z-base-32 and split into lines of `LINE_CHARS`
+//! characters (in groups of `GROUP_CHARS`). Each line starts with its line
+//! number and ends with a two-character checksum:
+//!
+//! ```text
+//! 01 cpzz rhuf cp4n y4dx qj3s kedn cf48 e3m1 ch
+//! 02 xro8 g7db qbsg kee gj
+//! ```
+//!
+//! The first checksum character is a Luhn mod 32 check character, which
+//! detects any single mistyped character (and almost all transpositions of
+//! adjacent characters). The second is derived from a CRC-32C of the line and
+//! also marks the final line, so missing lines are detected. Both cover the
+//! line number, so lines typed in the wrong order are detected too.
+//!
+//! When decoding, whitespace between groups is ignored, uppercase characters
+//! are accepted and visually confusable characters (such as `0` for `o`) are
+//! normalised, to make typing the data in by hand easier.
+
+use crate::{
+    shamir::encoding::{self, ZBASE32_ALPHABET},
+    v0::{FromWire, ToWire, WireError},
+};
+
+use std::{error, fmt};
+
+/// Number of z-base-32 characters (not including the line number and
+/// checksum) on each line. Each full line holds 20 bytes of data.
+pub const LINE_CHARS: usize = 32;
+
+/// Number of z-base-32 characters in each space-separated group of a line.
+pub const GROUP_CHARS: usize = 4;
+
+/// Reasons why line-encoded text could not be decoded.
+///
+/// All line numbers are the numbers written at the start of each line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LineError {
+    /// The line is not of the form `<number> <data> <checksum>`, or contains
+    /// characters which are not z-base-32.
+    Malformed { line: usize },
+    /// The line has a different number than expected (a line was skipped,
+    /// repeated or typed in the wrong order).
+    UnexpectedLine { expected: usize, found: usize },
+    /// The checksum of the line does not match (it was mistyped).
+    ChecksumMismatch { line: usize },
+    /// There were only `lines` lines, but the final line is missing.
+    Truncated { lines: usize },
+    /// There is an extra line after the final line.
+    TrailingLine { line: usize },
+    /// The data is not canonically z-base-32 encoded.
+    InvalidEncoding,
+    /// The data could not be deserialised.
+    Wire(WireError),
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineError::Malformed { line } => write!(f, "line {} is malformed", line),
+            LineError::UnexpectedLine { expected, found } => {
+                write!(f, "expected line {} but found line {}", expected, found)
+            }
+            LineError::ChecksumMismatch { line } => {
+                write!(f, "line {} was mistyped (checksum mismatch)", line)
+            }
+            LineError::Truncated { lines } => {
+                write!(f, "missing lines after line {}", lines)
+            }
+            LineError::TrailingLine { line } => {
+                write!(f, "unexpected line {} after the final line", line)
+            }
+            LineError::InvalidEncoding => write!(f, "data is not valid z-base-32"),
+            LineError::Wire(err) => write!(f, "failed to deserialise data: {}", err),
+        }
+    }
+}
+
+impl error::Error for LineError {}
+
+impl From<WireError> for LineError {
+    fn from(err: WireError) -> Self {
+        LineError::Wire(err)
+    }
+}
+
+// Two-character checksum of the given line, or None if the data contains
+// non-z-base-32 characters.
+fn line_checksum(number: usize, data: &str, last: bool) -> Option<String> {
+    let mut input = encoding::zbase32(&(number as u64).to_be_bytes());
+    input.push_str(data);
+
+    let check = encoding::zbase32_check(&input)?;
+    let crc = encoding::crc32c(input.as_bytes()) as usize;
+    let tail = ZBASE32_ALPHABET[((crc & 0xf) << 1) | usize::from(last)] as char;
+    Some(format!("{}{}", check, tail))
+}
+
+/// Encode `data` as numbered, checksummed lines of z-base-32.
+pub fn encode(data: &[u8]) -> String {
+    let encoded = zbase32::encode_full_bytes(data);
+    // z-base-32 is pure ASCII, so splitting at any byte is safe.
+    let chunks = match encoded.len() {
+        0 => vec![""],
+        _ => encoded
+            .as_bytes()
+            .chunks(LINE_CHARS)
+            .map(|chunk| std::str::from_utf8(chunk).expect("z-base-32 is ASCII"))
+            .collect(),
+    };
+    let width = chunks.len().to_string().len().max(2);
+
+    let mut text = String::new();
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let number = idx + 1;
+        let groups = chunk
+            .as_bytes()
+            .chunks(GROUP_CHARS)
+            .map(|group| std::str::from_utf8(group).expect("z-base-32 is ASCII"))
+            .collect::<Vec<_>>();
+        let checksum =
+            line_checksum(number, chunk, number == chunks.len()).expect("z-base-32 data is valid");
+
+        text.push_str(&format!("{:0width$} ", number, width = width));
+        for group in groups {
+            text.push_str(group);
+            text.push(' ');
+        }
+        text.push_str(&checksum);
+        text.push('\n');
+    }
+    text
+}
+
+/// Encode the wire format of `object` as numbered, checksummed lines.
+pub fn encode_wire<T: ToWire + ?Sized>(object: &T) -> String {
+    encode(&object.to_wire())
+}
+
+/// Decode text produced by `encode`, reporting which line was mistyped if
+/// the text is not valid. Blank lines are ignored.
+pub fn decode(text: &str) -> Result<Vec<u8>, LineError> {
+    let (mut encoded, mut lines, mut last) = (String::new(), 0, false);
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let expected = lines + 1;
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        let (number, checksum, groups) = match tokens.as_slice() {
+            [number, groups @ .., checksum] => (
+                number,
+                encoding::zbase32_normalize(checksum),
+                groups
+                    .iter()
+                    .map(|group| encoding::zbase32_normalize(group)),
+            ),
+            _ => return Err(LineError::Malformed { line: expected }),
+        };
+        let number = number
+            .parse::<usize>()
+            .map_err(|_| LineError::Malformed { line: expected })?;
+
+        if last {
+            return Err(LineError::TrailingLine { line: number });
+        }
+        if number != expected {
+            return Err(LineError::UnexpectedLine {
+                expected,
+                found: number,
+            });
+        }
+
+        let data = groups.collect::<String>();
+        let checksums = [false, true]
+            .iter()
+            .map(|last| line_checksum(number, &data, *last))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(LineError::Malformed { line: number })?;
+        last = match checksums.iter().position(|sum| *sum == checksum) {
+            Some(idx) => idx == 1,
+            None => return Err(LineError::ChecksumMismatch { line: number }),
+        };
+
+        encoded.push_str(&data);
+        lines = number;
+    }
+    if !last {
+        return Err(LineError::Truncated { lines });
+    }
+
+    let data = zbase32::decode_full_bytes_str(&encoded).map_err(|_| LineError::InvalidEncoding)?;
+    // Only accept the canonical encoding of the data.
+    if zbase32::encode_full_bytes(&data) != encoded {
+        return Err(LineError::InvalidEncoding);
+    }
+    Ok(data)
+}
+
+/// Decode an object from text produced by `encode_wire`.
+pub fn decode_wire<T: FromWire>(text: &str) -> Result<T, LineError> {
+    Ok(T::from_wire(decode(text)?)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::shamir::Shard;
+
+    #[quickcheck]
+    fn lines_roundtrip(data: Vec<u8>) -> bool {
+        let text = encode(&data);
+        text.lines()
+            .all(|line| line.split_whitespace().all(|t| t.len() <= GROUP_CHARS))
+            && decode(&text) == Ok(data.clone())
+            && decode(&text.to_ascii_uppercase()) == Ok(data.clone())
+            && decode(&text.replace('o', "0").replace('z', "2")) == Ok(data)
+    }
+
+    #[quickcheck]
+    fn lines_shard_roundtrip(shard: Shard) -> bool {
+        decode_wire::<Shard>(&encode_wire(&shard)).as_ref() == Ok(&shard)
+    }
+
+    #[quickcheck]
+    fn lines_typo(data: Vec<u8>, line: usize, pos: usize, delta: usize) -> bool {
+        let text = encode(&data);
+        let mut lines = text.lines().map(String::from).collect::<Vec<_>>();
+
+        // Replace one data character with a different one.
+        let number = line % lines.len();
+        let start = lines[number].find(' ').unwrap() + 1;
+        let end = lines[number].rfind(' ').unwrap();
+        let positions = (start..end)
+            .filter(|idx| lines[number].as_bytes()[*idx] != b' ')
+            .collect::<Vec<_>>();
+        if positions.is_empty() {
+            return true;
+        }
+        let pos = positions[pos % positions.len()];
+        let old = lines[number].as_bytes()[pos];
+        let code = ZBASE32_ALPHABET.iter().position(|c| *c == old).unwrap();
+        let new = ZBASE32_ALPHABET[(code + 1 + delta % 31) % 32] as char;
+        lines[number].replace_range(pos..=pos, &new.to_string());
+
+        decode(&lines.join("\n")) == Err(LineError::ChecksumMismatch { line: number + 1 })
+    }
+
+    #[test]
+    fn lines_errors() {
+        let text = encode(&[0x42; 64]);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+
+        let join = |lines: &[&str]| lines.join("\n");
+        assert_eq!(
+            decode(&join(&[lines[0], lines[2], lines[3]])),
+            Err(LineError::UnexpectedLine {
+                expected: 2,
+                found: 3
+            })
+        );
+        assert_eq!(
+            decode(&join(&lines[..3])),
+            Err(LineError::Truncated { lines: 3 })
+        );
+        assert_eq!(
+            decode(&join(&[&lines[..], &[lines[3]]].concat())),
+            Err(LineError::TrailingLine { line: 4 })
+        );
+        assert_eq!(
+            decode(&lines[0].replacen("01", "0x", 1)),
+            Err(LineError::Malformed { line: 1 })
+        );
+        assert_eq!(
+            decode(&join(&[lines[0], "02"])),
+            Err(LineError::Malformed { line: 2 })
+        );
+        assert_eq!(decode(""), Err(LineError::Truncated { lines: 0 }));
+    }
+}
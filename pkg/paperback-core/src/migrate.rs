@@ -0,0 +1,113 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Objects written with the original (headerless) wire format can still be
+//! decoded, so that old paper backups remain readable. This module reports
+//! which version an object was written with, and can re-encode old objects
+//! with the current version (`shamir::WIRE_VERSION`).
+//!
+//! Version 0 is the original format, which had no header at all.
+
+use crate::{
+    shamir::{header, WIRE_VERSION},
+    v0::{FromWire, ToWire, WireError},
+};
+
+/// Returns the version of the wire format `input` was written with, without
+/// decoding it.
+pub fn wire_version(input: &[u8]) -> Result<u32, WireError> {
+    header::read_header(input).map(|(_, version)| version)
+}
+
+/// Decode an object written with any version of the wire format, returning
+/// the version it was written with.
+pub fn decode_any_version<T: FromWire>(input: &[u8]) -> Result<(T, u32), WireError> {
+    let version = wire_version(input)?;
+    Ok((T::from_wire(input)?, version))
+}
+
+/// Re-encode an object written with any version of the wire format with the
+/// current version. Objects which already use the current version are
+/// re-encoded as well, so the output is always canonical.
+pub fn migrate<T: FromWire + ToWire>(input: &[u8]) -> Result<Vec<u8>, WireError> {
+    decode_any_version::<T>(input).map(|(object, _)| object.to_wire())
+}
+
+/// Returns whether `input` needs to be migrated to be written with the current
+/// version of the wire format.
+pub fn needs_migration(input: &[u8]) -> Result<bool, WireError> {
+    wire_version(input).map(|version| version != WIRE_VERSION)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        shamir::{encoding, Shard, WIRE_MAGIC},
+        v0::MainDocument,
+    };
+
+    #[quickcheck]
+    fn migrate_shard(shard: Shard) -> bool {
+        let bytes = shard.to_wire();
+        decode_any_version::<Shard>(&bytes) == Ok((shard.clone(), WIRE_VERSION))
+            && migrate::<Shard>(&bytes) == Ok(bytes.clone())
+            && needs_migration(&bytes) == Ok(false)
+    }
+
+    #[test]
+    fn migrate_baseline_shard() {
+        // A shard written by the first version of paperback, without a header.
+        let baseline = [
+            0xc1, 0xb5, 0xc2, 0xaf, 0x02, 0x05, 0xc8, 0xb2, 0xe0, 0xb9, 0x0b, 0x92, 0xab, 0xa2,
+            0xa7, 0x01, 0xf0, 0xb2, 0x94, 0xef, 0x04, 0xe0, 0xc7, 0x93, 0xe4, 0x0b, 0xc7, 0x96,
+            0x99, 0xb6, 0x0b, 0x02, 0x11,
+        ];
+        let (shard, version) = decode_any_version::<Shard>(&baseline).unwrap();
+        let migrated = migrate::<Shard>(&baseline).unwrap();
+
+        assert_eq!(version, 0);
+        assert_eq!(needs_migration(&baseline), Ok(true));
+        assert_eq!(
+            decode_any_version::<Shard>(&migrated),
+            Ok((shard, WIRE_VERSION))
+        );
+    }
+
+    #[quickcheck]
+    fn migrate_main_document(main: MainDocument) -> bool {
+        let legacy = main.wire_body();
+        let migrated = migrate::<MainDocument>(&legacy).unwrap();
+        decode_any_version::<MainDocument>(&legacy) == Ok((main.clone(), 0))
+            && migrated == main.to_wire()
+            && MainDocument::from_wire(&migrated).map(|main| main.checksum()) == Ok(main.checksum())
+    }
+
+    #[test]
+    fn migrate_errors() {
+        let mut future = WIRE_MAGIC.to_vec();
+        encoding::varint(u64::from(WIRE_VERSION) + 1, &mut future);
+        assert_eq!(wire_version(&future), Ok(WIRE_VERSION + 1));
+        assert!(matches!(
+            decode_any_version::<Shard>(&future),
+            Err(WireError::UnsupportedVersion { .. })
+        ));
+        assert!(migrate::<Shard>(&WIRE_MAGIC).is_err());
+    }
+}
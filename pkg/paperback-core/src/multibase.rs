@@ -0,0 +1,387 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Encoded strings start with the [multibase][multibase] code of their base,
+//! so decoders can tell how a string was encoded without being told. Only the
+//! canonical encoding of any data is accepted when decoding.
+//!
+//! Base45 only uses characters from the QR code alphanumeric charset, so
+//! QR codes of base45 strings can use the (denser) alphanumeric mode rather
+//! than byte mode, and carry more data than codes of the other bases.
+//!
+//! [multibase]: https://github.com/multiformats/multibase
+
+use crate::{
+    shamir::encoding,
+    v0::{FromWire, ToWire, WireError},
+};
+
+use std::{error, fmt};
+
+const BASE58BTC_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BASE64URL_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// The QR code alphanumeric charset, which is also the base45 alphabet.
+pub const QR_ALPHANUMERIC_CHARSET: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Bases supported by `encode` and `decode`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Base {
+    /// z-base-32 (the base used elsewhere in paperback), with code `h`.
+    Base32Z,
+    /// Base58 with the Bitcoin alphabet, with code `z`.
+    Base58Btc,
+    /// URL-safe base64 without padding, with code `u`.
+    Base64Url,
+    /// Base45 (RFC 9285), with code `R`. Strings in this base only contain
+    /// characters from `QR_ALPHANUMERIC_CHARSET`.
+    Base45,
+}
+
+impl Base {
+    /// All supported bases.
+    pub const ALL: [Base; 4] = [
+        Base::Base32Z,
+        Base::Base58Btc,
+        Base::Base64Url,
+        Base::Base45,
+    ];
+
+    /// The multibase code which prefixes strings in this base.
+    pub fn code(self) -> char {
+        match self {
+            Base::Base32Z => 'h',
+            Base::Base58Btc => 'z',
+            Base::Base64Url => 'u',
+            Base::Base45 => 'R',
+        }
+    }
+
+    /// Find the base with the given multibase code, if it is supported.
+    pub fn from_code(code: char) -> Option<Self> {
+        Self::ALL.iter().copied().find(|base| base.code() == code)
+    }
+
+    fn encode(self, data: &[u8]) -> String {
+        match self {
+            Base::Base32Z => zbase32::encode_full_bytes(data),
+            Base::Base58Btc => base58btc_encode(data),
+            Base::Base64Url => base64url_encode(data),
+            Base::Base45 => base45_encode(data),
+        }
+    }
+
+    // Undo transcription mistakes which don't change the meaning of a string
+    // in this base.
+    fn normalize(self, encoded: &str) -> String {
+        match self {
+            Base::Base32Z => encoding::zbase32_normalize(encoded),
+            _ => encoded.to_string(),
+        }
+    }
+
+    fn decode(self, encoded: &str) -> Option<Vec<u8>> {
+        match self {
+            Base::Base32Z => zbase32::decode_full_bytes_str(encoded).ok(),
+            Base::Base58Btc => base58btc_decode(encoded),
+            Base::Base64Url => base64url_decode(encoded),
+            Base::Base45 => base45_decode(encoded),
+        }
+    }
+
+    /// Whether every string in this base (including its multibase code) can
+    /// be stored in a QR code using alphanumeric mode.
+    pub fn is_qr_alphanumeric(self) -> bool {
+        self == Base::Base45
+    }
+}
+
+/// Reasons why a multibase string could not be decoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MultibaseError {
+    /// The string is empty, so has no multibase code.
+    Empty,
+    /// The multibase code is not one of the supported bases.
+    UnknownBase(char),
+    /// The string is not the canonical encoding of any data in its base.
+    InvalidEncoding(Base),
+    /// The decoded data could not be deserialised.
+    Wire(WireError),
+}
+
+impl fmt::Display for MultibaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultibaseError::Empty => write!(f, "missing multibase code"),
+            MultibaseError::UnknownBase(code) => {
+                write!(f, "unsupported multibase code {:?}", code)
+            }
+            MultibaseError::InvalidEncoding(base) => write!(f, "invalid {:?} encoding", base),
+            MultibaseError::Wire(err) => write!(f, "failed to deserialise data: {}", err),
+        }
+    }
+}
+
+impl error::Error for MultibaseError {}
+
+impl From<WireError> for MultibaseError {
+    fn from(err: WireError) -> Self {
+        MultibaseError::Wire(err)
+    }
+}
+
+/// Encode `data` in the given base, prefixed with its multibase code.
+pub fn encode<B: AsRef<[u8]>>(base: Base, data: B) -> String {
+    let mut encoded = base.code().to_string();
+    encoded.push_str(&base.encode(data.as_ref()));
+    encoded
+}
+
+/// Encode the wire format of `object` in the given base, prefixed with its
+/// multibase code.
+pub fn encode_wire<T: ToWire + ?Sized>(base: Base, object: &T) -> String {
+    encode(base, object.to_wire())
+}
+
+/// Decode a multibase string, returning the base it was encoded in.
+pub fn decode<S: AsRef<str>>(input: S) -> Result<(Base, Vec<u8>), MultibaseError> {
+    let input = input.as_ref();
+    let code = input.chars().next().ok_or(MultibaseError::Empty)?;
+    let base = Base::from_code(code).ok_or(MultibaseError::UnknownBase(code))?;
+    let encoded = base.normalize(&input[code.len_utf8()..]);
+
+    match base.decode(&encoded) {
+        // Only accept the canonical encoding of the data.
+        Some(data) if base.encode(&data) == encoded => Ok((base, data)),
+        _ => Err(MultibaseError::InvalidEncoding(base)),
+    }
+}
+
+/// Decode an object from a multibase string produced by `encode_wire`.
+pub fn decode_wire<T: FromWire, S: AsRef<str>>(input: S) -> Result<(Base, T), MultibaseError> {
+    let (base, data) = decode(input)?;
+    Ok((base, T::from_wire(data)?))
+}
+
+fn base58btc_encode(data: &[u8]) -> String {
+    // Leading zero bytes are encoded as leading '1's.
+    let zeros = data.iter().take_while(|b| **b == 0).count();
+
+    // Little-endian base58 digits of the rest of the data.
+    let mut digits: Vec<u8> = vec![];
+    for byte in &data[zeros..] {
+        let mut carry = u32::from(*byte);
+        for digit in digits.iter_mut() {
+            carry += u32::from(*digit) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    std::iter::repeat_n(BASE58BTC_ALPHABET[0], zeros)
+        .chain(
+            digits
+                .iter()
+                .rev()
+                .map(|d| BASE58BTC_ALPHABET[usize::from(*d)]),
+        )
+        .map(char::from)
+        .collect()
+}
+
+fn base58btc_decode(encoded: &str) -> Option<Vec<u8>> {
+    let zeros = encoded
+        .bytes()
+        .take_while(|c| *c == BASE58BTC_ALPHABET[0])
+        .count();
+
+    // Little-endian bytes of the rest of the data.
+    let mut bytes: Vec<u8> = vec![];
+    for c in encoded[zeros..].bytes() {
+        let mut carry = BASE58BTC_ALPHABET.iter().position(|a| *a == c)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += u32::from(*byte) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    let mut data = vec![0; zeros];
+    data.extend(bytes.iter().rev());
+    Some(data)
+}
+
+fn base64url_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() * 4).div_ceil(3));
+    for chunk in data.chunks(3) {
+        let buffer = chunk.iter().enumerate().fold(0u32, |buffer, (idx, b)| {
+            buffer | u32::from(*b) << (16 - 8 * idx)
+        });
+        // Each byte needs (at least part of) one more character.
+        for idx in 0..=chunk.len() {
+            let sextet = (buffer >> (18 - 6 * idx)) & 0x3f;
+            encoded.push(BASE64URL_ALPHABET[sextet as usize] as char);
+        }
+    }
+    encoded
+}
+
+fn base64url_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(encoded.len() * 3 / 4);
+    for chunk in encoded.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut buffer = 0u32;
+        for (idx, c) in chunk.iter().enumerate() {
+            let sextet = BASE64URL_ALPHABET.iter().position(|a| a == c)? as u32;
+            buffer |= sextet << (18 - 6 * idx);
+        }
+        for idx in 0..chunk.len() - 1 {
+            data.push((buffer >> (16 - 8 * idx)) as u8);
+        }
+    }
+    Some(data)
+}
+
+fn base45_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(2) * 3);
+    for chunk in data.chunks(2) {
+        // Each pair of bytes is three digits, and a trailing byte is two.
+        let mut n = chunk.iter().fold(0u32, |n, b| n << 8 | u32::from(*b));
+        for _ in 0..=chunk.len() {
+            encoded.push(QR_ALPHANUMERIC_CHARSET[(n % 45) as usize] as char);
+            n /= 45;
+        }
+    }
+    encoded
+}
+
+fn base45_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(encoded.len() * 2 / 3);
+    for chunk in encoded.as_bytes().chunks(3) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut n = 0u32;
+        for c in chunk.iter().rev() {
+            n = n * 45 + QR_ALPHANUMERIC_CHARSET.iter().position(|a| a == c)? as u32;
+        }
+        match chunk.len() {
+            3 if n <= 0xffff => data.extend_from_slice(&(n as u16).to_be_bytes()),
+            2 if n <= 0xff => data.push(n as u8),
+            _ => return None,
+        }
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::shamir::Shard;
+
+    #[test]
+    fn multibase_vectors() {
+        // Test vectors from the multibase specification.
+        let data = b"yes mani !";
+        assert_eq!(encode(Base::Base32Z, data), "hxf1zgedpcfzg1ebb");
+        assert_eq!(encode(Base::Base58Btc, data), "z7paNL19xttacUY");
+        assert_eq!(encode(Base::Base64Url, data), "ueWVzIG1hbmkgIQ");
+
+        let zeros = b"\x00\x00yes mani !";
+        assert_eq!(encode(Base::Base58Btc, zeros), "z117paNL19xttacUY");
+    }
+
+    #[test]
+    fn base45_vectors() {
+        // Test vectors from RFC 9285.
+        assert_eq!(base45_encode(b"AB"), "BB8");
+        assert_eq!(base45_encode(b"Hello!!"), "%69 VD92EX0");
+        assert_eq!(base45_encode(b"base-45"), "UJCLQE7W581");
+        assert_eq!(base45_decode("QED8WEX0"), Some(b"ietf!".to_vec()));
+        // "GGW" is 65536, which is too large for two bytes.
+        assert_eq!(base45_decode("GGW"), None);
+    }
+
+    #[quickcheck]
+    fn base45_is_qr_alphanumeric(data: Vec<u8>) -> bool {
+        encode(Base::Base45, &data)
+            .bytes()
+            .all(|c| QR_ALPHANUMERIC_CHARSET.contains(&c))
+    }
+
+    #[quickcheck]
+    fn multibase_roundtrip(data: Vec<u8>) -> bool {
+        Base::ALL
+            .iter()
+            .all(|base| decode(encode(*base, &data)) == Ok((*base, data.clone())))
+    }
+
+    #[quickcheck]
+    fn multibase_zbase32_confusables(data: Vec<u8>) -> bool {
+        let encoded = encode(Base::Base32Z, &data)
+            .to_ascii_uppercase()
+            .replacen('H', "h", 1)
+            .replace('O', "0");
+        decode(encoded) == Ok((Base::Base32Z, data))
+    }
+
+    #[quickcheck]
+    fn multibase_wire_roundtrip(shard: Shard) -> bool {
+        Base::ALL.iter().all(|base| {
+            let (found, decoded) = decode_wire::<Shard, _>(encode_wire(*base, &shard)).unwrap();
+            found == *base && decoded == shard
+        })
+    }
+
+    #[test]
+    fn multibase_errors() {
+        assert_eq!(decode(""), Err(MultibaseError::Empty));
+        assert_eq!(decode("Mabc"), Err(MultibaseError::UnknownBase('M')));
+        // "0" is not in the base58btc alphabet.
+        assert_eq!(
+            decode("z0"),
+            Err(MultibaseError::InvalidEncoding(Base::Base58Btc))
+        );
+        // A single base64 character cannot encode a whole byte.
+        assert_eq!(
+            decode("uA"),
+            Err(MultibaseError::InvalidEncoding(Base::Base64Url))
+        );
+        // Non-zero trailing bits are not canonical.
+        assert_eq!(
+            decode("ueWVzIG1hbmkgIR"),
+            Err(MultibaseError::InvalidEncoding(Base::Base64Url))
+        );
+        assert!(matches!(
+            decode_wire::<Shard, _>("h"),
+            Err(MultibaseError::Wire(_))
+        ));
+    }
+}
@@ -19,7 +19,10 @@
 #![allow(dead_code)]
 
 use nom::{error::ErrorKind, Err as NomErr, IResult, Needed};
-use unsigned_varint::decode::{self, Error};
+use unsigned_varint::{
+    decode::{self, Error},
+    encode,
+};
 
 // This is copied from a PR I wrote to add a nom parser to unsigned-varint:
 //   <https://github.com/paritytech/unsigned-varint/pull/27>
@@ -28,12 +31,19 @@ macro_rules! gen {
         $(
             #[doc = " `nom` combinator to decode a variable-length encoded "]
             #[doc = $d]
-            #[doc = "."]
+            #[doc = ", which must be in its canonical (shortest) form."]
             pub fn $type(input: &[u8]) -> IResult<&[u8], $type> {
                 let (n, remain) = decode::$type(input).map_err(|err| match err {
                     Error::Insufficient => NomErr::Incomplete(Needed::Unknown),
                     Error::Overflow | _ => NomErr::Error((input, ErrorKind::TooLarge)),
                 })?;
+                // Reject over-long encodings (and encodings with bits which
+                // overflow the type), so that every value has exactly one
+                // valid encoding.
+                let consumed = &input[..input.len() - remain.len()];
+                if encode::$type(n, &mut encode::$b()) != consumed {
+                    return Err(NomErr::Error((input, ErrorKind::TooLarge)));
+                }
                 Ok((remain, n))
             }
         )*
@@ -0,0 +1,303 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Any number of objects (of any kind) can be stored in a single stream or
+//! file by concatenating their wire encodings. Each object is framed by its
+//! length and tagged with its kind (see `shamir::DocumentKind`), so the stream
+//! can be split back into the original objects (with `decode_all`) without
+//! knowing in advance what it contains.
+//!
+//! Objects serialised before type tags were added to the wire format cannot
+//! be stored in a stream, and must be re-serialised first.
+//!
+//! If a stream has been cut short (for instance, the bottom of the page it
+//! was printed on was torn off), `decode_partial` returns every complete
+//! record along with a description of what is missing from the rest.
+
+use crate::{
+    shamir::{header, DocumentKind},
+    v0::{FromWire, ToWire, WireError},
+};
+
+/// A single serialised object split from a stream by `decode_all`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Record<'a> {
+    kind: DocumentKind,
+    bytes: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    /// Returns the kind of object stored in the record.
+    pub fn kind(&self) -> DocumentKind {
+        self.kind
+    }
+
+    /// Returns the wire encoding of the object (including its header).
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Deserialise the object stored in the record.
+    pub fn decode<T: FromWire>(&self) -> Result<T, WireError> {
+        T::from_wire(self.bytes)
+    }
+}
+
+/// Description of the part of a record missing from a truncated stream.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Truncation<'a> {
+    index: usize,
+    field: &'static str,
+    offset: usize,
+    missing: usize,
+    bytes: &'a [u8],
+}
+
+impl<'a> Truncation<'a> {
+    /// Returns the index of the truncated record in the stream (which is the
+    /// number of complete records before it).
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the field of the record which was cut off (one of `magic`,
+    /// `version`, `kind`, `flags`, `length`, `body` or `checksum`). Every
+    /// field after it is missing entirely.
+    pub fn field(&self) -> &'static str {
+        self.field
+    }
+
+    /// Returns the position (in bytes) of the start of the truncated field
+    /// within the stream.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the number of bytes of the truncated field which are missing.
+    /// For the variable-length integers in the header, this is only a lower
+    /// bound.
+    pub fn missing(&self) -> usize {
+        self.missing
+    }
+
+    /// Returns the bytes of the truncated record which are present.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+}
+
+/// Serialise every object in `objects` into a single stream.
+pub fn encode_all<'a, I>(objects: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = &'a dyn ToWire>,
+{
+    let mut bytes = vec![];
+    objects
+        .into_iter()
+        .for_each(|object| object.to_wire_into(&mut bytes));
+    bytes
+}
+
+/// Split a stream produced by `encode_all` into its records.
+///
+/// The checksum of every record is verified, but the records are not
+/// deserialised (see `Record::decode`). Error offsets are relative to the
+/// start of the stream.
+pub fn decode_all(input: &[u8]) -> Result<Vec<Record<'_>>, WireError> {
+    let mut records = vec![];
+    let mut remain = input;
+    while !remain.is_empty() {
+        let offset = input.len() - remain.len();
+        let (kind, bytes, rest) = header::split_record(remain)
+            .map_err(|err| err.in_field(format!("record[{}]", records.len()), offset))?;
+        records.push(Record { kind, bytes });
+        remain = rest;
+    }
+    Ok(records)
+}
+
+/// Split a possibly truncated stream produced by `encode_all` into as many
+/// complete records as possible, like `decode_all`.
+///
+/// If the stream ends part of the way through a record, the complete records
+/// before it are returned along with a description of what is missing (the
+/// incomplete record cannot be verified, so it is not returned as a record).
+/// Streams which are damaged in any other way are still rejected.
+pub fn decode_partial(
+    input: &[u8],
+) -> Result<(Vec<Record<'_>>, Option<Truncation<'_>>), WireError> {
+    let mut records = vec![];
+    let mut remain = input;
+    while !remain.is_empty() {
+        let offset = input.len() - remain.len();
+        let index = records.len();
+        match header::split_record(remain) {
+            Ok((kind, bytes, rest)) => {
+                records.push(Record { kind, bytes });
+                remain = rest;
+            }
+            Err(err) => {
+                return match header::truncation(remain) {
+                    Some((field, field_offset, missing)) => Ok((
+                        records,
+                        Some(Truncation {
+                            index,
+                            field,
+                            offset: offset + field_offset,
+                            missing,
+                            bytes: remain,
+                        }),
+                    )),
+                    None => Err(err.in_field(format!("record[{}]", index), offset)),
+                }
+            }
+        }
+    }
+    Ok((records, None))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        shamir::{Dealer, Shard, ShardCommitments, WIRE_MAGIC},
+        v0::{EncryptedKeyShard, MainDocument},
+    };
+
+    #[quickcheck]
+    fn records_roundtrip(main: MainDocument, shards: Vec<EncryptedKeyShard>) -> bool {
+        let mut objects = vec![&main as &dyn ToWire];
+        objects.extend(shards.iter().map(|shard| shard as &dyn ToWire));
+        let bytes = encode_all(objects);
+
+        let records = decode_all(&bytes).unwrap();
+        records.len() == shards.len() + 1
+            && records[0].kind() == DocumentKind::MainDocument
+            && records[0].decode::<MainDocument>().as_ref() == Ok(&main)
+            && records[1..].iter().zip(&shards).all(|(record, shard)| {
+                record.kind() == DocumentKind::EncryptedKeyShard
+                    && record.decode::<EncryptedKeyShard>().as_ref() == Ok(shard)
+            })
+            && records
+                .iter()
+                .map(|r| r.as_bytes())
+                .collect::<Vec<_>>()
+                .concat()
+                == bytes
+    }
+
+    #[test]
+    fn records_mixed() {
+        let mut dealer = Dealer::new(2, b"paperback");
+        let shard = dealer.next_shard();
+        let commitments = dealer.commitments();
+        let bytes = encode_all(vec![&shard as &dyn ToWire, &commitments]);
+
+        let records = decode_all(&bytes).unwrap();
+        assert_eq!(records[0].decode::<Shard>(), Ok(shard));
+        assert_eq!(records[1].decode::<ShardCommitments>(), Ok(commitments));
+        assert!(records[1].decode::<Shard>().is_err());
+        assert_eq!(decode_all(&[]), Ok(vec![]));
+    }
+
+    #[quickcheck]
+    fn records_partial(shards: Vec<Shard>, cut: usize) -> bool {
+        let objects = shards.iter().map(|shard| shard as &dyn ToWire);
+        let bytes = encode_all(objects);
+        let cut = cut % (bytes.len() + 1);
+
+        // The complete records before the cut are recovered, and the missing
+        // bytes of the truncated record are accounted for exactly (unless the
+        // cut is within a varint of the header).
+        let (records, truncation) = decode_partial(&bytes[..cut]).unwrap();
+        let complete = records.iter().map(|r| r.as_bytes().len()).sum::<usize>();
+        let decoded = records
+            .iter()
+            .zip(&shards)
+            .all(|(r, s)| r.decode().as_ref() == Ok(s));
+        let end = |t: &Truncation| cut + t.missing();
+        decoded
+            && match truncation {
+                None => cut == bytes.len() || complete == cut,
+                Some(t) => {
+                    let record = shards[t.index()].to_wire();
+                    t.index() == records.len()
+                        && t.as_bytes() == &bytes[complete..cut]
+                        && match t.field() {
+                            "magic" => end(&t) == complete + WIRE_MAGIC.len(),
+                            "body" => end(&t) == complete + record.len() - 4,
+                            "checksum" => end(&t) == complete + record.len(),
+                            _ => t.missing() >= 1,
+                        }
+                        && match t.field() {
+                            "magic" => t.offset() == complete,
+                            _ => t.offset() > complete && t.offset() <= cut,
+                        }
+                }
+            }
+    }
+
+    #[test]
+    fn records_partial_fields() {
+        let shard = Dealer::new(2, b"paperback").next_shard();
+        let record = shard.to_wire();
+        let bytes = [&record[..], &record[..]].concat();
+
+        let (records, truncation) = decode_partial(&bytes[..record.len() + 2]).unwrap();
+        let truncation = truncation.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            (truncation.index(), truncation.field(), truncation.missing()),
+            (1, "magic", 1)
+        );
+
+        let (_, truncation) = decode_partial(&bytes[..bytes.len() - 10]).unwrap();
+        let truncation = truncation.unwrap();
+        assert_eq!((truncation.field(), truncation.missing()), ("body", 6));
+        assert_eq!(truncation.offset(), record.len() + 7);
+
+        let (_, truncation) = decode_partial(&bytes[..bytes.len() - 1]).unwrap();
+        let truncation = truncation.unwrap();
+        assert_eq!((truncation.field(), truncation.missing()), ("checksum", 1));
+        assert_eq!(truncation.offset(), bytes.len() - 4);
+
+        // Other damage is still an error.
+        let mut corrupted = bytes.clone();
+        corrupted[record.len() - 1] ^= 1;
+        assert_eq!(
+            decode_partial(&corrupted).map_err(|err| err.cause().clone()),
+            Err(WireError::Corrupted)
+        );
+    }
+
+    #[quickcheck]
+    fn records_errors(shard: Shard) -> bool {
+        let record = shard.to_wire();
+        let bytes = [&record[..], &record[..]].concat();
+
+        // Untagged (legacy) objects cannot be split.
+        let legacy = [&record[..], &shard.wire_bytes()[..]].concat();
+        let err = decode_all(&legacy).unwrap_err();
+
+        decode_all(&bytes[..bytes.len() - 1]).map_err(|err| err.cause().clone())
+            == Err(WireError::UnexpectedEof)
+            && err.field() == Some("record[1]")
+            && err.offset() == Some(record.len())
+    }
+}
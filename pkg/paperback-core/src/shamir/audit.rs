@@ -0,0 +1,261 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::shamir::{gf::GfField, shamir::GenericShard};
+#[cfg(feature = "std")]
+use crate::{
+    shamir::{
+        encoding,
+        header::{self, DocumentKind},
+        limits::{FieldOffsets, WireError, WireLimits},
+    },
+    v0::{FromWire, ToWire},
+};
+
+use alloc::{string::String, vec::Vec};
+
+/// Record of a `Shard` issued by a `Dealer` (see `Dealer::issued`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IssuedShard {
+    id: String,
+    x: Vec<u8>,
+    timestamp: Option<u64>,
+}
+
+impl IssuedShard {
+    pub(super) fn new<F: GfField>(shard: &GenericShard<F>) -> Self {
+        IssuedShard {
+            id: shard.id(),
+            x: shard.x().as_bytes().to_vec(),
+            timestamp: now(),
+        }
+    }
+
+    /// Returns the identifier of the issued `Shard`.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the x-coordinate of the issued `Shard` (which can be passed to
+    /// `Dealer::shard_at` to re-issue it).
+    pub fn x(&self) -> &[u8] {
+        &self.x
+    }
+
+    /// Returns when the `Shard` was issued (in seconds since the Unix epoch),
+    /// or `None` if the time is not known.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+}
+
+#[cfg(feature = "std")]
+fn now() -> Option<u64> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+// Without std there is no clock to read.
+#[cfg(not(feature = "std"))]
+fn now() -> Option<u64> {
+    None
+}
+
+/// Exportable log of every `Shard` issued by a `Dealer` (see
+/// `Dealer::issued_log`), in the order they were issued.
+///
+/// This allows backup owners to keep track of which custodian holds which
+/// shard. With the `serde` feature, the log can also be exported as JSON (or
+/// any other `serde` format).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IssuedLog {
+    entries: Vec<IssuedShard>,
+}
+
+impl IssuedLog {
+    pub(super) fn new(entries: Vec<IssuedShard>) -> Self {
+        IssuedLog { entries }
+    }
+
+    /// Returns the records of each issued `Shard`.
+    pub fn entries(&self) -> &[IssuedShard] {
+        &self.entries
+    }
+}
+
+#[cfg(feature = "std")]
+impl ToWire for IssuedLog {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.wire_len());
+        self.to_wire_into(&mut bytes);
+        bytes
+    }
+
+    fn wire_len(&self) -> usize {
+        header::record_len(DocumentKind::IssuedLog, self.wire_body_len())
+    }
+
+    fn to_wire_into(&self, bytes: &mut Vec<u8>) {
+        header::write_record_into(
+            bytes,
+            DocumentKind::IssuedLog,
+            self.wire_body_len(),
+            |bytes| self.write_wire_body(bytes),
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl IssuedLog {
+    // Append the wire encoding (without the header) to bytes.
+    fn write_wire_body(&self, bytes: &mut Vec<u8>) {
+        // Encode the number of entries, followed by each entry.
+        encoding::varint(self.entries.len() as u64, bytes);
+        for entry in &self.entries {
+            // Encode id and x-value (length-prefixed).
+            encoding::varint(entry.id.len() as u64, bytes);
+            bytes.extend_from_slice(entry.id.as_bytes());
+            encoding::varint(entry.x.len() as u64, bytes);
+            bytes.extend_from_slice(&entry.x);
+
+            // Encode timestamp (with zero meaning the time is unknown).
+            encoding::varint(entry.timestamp.unwrap_or(0), bytes);
+        }
+    }
+
+    // Length (in bytes) of the wire encoding, without the header.
+    fn wire_body_len(&self) -> usize {
+        encoding::varint_len(self.entries.len() as u64)
+            + self
+                .entries
+                .iter()
+                .map(|entry| {
+                    encoding::varint_len(entry.id.len() as u64)
+                        + entry.id.len()
+                        + encoding::varint_len(entry.x.len() as u64)
+                        + entry.x.len()
+                        + encoding::varint_len(entry.timestamp.unwrap_or(0))
+                })
+                .sum::<usize>()
+    }
+}
+
+#[cfg(feature = "std")]
+impl IssuedLog {
+    /// Deserialise an `IssuedLog`, rejecting any which declare more entries
+    /// than permitted by `limits`.
+    pub fn from_wire_partial_with_limits<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        header::read_record(input, DocumentKind::IssuedLog, |body| {
+            Self::from_wire_body(body, limits)
+        })
+    }
+
+    // Parse the layout shared by every version, without the header and framing.
+    fn from_wire_body<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        use crate::nom_helpers;
+        use nom::{bytes::complete::take, combinator::complete};
+
+        // Identifiers and x-values are never longer than this.
+        const MAX_FIELD_LEN: usize = 64;
+
+        let fields = FieldOffsets::new(input);
+
+        let at = fields.field("entries length", input);
+        let (mut input, count) = WireError::nom(complete(nom_helpers::usize)(input)).map_err(at)?;
+        WireLimits::check("issued", limits.max_issued, count).map_err(at)?;
+
+        let mut entries = Vec::with_capacity(count.min(input.len()));
+        for i in 0..count {
+            let at = |err: WireError| err.in_field(format!("entries[{}]", i), fields.offset(input));
+            let (remain, id_len) =
+                WireError::nom(complete(nom_helpers::usize)(input)).map_err(at)?;
+            WireLimits::check("id", MAX_FIELD_LEN, id_len).map_err(at)?;
+            let (remain, id) = WireError::nom(take(id_len)(remain)).map_err(at)?;
+            let (remain, x_len) =
+                WireError::nom(complete(nom_helpers::usize)(remain)).map_err(at)?;
+            WireLimits::check("x", MAX_FIELD_LEN, x_len).map_err(at)?;
+            let (remain, x) = WireError::nom(take(x_len)(remain)).map_err(at)?;
+            let (remain, timestamp) =
+                WireError::nom(complete(nom_helpers::u64)(remain)).map_err(at)?;
+
+            entries.push(IssuedShard {
+                id: String::from_utf8(id.to_vec())
+                    .map_err(|err| at(format!("{:?}", err).into()))?,
+                x: x.to_vec(),
+                timestamp: Some(timestamp).filter(|t| *t != 0),
+            });
+            input = remain;
+        }
+
+        Ok((IssuedLog { entries }, input))
+    }
+}
+
+#[cfg(feature = "std")]
+impl FromWire for IssuedLog {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        Self::from_wire_partial_with_limits(input, &WireLimits::DEFAULT)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    use crate::shamir::shamir::Dealer;
+
+    use quickcheck::TestResult;
+
+    #[quickcheck]
+    fn dealer_issued_log(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret);
+        let mut shards = dealer.shards(n);
+        shards.push(dealer.next_shard());
+        shards.push(dealer.next_shard_indexed());
+        shards.push(dealer.shard_at(shards[0].x().as_bytes()).unwrap());
+
+        let log = dealer.issued_log();
+        let log2 = IssuedLog::from_wire(log.to_wire()).unwrap();
+
+        TestResult::from_bool(
+            log == log2
+                && dealer.issued().count() == shards.len()
+                && dealer.issued().zip(&shards).all(|(entry, shard)| {
+                    entry.id() == shard.id()
+                        && entry.x() == shard.x().as_bytes()
+                        && entry.timestamp().is_some()
+                }),
+        )
+    }
+}
@@ -0,0 +1,320 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::{
+        encoding,
+        gf::{GfElemPrimitive, GfField},
+        header::{self, DocumentKind},
+        limits::{FieldOffsets, WireError, WireLimits},
+        shamir::{GenericDealer, GenericShard, DEALER_ID_LENGTH},
+    },
+    v0::{FromWire, ToWire},
+};
+
+use std::{collections::BTreeMap, error, fmt};
+
+/// Length (in bytes) of the commitment to each `Shard`.
+pub const COMMITMENT_LENGTH: usize = 32;
+
+// BLAKE2b personalisation used for shard commitments.
+const COMMITMENT_PERSONAL: &[u8] = b"paperback-commit";
+
+/// Commitments to every `Shard` issued by a `Dealer`, which can be published
+/// so that custodians can check their shards (see `verify_shard`).
+///
+/// Each commitment is a hash of the entire wire encoding of a shard. Note
+/// that the y-values of shards with a threshold of one (or shards created
+/// with `Scheme::Ramp`) are derived directly from the secret, and so the
+/// commitments to such shards can be used to check guesses of the secret.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ShardCommitments {
+    dealer_id: [u8; DEALER_ID_LENGTH],
+    hashes: BTreeMap<GfElemPrimitive, [u8; COMMITMENT_LENGTH]>,
+}
+
+impl ShardCommitments {
+    /// Returns the identifier of the `Dealer` the commitments are for (see
+    /// `Shard::dealer_id`).
+    pub fn dealer_id(&self) -> String {
+        encoding::zbase32(&self.dealer_id)
+    }
+
+    /// Returns the number of shards committed to.
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Returns whether there are no commitments.
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
+
+fn commitment<F: GfField>(shard: &GenericShard<F>) -> [u8; COMMITMENT_LENGTH] {
+    let mut hash = [0u8; COMMITMENT_LENGTH];
+    hash.copy_from_slice(
+        blake2b_simd::Params::new()
+            .hash_length(COMMITMENT_LENGTH)
+            .personal(COMMITMENT_PERSONAL)
+            .hash(&shard.wire_bytes())
+            .as_bytes(),
+    );
+    hash
+}
+
+impl<F: GfField> GenericDealer<F> {
+    /// Generate commitments to every `Shard` issued by this `Dealer` so far.
+    ///
+    /// The commitments must be re-generated (and re-published) after issuing
+    /// any new shards. A `Dealer` recovered with `Dealer::recover` only knows
+    /// about the shards it was recovered from.
+    pub fn commitments(&self) -> ShardCommitments {
+        ShardCommitments {
+            dealer_id: self.dealer_id_bytes(),
+            hashes: self
+                .issued_shards()
+                .map(|shard| (shard.x_inner(), commitment(&shard)))
+                .collect(),
+        }
+    }
+}
+
+/// Reasons why a `Shard` does not match a set of `ShardCommitments`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VerifyError {
+    /// The shard was created by a different `Dealer`.
+    WrongDealer { id: String },
+    /// There is no commitment for the shard.
+    UnknownShard { id: String },
+    /// The shard does not match its commitment, so it has been modified (or
+    /// was mis-transcribed) since the commitments were published.
+    Mismatch { id: String },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::WrongDealer { id } => {
+                write!(f, "shard {} is from a different dealer", id)
+            }
+            VerifyError::UnknownShard { id } => write!(f, "no commitment for shard {}", id),
+            VerifyError::Mismatch { id } => {
+                write!(f, "shard {} does not match its commitment", id)
+            }
+        }
+    }
+}
+
+impl error::Error for VerifyError {}
+
+/// Check that a single `Shard` matches the published `ShardCommitments` for
+/// its `Dealer`.
+///
+/// Unlike recovery, this does not require any other shards, so a custodian
+/// can periodically check that their shard (after scanning it back in) still
+/// corresponds to the original backup.
+pub fn verify_shard<F: GfField>(
+    shard: &GenericShard<F>,
+    commitments: &ShardCommitments,
+) -> Result<(), VerifyError> {
+    let id = shard.id();
+    if shard.dealer_id_bytes() != commitments.dealer_id {
+        return Err(VerifyError::WrongDealer { id });
+    }
+    match commitments.hashes.get(&shard.x_inner()) {
+        None => Err(VerifyError::UnknownShard { id }),
+        Some(hash) if *hash != commitment(shard) => Err(VerifyError::Mismatch { id }),
+        Some(_) => Ok(()),
+    }
+}
+
+impl ToWire for ShardCommitments {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.wire_len());
+        self.to_wire_into(&mut bytes);
+        bytes
+    }
+
+    fn wire_len(&self) -> usize {
+        header::record_len(DocumentKind::ShardCommitments, self.wire_body_len())
+    }
+
+    fn to_wire_into(&self, bytes: &mut Vec<u8>) {
+        header::write_record_into(
+            bytes,
+            DocumentKind::ShardCommitments,
+            self.wire_body_len(),
+            |bytes| self.write_wire_body(bytes),
+        )
+    }
+}
+
+impl ShardCommitments {
+    // Append the wire encoding (without the header) to bytes.
+    fn write_wire_body(&self, bytes: &mut Vec<u8>) {
+        // Encode dealer identifier.
+        bytes.extend_from_slice(&self.dealer_id);
+
+        // Encode each commitment (length-prefixed).
+        encoding::varint(self.hashes.len() as u64, bytes);
+        for (x, hash) in &self.hashes {
+            encoding::varint(*x, bytes);
+            bytes.extend_from_slice(hash);
+        }
+    }
+
+    // Length (in bytes) of the wire encoding, without the header.
+    fn wire_body_len(&self) -> usize {
+        self.dealer_id.len()
+            + encoding::varint_len(self.hashes.len() as u64)
+            + self
+                .hashes
+                .iter()
+                .map(|(x, hash)| encoding::varint_len(*x) + hash.len())
+                .sum::<usize>()
+    }
+}
+
+impl ShardCommitments {
+    /// Deserialise `ShardCommitments`, rejecting any which declare more
+    /// commitments than permitted by `limits`.
+    pub fn from_wire_partial_with_limits<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        header::read_record(input, DocumentKind::ShardCommitments, |body| {
+            Self::from_wire_body(body, limits)
+        })
+    }
+
+    // Parse the layout shared by every version, without the header and framing.
+    fn from_wire_body<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        use crate::nom_helpers;
+        use nom::{bytes::complete::take, combinator::complete};
+
+        let fields = FieldOffsets::new(input);
+
+        let at = fields.field("dealer_id", input);
+        let (input, id) = WireError::nom(take(DEALER_ID_LENGTH)(input)).map_err(at)?;
+        let mut dealer_id = [0u8; DEALER_ID_LENGTH];
+        dealer_id.copy_from_slice(id);
+
+        let at = fields.field("commitments length", input);
+        let (mut input, count) = WireError::nom(complete(nom_helpers::usize)(input)).map_err(at)?;
+        WireLimits::check("commitments", limits.max_commitments, count).map_err(at)?;
+
+        let mut hashes = BTreeMap::new();
+        for i in 0..count {
+            let at =
+                |err: WireError| err.in_field(format!("commitments[{}]", i), fields.offset(input));
+            let (remain, x) = WireError::nom(complete(nom_helpers::u64)(input)).map_err(at)?;
+            let (remain, commitment) =
+                WireError::nom(take(COMMITMENT_LENGTH)(remain)).map_err(at)?;
+            let mut hash = [0u8; COMMITMENT_LENGTH];
+            hash.copy_from_slice(commitment);
+            // Commitments are encoded in order of their x-values.
+            if hashes.keys().next_back().is_some_and(|last| *last >= x) {
+                return Err(at(format!(
+                    "duplicate or unsorted commitment for x-value {}",
+                    x
+                )
+                .into()));
+            }
+            hashes.insert(x, hash);
+            input = remain;
+        }
+
+        Ok((ShardCommitments { dealer_id, hashes }, input))
+    }
+}
+
+impl FromWire for ShardCommitments {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        Self::from_wire_partial_with_limits(input, &WireLimits::DEFAULT)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::shamir::shamir::{Dealer, Shard, MAC_KEY_LENGTH};
+
+    use quickcheck::TestResult;
+
+    #[quickcheck]
+    fn verify_shard_commitments(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) || secret.is_empty() {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret).authenticated(&[0x5a; MAC_KEY_LENGTH]);
+        let shards = dealer.shards(n);
+        let commitments = ShardCommitments::from_wire(dealer.commitments().to_wire()).unwrap();
+
+        let ok = commitments.len() == n as usize
+            && commitments.dealer_id() == dealer.id()
+            && shards.iter().all(|s| verify_shard(s, &commitments).is_ok());
+
+        // Shards issued after the commitments were generated, shards from
+        // other dealers and modified shards must all be rejected.
+        let late = dealer.next_shard();
+        let other = Dealer::new(n, &secret).next_shard();
+        let mut bytes = shards[0].wire_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let modified = Shard::from_wire(header::write_record(DocumentKind::Shard, &bytes)).unwrap();
+
+        TestResult::from_bool(
+            ok && matches!(
+                verify_shard(&late, &commitments),
+                Err(VerifyError::UnknownShard { .. })
+            ) && matches!(
+                verify_shard(&other, &commitments),
+                Err(VerifyError::WrongDealer { .. })
+            ) && matches!(
+                verify_shard(&modified, &commitments),
+                Err(VerifyError::Mismatch { .. })
+            ),
+        )
+    }
+
+    #[test]
+    fn commitments_wire_canonical() {
+        let commitments = |xs: &[u8]| {
+            let mut bytes = [0; DEALER_ID_LENGTH].to_vec();
+            bytes.push(xs.len() as u8);
+            for x in xs {
+                bytes.push(*x);
+                bytes.extend_from_slice(&[0; COMMITMENT_LENGTH]);
+            }
+            ShardCommitments::from_wire(header::write_record(
+                DocumentKind::ShardCommitments,
+                &bytes,
+            ))
+        };
+
+        assert!(commitments(&[1, 2]).is_ok());
+        assert!(commitments(&[2, 1]).is_err());
+        assert!(commitments(&[1, 1]).is_err());
+    }
+}
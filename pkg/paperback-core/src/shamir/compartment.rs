@@ -0,0 +1,343 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::{
+        encoding,
+        header::{self, DocumentKind},
+        limits::{FieldOffsets, WireError, WireLimits},
+        shamir::{recover_secret, Dealer, Shard, ShardError},
+    },
+    v0::{FromWire, ToWire},
+};
+
+use std::collections::{BTreeMap, HashSet};
+
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroize;
+
+/// Piece of a secret which has been sharded with compartmented secret sharing
+/// (see `CompartmentDealer`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CompartmentShard {
+    group: String,
+    compartments: u32,
+    shard: Shard,
+}
+
+impl CompartmentShard {
+    /// Returns the name of the group (compartment) this shard belongs to.
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    /// Returns the total number of groups, all of which must meet their own
+    /// threshold to recover the secret.
+    pub fn compartments(&self) -> u32 {
+        self.compartments
+    }
+
+    /// Returns the underlying `Shard` (whose threshold is the number of shards
+    /// from this group needed for recovery).
+    pub fn shard(&self) -> &Shard {
+        &self.shard
+    }
+}
+
+impl ToWire for CompartmentShard {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.wire_len());
+        self.to_wire_into(&mut bytes);
+        bytes
+    }
+
+    fn wire_len(&self) -> usize {
+        header::record_len(DocumentKind::CompartmentShard, self.wire_body_len())
+    }
+
+    fn to_wire_into(&self, bytes: &mut Vec<u8>) {
+        header::write_record_into(
+            bytes,
+            DocumentKind::CompartmentShard,
+            self.wire_body_len(),
+            |bytes| self.write_wire_body(bytes),
+        )
+    }
+}
+
+impl CompartmentShard {
+    // Append the wire encoding (without the header) to bytes.
+    fn write_wire_body(&self, bytes: &mut Vec<u8>) {
+        // Encode group name (length-prefixed).
+        encoding::varint(self.group.len() as u64, bytes);
+        bytes.extend_from_slice(self.group.as_bytes());
+
+        // Encode number of compartments.
+        encoding::varint(self.compartments.into(), bytes);
+
+        // Encode shard.
+        self.shard.write_wire_bytes(bytes);
+    }
+
+    // Length (in bytes) of the wire encoding, without the header.
+    fn wire_body_len(&self) -> usize {
+        encoding::varint_len(self.group.len() as u64)
+            + self.group.len()
+            + encoding::varint_len(self.compartments.into())
+            + self.shard.wire_bytes_len()
+    }
+}
+
+impl CompartmentShard {
+    /// Deserialise a `CompartmentShard`, rejecting any shard which declares
+    /// lengths larger than those permitted by `limits`.
+    pub fn from_wire_partial_with_limits<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        header::read_record(input, DocumentKind::CompartmentShard, |body| {
+            Self::from_wire_body(body, limits)
+        })
+    }
+
+    // Parse the layout shared by every version, without the header and framing.
+    fn from_wire_body<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        use crate::nom_helpers;
+        use nom::{bytes::complete::take, combinator::complete};
+
+        let fields = FieldOffsets::new(input);
+
+        let at = fields.field("group", input);
+        let (input, group_len) = WireError::nom(complete(nom_helpers::usize)(input)).map_err(at)?;
+        WireLimits::check("group", limits.max_group_len, group_len).map_err(at)?;
+        let (input, group) = WireError::nom(take(group_len)(input)).map_err(at)?;
+        let group = String::from_utf8(group.to_vec())
+            .map_err(|err| WireError::from(format!("{:?}", err)))
+            .map_err(at)?;
+
+        let at = fields.field("compartments", input);
+        let (input, compartments) =
+            WireError::nom(complete(nom_helpers::u32)(input)).map_err(at)?;
+
+        let at = fields.field("shard", input);
+        let (shard, remain) = Shard::from_wire_body(input, limits).map_err(at)?;
+
+        Ok((
+            CompartmentShard {
+                group,
+                compartments,
+                shard,
+            },
+            remain,
+        ))
+    }
+}
+
+impl FromWire for CompartmentShard {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        Self::from_wire_partial_with_limits(input, &WireLimits::DEFAULT)
+    }
+}
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for CompartmentShard {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        Self {
+            group: String::arbitrary(g),
+            compartments: g.next_u32(),
+            shard: Shard::arbitrary(g),
+        }
+    }
+}
+
+/// Factory to share a secret between several groups of custodians, such that
+/// the secret can only be recovered if *every* group independently meets its
+/// own threshold.
+///
+/// The secret is split into one random share per group (which must all be
+/// XOR-ed together to get the secret), and each group's share is then sharded
+/// with its own `Dealer`.
+#[derive(Clone, Debug)]
+pub struct CompartmentDealer {
+    dealers: BTreeMap<String, Dealer>,
+}
+
+impl CompartmentDealer {
+    /// Construct a new `CompartmentDealer` to shard the `secret` between the
+    /// given groups, each given as a name and the number of shards from that
+    /// group required to reconstruct the secret.
+    ///
+    /// Panics if no groups are given, a group name is repeated, or a group has
+    /// a threshold of zero.
+    pub fn new<N: AsRef<str>, B: AsRef<[u8]>>(groups: &[(N, u32)], secret: B) -> Self {
+        assert!(!groups.is_empty(), "must have at least one group");
+        let names = groups
+            .iter()
+            .map(|(name, _)| name.as_ref())
+            .collect::<HashSet<_>>();
+        assert_eq!(names.len(), groups.len(), "group names must be unique");
+
+        let secret = secret.as_ref();
+        let mut last_share = secret.to_vec();
+        let mut dealers = BTreeMap::new();
+        for (i, (name, threshold)) in groups.iter().enumerate() {
+            let dealer = if i == groups.len() - 1 {
+                Dealer::new(*threshold, &last_share)
+            } else {
+                let mut share = vec![0u8; secret.len()];
+                OsRng.fill_bytes(&mut share);
+                last_share.iter_mut().zip(&share).for_each(|(a, b)| *a ^= b);
+                let dealer = Dealer::new(*threshold, &share);
+                share.zeroize();
+                dealer
+            };
+            dealers.insert(name.as_ref().to_string(), dealer);
+        }
+        last_share.zeroize();
+
+        CompartmentDealer { dealers }
+    }
+
+    /// Returns the names of the groups the secret is shared between.
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.dealers.keys().map(String::as_str)
+    }
+
+    /// Generate a new `CompartmentShard` for the given group, or `None` if
+    /// there is no such group.
+    pub fn next_shard(&mut self, group: &str) -> Option<CompartmentShard> {
+        let compartments = self.dealers.len() as u32;
+        self.dealers.get_mut(group).map(|dealer| CompartmentShard {
+            group: group.to_string(),
+            compartments,
+            shard: dealer.next_shard(),
+        })
+    }
+}
+
+/// Reconstruct a secret from a set of `CompartmentShard`s.
+///
+/// Shards from every group must be provided, and each group must meet its own
+/// threshold (see `recover_secret`).
+pub fn recover_compartmented_secret<S: AsRef<[CompartmentShard]>>(
+    shards: S,
+) -> Result<Vec<u8>, ShardError> {
+    let shards = shards.as_ref();
+    let compartments = shards.first().ok_or(ShardError::NoShards)?.compartments;
+    if shards.iter().any(|s| s.compartments != compartments) {
+        return Err(ShardError::InconsistentCompartments);
+    }
+
+    let mut groups = BTreeMap::<&str, Vec<Shard>>::new();
+    for shard in shards {
+        groups
+            .entry(&shard.group)
+            .or_default()
+            .push(shard.shard.clone());
+    }
+    let count = groups.len();
+    if count > compartments as usize {
+        return Err(ShardError::InconsistentCompartments);
+    }
+    if count < compartments as usize {
+        return Err(ShardError::NotEnoughCompartments {
+            compartments,
+            count,
+        });
+    }
+
+    let mut secret: Option<Vec<u8>> = None;
+    for (group, shards) in groups {
+        let mut share = recover_secret(shards).map_err(|err| ShardError::Compartment {
+            group: group.to_string(),
+            err: Box::new(err),
+        })?;
+        match &mut secret {
+            None => secret = Some(share.clone()),
+            Some(secret) if secret.len() != share.len() => {
+                return Err(ShardError::InconsistentSecretLength)
+            }
+            Some(secret) => secret.iter_mut().zip(&share).for_each(|(a, b)| *a ^= b),
+        }
+        share.zeroize();
+    }
+    Ok(secret.expect("must be at least one compartment"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use quickcheck::TestResult;
+
+    #[quickcheck]
+    fn compartment_shard_bytes_roundtrip(shard: CompartmentShard) {
+        let shard2 = CompartmentShard::from_wire(shard.to_wire()).unwrap();
+        assert_eq!(shard, shard2);
+    }
+
+    #[quickcheck]
+    fn recover_compartmented_secret_success(thresholds: Vec<u8>, secret: Vec<u8>) -> TestResult {
+        let groups = thresholds
+            .iter()
+            .take(4)
+            .enumerate()
+            .map(|(i, n)| (format!("group-{}", i), 1 + u32::from(*n) % 4))
+            .collect::<Vec<_>>();
+        if groups.len() < 2 {
+            return TestResult::discard();
+        }
+
+        let mut dealer = CompartmentDealer::new(&groups, &secret);
+        let shards = groups
+            .iter()
+            .flat_map(|(name, n)| {
+                (0..*n)
+                    .map(|_| dealer.next_shard(name).unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // Missing shards from one group must cause recovery to fail, even if
+        // the other groups have more than enough shards.
+        let (last, n) = groups.last().unwrap();
+        let mut partial = shards[..shards.len() - 1].to_vec();
+        partial.extend((0..4).flat_map(|_| dealer.next_shard(&groups[0].0)));
+        let expected = match n {
+            1 => ShardError::NotEnoughCompartments {
+                compartments: groups.len() as u32,
+                count: groups.len() - 1,
+            },
+            _ => ShardError::Compartment {
+                group: last.clone(),
+                err: Box::new(ShardError::NotEnoughShards {
+                    threshold: *n,
+                    count: (*n - 1) as usize,
+                }),
+            },
+        };
+
+        TestResult::from_bool(
+            recover_compartmented_secret(&shards) == Ok(secret)
+                && recover_compartmented_secret(&partial) == Err(expected),
+        )
+    }
+}
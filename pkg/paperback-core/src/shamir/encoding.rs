@@ -0,0 +1,225 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Minimal encoders used by the core of the shamir module, which (unlike the
+//! `unsigned-varint` and `zbase32` crates used elsewhere) do not require std.
+//! They produce identical output to those crates.
+
+use alloc::{string::String, vec::Vec};
+
+pub(crate) const ZBASE32_ALPHABET: &[u8; 32] = b"ybndrfg8ejkmcpqxot1uwisza345h769";
+
+/// Append the unsigned LEB128 varint encoding of `value` to `bytes`.
+pub(crate) fn varint(mut value: u64, bytes: &mut Vec<u8>) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Length (in bytes) of the varint encoding of `value` (see `varint`).
+pub(crate) fn varint_len(value: u64) -> usize {
+    (64 - (value | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+/// Compute the CRC-32C (Castagnoli) checksum of `data`.
+#[cfg(feature = "std")]
+pub(crate) fn crc32c(data: &[u8]) -> u32 {
+    // Reflected CRC-32C (Castagnoli) polynomial.
+    const POLY: u32 = 0x82f6_3b78;
+
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = (crc >> 1) ^ (POLY & (crc & 1).wrapping_neg());
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    !data.iter().fold(!0u32, |crc, byte| {
+        TABLE[usize::from((crc as u8) ^ byte)] ^ (crc >> 8)
+    })
+}
+
+/// Encode all of the bits of `data` as z-base-32 (with the final character
+/// padded with zero bits).
+pub(crate) fn zbase32(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() * 8).div_ceil(5));
+    let (mut buffer, mut bits) = (0u16, 0);
+    for byte in data {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ZBASE32_ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ZBASE32_ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+    encoded
+}
+
+/// Normalise hand-transcribed z-base-32 so that it can be decoded.
+///
+/// Upper-case characters are lower-cased, and characters which are not in
+/// the alphabet but are easily confused with ones that are (`0`, `l`, `2` and
+/// `v`) are replaced with the character they were likely copied from (`o`,
+/// `1`, `z` and `u`). Note that `i` and `1` are both in the alphabet, so are
+/// left alone.
+pub(crate) fn zbase32_normalize(data: &str) -> String {
+    data.chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            '0' => 'o',
+            'l' => '1',
+            '2' => 'z',
+            'v' => 'u',
+            c => c,
+        })
+        .collect()
+}
+
+/// Compute the [Luhn mod N][luhn] check character for a z-base-32 string,
+/// or `None` if the string contains non-z-base-32 characters.
+///
+/// The check character detects any single mistyped character and almost all
+/// transpositions of adjacent characters.
+///
+/// [luhn]: https://en.wikipedia.org/wiki/Luhn_mod_N_algorithm
+pub(crate) fn zbase32_check(data: &str) -> Option<char> {
+    let n = ZBASE32_ALPHABET.len();
+    let sum = luhn_sum(data, 2)?;
+    Some(ZBASE32_ALPHABET[(n - sum % n) % n] as char)
+}
+
+/// Check whether the final character of a z-base-32 string is the correct
+/// check character (see `zbase32_check`) for the rest of the string.
+pub(crate) fn zbase32_verify(data: &str) -> bool {
+    !data.is_empty() && luhn_sum(data, 1).map(|sum| sum % ZBASE32_ALPHABET.len()) == Some(0)
+}
+
+// Luhn mod N sum of the string, starting from the rightmost character (with
+// the given factor).
+fn luhn_sum(data: &str, mut factor: usize) -> Option<usize> {
+    let n = ZBASE32_ALPHABET.len();
+    data.bytes().rev().try_fold(0, |sum, c| {
+        let code = ZBASE32_ALPHABET.iter().position(|a| *a == c)?;
+        let addend = factor * code;
+        factor = 3 - factor;
+        Some(sum + addend / n + addend % n)
+    })
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    use unsigned_varint::encode;
+
+    #[quickcheck]
+    fn varint_matches_unsigned_varint(value: u64) -> bool {
+        let mut bytes = vec![];
+        varint(value, &mut bytes);
+        bytes == encode::u64(value, &mut encode::u64_buffer())
+    }
+
+    #[quickcheck]
+    fn varint_len_matches_varint(value: u64) -> bool {
+        let mut bytes = vec![];
+        varint(value, &mut bytes);
+        bytes.len() == varint_len(value)
+    }
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(crc32c(b""), 0);
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+    }
+
+    #[quickcheck]
+    fn crc32c_detects_bit_flips(data: Vec<u8>, pos: usize, bit: u8) -> bool {
+        let mut flipped = data.clone();
+        if let Some(byte) = flipped.get_mut(pos % data.len().max(1)) {
+            *byte ^= 1 << (bit % 8);
+        }
+        data.is_empty() || crc32c(&data) != crc32c(&flipped)
+    }
+
+    #[quickcheck]
+    fn zbase32_matches_zbase32(data: Vec<u8>) -> bool {
+        zbase32(&data) == ::zbase32::encode_full_bytes(&data)
+    }
+
+    #[quickcheck]
+    fn zbase32_check_detects_typos(data: Vec<u8>, pos: usize, delta: usize) -> bool {
+        let encoded = zbase32(&data);
+        let checked = format!("{}{}", encoded, zbase32_check(&encoded).unwrap());
+        let bytes = checked.as_bytes();
+
+        // Replace one character with a different one.
+        let pos = pos % bytes.len();
+        let code = ZBASE32_ALPHABET
+            .iter()
+            .position(|a| *a == bytes[pos])
+            .unwrap();
+        let mut typo = bytes.to_vec();
+        typo[pos] = ZBASE32_ALPHABET[(code + 1 + delta % 31) % 32];
+
+        // Swap two (different) adjacent characters.
+        let mut swapped = bytes.to_vec();
+        let transposed = match pos + 1 < bytes.len() && bytes[pos] != bytes[pos + 1] {
+            true => {
+                swapped.swap(pos, pos + 1);
+                Some(String::from_utf8(swapped).unwrap())
+            }
+            false => None,
+        };
+
+        zbase32_verify(&checked)
+            && !zbase32_verify(&String::from_utf8(typo).unwrap())
+            && !zbase32_verify("h")
+            && transposed.is_none_or(|t| !zbase32_verify(&t) || is_undetected_swap(&t))
+    }
+
+    #[quickcheck]
+    fn zbase32_normalize_confusables(data: Vec<u8>) -> bool {
+        let encoded = zbase32(&data);
+        let confused = encoded
+            .to_ascii_uppercase()
+            .replace('O', "0")
+            .replace('1', "l")
+            .replace('Z', "2")
+            .replace('U', "v");
+        zbase32_normalize(&confused) == encoded && zbase32_normalize(&encoded) == encoded
+    }
+
+    // Luhn mod N cannot detect swapping the first and last characters of the
+    // alphabet (like "09" and "90" in the decimal Luhn algorithm).
+    fn is_undetected_swap(data: &str) -> bool {
+        data.contains("y9") || data.contains("9y")
+    }
+}
@@ -0,0 +1,286 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Systematic Reed-Solomon erasure coding of non-secret data.
+//!
+//! The data is split into stripes of `threshold` field elements, and each
+//! stripe is treated as the values at `x = 1, ..., threshold` of a polynomial
+//! of degree `threshold - 1`. Piece `i` holds the value of every stripe's
+//! polynomial at `x = i`, so the first `threshold` pieces are just the data
+//! itself (split into columns) and any `threshold` pieces can be interpolated
+//! to restore the rest. Unlike a `Dealer`, no randomness is involved -- the
+//! pieces provide redundancy and *not* secrecy.
+
+use crate::shamir::gf::{Gf32, GfElem, GfField, GfPolynomial};
+
+use std::{collections::HashMap, error, fmt};
+
+/// Maximum number of pieces `erasure_encode` can produce.
+pub const MAX_ERASURE_PIECES: u32 = 255;
+
+/// Reasons why `erasure_decode` could not restore the data.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ErasureError {
+    /// A threshold of zero (or larger than `MAX_ERASURE_PIECES`) was given.
+    InvalidThreshold(u32),
+    /// A piece index of zero (or larger than `MAX_ERASURE_PIECES`) was given.
+    InvalidIndex(u32),
+    /// Two pieces with the same index contain different data.
+    DuplicateIndex(u32),
+    /// A piece has the wrong length for the data it is part of.
+    LengthMismatch { expected: usize, found: usize },
+    /// Fewer than `threshold` distinct pieces were given.
+    TooFewPieces { required: u32, found: u32 },
+}
+
+impl fmt::Display for ErasureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErasureError::InvalidThreshold(threshold) => {
+                write!(f, "invalid erasure coding threshold {}", threshold)
+            }
+            ErasureError::InvalidIndex(index) => write!(f, "invalid piece index {}", index),
+            ErasureError::DuplicateIndex(index) => {
+                write!(f, "conflicting pieces with index {}", index)
+            }
+            ErasureError::LengthMismatch { expected, found } => write!(
+                f,
+                "piece is {} bytes long but should be {} bytes long",
+                found, expected
+            ),
+            ErasureError::TooFewPieces { required, found } => write!(
+                f,
+                "need at least {} distinct pieces but only {} were given",
+                required, found
+            ),
+        }
+    }
+}
+
+impl error::Error for ErasureError {}
+
+fn piece_x(index: u32) -> GfElem {
+    GfElem::from_inner(index.into()).expect("piece index must be in the field")
+}
+
+/// Length (in bytes) of each piece produced by `erasure_encode` for data of
+/// length `length`.
+pub fn erasure_piece_len(length: usize, threshold: u32) -> usize {
+    let stripe_len = threshold as usize * Gf32::BYTES;
+    length.div_ceil(stripe_len) * Gf32::BYTES
+}
+
+/// Split `data` into `pieces` pieces (with indices `1, ..., pieces`), any
+/// `threshold` of which can be passed to `erasure_decode` to restore it.
+///
+/// Panics if `threshold` is zero, or `pieces` is smaller than `threshold` or
+/// larger than `MAX_ERASURE_PIECES`.
+pub fn erasure_encode(data: &[u8], threshold: u32, pieces: u32) -> Vec<Vec<u8>> {
+    assert!(threshold > 0, "must at least have a threshold of one");
+    assert!(
+        pieces >= threshold,
+        "must have at least as many pieces as the threshold"
+    );
+    assert!(pieces <= MAX_ERASURE_PIECES, "too many pieces requested");
+
+    let k = threshold as usize;
+    let stripe_len = k * Gf32::BYTES;
+    let piece_len = erasure_piece_len(data.len(), threshold);
+
+    // The x-values of the data (and thus the weights of the parity pieces)
+    // are the same for every stripe.
+    let data_xs = (1..=threshold).map(piece_x).collect::<Vec<_>>();
+    let weights = (threshold + 1..=pieces)
+        .map(|index| GfPolynomial::lagrange_weights(&data_xs, piece_x(index)))
+        .collect::<Vec<_>>();
+
+    let mut output = vec![Vec::with_capacity(piece_len); pieces as usize];
+    for stripe in data.chunks(stripe_len) {
+        let mut ys = Vec::with_capacity(k);
+        let mut remain = stripe;
+        for _ in 0..k {
+            let (y, rest) = GfElem::from_bytes_partial(remain);
+            ys.push(y);
+            remain = rest;
+        }
+        for (piece, y) in output.iter_mut().zip(&ys) {
+            piece.extend_from_slice(&y.to_bytes());
+        }
+        for (piece, weights) in output[k..].iter_mut().zip(&weights) {
+            let y = weights
+                .iter()
+                .zip(&ys)
+                .fold(GfElem::ZERO, |acc, (w, y)| acc + *w * *y);
+            piece.extend_from_slice(&y.to_bytes());
+        }
+    }
+    output
+}
+
+/// Restore `length` bytes of data from at least `threshold` of the pieces
+/// produced by `erasure_encode`, given as `(index, piece)` pairs.
+///
+/// Pieces are not authenticated, so a corrupted piece results in corrupted
+/// data -- callers should check the restored data against a checksum.
+pub fn erasure_decode(
+    pieces: &[(u32, &[u8])],
+    threshold: u32,
+    length: usize,
+) -> Result<Vec<u8>, ErasureError> {
+    if threshold == 0 || threshold > MAX_ERASURE_PIECES {
+        return Err(ErasureError::InvalidThreshold(threshold));
+    }
+    let k = threshold as usize;
+    let elem_len = Gf32::BYTES;
+    let piece_len = erasure_piece_len(length, threshold);
+
+    let mut unique = HashMap::<u32, &[u8]>::new();
+    for (index, piece) in pieces {
+        if *index == 0 || *index > MAX_ERASURE_PIECES {
+            return Err(ErasureError::InvalidIndex(*index));
+        }
+        if piece.len() != piece_len {
+            return Err(ErasureError::LengthMismatch {
+                expected: piece_len,
+                found: piece.len(),
+            });
+        }
+        match unique.insert(*index, piece) {
+            Some(other) if other != *piece => return Err(ErasureError::DuplicateIndex(*index)),
+            _ => (),
+        }
+    }
+    if unique.len() < k {
+        return Err(ErasureError::TooFewPieces {
+            required: threshold,
+            found: unique.len() as u32,
+        });
+    }
+
+    // Any threshold pieces will do, but prefer the data pieces since they
+    // don't need to be interpolated.
+    let mut chosen = unique.into_iter().collect::<Vec<_>>();
+    chosen.sort_unstable_by_key(|(index, _)| *index);
+    chosen.truncate(k);
+    let xs = chosen
+        .iter()
+        .map(|(index, _)| piece_x(*index))
+        .collect::<Vec<_>>();
+
+    // Restore each of the data pieces.
+    let columns = (1..=threshold)
+        .map(|index| match chosen.iter().find(|(i, _)| *i == index) {
+            Some((_, piece)) => piece.to_vec(),
+            None => {
+                let weights = GfPolynomial::lagrange_weights(&xs, piece_x(index));
+                let mut column = Vec::with_capacity(piece_len);
+                for offset in (0..piece_len).step_by(elem_len) {
+                    let y =
+                        weights
+                            .iter()
+                            .zip(&chosen)
+                            .fold(GfElem::ZERO, |acc, (w, (_, piece))| {
+                                acc + *w * GfElem::from_bytes(&piece[offset..offset + elem_len])
+                            });
+                    column.extend_from_slice(&y.to_bytes());
+                }
+                column
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Interleave the columns back into stripes.
+    let mut data = Vec::with_capacity(piece_len * k);
+    for offset in (0..piece_len).step_by(elem_len) {
+        for column in &columns {
+            data.extend_from_slice(&column[offset..offset + elem_len]);
+        }
+    }
+    data.truncate(length);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use quickcheck::TestResult;
+
+    #[quickcheck]
+    fn erasure_roundtrip(data: Vec<u8>, threshold: u8, extra: u8, lost: Vec<u8>) -> TestResult {
+        let threshold = (threshold % 16) as u32 + 1;
+        let pieces = threshold + (extra % 8) as u32;
+        let encoded = erasure_encode(&data, threshold, pieces);
+
+        // Drop as many pieces as we can get away with.
+        let mut kept = encoded
+            .iter()
+            .enumerate()
+            .map(|(i, piece)| (i as u32 + 1, piece.as_slice()))
+            .collect::<Vec<_>>();
+        for i in lost {
+            if kept.len() > threshold as usize {
+                kept.remove(i as usize % kept.len());
+            }
+        }
+
+        TestResult::from_bool(
+            encoded
+                .iter()
+                .all(|piece| piece.len() == erasure_piece_len(data.len(), threshold))
+                && erasure_decode(&kept, threshold, data.len()) == Ok(data),
+        )
+    }
+
+    #[quickcheck]
+    fn erasure_too_few(data: Vec<u8>, threshold: u8) -> TestResult {
+        let threshold = (threshold % 16) as u32 + 2;
+        let encoded = erasure_encode(&data, threshold, threshold + 2);
+        let kept = encoded[3..]
+            .iter()
+            .enumerate()
+            .map(|(i, piece)| (i as u32 + 4, piece.as_slice()))
+            .collect::<Vec<_>>();
+
+        TestResult::from_bool(
+            erasure_decode(&kept, threshold, data.len())
+                == Err(ErasureError::TooFewPieces {
+                    required: threshold,
+                    found: threshold - 1,
+                }),
+        )
+    }
+
+    #[test]
+    fn erasure_conflicting() {
+        let data = (0..100).collect::<Vec<u8>>();
+        let encoded = erasure_encode(&data, 2, 3);
+        let mut corrupted = encoded[0].clone();
+        corrupted[0] ^= 1;
+        let pieces = [(1, encoded[0].as_slice()), (1, corrupted.as_slice())];
+        assert_eq!(
+            erasure_decode(&pieces, 2, data.len()),
+            Err(ErasureError::DuplicateIndex(1))
+        );
+        let pieces = [(1, encoded[0].as_slice()), (0, encoded[1].as_slice())];
+        assert_eq!(
+            erasure_decode(&pieces, 2, data.len()),
+            Err(ErasureError::InvalidIndex(0))
+        );
+    }
+}
@@ -16,19 +16,102 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{
-    cmp, mem,
+use alloc::{vec, vec::Vec};
+use core::{
+    cmp,
+    fmt::Debug,
+    hash::Hash,
+    marker::PhantomData,
+    mem,
     ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign},
 };
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
 
-use itertools::Itertools;
+use paperback_clmul::GfBackend;
 use rand::RngCore;
+use zeroize::Zeroize;
 
-/// Primitive uint type for GfElems.
-pub type GfElemPrimitive = u32;
+/// Primitive uint type for GfElems (large enough to hold an element of any of
+/// the supported fields).
+pub type GfElemPrimitive = u64;
 
-/// A field element of `GF(2^32)`, with characteristic polynomial
+mod private {
+    pub trait Sealed {}
+}
+
+/// A binary field `GF(2^n)` which `GfElem`s can be members of.
+///
+/// Smaller fields result in smaller shards for tiny secrets, while larger fields
+/// result in fewer (but larger) field elements for large secrets. This trait is
+/// sealed, as the field polynomial must be carefully chosen.
+pub trait GfField:
+    private::Sealed + Copy + Clone + Debug + Default + PartialEq + Eq + Hash + Send + Sync + 'static
+{
+    /// Degree `n` of the field (the number of bits in each element).
+    const BITS: u32;
+
+    /// The characteristic polynomial of the field, excluding the `x^n` term.
+    const POLYNOMIAL: GfElemPrimitive;
+
+    /// Number of bytes in each element.
+    const BYTES: usize = (Self::BITS / 8) as usize;
+
+    /// Mask of the bits which can be set in an element.
+    const MASK: GfElemPrimitive = GfElemPrimitive::MAX >> (64 - Self::BITS);
+}
+
+/// The field `GF(2^16)`, with characteristic polynomial
+/// `x^16 + x^5 + x^3 + x^2 + 1`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Gf16;
+
+impl private::Sealed for Gf16 {}
+
+impl GfField for Gf16 {
+    const BITS: u32 = 16;
+    // Like Gf32, this is the smallest degree-16 polynomial which is both
+    // irreducible and primitive in GF(2).
+    //
+    // x^16 + x^5 + x^3 + x^2 + 1
+    const POLYNOMIAL: GfElemPrimitive = 0b0000_0000_0010_1101;
+}
+
+/// The field `GF(2^32)`, with characteristic polynomial
 /// `x^32 + x^22 + x^2 + x^1 + 1`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Gf32;
+
+impl private::Sealed for Gf32 {}
+
+impl GfField for Gf32 {
+    const BITS: u32 = 32;
+    // Can be an arbitrary polynomial, but this one was chosen because it is the
+    // smallest (both numerically and in terms of the number of bits set)
+    // degree-32 polynomial which is both irreducible and primitive in GF(2).
+    //
+    // x^32 + x^22 + x^2 + x^1 + 1
+    const POLYNOMIAL: GfElemPrimitive = 0b0000_0000_0100_0000_0000_0000_0000_0111;
+}
+
+/// The field `GF(2^64)`, with characteristic polynomial
+/// `x^64 + x^4 + x^3 + x^1 + 1`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Gf64;
+
+impl private::Sealed for Gf64 {}
+
+impl GfField for Gf64 {
+    const BITS: u32 = 64;
+    // Like Gf32, this is the smallest degree-64 polynomial which is both
+    // irreducible and primitive in GF(2).
+    //
+    // x^64 + x^4 + x^3 + x^1 + 1
+    const POLYNOMIAL: GfElemPrimitive = 0b0001_1011;
+}
+
+/// A field element of `GF(2^n)`, defaulting to `GF(2^32)` (see `GfField` for
+/// the supported fields).
 ///
 /// This is a home-brew implementation of GF mathematics that hopefully runs in
 /// constant-enough time. It appears there are no clearly-good-to-use
@@ -36,47 +119,45 @@ pub type GfElemPrimitive = u32;
 /// purposes).
 // NOTE: PartialEq is not timing-safe.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub struct GfElem(GfElemPrimitive);
+pub struct GfElem<F: GfField = Gf32>(GfElemPrimitive, PhantomData<F>);
 
 /// (x, y) in GF.
-pub type GfPoint = (GfElem, GfElem);
-
-impl GfElem {
-    // Can be an arbitrary polynomial, but this one was chosen because it is the
-    // smallest (both numerically and in terms of the number of bits set)
-    // degree-32 polynomial which is both irreducible and primitive in GF(2).
-    //
-    // x^32 + x^22 + x^2 + x^1 + 1
-    const POLYNOMIAL: u64 = 0b1_0000_0000_0100_0000_0000_0000_0000_0111;
+pub type GfPoint<F = Gf32> = (GfElem<F>, GfElem<F>);
 
+impl<F: GfField> GfElem<F> {
     /// Additive identity.
-    pub const ZERO: GfElem = GfElem(0);
+    pub const ZERO: Self = GfElem(0, PhantomData);
 
     /// Multiplicative identity.
-    pub const ONE: GfElem = GfElem(1);
+    pub const ONE: Self = GfElem(1, PhantomData);
 
     pub fn new_rand<R: RngCore + ?Sized>(r: &mut R) -> Self {
-        Self(r.next_u32())
+        Self(r.next_u64() & F::MASK, PhantomData)
     }
 
     pub(crate) fn inner(&self) -> GfElemPrimitive {
         self.0
     }
 
-    pub(crate) fn from_inner(v: GfElemPrimitive) -> Self {
-        Self(v)
+    /// Construct a `GfElem` from its underlying value, or `None` if the value
+    /// is too large to be a member of the field.
+    pub(crate) fn from_inner(v: GfElemPrimitive) -> Option<Self> {
+        match v & !F::MASK {
+            0 => Some(Self(v, PhantomData)),
+            _ => None,
+        }
     }
 
     pub fn from_bytes_partial(bytes: &[u8]) -> (Self, &[u8]) {
-        let len = cmp::min(bytes.len(), mem::size_of::<GfElemPrimitive>());
+        let len = cmp::min(bytes.len(), F::BYTES);
 
         // Pad with zeroes.
         let mut padded = [0u8; mem::size_of::<GfElemPrimitive>()];
-        padded[..len].copy_from_slice(bytes);
+        padded[..len].copy_from_slice(&bytes[..len]);
 
         // Convert to GfElem.
         (
-            GfElem(GfElemPrimitive::from_le_bytes(padded)),
+            GfElem(GfElemPrimitive::from_le_bytes(padded), PhantomData),
             &bytes[len..],
         )
     }
@@ -88,11 +169,15 @@ impl GfElem {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        self.0.to_le_bytes().to_vec()
+        self.0.to_le_bytes()[..F::BYTES].to_vec()
     }
 
     // NOTE: Definitely not constant-time.
-    pub fn pow(self, mut n: usize) -> Self {
+    pub fn pow(self, n: usize) -> Self {
+        self.pow_inner(n as u64)
+    }
+
+    fn pow_inner(self, mut n: u64) -> Self {
         // Multiplication is not really cheap, so we optimise it by doing it
         // with an O(log(n)) worst case rather than the obvious O(n).
         let mut mult = self;
@@ -108,23 +193,27 @@ impl GfElem {
     }
 
     pub fn inverse(self) -> Option<Self> {
-        match self {
-            Self::ZERO => None,
-            // TODO: Switch to Itoh-Tsujii inversion algorithm. pow(2^32-2)
-            //       isn't cheap, even though it is theoretically constant-time.
-            _ => Some(self.pow(GfElemPrimitive::max_value() as usize - 1)),
+        if self == Self::ZERO {
+            return None;
         }
+        if let Some(tables) = Self::log_tables() {
+            let log = tables.log[self.0 as usize] as usize;
+            return Some(Self(tables.exp[GF16_ORDER - log].into(), PhantomData));
+        }
+        // TODO: Switch to Itoh-Tsujii inversion algorithm. pow(2^n-2) isn't
+        //       cheap, even though it is theoretically constant-time.
+        Some(self.pow_inner(F::MASK - 1))
     }
 }
 
 #[cfg(test)]
-impl quickcheck::Arbitrary for GfElem {
+impl<F: GfField> quickcheck::Arbitrary for GfElem<F> {
     fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
-        Self(g.next_u32())
+        Self::new_rand(g)
     }
 }
 
-impl Add for GfElem {
+impl<F: GfField> Add for GfElem<F> {
     type Output = Self;
     fn add(mut self, rhs: Self) -> Self::Output {
         self += rhs;
@@ -132,13 +221,13 @@ impl Add for GfElem {
     }
 }
 
-impl AddAssign for GfElem {
+impl<F: GfField> AddAssign for GfElem<F> {
     fn add_assign(&mut self, rhs: Self) {
         self.0 ^= rhs.0
     }
 }
 
-impl Sub for GfElem {
+impl<F: GfField> Sub for GfElem<F> {
     type Output = Self;
     fn sub(mut self, rhs: Self) -> Self::Output {
         self -= rhs;
@@ -146,14 +235,14 @@ impl Sub for GfElem {
     }
 }
 
-impl SubAssign for GfElem {
+impl<F: GfField> SubAssign for GfElem<F> {
     fn sub_assign(&mut self, rhs: Self) {
         // Subtraction in GF(2^n) is identical to addition.
         *self += rhs
     }
 }
 
-impl Mul for GfElem {
+impl<F: GfField> Mul for GfElem<F> {
     type Output = Self;
     fn mul(mut self, rhs: Self) -> Self::Output {
         self *= rhs;
@@ -161,28 +250,84 @@ impl Mul for GfElem {
     }
 }
 
-impl MulAssign for GfElem {
+impl<F: GfField> MulAssign for GfElem<F> {
     fn mul_assign(&mut self, rhs: Self) {
+        let backend = GfBackend::detect();
+        self.0 = match (backend.clmul(self.0, rhs.0), Self::log_tables()) {
+            (Some(product), _) => Self::reduce(backend, product),
+            (None, Some(tables)) => tables.mul(self.0, rhs.0),
+            (None, None) => Self::mul_portable(self.0, rhs.0),
+        };
+    }
+}
+
+impl<F: GfField> GfElem<F> {
+    fn mul_portable(mut a: GfElemPrimitive, mut b: GfElemPrimitive) -> GfElemPrimitive {
         // A modified and hopefully-constant-time implementation of Russian
         // Peasant Multiplication which avoids branching by using masks instead.
         //   <https://en.wikipedia.org/wiki/Finite_field_arithmetic#D_programming_example>
-
-        let mut a = self.0;
-        let mut b = rhs.0;
         let mut p: GfElemPrimitive = 0;
-        for _ in 0..32 {
-            let mask = ((a >> 31) & 1).wrapping_neg() as u64;
+        for _ in 0..F::BITS {
+            let mask = ((a >> (F::BITS - 1)) & 1).wrapping_neg();
             p ^= a & (b & 1).wrapping_neg();
-            a = (((a as u64) << 1) ^ (Self::POLYNOMIAL & mask)) as GfElemPrimitive;
+            // The x^n term of the polynomial is implicit, as it is shifted out
+            // of the field by the mask.
+            a = ((a << 1) ^ (F::POLYNOMIAL & mask)) & F::MASK;
             b >>= 1;
         }
+        p
+    }
+
+    // Multiply by x, reducing modulo the field polynomial.
+    fn mul_x(a: GfElemPrimitive) -> GfElemPrimitive {
+        let mask = ((a >> (F::BITS - 1)) & 1).wrapping_neg();
+        ((a << 1) ^ (F::POLYNOMIAL & mask)) & F::MASK
+    }
+
+    // Logarithm tables are only practical for GF(2^16), and are only used by
+    // the portable backend (hardware carry-less multiplication is faster).
+    // Table lookups index memory with secret values, so they are disabled in
+    // constant-time mode.
+    fn log_tables() -> Option<&'static Gf16Tables> {
+        match (F::BITS, GfBackend::detect()) {
+            _ if CONSTANT_TIME => None,
+            // Lazily building the tables requires std.
+            #[cfg(feature = "std")]
+            (16, GfBackend::Portable) => Some(Gf16Tables::get()),
+            _ => None,
+        }
+    }
+
+    // Reduce the carry-less product of two field elements modulo the field
+    // polynomial.
+    fn reduce(backend: GfBackend, mut product: u128) -> GfElemPrimitive {
+        // Since x^n == POLYNOMIAL (mod the field polynomial), the terms above
+        // x^n can be replaced by multiplying them with POLYNOMIAL. Each round
+        // reduces the degree of the product by n - deg(POLYNOMIAL).
+        // The number of rounds only depends on the field (not the product),
+        // so that the reduction is constant-time.
+        let mask = u128::from(F::MASK);
+        let poly_degree = GfElemPrimitive::BITS - 1 - F::POLYNOMIAL.leading_zeros();
+        let mut degree = 2 * (F::BITS - 1);
+        while degree >= F::BITS {
+            let high = (product >> F::BITS) as u64;
+            let folded = backend
+                .clmul(high, F::POLYNOMIAL)
+                .expect("backend must support carry-less multiplication");
+            product = folded ^ (product & mask);
+            degree = degree - F::BITS + poly_degree;
+        }
+        product as GfElemPrimitive
+    }
+}
 
-        // Save the product.
-        self.0 = p;
+impl<F: GfField> Zeroize for GfElem<F> {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
     }
 }
 
-impl Div for GfElem {
+impl<F: GfField> Div for GfElem<F> {
     type Output = Self;
     fn div(mut self, rhs: Self) -> Self::Output {
         self /= rhs;
@@ -190,43 +335,174 @@ impl Div for GfElem {
     }
 }
 
-impl DivAssign for GfElem {
+impl<F: GfField> DivAssign for GfElem<F> {
     fn div_assign(&mut self, rhs: Self) {
         *self *= rhs.inverse().expect("rhs cannot be inverted")
     }
 }
 
-/// A polynomial in `GF(2^32)`.
+/// Whether `GfElem` arithmetic avoids secret-dependent table lookups (enabled
+/// with the `constant-time` feature).
+///
+/// Multiplication is always branch-free, but the table-based optimisations
+/// used by the portable backend access memory at locations which depend on
+/// the (secret) operands and thus could leak them through the cache.
+pub const CONSTANT_TIME: bool = cfg!(feature = "constant-time");
+
+// Order of the multiplicative group of GF(2^16).
+const GF16_ORDER: usize = 0xffff;
+
+/// Logarithm and exponent tables for `GF(2^16)` (with `x` as the generator),
+/// which turn multiplication into a pair of table lookups.
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+struct Gf16Tables {
+    // exp[i] = x^i, stored twice over so that log[a] + log[b] never needs to
+    // be reduced.
+    exp: Vec<u16>,
+    // log[x^i] = i (log[0] is unused).
+    log: Vec<u16>,
+}
+
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+impl Gf16Tables {
+    #[cfg(feature = "std")]
+    fn get() -> &'static Self {
+        static TABLES: OnceLock<Gf16Tables> = OnceLock::new();
+        TABLES.get_or_init(|| {
+            let mut exp = vec![0u16; 2 * GF16_ORDER];
+            let mut log = vec![0u16; GF16_ORDER + 1];
+            let mut elem = 1;
+            for i in 0..GF16_ORDER {
+                exp[i] = elem as u16;
+                exp[i + GF16_ORDER] = elem as u16;
+                log[elem as usize] = i as u16;
+                elem = GfElem::<Gf16>::mul_x(elem);
+            }
+            Self { exp, log }
+        })
+    }
+
+    fn mul(&self, a: GfElemPrimitive, b: GfElemPrimitive) -> GfElemPrimitive {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        let log = self.log[a as usize] as usize + self.log[b as usize] as usize;
+        self.exp[log].into()
+    }
+}
+
+/// Precomputed table for multiplying `GfElem`s by a fixed value.
+///
+/// This is much faster than the portable multiplication code when the same
+/// value is used for many multiplications (such as when evaluating many
+/// polynomials at the same `x` value), as each multiplication becomes a
+/// lookup for each byte of the other operand.
+#[derive(Clone, Debug)]
+pub struct GfMulTable<F: GfField = Gf32> {
+    // table[k][v] = c * (v << 8k)
+    table: Vec<[GfElemPrimitive; 256]>,
+    _field: PhantomData<F>,
+}
+
+impl<F: GfField> GfMulTable<F> {
+    /// Construct a table for multiplying by `c`.
+    pub fn new(c: GfElem<F>) -> Self {
+        // c * x^i for each bit of the other operand.
+        let mut basis = Vec::with_capacity(F::BITS as usize);
+        let mut elem = c.0;
+        for _ in 0..F::BITS {
+            basis.push(elem);
+            elem = GfElem::<F>::mul_x(elem);
+        }
+
+        // Since multiplication distributes over addition, each entry is the
+        // sum of the basis values for each bit set in the index.
+        let table = basis
+            .chunks(8)
+            .map(|basis| {
+                let mut row = [0; 256];
+                for v in 1..256usize {
+                    row[v] = row[v & (v - 1)] ^ basis[v.trailing_zeros() as usize];
+                }
+                row
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            table,
+            _field: PhantomData,
+        }
+    }
+
+    /// Multiply `b` by the value the table was constructed for.
+    pub fn mul(&self, b: GfElem<F>) -> GfElem<F> {
+        let product = b.0.to_le_bytes()[..F::BYTES]
+            .iter()
+            .zip(&self.table)
+            .fold(0, |acc, (v, row)| acc ^ row[*v as usize]);
+        GfElem(product, PhantomData)
+    }
+}
+
+/// Multiplies `GfElem`s by a fixed value, using a `GfMulTable` when that is
+/// faster than regular multiplication.
+#[derive(Clone, Debug)]
+pub struct GfMultiplier<F: GfField = Gf32> {
+    c: GfElem<F>,
+    table: Option<GfMulTable<F>>,
+}
+
+impl<F: GfField> GfMultiplier<F> {
+    // Building a table costs about as much as 40 portable multiplications
+    // (regardless of the field size), so it is only worth it for values which
+    // are used many times.
+    const MIN_TABLE_USES: usize = 64;
+
+    /// Construct a multiplier by `c`, which will be used about `uses` times.
+    pub fn new(c: GfElem<F>, uses: usize) -> Self {
+        // Table lookups are only faster than hardware carry-less
+        // multiplication, and are disabled in constant-time mode.
+        let table = match GfBackend::detect() {
+            GfBackend::Portable if !CONSTANT_TIME && uses >= Self::MIN_TABLE_USES => {
+                Some(GfMulTable::new(c))
+            }
+            _ => None,
+        };
+        Self { c, table }
+    }
+
+    /// Multiply `b` by the value the multiplier was constructed for.
+    pub fn mul(&self, b: GfElem<F>) -> GfElem<F> {
+        match &self.table {
+            Some(table) => table.mul(b),
+            None => self.c * b,
+        }
+    }
+}
+
+/// A polynomial in `GF(2^n)`.
 // The coefficients are in *increasing* degree.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct GfPolynomial(Vec<GfElem>);
-
-impl GfPolynomial {
-    pub fn new_rand<R: RngCore + ?Sized>(n: GfElemPrimitive, r: &mut R) -> Self {
-        let k = n + 1;
-        Self(
-            (0..k)
-                .map(|_| {
-                    // We must make sure that none of the coefficients are zero
-                    // elements. This is purely an abundance of caution, but it
-                    // ensures we don't accidentally end up with a polynomial
-                    // that doesn't have enough coefficients (resulting it being
-                    // easy to invert).
-                    let mut elem = GfElem::ZERO;
-                    while elem == GfElem::ZERO {
-                        elem = GfElem::new_rand(r);
-                    }
-                    elem
-                })
-                .collect::<Vec<_>>(),
-        )
+pub struct GfPolynomial<F: GfField = Gf32>(Vec<GfElem<F>>);
+
+impl<F: GfField> GfPolynomial<F> {
+    fn rand_coeff<R: RngCore + ?Sized>(r: &mut R) -> GfElem<F> {
+        // We must make sure that none of the coefficients are zero elements.
+        // This is purely an abundance of caution, but it ensures we don't
+        // accidentally end up with a polynomial that doesn't have enough
+        // coefficients (resulting it being easy to invert).
+        let mut elem = GfElem::ZERO;
+        while elem == GfElem::ZERO {
+            elem = GfElem::new_rand(r);
+        }
+        elem
     }
 
-    #[allow(unused)]
-    pub fn degree(&self) -> GfElemPrimitive {
+    #[cfg(test)]
+    fn degree(&self) -> u32 {
         match self.0.len() {
             0 => panic!("GfPolynomial must have at least one element"),
-            n => (n - 1) as GfElemPrimitive,
+            n => (n - 1) as u32,
         }
     }
 
@@ -234,118 +510,98 @@ impl GfPolynomial {
     ///
     /// This is computed much more efficiently than
     /// `GfPolynomials::evaluate(GfElem::ZERO)`.
-    pub fn constant(&self) -> GfElem {
+    pub fn constant(&self) -> GfElem<F> {
         *self
             .0
             .first()
             .expect("GfPolynomial must have at least one element")
     }
 
-    /// Yield a mutable reference to the constant term of the polynomial.
-    ///
-    /// This allows you to modify the constant term of the polynomial. Note that
-    /// this invalidates all previously computed `GfPolynomial::evaluate`
-    /// results.
-    pub fn constant_mut(&mut self) -> &mut GfElem {
-        self.0
-            .first_mut()
-            .expect("GfPolynomial must have at least one element")
+    /// The coefficients of the polynomial (in *increasing* degree).
+    pub fn coeffs(&self) -> &[GfElem<F>] {
+        &self.0
     }
 
     /// Evaluate the polynomial at a given `x` value.
-    pub fn evaluate(&self, x: GfElem) -> GfElem {
+    pub fn evaluate(&self, x: GfElem<F>) -> GfElem<F> {
         // Implementation of Horner's method for evaluating a polynomial, which
         // results in only O(n) operations (n additions, and n multiplications)
         // rather than the far less optimal. Since we order the polynomial
         // terms in terms of decreasing degree, we need to do it in reverse.
+        let x = GfMultiplier::new(x, self.0.len());
         self.0
             .iter()
             .rev()
-            .fold(GfElem::ZERO, |acc, coeff| *coeff + x * acc)
+            .fold(GfElem::ZERO, |acc, coeff| *coeff + x.mul(acc))
     }
 
-    /// Interpolate the constant term of a polynomial of degree `n` in
-    /// `GF(2^32)`, given a set of points along that polynomial.
+    /// Compute the [Lagrange basis polynomials][lagrange] for the given set of
+    /// `xs`, evaluated at `x`.
     ///
-    /// The process for this computation is [Lagrange interpolation][lagrange].
-    ///
-    /// This much more efficient than `GfPolynomial::lagrange(...).constant()`,
-    /// and thus should be used if you only need to retreive the constant term
-    /// of an unknown polynomial.
+    /// The value at `x` of the polynomial interpolated through `(xs[j],
+    /// ys[j])` is `\sum_j ys[j] * weights[j]`. Since the weights only depend
+    /// on the x-values, this allows many polynomials which share x-values to
+    /// be evaluated at `x` with only `O(n)` operations each.
     ///
     /// [lagrange]: https://en.wikipedia.org/wiki/Lagrange_polynomial
-    pub fn lagrange_constant<P: AsRef<[GfPoint]>>(n: GfElemPrimitive, points: P) -> GfElem {
-        let points = points.as_ref();
-        let k = points.len();
-        assert!(
-            k == (n + 1) as usize,
-            "need exactly n+1 points for interpolation"
-        );
-
-        let (xs, ys): (Vec<_>, Vec<_>) = points.iter().copied().unzip();
-
-        // Pre-invert all x values to avoid recalculating it n times.
-        let xs_inv = xs
-            .iter()
-            .map(|x| x.inverse().expect("all x values must be invertible"))
-            .collect::<Vec<_>>();
+    pub fn lagrange_weights(xs: &[GfElem<F>], x: GfElem<F>) -> Vec<GfElem<F>> {
+        // If x is one of the xs, the basis polynomials are trivial (and the
+        // barycentric form below would divide by zero).
+        if let Some(i) = xs.iter().position(|x_j| *x_j == x) {
+            return (0..xs.len())
+                .map(|j| if i == j { GfElem::ONE } else { GfElem::ZERO })
+                .collect::<Vec<_>>();
+        }
 
-        // To interpolate only the constant term of a polynomial, you can take
-        // the full Lagrange polynomial expressions (which requires expanding a
-        // multi binomial expression)
-        //
-        //     L(x) = \sum_{j_0}^k y_j l_j(x)
-        //   l_j(x) = \prod_{m=0,m!=j}^{k} \frac{x-x_m}{x_j-x_m}
-        //
-        // where k is the number of points (which is equial to the threshold, or
-        // the polynomial degree + 1), and simplify it. By substituting x=0
-        // (removing all of the x terms) we get a simpler expression with no
-        // multi binomial expansion
-        //
-        //     L(0) = \sum_{j_0}^k y_j l_j(0)
-        //   l_j(0) = \prod_{m=0,m!=j}^{k} \frac{x_m}{x_m-x_j}
-        //
-        // and then you can make an additional simplification (to reduce the
-        // number of numerical operations -- notably division because computing
-        // the multiplicative inverse is currently fairly expensive) by
-        // re-arranging the fraction so that we only need a single division at
-        // the end and divisions are by individual x_m values, which we can
-        // pre-compute the multiplicative inverse of
+        // Using the barycentric form of the Lagrange basis polynomials
         //
-        //        L(0) = \sum_{j_0}^k \frac{y_j}{linv_j(0)}
-        //   linv_j(0) = \prod_{m=0,m!=j}^{k} (1-\frac{x_j}{x_m})
+        //   l_j(x) = l(x) \frac{w_j}{x-x_j}
+        //     l(x) = \prod_{m=0}^{k} (x-x_m)
         //
-        // giving us the final expression
-        //
-        //   L(0) = \sum_{j=0}^{k} \frac{y_j}
-        //                              {\prod_{m=0,m!=j}^{k}
-        //                                    (1-\frac{x_j}{x_m})}
-        (0..k).fold(GfElem::ZERO, |acc, j| {
-            // \sum_{j=0}^{k} \frac{y_j}...
-            acc + ys[j]
-                // ...{linv_j(0)}
-                / (0..k as usize)
+        // means that only the barycentric weights need O(n^2) operations, and
+        // there are only n+1 divisions.
+        let l = xs.iter().fold(GfElem::ONE, |acc, x_m| acc * (x - *x_m));
+        Self::barycentric_weights(xs)
+            .into_iter()
+            .zip(xs)
+            .map(|(w_j, x_j)| l * w_j / (x - *x_j))
+            .collect::<Vec<_>>()
+    }
+
+    /// Compute the [barycentric weights][barycentric] for the given set of
+    /// `xs`.
+    ///
+    /// The weights only depend on the x-values, so they can be computed once
+    /// and then re-used when interpolating many polynomials which share
+    /// x-values (such as the polynomials for each chunk of a secret).
+    ///
+    /// [barycentric]: https://en.wikipedia.org/wiki/Lagrange_polynomial#Barycentric_form
+    pub fn barycentric_weights(xs: &[GfElem<F>]) -> Vec<GfElem<F>> {
+        //   w_j = \frac{1}{\prod_{m=0,m!=j}^{k} (x_j-x_m)}
+        (0..xs.len())
+            .map(|j| {
+                (0..xs.len())
                     .filter(|m| *m != j)
-                    .fold(GfElem::ONE, |acc, m| {
-                        // (1-frac{x_j}{x_m}) == (1-x_j*xinv_m)
-                        acc * (GfElem::ONE - xs[j] * xs_inv[m])
-                    })
-        })
+                    .fold(GfElem::ONE, |acc, m| acc * (xs[j] - xs[m]))
+                    .inverse()
+                    .expect("all x values must be distinct")
+            })
+            .collect::<Vec<_>>()
     }
 
-    /// Interpolate a polynomial of degree `n` in `GF(2^32)`, given a set of
+    /// Interpolate a polynomial of degree `n` in `GF(2^n)`, given a set of
     /// points along that polynomial.
     ///
     /// The process for this computation is [Lagrange interpolation][lagrange].
     ///
     /// This is much slower than computing just the constant term with
-    /// `GfPolynomial::lagrange_constant` because it requires a complete
+    /// `GfPolynomial::lagrange_weights` because it requires a complete
     /// mutli-binomial expansion (which is `O(n^2)` on a good day). This method
     /// is only useful if you need to reconstruct the polynomial to calculate
     /// values that are not `x == GfElem::ZERO`.
     ///
     /// [lagrange]: https://en.wikipedia.org/wiki/Lagrange_polynomial
-    pub fn lagrange<P: AsRef<[GfPoint]>>(n: GfElemPrimitive, points: P) -> Self {
+    pub fn lagrange<P: AsRef<[GfPoint<F>]>>(n: u32, points: P) -> Self {
         let points = points.as_ref();
         let k = points.len();
         assert!(
@@ -391,6 +647,11 @@ impl GfPolynomial {
         // where SUM_COMB({x}, n) is the sum of all combinations of length n of
         // the set {x} (without replacement). The proof is left to the reader,
         // but this is just a special-case of multi-binomial expansion.
+        //
+        // Rather than summing every combination (which is exponential in k),
+        // we compute the coefficients by multiplying in each (x-a_m) term one
+        // at a time, which is how the pattern above arises in the first
+        // place.
         let mut polys = (0..k).map(|j| {
             let idxs = (0..k).filter(|m| *m != j).collect::<Vec<_>>();
 
@@ -401,21 +662,22 @@ impl GfPolynomial {
                     .fold(GfElem::ONE, |acc, m| acc * (xs[j] - xs[*m]));
 
             // \sum_{i=0}^{k} SUM_COMB({-a}, i) x^i
-            let coeffs = (0..k)
-                .map(|i| {
-                    // SUM_COMB({-a}, i), but note that we don't have to negate
-                    // the xs values because in GF(2^32) addition and
-                    // subtraction are identical operations (thus -x == x).
-                    idxs.iter()
-                        .map(|i| xs[*i])
-                        .combinations(i)
-                        .map(|xs| xs.iter().fold(GfElem::ONE, |acc, x| acc * *x))
-                        .fold(GfElem::ZERO, Add::add)
+            let coeffs = idxs
+                .iter()
+                // (x-a_m) p(x) == x p(x) + a_m p(x), but note that we don't
+                // have to negate the xs values because in GF(2^n) addition and
+                // subtraction are identical operations (thus -x == x). We
+                // store coefficients in increasing order of x powers.
+                .fold(vec![GfElem::ONE], |coeffs, m| {
+                    let mut next = vec![GfElem::ZERO; coeffs.len() + 1];
+                    for (i, coeff) in coeffs.iter().enumerate() {
+                        next[i + 1] += *coeff;
+                        next[i] += xs[*m] * *coeff;
+                    }
+                    next
                 })
+                .into_iter()
                 .map(|x| scale * x)
-                // We store coefficients in increasing order of x powers (the
-                // opposite of the formula outlined above).
-                .rev()
                 .collect::<Vec<_>>();
 
             GfPolynomial(coeffs)
@@ -424,10 +686,167 @@ impl GfPolynomial {
         let first_poly = polys.next().expect("must be at least one polynomial");
         polys.fold(first_poly, |acc, p| acc + p)
     }
+
+    /// Recover a polynomial of degree `n` in `GF(2^n)`, given a set of points
+    /// along that polynomial of which up to `max_errors` may be incorrect.
+    ///
+    /// The process for this computation is the [Berlekamp-Welch
+    /// algorithm][berlekamp-welch], which requires at least `n + 1 +
+    /// 2*max_errors` points. `None` is returned if there are more errors than
+    /// can be corrected.
+    ///
+    /// [berlekamp-welch]: https://en.wikipedia.org/wiki/Berlekamp%E2%80%93Welch_algorithm
+    pub fn berlekamp_welch<P: AsRef<[GfPoint<F>]>>(
+        n: u32,
+        max_errors: usize,
+        points: P,
+    ) -> Option<Self> {
+        let points = points.as_ref();
+        let k = (n + 1) as usize;
+        let e = max_errors;
+        assert!(
+            points.len() >= k + 2 * e,
+            "need at least n+1+2e points for error correction"
+        );
+
+        // We are looking for an error-locator polynomial E(x) (monic, of
+        // degree e) and Q(x) = P(x) E(x) (of degree k+e-1) such that
+        //
+        //   y_i E(x_i) = Q(x_i)
+        //
+        // for every point. Expanding this gives a linear equation for each
+        // point, with the unknowns being the coefficients of Q and E (other
+        // than the leading coefficient of E, which is 1)
+        //
+        //   \sum_{j=0}^{k+e-1} q_j x_i^j - y_i \sum_{j=0}^{e-1} e_j x_i^j = y_i x_i^e
+        //
+        // and (since -x == x in GF(2^n)) we can ignore the subtraction.
+        let unknowns = k + 2 * e;
+        let mut matrix = points
+            .iter()
+            .map(|(x, y)| {
+                let powers = (0..k + e).map(|j| x.pow(j)).collect::<Vec<_>>();
+                powers
+                    .iter()
+                    .copied()
+                    .chain(powers[..e].iter().map(|p| *y * *p))
+                    .chain(Some(*y * x.pow(e)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let solution = gaussian_elimination(&mut matrix, unknowns)?;
+
+        let q = GfPolynomial(solution[..k + e].to_vec());
+        let err = GfPolynomial(
+            solution[k + e..]
+                .iter()
+                .copied()
+                .chain(Some(GfElem::ONE))
+                .collect::<Vec<_>>(),
+        );
+
+        // P(x) = Q(x) / E(x), which must divide exactly if we had few enough
+        // errors to correct.
+        let (poly, remainder) = q.div_rem(&err);
+        if remainder.0.iter().any(|c| *c != GfElem::ZERO) {
+            return None;
+        }
+
+        // Double-check that enough points agree with the polynomial.
+        let errors = points
+            .iter()
+            .filter(|(x, y)| poly.evaluate(*x) != *y)
+            .count();
+        match errors <= e {
+            true => Some(poly),
+            false => None,
+        }
+    }
+
+    /// Polynomial long division, returning the quotient and remainder.
+    ///
+    /// The leading coefficient of the divisor must be non-zero.
+    fn div_rem(&self, divisor: &Self) -> (Self, Self) {
+        let d = divisor.0.len();
+        let lead_inv = divisor
+            .0
+            .last()
+            .and_then(|c| c.inverse())
+            .expect("divisor must have a non-zero leading coefficient");
+
+        let mut remainder = self.0.clone();
+        if remainder.len() < d {
+            return (GfPolynomial(vec![GfElem::ZERO]), GfPolynomial(remainder));
+        }
+
+        let mut quotient = vec![GfElem::ZERO; remainder.len() - d + 1];
+        for i in (0..quotient.len()).rev() {
+            let coeff = remainder[i + d - 1] * lead_inv;
+            quotient[i] = coeff;
+            for (j, c) in divisor.0.iter().enumerate() {
+                remainder[i + j] -= coeff * *c;
+            }
+        }
+        remainder.truncate(d - 1);
+
+        (GfPolynomial(quotient), GfPolynomial(remainder))
+    }
+}
+
+/// Solve the system of linear equations described by the augmented matrix
+/// (each row has `unknowns` coefficients followed by the constant term),
+/// returning `None` if the system is inconsistent.
+///
+/// If the system is under-determined, the free variables are set to zero.
+fn gaussian_elimination<F: GfField>(
+    matrix: &mut [Vec<GfElem<F>>],
+    unknowns: usize,
+) -> Option<Vec<GfElem<F>>> {
+    let mut pivots = vec![];
+    let mut row = 0;
+    for col in 0..unknowns {
+        // Find a row with a non-zero entry in this column.
+        let pivot = match (row..matrix.len()).find(|r| matrix[*r][col] != GfElem::ZERO) {
+            Some(pivot) => pivot,
+            None => continue,
+        };
+        matrix.swap(row, pivot);
+
+        // Normalise the pivot row.
+        let inv = matrix[row][col].inverse().expect("pivot must be non-zero");
+        matrix[row].iter_mut().for_each(|c| *c *= inv);
+
+        // Eliminate this column from every other row.
+        let pivot_row = matrix[row].clone();
+        for (r, other) in matrix.iter_mut().enumerate() {
+            let factor = other[col];
+            if r != row && factor != GfElem::ZERO {
+                other
+                    .iter_mut()
+                    .zip(&pivot_row)
+                    .skip(col)
+                    .for_each(|(c, p)| *c -= factor * *p);
+            }
+        }
+
+        pivots.push((row, col));
+        row += 1;
+    }
+
+    // Any remaining rows must be all-zero, otherwise there is no solution.
+    if matrix[row..].iter().any(|r| r[unknowns] != GfElem::ZERO) {
+        return None;
+    }
+
+    let mut solution = vec![GfElem::ZERO; unknowns];
+    for (row, col) in pivots {
+        solution[col] = matrix[row][unknowns];
+    }
+    Some(solution)
 }
 
 #[cfg(test)]
-impl quickcheck::Arbitrary for GfPolynomial {
+impl<F: GfField> quickcheck::Arbitrary for GfPolynomial<F> {
     fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
         GfPolynomial(
             (0..g.size())
@@ -437,7 +856,7 @@ impl quickcheck::Arbitrary for GfPolynomial {
     }
 }
 
-impl Add for GfPolynomial {
+impl<F: GfField> Add for GfPolynomial<F> {
     type Output = Self;
     fn add(mut self, rhs: Self) -> Self::Output {
         self += rhs;
@@ -445,7 +864,7 @@ impl Add for GfPolynomial {
     }
 }
 
-impl AddAssign for GfPolynomial {
+impl<F: GfField> AddAssign for GfPolynomial<F> {
     fn add_assign(&mut self, rhs: Self) {
         for (i, rhs_coeff) in rhs.0.iter().enumerate() {
             match self.0.get_mut(i) {
@@ -459,6 +878,157 @@ impl AddAssign for GfPolynomial {
     }
 }
 
+impl<F: GfField> Zeroize for GfPolynomial<F> {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<F: GfField> Drop for GfPolynomial<F> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// A set of polynomials in `GF(2^n)` which all have the same degree.
+///
+/// All of the coefficients are stored in a single contiguous allocation, with
+/// each polynomial's coefficients stored next to each other (in *increasing*
+/// degree, as with `GfPolynomial`). This avoids having a separate allocation
+/// for every polynomial when sharding large secrets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GfPolynomials<F: GfField = Gf32> {
+    // Number of coefficients in each polynomial (the degree + 1).
+    stride: usize,
+    coeffs: Vec<GfElem<F>>,
+}
+
+impl<F: GfField> Zeroize for GfPolynomials<F> {
+    fn zeroize(&mut self) {
+        self.coeffs.zeroize();
+    }
+}
+
+impl<F: GfField> Drop for GfPolynomials<F> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl<F: GfField> GfPolynomials<F> {
+    /// Construct an empty set of polynomials of degree `n`, with enough space
+    /// reserved for `capacity` polynomials.
+    pub fn with_capacity(n: u32, capacity: usize) -> Self {
+        let stride = (n + 1) as usize;
+        Self {
+            stride,
+            coeffs: Vec::with_capacity(stride * capacity),
+        }
+    }
+
+    /// Construct a set of degree-0 polynomials with the given constant terms.
+    pub fn from_constants<I: IntoIterator<Item = GfElem<F>>>(constants: I) -> Self {
+        Self {
+            stride: 1,
+            coeffs: constants.into_iter().collect::<Vec<_>>(),
+        }
+    }
+
+    /// Append a polynomial to the set.
+    ///
+    /// Panics if the polynomial has a different number of coefficients to the
+    /// other polynomials in the set.
+    pub fn push(&mut self, poly: GfPolynomial<F>) {
+        assert!(
+            poly.0.len() == self.stride,
+            "all polynomials in GfPolynomials must have the same degree"
+        );
+        self.coeffs.extend_from_slice(&poly.0);
+    }
+
+    /// Append a random polynomial with the given constant term to the set.
+    ///
+    /// This is equivalent to pushing a `GfPolynomial::new_rand` with its
+    /// constant term replaced, but without the intermediate allocation.
+    pub fn push_rand<R: RngCore + ?Sized>(&mut self, constant: GfElem<F>, r: &mut R) {
+        self.coeffs.push(constant);
+        for _ in 1..self.stride {
+            self.coeffs.push(GfPolynomial::rand_coeff(r));
+        }
+    }
+
+    /// Construct a set of polynomials of degree `n` from the coefficients of
+    /// every polynomial (in the same layout as `GfPolynomials::coeffs`).
+    ///
+    /// Returns `None` if the number of coefficients is not a multiple of
+    /// `n + 1`.
+    pub fn from_coeffs(n: u32, coeffs: Vec<GfElem<F>>) -> Option<Self> {
+        let stride = (n + 1) as usize;
+        match coeffs.len() % stride {
+            0 => Some(Self { stride, coeffs }),
+            _ => None,
+        }
+    }
+
+    /// The coefficients of every polynomial in the set, stored one polynomial
+    /// after another (each in *increasing* degree).
+    pub fn coeffs(&self) -> &[GfElem<F>] {
+        &self.coeffs
+    }
+
+    /// Iterate over the coefficients of each polynomial in the set.
+    pub fn iter(&self) -> impl Iterator<Item = &[GfElem<F>]> {
+        self.coeffs.chunks_exact(self.stride)
+    }
+
+    /// Iterate over the constant term of each polynomial in the set.
+    pub fn constants(&self) -> impl Iterator<Item = GfElem<F>> + '_ {
+        self.coeffs.iter().step_by(self.stride).copied()
+    }
+
+    /// Evaluate every polynomial in the set at a given `x` value.
+    pub fn evaluate(&self, x: GfElem<F>) -> impl Iterator<Item = GfElem<F>> + '_ {
+        // Every multiplication is by x, so (without hardware carry-less
+        // multiplication) it is much faster to use a precomputed table.
+        let x = GfMultiplier::new(x, self.coeffs.len());
+        // Horner's method, as with GfPolynomial::evaluate.
+        self.iter().map(move |coeffs| {
+            coeffs
+                .iter()
+                .rev()
+                .fold(GfElem::ZERO, |acc, coeff| *coeff + x.mul(acc))
+        })
+    }
+
+    /// Evaluate every polynomial in the set at each of the given `x` values,
+    /// returning the values for each `x` (in the same order as `xs`).
+    ///
+    /// This is faster than calling `GfPolynomials::evaluate` for each `x`, as
+    /// each polynomial's coefficients are only walked over once.
+    #[cfg(feature = "std")]
+    pub fn evaluate_many(&self, xs: &[GfElem<F>]) -> Vec<Vec<GfElem<F>>> {
+        let xs = xs
+            .iter()
+            .map(|x| GfMultiplier::new(*x, self.coeffs.len()))
+            .collect::<Vec<_>>();
+        let mut ys = xs
+            .iter()
+            .map(|_| Vec::with_capacity(self.coeffs.len() / self.stride))
+            .collect::<Vec<_>>();
+        for coeffs in self.iter() {
+            for (i, x) in xs.iter().enumerate() {
+                // Horner's method, as with GfPolynomial::evaluate.
+                let y = coeffs
+                    .iter()
+                    .rev()
+                    .fold(GfElem::ZERO, |acc, coeff| *coeff + x.mul(acc));
+                ys[i].push(y);
+            }
+        }
+        ys
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -546,6 +1116,83 @@ mod test {
         }
     }
 
+    #[quickcheck]
+    fn mul_backend_matches_portable(a: GfElem<Gf16>, b: GfElem<Gf32>, c: GfElem<Gf64>) -> bool {
+        // Whatever backend was selected must agree with the portable code.
+        (a * a).0 == GfElem::<Gf16>::mul_portable(a.0, a.0)
+            && (b * b).0 == GfElem::<Gf32>::mul_portable(b.0, b.0)
+            && (c * c).0 == GfElem::<Gf64>::mul_portable(c.0, c.0)
+    }
+
+    #[quickcheck]
+    fn mul_table(
+        (a, b): (GfElem<Gf16>, GfElem<Gf16>),
+        (c, d): (GfElem<Gf32>, GfElem<Gf32>),
+        (e, f): (GfElem<Gf64>, GfElem<Gf64>),
+    ) -> bool {
+        GfMulTable::new(a).mul(b).0 == GfElem::<Gf16>::mul_portable(a.0, b.0)
+            && GfMulTable::new(c).mul(d).0 == GfElem::<Gf32>::mul_portable(c.0, d.0)
+            && GfMulTable::new(e).mul(f).0 == GfElem::<Gf64>::mul_portable(e.0, f.0)
+    }
+
+    #[quickcheck]
+    fn multiplier(
+        uses: u8,
+        (a, b): (GfElem<Gf16>, GfElem<Gf16>),
+        (c, d): (GfElem, GfElem),
+    ) -> bool {
+        let uses = uses as usize;
+        GfMultiplier::new(a, uses).mul(b) == a * b && GfMultiplier::new(c, uses).mul(d) == c * d
+    }
+
+    #[test]
+    fn constant_time_no_tables() {
+        if CONSTANT_TIME {
+            assert!(GfElem::<Gf16>::log_tables().is_none());
+        }
+    }
+
+    // The tables are built lazily, which requires std.
+    #[quickcheck]
+    #[cfg(feature = "std")]
+    fn gf16_log_tables(a: GfElem<Gf16>, b: GfElem<Gf16>) -> bool {
+        let tables = Gf16Tables::get();
+        let inverse = match a {
+            GfElem::<Gf16>::ZERO => true,
+            _ => {
+                let log = tables.log[a.0 as usize] as usize;
+                tables.mul(a.0, tables.exp[GF16_ORDER - log].into()) == 1
+            }
+        };
+        inverse && tables.mul(a.0, b.0) == GfElem::<Gf16>::mul_portable(a.0, b.0)
+    }
+
+    #[quickcheck]
+    fn other_fields_inverse(a: GfElem<Gf16>, b: GfElem<Gf64>) -> bool {
+        (a == GfElem::ZERO || a * a.inverse().unwrap() == GfElem::ONE)
+            && (b == GfElem::ZERO || b * b.inverse().unwrap() == GfElem::ONE)
+    }
+
+    #[quickcheck]
+    fn other_fields_distributivity(a: GfElem<Gf16>, b: GfElem<Gf16>, c: GfElem<Gf64>) -> bool {
+        let d = GfElem::<Gf64>::new_rand(&mut OsRng);
+        (a * (a + b)) == ((a * a) + (a * b)) && (c * (c + d)) == ((c * c) + (c * d))
+    }
+
+    #[test]
+    fn gf16_primitive() {
+        // x must generate the entire multiplicative group of the field.
+        let x = GfElem::<Gf16>::from_inner(2).unwrap();
+        let order = (1..=Gf16::MASK)
+            .scan(GfElem::ONE, |acc, i| {
+                *acc *= x;
+                Some((i, *acc))
+            })
+            .find(|(_, acc)| *acc == GfElem::ONE)
+            .map(|(i, _)| i);
+        assert_eq!(order, Some(Gf16::MASK));
+    }
+
     // Inefficient, but "obviously correct" implementation of
     // GfPolynomial::evaluate(), to compare against for the test.
     fn manual_poly(poly: GfPolynomial, x: GfElem) -> GfElem {
@@ -575,6 +1222,28 @@ mod test {
         poly.evaluate(GfElem::ZERO) == poly.constant()
     }
 
+    #[quickcheck]
+    fn polynomials_evaluate(polys: Vec<GfPolynomial>, x: GfElem) -> TestResult {
+        let n = match polys.first() {
+            Some(poly) if !poly.0.is_empty() => poly.degree(),
+            _ => return TestResult::discard(),
+        };
+        if polys.iter().any(|poly| poly.0.len() != (n + 1) as usize) {
+            return TestResult::discard();
+        }
+
+        let mut flat = GfPolynomials::with_capacity(n, polys.len());
+        polys.iter().cloned().for_each(|poly| flat.push(poly));
+
+        TestResult::from_bool(
+            flat.evaluate(x)
+                .eq(polys.iter().map(|poly| poly.evaluate(x)))
+                && flat
+                    .constants()
+                    .eq(polys.iter().map(GfPolynomial::constant)),
+        )
+    }
+
     #[quickcheck]
     fn polynomial_lagrange_constant(poly: GfPolynomial) -> bool {
         let n = poly.degree();
@@ -582,12 +1251,82 @@ mod test {
             .map(|_| GfElem::new_rand(&mut OsRng))
             .collect::<Vec<_>>();
         let ys = xs.iter().map(|x| poly.evaluate(*x));
-        let points = xs.iter().copied().zip(ys).collect::<Vec<_>>();
-        let constant = GfPolynomial::lagrange_constant(n, points.as_slice());
+        let weights = GfPolynomial::lagrange_weights(&xs, GfElem::ZERO);
+        let constant = ys
+            .zip(weights)
+            .fold(GfElem::ZERO, |acc, (y, w)| acc + y * w);
 
         poly.constant() == constant
     }
 
+    #[quickcheck]
+    fn polynomial_lagrange_weights(poly: GfPolynomial, x: GfElem) -> bool {
+        // Really large n values take a very long time to compute.
+        let poly = GfPolynomial(poly.0.iter().copied().take(32).collect::<Vec<_>>());
+        let n = poly.degree();
+        let xs = (0..n + 1)
+            .map(|_| GfElem::new_rand(&mut OsRng))
+            .collect::<Vec<_>>();
+        let weights = GfPolynomial::lagrange_weights(&xs, x);
+        let y = xs
+            .iter()
+            .zip(weights)
+            .fold(GfElem::ZERO, |acc, (xi, w)| acc + poly.evaluate(*xi) * w);
+
+        poly.evaluate(x) == y
+    }
+
+    #[quickcheck]
+    fn polynomial_barycentric_weights(poly: GfPolynomial) -> bool {
+        let poly = GfPolynomial(poly.0.iter().copied().take(32).collect::<Vec<_>>());
+        let n = poly.degree();
+        let xs = (0..n + 1)
+            .map(|_| GfElem::new_rand(&mut OsRng))
+            .collect::<Vec<_>>();
+        let weights = GfPolynomial::barycentric_weights(&xs);
+
+        // The weights at any of the xs pick out exactly that point.
+        let at_point = xs.iter().all(|x| {
+            let weights = GfPolynomial::lagrange_weights(&xs, *x);
+            let y = xs
+                .iter()
+                .zip(weights)
+                .fold(GfElem::ZERO, |acc, (xi, w)| acc + poly.evaluate(*xi) * w);
+            poly.evaluate(*x) == y
+        });
+
+        at_point
+            && (0..xs.len()).all(|j| {
+                (0..xs.len())
+                    .filter(|m| *m != j)
+                    .fold(weights[j], |acc, m| acc * (xs[j] - xs[m]))
+                    == GfElem::ONE
+            })
+    }
+
+    #[quickcheck]
+    fn polynomial_berlekamp_welch(poly: GfPolynomial, max_errors: usize, bad: Vec<usize>) -> bool {
+        // Really large n values take a very long time to recover.
+        let poly = GfPolynomial(poly.0.iter().copied().take(8).collect::<Vec<_>>());
+        let n = poly.degree();
+        let max_errors = max_errors % 4;
+
+        let xs = (0..n as usize + 1 + 2 * max_errors)
+            .map(|_| GfElem::new_rand(&mut OsRng))
+            .collect::<Vec<_>>();
+        let mut points = xs
+            .iter()
+            .map(|x| (*x, poly.evaluate(*x)))
+            .collect::<Vec<_>>();
+        // Corrupt up to max_errors points.
+        let len = points.len();
+        for i in bad.iter().take(max_errors) {
+            points[i % len].1 += GfElem::ONE;
+        }
+
+        GfPolynomial::berlekamp_welch(n, max_errors, points) == Some(poly)
+    }
+
     #[quickcheck]
     fn polynomial_lagrange(poly: GfPolynomial) -> TestResult {
         let n = poly.degree();
@@ -0,0 +1,569 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::shamir::{encoding, limits::WireError};
+
+use std::fmt;
+
+/// Magic bytes at the start of every serialised shard (and the other `ToWire`
+/// types in paperback), followed by the wire format version as a varint.
+///
+/// The magic is "pb" with the varint continuation bit set on each byte,
+/// terminated by a zero byte. This is a non-minimal varint, which the original
+/// (headerless) wire format could never start with, so headerless data can
+/// still be told apart and decoded.
+pub const WIRE_MAGIC: [u8; 3] = [b'p' | 0x80, b'b' | 0x80, 0x00];
+
+/// Version of the wire format written by `ToWire`.
+///
+/// The version is followed by a varint tag identifying the kind of object (see
+/// `DocumentKind`), a varint of flags describing the layout of the object, the
+/// length of the object (as a varint), the object itself, and a little-endian
+/// CRC-32C of everything before it.
+///
+/// Data written before the header was introduced (with no magic at all) can
+/// still be decoded, so that shards printed by older versions of paperback can
+/// always be recovered.
+pub const WIRE_VERSION: u32 = 1;
+
+/// Version given to data written before the header was introduced.
+pub(super) const LEGACY_VERSION: u32 = 0;
+
+/// Length (in bytes) of the checksum at the end of a serialised object.
+pub(super) const CRC_LENGTH: usize = 4;
+
+/// Flag set when the y-values of a `Shard` are stored with a fixed width
+/// rather than as varints.
+pub(super) const FLAG_FIXED_WIDTH_YS: u64 = 1 << 0;
+
+/// Kinds of object which can be serialised with `ToWire`, as recorded in the
+/// header of the wire format.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DocumentKind {
+    /// A `shamir::Shard` (in any field).
+    Shard,
+    /// A `shamir::PackedShard`.
+    PackedShard,
+    /// A `shamir::CompartmentShard`.
+    CompartmentShard,
+    /// A `shamir::WeightedShard`.
+    WeightedShard,
+    /// A `shamir::IssuedLog`.
+    IssuedLog,
+    /// A `shamir::ShardCommitments`.
+    ShardCommitments,
+    /// A `v0::MainDocument`.
+    MainDocument,
+    /// A `v0::EncryptedKeyShard`.
+    EncryptedKeyShard,
+    /// A `v0::DetachedSignature`.
+    DetachedSignature,
+    /// A `v0::Bundle`.
+    Bundle,
+    /// A `v0::SupersededNotice`.
+    SupersededNotice,
+    /// A `v0::ExpansionGrant`.
+    ExpansionGrant,
+    /// A `v0::RevocationList`.
+    RevocationList,
+    /// A `v0::PossessionVerifier`.
+    PossessionVerifier,
+    /// A `v0::RecoveryRecord`.
+    RecoveryRecord,
+    /// A `v0::MainDocumentPage`.
+    MainDocumentPage,
+    /// The key of a streamed `v0::Backup` (see `v0::Backup::new_stream`).
+    StreamKey,
+    /// Data which is not a tagged paperback object. This includes objects
+    /// written before the header was added to the wire format.
+    Unknown,
+}
+
+impl DocumentKind {
+    const TAGGED: [DocumentKind; 17] = [
+        DocumentKind::Shard,
+        DocumentKind::PackedShard,
+        DocumentKind::CompartmentShard,
+        DocumentKind::WeightedShard,
+        DocumentKind::IssuedLog,
+        DocumentKind::ShardCommitments,
+        DocumentKind::MainDocument,
+        DocumentKind::EncryptedKeyShard,
+        DocumentKind::DetachedSignature,
+        DocumentKind::Bundle,
+        DocumentKind::SupersededNotice,
+        DocumentKind::ExpansionGrant,
+        DocumentKind::RevocationList,
+        DocumentKind::PossessionVerifier,
+        DocumentKind::RecoveryRecord,
+        DocumentKind::MainDocumentPage,
+        DocumentKind::StreamKey,
+    ];
+
+    // Type tag written in the header (Unknown is never written).
+    pub(crate) fn tag(self) -> u64 {
+        match self {
+            DocumentKind::Unknown => 0,
+            DocumentKind::Shard => 1,
+            DocumentKind::PackedShard => 2,
+            DocumentKind::CompartmentShard => 3,
+            DocumentKind::WeightedShard => 4,
+            DocumentKind::IssuedLog => 5,
+            DocumentKind::ShardCommitments => 6,
+            DocumentKind::MainDocument => 7,
+            DocumentKind::EncryptedKeyShard => 8,
+            DocumentKind::DetachedSignature => 9,
+            DocumentKind::Bundle => 10,
+            DocumentKind::SupersededNotice => 11,
+            DocumentKind::ExpansionGrant => 12,
+            DocumentKind::RevocationList => 13,
+            DocumentKind::PossessionVerifier => 14,
+            DocumentKind::RecoveryRecord => 15,
+            DocumentKind::MainDocumentPage => 16,
+            DocumentKind::StreamKey => 17,
+        }
+    }
+
+    pub(crate) fn from_tag(tag: u64) -> Self {
+        Self::TAGGED
+            .iter()
+            .copied()
+            .find(|kind| kind.tag() == tag)
+            .unwrap_or(DocumentKind::Unknown)
+    }
+}
+
+impl fmt::Display for DocumentKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DocumentKind::Shard => write!(f, "shard"),
+            DocumentKind::PackedShard => write!(f, "packed shard"),
+            DocumentKind::CompartmentShard => write!(f, "compartment shard"),
+            DocumentKind::WeightedShard => write!(f, "weighted shard"),
+            DocumentKind::IssuedLog => write!(f, "issued shard log"),
+            DocumentKind::ShardCommitments => write!(f, "shard commitments"),
+            DocumentKind::MainDocument => write!(f, "main document"),
+            DocumentKind::EncryptedKeyShard => write!(f, "encrypted key shard"),
+            DocumentKind::DetachedSignature => write!(f, "detached signature"),
+            DocumentKind::Bundle => write!(f, "bundle"),
+            DocumentKind::SupersededNotice => write!(f, "superseded notice"),
+            DocumentKind::ExpansionGrant => write!(f, "expansion grant"),
+            DocumentKind::RevocationList => write!(f, "revocation list"),
+            DocumentKind::PossessionVerifier => write!(f, "possession verifier"),
+            DocumentKind::RecoveryRecord => write!(f, "recovery record"),
+            DocumentKind::MainDocumentPage => write!(f, "main document page"),
+            DocumentKind::StreamKey => write!(f, "stream key"),
+            DocumentKind::Unknown => write!(f, "unknown object"),
+        }
+    }
+}
+
+/// Returns the kind of object serialised in `input`, from the type tag in its
+/// header.
+///
+/// Only the header is inspected, so this does not mean that `input` can be
+/// deserialised (it may be truncated or corrupted). Objects written before
+/// the header was added are `DocumentKind::Unknown`.
+pub fn detect<B: AsRef<[u8]>>(input: B) -> DocumentKind {
+    use crate::nom_helpers;
+    use nom::combinator::complete;
+
+    match read_header(input.as_ref()) {
+        Ok((input, WIRE_VERSION)) => complete(nom_helpers::u64)(input)
+            .map_or(DocumentKind::Unknown, |(_, tag)| {
+                DocumentKind::from_tag(tag)
+            }),
+        _ => DocumentKind::Unknown,
+    }
+}
+
+// Append the current header to bytes, to start a serialised object.
+pub(super) fn write_header(bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&WIRE_MAGIC);
+    encoding::varint(WIRE_VERSION.into(), bytes);
+}
+
+// Parse the header of a serialised object, returning its version. Data without
+// a header is treated as LEGACY_VERSION.
+pub(crate) fn read_header(input: &[u8]) -> Result<(&[u8], u32), WireError> {
+    use crate::nom_helpers;
+    use nom::combinator::complete;
+
+    match input.strip_prefix(&WIRE_MAGIC[..]) {
+        None => Ok((input, LEGACY_VERSION)),
+        Some(input) => match WireError::nom(complete(nom_helpers::u32)(input))
+            .map_err(|err| err.in_field("version", WIRE_MAGIC.len()))?
+        {
+            (_, LEGACY_VERSION) => Err(WireError::UnsupportedVersion {
+                version: LEGACY_VERSION,
+            }),
+            (input, version) => Ok((input, version)),
+        },
+    }
+}
+
+// Serialise an object with the current header (and no flags), framed by its
+// length and followed by a checksum.
+#[cfg(test)]
+pub(crate) fn write_record(kind: DocumentKind, body: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(record_len(kind, body.len()));
+    write_record_into(&mut bytes, kind, body.len(), |bytes| {
+        bytes.extend_from_slice(body)
+    });
+    bytes
+}
+
+// Length (in bytes) of a serialised object whose body is body_len bytes long
+// (see write_record).
+pub(crate) fn record_len(kind: DocumentKind, body_len: usize) -> usize {
+    flagged_record_len(kind, 0, body_len)
+}
+
+// Length (in bytes) of a serialised object with the given flags.
+pub(super) fn flagged_record_len(kind: DocumentKind, flags: u64, body_len: usize) -> usize {
+    WIRE_MAGIC.len()
+        + encoding::varint_len(WIRE_VERSION.into())
+        + encoding::varint_len(kind.tag())
+        + encoding::varint_len(flags)
+        + encoding::varint_len(body_len as u64)
+        + body_len
+        + CRC_LENGTH
+}
+
+// Append a serialised object to bytes, like write_record, with write_body
+// appending the body (which must be exactly body_len bytes long).
+pub(crate) fn write_record_into<W>(
+    bytes: &mut Vec<u8>,
+    kind: DocumentKind,
+    body_len: usize,
+    write_body: W,
+) where
+    W: FnOnce(&mut Vec<u8>),
+{
+    write_flagged_record_into(bytes, kind, 0, body_len, write_body)
+}
+
+// Append a serialised object with the given flags to bytes (see
+// write_record_into).
+pub(super) fn write_flagged_record_into<W>(
+    bytes: &mut Vec<u8>,
+    kind: DocumentKind,
+    flags: u64,
+    body_len: usize,
+    write_body: W,
+) where
+    W: FnOnce(&mut Vec<u8>),
+{
+    let start = bytes.len();
+    write_header(bytes);
+    encoding::varint(kind.tag(), bytes);
+    encoding::varint(flags, bytes);
+    encoding::varint(body_len as u64, bytes);
+    let body_start = bytes.len();
+    write_body(bytes);
+    debug_assert_eq!(bytes.len() - body_start, body_len);
+    let checksum = encoding::crc32c(&bytes[start..]);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+}
+
+// Parse a serialised object of the given kind and any supported version, using
+// parse to parse the layout of the object itself (which is the same in every
+// version). Objects with any flags set are rejected.
+pub(crate) fn read_record<'a, T, P>(
+    input: &'a [u8],
+    kind: DocumentKind,
+    parse: P,
+) -> Result<(T, &'a [u8]), WireError>
+where
+    P: FnOnce(&'a [u8]) -> Result<(T, &'a [u8]), WireError>,
+{
+    read_flagged_record(input, kind, 0, |body, _| parse(body))
+}
+
+// Parse a serialised object like read_flagged_record, but parse objects
+// written before the header was added with legacy (for objects whose layout
+// has changed since then).
+pub(super) fn read_flagged_record_or_legacy<'a, T, L, P>(
+    input: &'a [u8],
+    kind: DocumentKind,
+    supported: u64,
+    legacy: L,
+    parse: P,
+) -> Result<(T, &'a [u8]), WireError>
+where
+    L: FnOnce(&'a [u8]) -> Result<(T, &'a [u8]), WireError>,
+    P: FnOnce(&'a [u8], u64) -> Result<(T, &'a [u8]), WireError>,
+{
+    match read_header(input)? {
+        (body, LEGACY_VERSION) => legacy(body),
+        _ => read_flagged_record(input, kind, supported, parse),
+    }
+}
+
+// Parse a serialised object like read_record, passing its flags to parse.
+// Objects with any flags other than those in supported are rejected, and
+// objects written before flags were introduced have no flags set.
+pub(super) fn read_flagged_record<'a, T, P>(
+    input: &'a [u8],
+    kind: DocumentKind,
+    supported: u64,
+    parse: P,
+) -> Result<(T, &'a [u8]), WireError>
+where
+    P: FnOnce(&'a [u8], u64) -> Result<(T, &'a [u8]), WireError>,
+{
+    use crate::nom_helpers;
+    use nom::combinator::complete;
+
+    let (body, version) = read_header(input)?;
+    let offset = |remain: &[u8]| input.len() - remain.len();
+    match version {
+        // Objects whose layout changed when the header was added use
+        // read_flagged_record_or_legacy instead.
+        LEGACY_VERSION => parse(body, 0).map_err(|err| err.offset_by(offset(body))),
+        WIRE_VERSION => {
+            let tag_offset = offset(body);
+            let (body, found) = WireError::nom(complete(nom_helpers::u64)(body))
+                .map(|(body, tag)| (body, DocumentKind::from_tag(tag)))
+                .map_err(|err| err.in_field("kind", tag_offset))?;
+            let flags_offset = offset(body);
+            let (body, flags) = WireError::nom(complete(nom_helpers::u64)(body))
+                .map_err(|err| err.in_field("flags", flags_offset))?;
+            let (body, length) = WireError::nom(complete(nom_helpers::usize)(body))
+                .map_err(|err| err.in_field("length", offset(body)))?;
+            if body.len() < CRC_LENGTH || body.len() - CRC_LENGTH < length {
+                return Err(WireError::UnexpectedEof);
+            }
+            let (framed, remain) = input.split_at(offset(body) + length);
+            let (checksum, remain) = remain.split_at(CRC_LENGTH);
+            let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+            if encoding::crc32c(framed) != checksum {
+                return Err(WireError::Corrupted);
+            }
+            if found != kind {
+                return Err(WireError::from(format!(
+                    "wrong kind of object ({} but expected {})",
+                    found, kind
+                ))
+                .in_field("kind", tag_offset));
+            }
+            if flags & !supported != 0 {
+                return Err(WireError::from(format!(
+                    "unsupported flags {:#x}",
+                    flags & !supported
+                ))
+                .in_field("flags", flags_offset));
+            }
+            match parse(&body[..length], flags).map_err(|err| err.offset_by(offset(body)))? {
+                (object, []) => Ok((object, remain)),
+                (_, extra) => Err(WireError::Malformed(format!(
+                    "{} unused bytes left within the declared length",
+                    extra.len()
+                ))),
+            }
+        }
+        version => Err(WireError::UnsupportedVersion { version }),
+    }
+}
+
+// Split the first serialised object (of any kind) from input, checking its
+// checksum but not parsing it. Objects without a header can't be split, since
+// they can only be delimited by parsing them.
+pub(crate) fn split_record(input: &[u8]) -> Result<(DocumentKind, &[u8], &[u8]), WireError> {
+    let kind = match detect(input) {
+        DocumentKind::Unknown => {
+            return Err(WireError::from(
+                "object has no (known) type tag".to_string(),
+            ))
+        }
+        kind => kind,
+    };
+    let ((), remain) = read_flagged_record(input, kind, u64::MAX, |body, _| {
+        Ok(((), &body[body.len()..]))
+    })?;
+    let (record, remain) = input.split_at(input.len() - remain.len());
+    Ok((kind, record, remain))
+}
+
+// If input is a tagged serialised object which has been cut short, return the
+// field of the record which was cut off, its offset within input and the
+// number of bytes of that field which are missing (for varint fields, this is
+// only a lower bound). Returns None if input is not truncated, or if it is
+// damaged in some other way.
+pub(crate) fn truncation(input: &[u8]) -> Option<(&'static str, usize, usize)> {
+    use crate::nom_helpers;
+    use nom::combinator::complete;
+    use std::convert::TryFrom;
+
+    if input.len() < WIRE_MAGIC.len() {
+        return match WIRE_MAGIC.starts_with(input) {
+            true => Some(("magic", 0, WIRE_MAGIC.len() - input.len())),
+            false => None,
+        };
+    }
+    let offset = |remain: &[u8]| input.len() - remain.len();
+    let mut body = input.strip_prefix(&WIRE_MAGIC[..])?;
+    let mut length = 0;
+    for field in &["version", "kind", "flags", "length"] {
+        match WireError::nom(complete(nom_helpers::u64)(body)) {
+            // Only objects with a header can be delimited without parsing them.
+            Ok((_, version)) if *field == "version" && version != u64::from(WIRE_VERSION) => {
+                return None
+            }
+            Ok((remain, value)) => {
+                length = value;
+                body = remain;
+            }
+            Err(WireError::UnexpectedEof) => return Some((field, offset(body), 1)),
+            Err(_) => return None,
+        }
+    }
+    let length = usize::try_from(length).ok()?;
+    match body.len() {
+        available if available < length => Some(("body", offset(body), length - available)),
+        available if available - length < CRC_LENGTH => Some((
+            "checksum",
+            offset(body) + length,
+            CRC_LENGTH - (available - length),
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use unsigned_varint::encode;
+
+    const KIND: DocumentKind = DocumentKind::Shard;
+
+    #[test]
+    fn wire_header_roundtrip() {
+        let mut bytes = vec![];
+        write_header(&mut bytes);
+        bytes.push(0x42);
+        assert_eq!(read_header(&bytes), Ok((&[0x42][..], WIRE_VERSION)));
+    }
+
+    #[quickcheck]
+    fn wire_header_legacy(x: u64) -> bool {
+        // Headerless data always starts with a minimal varint.
+        let bytes = encode::u64(x, &mut encode::u64_buffer()).to_vec();
+        read_header(&bytes) == Ok((&bytes[..], LEGACY_VERSION))
+    }
+
+    #[test]
+    fn wire_record_roundtrip() {
+        let mut bytes = write_record(KIND, b"body");
+        bytes.push(0x42);
+        let parse = |body| Ok((body, &[][..]));
+        assert_eq!(
+            read_record(&bytes, KIND, parse),
+            Ok((&b"body"[..], &[0x42][..]))
+        );
+    }
+
+    #[quickcheck]
+    fn wire_record_len(prefix: Vec<u8>, body: Vec<u8>) -> bool {
+        let mut bytes = prefix.clone();
+        write_record_into(&mut bytes, KIND, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        let record = write_record(KIND, &body);
+        record.len() == record_len(KIND, body.len()) && bytes == [prefix, record].concat()
+    }
+
+    #[quickcheck]
+    fn wire_record_corrupted(body: Vec<u8>, pos: usize, bit: u8) -> bool {
+        let mut bytes = write_record(KIND, &body);
+        // Flip any bit after the header and length.
+        let start = bytes.len() - body.len() - CRC_LENGTH;
+        let pos = start + pos % (bytes.len() - start);
+        bytes[pos] ^= 1 << (bit % 8);
+        let parse = |body| Ok((body, &[][..]));
+        read_record(&bytes, KIND, parse) == Err(WireError::Corrupted)
+    }
+
+    #[test]
+    fn wire_record_unused() {
+        let bytes = write_record(KIND, b"body");
+        assert_eq!(
+            read_record(&bytes, KIND, |body| Ok(((), &body[2..]))),
+            Err(WireError::Malformed(
+                "2 unused bytes left within the declared length".into()
+            ))
+        );
+    }
+
+    #[quickcheck]
+    fn wire_record_flags(flags: u64, body: Vec<u8>) -> bool {
+        let mut bytes = vec![];
+        write_flagged_record_into(&mut bytes, KIND, flags, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        let parse = |body, flags| Ok(((body, flags), &[][..]));
+
+        // Only the supported flags are accepted.
+        let unsupported = read_record(&bytes, KIND, |body| Ok((body, &[][..])))
+            .map_err(|err| (err.field().map(String::from), err.cause().clone()));
+        bytes.len() == flagged_record_len(KIND, flags, body.len())
+            && read_flagged_record(&bytes, KIND, flags, parse) == Ok(((&body[..], flags), &[][..]))
+            && match flags {
+                0 => unsupported == Ok((&body[..], &[][..])),
+                _ => {
+                    matches!(unsupported, Err((Some(field), WireError::Malformed(_))) if field == "flags")
+                }
+            }
+    }
+
+    #[test]
+    fn wire_record_kinds() {
+        for kind in DocumentKind::TAGGED.iter().copied() {
+            let bytes = write_record(kind, b"body");
+            assert_eq!(detect(&bytes), kind);
+            assert_eq!(DocumentKind::from_tag(kind.tag()), kind);
+
+            let parse = |body| Ok((body, &[][..]));
+            let expected = match kind {
+                DocumentKind::Shard => DocumentKind::MainDocument,
+                _ => DocumentKind::Shard,
+            };
+            let err = read_record(&bytes, expected, parse).unwrap_err();
+            assert_eq!(err.field(), Some("kind"));
+            assert_eq!(err.offset(), Some(WIRE_MAGIC.len() + 1));
+        }
+
+        // Untagged data cannot be detected.
+        assert_eq!(detect(b"\x00\x01"), DocumentKind::Unknown);
+        assert_eq!(detect(WIRE_MAGIC), DocumentKind::Unknown);
+        let mut future = WIRE_MAGIC.to_vec();
+        future.extend_from_slice(&[WIRE_VERSION as u8, 0x7f]);
+        assert_eq!(detect(&future), DocumentKind::Unknown);
+    }
+
+    #[test]
+    fn wire_header_unsupported() {
+        let mut bytes = WIRE_MAGIC.to_vec();
+        bytes.push(0);
+        assert_eq!(
+            read_header(&bytes),
+            Err(WireError::UnsupportedVersion { version: 0 })
+        );
+    }
+}
@@ -0,0 +1,257 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{error, fmt};
+
+use nom::{error::ErrorKind, Err as NomErr, IResult};
+
+/// Upper bounds on the lengths a serialised shard may declare.
+///
+/// The wire format of the shard types is length-prefixed, so a corrupted (or
+/// malicious) shard could otherwise claim to contain an enormous number of
+/// values and cause a huge allocation before parsing fails. The defaults are
+/// far larger than anything paperback itself will produce.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WireLimits {
+    /// Maximum number of y-values in a `Shard`.
+    pub max_ys: usize,
+    /// Maximum length (in bytes) of the secret a `Shard` is part of.
+    pub max_secret_len: usize,
+    /// Maximum length (in bytes) of the group name of a `CompartmentShard`.
+    pub max_group_len: usize,
+    /// Maximum number of `Shard`s in a `WeightedShard`.
+    pub max_weight: usize,
+    /// Maximum number of secrets packed into a `PackedShard`.
+    pub max_secrets: usize,
+    /// Maximum number of shards in a set of `ShardCommitments`.
+    pub max_commitments: usize,
+    /// Maximum number of entries in an `IssuedLog`.
+    pub max_issued: usize,
+}
+
+impl WireLimits {
+    /// Default limits used by `FromWire`.
+    pub const DEFAULT: WireLimits = WireLimits {
+        max_ys: 1 << 24,
+        max_secret_len: 1 << 24,
+        max_group_len: 1 << 12,
+        max_weight: 1 << 10,
+        max_secrets: 1 << 10,
+        max_commitments: 1 << 16,
+        max_issued: 1 << 16,
+    };
+
+    pub(crate) fn check(field: &'static str, limit: usize, length: usize) -> Result<(), WireError> {
+        match length {
+            length if length > limit => Err(WireError::LimitExceeded {
+                field,
+                limit,
+                length,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for WireLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Reasons why a serialised object could not be parsed by `FromWire`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WireError {
+    /// The input ended before the object was fully parsed.
+    UnexpectedEof,
+    /// A variable-length integer is invalid, too large for its type, or not
+    /// in its canonical (shortest) form.
+    InvalidVarint,
+    /// The object was fully parsed, but was followed by extra bytes.
+    TrailingData { length: usize },
+    /// A length declared by the object is larger than the configured limit.
+    LimitExceeded {
+        field: &'static str,
+        limit: usize,
+        length: usize,
+    },
+    /// The object was written with a version of the wire format which is not
+    /// supported.
+    UnsupportedVersion { version: u32 },
+    /// The checksum of the object does not match its contents, so it was
+    /// damaged (for instance, by a bad scan) after it was serialised.
+    Corrupted,
+    /// The object is not validly encoded.
+    Malformed(String),
+    /// A particular field of the object could not be parsed. `offset` is the
+    /// position (in bytes) of the start of the field within the input.
+    InField {
+        field: String,
+        offset: usize,
+        error: Box<WireError>,
+    },
+}
+
+impl WireError {
+    /// Returns the name of the field which could not be parsed (if known).
+    ///
+    /// Fields of nested objects are separated by `.`, such as `shards[1].y[3]`.
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            WireError::InField { field, .. } => Some(field),
+            _ => None,
+        }
+    }
+
+    /// Returns the position (in bytes) within the input of the start of the
+    /// field which could not be parsed (if known).
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            WireError::InField { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying reason the object could not be parsed, without
+    /// any information about which field it occurred in.
+    pub fn cause(&self) -> &WireError {
+        match self {
+            WireError::InField { error, .. } => error.cause(),
+            err => err,
+        }
+    }
+
+    // Record that the error occurred in the given field, at the given offset.
+    // If the error is already in a field, that field is nested in this one.
+    pub(crate) fn in_field<S: Into<String>>(self, field: S, offset: usize) -> Self {
+        let field = field.into();
+        match self {
+            WireError::InField {
+                field: inner,
+                offset: inner_offset,
+                error,
+            } => WireError::InField {
+                field: format!("{}.{}", field, inner),
+                offset: offset + inner_offset,
+                error,
+            },
+            error => WireError::InField {
+                field,
+                offset,
+                error: Box::new(error),
+            },
+        }
+    }
+
+    // Shift the offset of the error (if any) by the given number of bytes, for
+    // objects which were parsed after a prefix of the input.
+    pub(crate) fn offset_by(self, shift: usize) -> Self {
+        match self {
+            WireError::InField {
+                field,
+                offset,
+                error,
+            } => WireError::InField {
+                field,
+                offset: offset + shift,
+                error,
+            },
+            err => err,
+        }
+    }
+
+    pub(crate) fn nom<O>(res: IResult<&[u8], O>) -> Result<(&[u8], O), WireError> {
+        res.map_err(|err| match err {
+            NomErr::Incomplete(_)
+            | NomErr::Error((_, ErrorKind::Complete))
+            | NomErr::Error((_, ErrorKind::Eof)) => WireError::UnexpectedEof,
+            // nom_helpers uses TooLarge for all varint decoding errors.
+            NomErr::Error((_, ErrorKind::TooLarge)) => WireError::InvalidVarint,
+            NomErr::Error((_, kind)) | NomErr::Failure((_, kind)) => {
+                WireError::Malformed(format!("invalid encoding ({:?})", kind))
+            }
+        })
+    }
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WireError::UnexpectedEof => write!(f, "unexpected end of input"),
+            WireError::InvalidVarint => write!(f, "invalid variable-length integer"),
+            WireError::TrailingData { length } => {
+                write!(f, "{} trailing bytes left after deserialisation", length)
+            }
+            WireError::LimitExceeded {
+                field,
+                limit,
+                length,
+            } => write!(
+                f,
+                "{} length {} exceeds the limit of {}",
+                field, length, limit
+            ),
+            WireError::UnsupportedVersion { version } => {
+                write!(f, "unsupported wire format version {}", version)
+            }
+            WireError::Corrupted => write!(f, "data corrupted (checksum mismatch)"),
+            WireError::Malformed(msg) => write!(f, "{}", msg),
+            WireError::InField {
+                field,
+                offset,
+                error,
+            } => write!(f, "{} (at byte {}): {}", field, offset, error),
+        }
+    }
+}
+
+impl error::Error for WireError {}
+
+// Computes the offset of each field of an object from the start of its input,
+// so that errors can point at the offending part of the input.
+pub(crate) struct FieldOffsets<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> FieldOffsets<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Self {
+        FieldOffsets { input }
+    }
+
+    // Offset of the remaining input (which must be a suffix of the input).
+    pub(crate) fn offset(&self, remain: &[u8]) -> usize {
+        self.input.len() - remain.len()
+    }
+
+    // Returns a function which records that an error occurred in the named
+    // field, starting at the remaining input.
+    pub(crate) fn field(
+        &self,
+        field: &'static str,
+        remain: &[u8],
+    ) -> impl Fn(WireError) -> WireError + Copy {
+        let offset = self.offset(remain);
+        move |err| err.in_field(field, offset)
+    }
+}
+
+impl From<String> for WireError {
+    fn from(msg: String) -> Self {
+        WireError::Malformed(msg)
+    }
+}
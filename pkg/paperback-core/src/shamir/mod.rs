@@ -16,7 +16,60 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+mod audit;
+#[cfg(feature = "std")]
+mod commit;
+#[cfg(feature = "std")]
+mod compartment;
+pub(crate) mod encoding;
+#[cfg(feature = "std")]
+mod erasure;
 mod gf;
+#[cfg(feature = "std")]
+pub(crate) mod header;
+#[cfg(feature = "std")]
+mod limits;
+#[cfg(feature = "std")]
+mod packed;
 mod shamir;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "std")]
+mod weighted;
 
-pub use shamir::{recover_secret, Dealer, Shard};
+pub use audit::{IssuedLog, IssuedShard};
+#[cfg(feature = "std")]
+pub use commit::{verify_shard, ShardCommitments, VerifyError, COMMITMENT_LENGTH};
+#[cfg(feature = "std")]
+pub use compartment::{recover_compartmented_secret, CompartmentDealer, CompartmentShard};
+#[cfg(feature = "std")]
+pub use erasure::{
+    erasure_decode, erasure_encode, erasure_piece_len, ErasureError, MAX_ERASURE_PIECES,
+};
+pub use gf::{Gf16, Gf32, Gf64, GfField, CONSTANT_TIME};
+#[cfg(feature = "std")]
+pub use header::{detect, DocumentKind, WIRE_MAGIC, WIRE_VERSION};
+#[cfg(feature = "std")]
+pub use limits::{WireError, WireLimits};
+#[cfg(feature = "std")]
+pub use packed::{
+    recover_packed_secret, recover_packed_secrets, recover_packed_secrets_with_key, PackedDealer,
+    PackedShard,
+};
+pub use paperback_clmul::GfBackend;
+#[cfg(feature = "std")]
+pub use shamir::{
+    check_consistency, recover_secret_to, recover_secret_to_with_key, recover_secret_with_report,
+    reshard, ConsistencyReport, RecoveryReport, ShardView,
+};
+pub use shamir::{
+    recover_secret, recover_secret_corrected, recover_secret_range, recover_secret_with_key,
+    Dealer, DealerError, GenericDealer, GenericShard, MacKey, RecoverError, Scheme, Shard,
+    ShardError, ShardValue, YsLayout, CHECKSUM_LENGTH, DEALER_ID_LENGTH, MAC_KEY_LENGTH,
+};
+#[cfg(feature = "std")]
+pub use stream::{
+    recover_mapped, recover_stream, recover_stream_with_key, StreamDealer, DEFAULT_BLOCK_SIZE,
+};
+#[cfg(feature = "std")]
+pub use weighted::{recover_weighted_secret, WeightedShard};
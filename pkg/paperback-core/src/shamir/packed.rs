@@ -0,0 +1,354 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::{
+        encoding,
+        header::{self, DocumentKind},
+        limits::{FieldOffsets, WireError, WireLimits},
+        shamir::{
+            recover_secret, recover_secret_range, recover_secret_with_key, Dealer, MacKey, Shard,
+            ShardError,
+        },
+    },
+    v0::{FromWire, ToWire},
+};
+
+use std::ops::Range;
+
+use zeroize::Zeroize;
+
+/// Piece of several secrets which have been packed together and sharded as one
+/// (see `PackedDealer`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PackedShard {
+    lengths: Vec<usize>,
+    shard: Shard,
+}
+
+impl PackedShard {
+    /// Returns the identifier of the underlying `Shard`.
+    pub fn id(&self) -> String {
+        self.shard.id()
+    }
+
+    /// Returns the number of secrets packed into the shard.
+    pub fn secret_count(&self) -> usize {
+        self.lengths.len()
+    }
+
+    /// Returns the length (in bytes) of each of the packed secrets.
+    pub fn secret_lens(&self) -> &[usize] {
+        &self.lengths
+    }
+
+    /// Returns the underlying `Shard` (which stores all of the secrets
+    /// concatenated together).
+    pub fn shard(&self) -> &Shard {
+        &self.shard
+    }
+
+    // Range of the underlying secret which contains the given packed secret.
+    fn range(&self, index: usize) -> Option<Range<usize>> {
+        let len = *self.lengths.get(index)?;
+        let start = self.lengths[..index].iter().sum::<usize>();
+        Some(start..start + len)
+    }
+}
+
+impl ToWire for PackedShard {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.wire_len());
+        self.to_wire_into(&mut bytes);
+        bytes
+    }
+
+    fn wire_len(&self) -> usize {
+        header::record_len(DocumentKind::PackedShard, self.wire_body_len())
+    }
+
+    fn to_wire_into(&self, bytes: &mut Vec<u8>) {
+        header::write_record_into(
+            bytes,
+            DocumentKind::PackedShard,
+            self.wire_body_len(),
+            |bytes| self.write_wire_body(bytes),
+        )
+    }
+}
+
+impl PackedShard {
+    // Append the wire encoding (without the header) to bytes.
+    fn write_wire_body(&self, bytes: &mut Vec<u8>) {
+        // Encode the number of secrets, followed by each secret's length.
+        encoding::varint(self.lengths.len() as u64, bytes);
+        self.lengths
+            .iter()
+            .for_each(|len| encoding::varint(*len as u64, bytes));
+
+        // Encode shard.
+        self.shard.write_wire_bytes(bytes);
+    }
+
+    // Length (in bytes) of the wire encoding, without the header.
+    fn wire_body_len(&self) -> usize {
+        encoding::varint_len(self.lengths.len() as u64)
+            + self
+                .lengths
+                .iter()
+                .map(|len| encoding::varint_len(*len as u64))
+                .sum::<usize>()
+            + self.shard.wire_bytes_len()
+    }
+}
+
+impl PackedShard {
+    /// Deserialise a `PackedShard`, rejecting any shard which declares
+    /// lengths larger than those permitted by `limits`.
+    pub fn from_wire_partial_with_limits<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        header::read_record(input, DocumentKind::PackedShard, |body| {
+            Self::from_wire_body(body, limits)
+        })
+    }
+
+    // Parse the layout shared by every version, without the header and framing.
+    fn from_wire_body<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        use crate::nom_helpers;
+        use nom::combinator::complete;
+
+        let fields = FieldOffsets::new(input);
+
+        let at = fields.field("secret count", input);
+        let (mut input, count) = WireError::nom(complete(nom_helpers::usize)(input)).map_err(at)?;
+        WireLimits::check("secrets", limits.max_secrets, count).map_err(at)?;
+
+        let mut lengths = Vec::with_capacity(count.min(input.len()));
+        for i in 0..count {
+            let at = |err: WireError| err.in_field(format!("lengths[{}]", i), fields.offset(input));
+            let (remain, len) = WireError::nom(complete(nom_helpers::usize)(input)).map_err(at)?;
+            WireLimits::check("secret", limits.max_secret_len, len).map_err(at)?;
+            lengths.push(len);
+            input = remain;
+        }
+
+        let at = fields.field("shard", input);
+        let (shard, remain) = Shard::from_wire_body(input, limits).map_err(at)?;
+        if lengths.iter().sum::<usize>() != shard.secret_len() {
+            return Err(at(String::from(
+                "packed secret lengths do not match the shard",
+            )
+            .into()));
+        }
+
+        Ok((PackedShard { lengths, shard }, remain))
+    }
+}
+
+impl FromWire for PackedShard {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        Self::from_wire_partial_with_limits(input, &WireLimits::DEFAULT)
+    }
+}
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for PackedShard {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let shard = Shard::arbitrary(g);
+        // Split the shard's secret into (up to) four secrets.
+        let mut remaining = shard.secret_len();
+        let mut lengths = (0..g.next_u32() % 4)
+            .map(|_| {
+                let len = g.next_u32() as usize % (remaining + 1);
+                remaining -= len;
+                len
+            })
+            .collect::<Vec<_>>();
+        lengths.push(remaining);
+        Self { lengths, shard }
+    }
+}
+
+/// Factory to share several independent secrets using a single set of shards.
+///
+/// The secrets are concatenated and sharded with one `Dealer`, and the length
+/// of each secret is recorded in every `PackedShard`. This allows (for
+/// instance) several passwords to be backed up with one set of physical
+/// shards, while still allowing each secret to be recovered individually with
+/// `recover_packed_secret`.
+#[derive(Clone, Debug)]
+pub struct PackedDealer {
+    lengths: Vec<usize>,
+    dealer: Dealer,
+}
+
+impl PackedDealer {
+    /// Construct a new `PackedDealer` to shard the `secrets`, requiring at
+    /// least `threshold` shards to reconstruct them.
+    ///
+    /// Panics if no secrets are given.
+    pub fn new<B: AsRef<[u8]>>(threshold: u32, secrets: &[B]) -> Self {
+        assert!(!secrets.is_empty(), "must have at least one secret");
+        let lengths = secrets.iter().map(|s| s.as_ref().len()).collect();
+        let mut packed = secrets
+            .iter()
+            .flat_map(|s| s.as_ref().iter().copied())
+            .collect::<Vec<_>>();
+        let dealer = Dealer::new(threshold, &packed);
+        packed.zeroize();
+
+        PackedDealer { lengths, dealer }
+    }
+
+    /// Attach a MAC to every shard created by this `PackedDealer` (see
+    /// `Dealer::authenticated`).
+    ///
+    /// Note that MACs are only verified by `recover_packed_secrets_with_key`.
+    pub fn authenticated(mut self, key: &MacKey) -> Self {
+        self.dealer = self.dealer.authenticated(key);
+        self
+    }
+
+    /// Returns the number of secrets packed by this `PackedDealer`.
+    pub fn secret_count(&self) -> usize {
+        self.lengths.len()
+    }
+
+    /// Generate a new `PackedShard` for the secrets.
+    pub fn next_shard(&mut self) -> PackedShard {
+        PackedShard {
+            lengths: self.lengths.clone(),
+            shard: self.dealer.next_shard(),
+        }
+    }
+}
+
+// Check that the packed shards agree about the packed secrets, returning the
+// underlying shards.
+fn unpack_shards(shards: &[PackedShard]) -> Result<Vec<Shard>, ShardError> {
+    let first = shards.first().ok_or(ShardError::NoShards)?;
+    if shards.iter().any(|s| s.lengths != first.lengths) {
+        return Err(ShardError::InconsistentPacking);
+    }
+    Ok(shards.iter().map(|s| s.shard.clone()).collect())
+}
+
+/// Reconstruct a single packed secret (given by its index in the list of
+/// secrets passed to `PackedDealer::new`) from a set of `PackedShard`s.
+///
+/// Only the part of the packed secrets containing the requested secret is
+/// recovered (see `recover_secret_range`), so the other secrets are never
+/// held in memory. As a result, the MACs of authenticated shards are not
+/// verified -- use `recover_packed_secrets_with_key` if that is required.
+pub fn recover_packed_secret<S: AsRef<[PackedShard]>>(
+    shards: S,
+    index: usize,
+) -> Result<Vec<u8>, ShardError> {
+    let shards = shards.as_ref();
+    let inner = unpack_shards(shards)?;
+    let range = shards[0].range(index).ok_or(ShardError::NoSuchSecret {
+        index,
+        count: shards[0].lengths.len(),
+    })?;
+    recover_secret_range(inner, range)
+}
+
+/// Reconstruct every packed secret from a set of `PackedShard`s, in the same
+/// order they were passed to `PackedDealer::new`.
+///
+/// See `recover_secret` for more details.
+pub fn recover_packed_secrets<S: AsRef<[PackedShard]>>(
+    shards: S,
+) -> Result<Vec<Vec<u8>>, ShardError> {
+    unpack_secrets(shards.as_ref(), None)
+}
+
+/// Reconstruct every packed secret from a set of authenticated
+/// `PackedShard`s, verifying the MAC of every shard with `key`.
+///
+/// See `recover_secret_with_key` for more details.
+pub fn recover_packed_secrets_with_key<S: AsRef<[PackedShard]>>(
+    shards: S,
+    key: &MacKey,
+) -> Result<Vec<Vec<u8>>, ShardError> {
+    unpack_secrets(shards.as_ref(), Some(key))
+}
+
+fn unpack_secrets(
+    shards: &[PackedShard],
+    key: Option<&MacKey>,
+) -> Result<Vec<Vec<u8>>, ShardError> {
+    let inner = unpack_shards(shards)?;
+    let mut packed = match key {
+        Some(key) => recover_secret_with_key(inner, key)?,
+        None => recover_secret(inner)?,
+    };
+    let secrets = (0..shards[0].lengths.len())
+        .filter_map(|i| shards[0].range(i))
+        .map(|range| packed.get(range).map(<[u8]>::to_vec))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(ShardError::InconsistentSecretLength);
+    packed.zeroize();
+    secrets
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shamir::MAC_KEY_LENGTH;
+
+    use quickcheck::TestResult;
+
+    #[quickcheck]
+    fn packed_shard_bytes_roundtrip(shard: PackedShard) {
+        let shard2 = PackedShard::from_wire(shard.to_wire()).unwrap();
+        assert_eq!(shard, shard2);
+    }
+
+    #[quickcheck]
+    fn recover_packed_secret_success(n: u32, secrets: Vec<Vec<u8>>) -> TestResult {
+        if !(1..=8).contains(&n) || secrets.is_empty() {
+            return TestResult::discard();
+        }
+        let secrets = secrets.into_iter().take(8).collect::<Vec<_>>();
+
+        let key = [0x5a; MAC_KEY_LENGTH];
+        let mut dealer = PackedDealer::new(n, &secrets).authenticated(&key);
+        let shards = (0..n).map(|_| dealer.next_shard()).collect::<Vec<_>>();
+
+        TestResult::from_bool(
+            dealer.secret_count() == secrets.len()
+                && recover_packed_secrets_with_key(&shards, &key) == Ok(secrets.clone())
+                && recover_packed_secrets(&shards) == Ok(secrets.clone())
+                && secrets
+                    .iter()
+                    .enumerate()
+                    .all(|(i, secret)| recover_packed_secret(&shards, i) == Ok(secret.clone()))
+                && recover_packed_secret(&shards, secrets.len())
+                    == Err(ShardError::NoSuchSecret {
+                        index: secrets.len(),
+                        count: secrets.len(),
+                    }),
+        )
+    }
+}
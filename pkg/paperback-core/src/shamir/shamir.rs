@@ -16,37 +16,231 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::shamir::{
+    audit::{IssuedLog, IssuedShard},
+    encoding,
+    gf::{Gf32, GfElem, GfElemPrimitive, GfField, GfMultiplier, GfPolynomial, GfPolynomials},
+};
+#[cfg(feature = "std")]
 use crate::{
-    shamir::gf::{GfElem, GfElemPrimitive, GfPolynomial},
+    shamir::{
+        header::{self, DocumentKind},
+        limits::{FieldOffsets, WireError, WireLimits},
+    },
     v0::{FromWire, ToWire},
 };
 
-use std::mem;
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::{fmt, ops::Range};
+#[cfg(feature = "std")]
+use std::{
+    error,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
 
+#[cfg(feature = "std")]
+use itertools::Itertools;
+#[cfg(feature = "std")]
 use rand::rngs::OsRng;
-use unsigned_varint::encode;
+use rand::{CryptoRng, RngCore};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use zeroize::Zeroize;
+
+/// Length (in bytes) of the MAC attached to authenticated `Shard`s.
+pub const MAC_LENGTH: usize = 32;
+
+/// Length (in bytes) of the key used to compute the MACs of authenticated
+/// `Shard`s.
+pub const MAC_KEY_LENGTH: usize = 32;
+
+/// Key used to compute (and verify) the MACs of authenticated `Shard`s. It is
+/// supplied by the caller, and must not be derived from the secret itself.
+pub type MacKey = [u8; MAC_KEY_LENGTH];
+
+/// Length (in bytes) of the random identifier shared by every `Shard` from
+/// the same `Dealer`.
+pub const DEALER_ID_LENGTH: usize = 8;
+
+/// Length (in characters) of `Shard::checksum`.
+pub const CHECKSUM_LENGTH: usize = 8;
+
+// Number of bytes of hash in each checksum (each z-base-32 character encodes
+// five bits).
+const CHECKSUM_BYTES: usize = CHECKSUM_LENGTH * 5 / 8;
+
+// BLAKE2b personalisation used for shard checksums.
+const CHECKSUM_PERSONAL: &[u8] = b"paperback-chksum";
+
+/// Scheme used by a `Dealer` to shard a secret.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Scheme {
+    /// [Shamir Secret Sharing][sss], where fewer than `threshold` shards
+    /// reveal *nothing* about the secret. Each shard is as large as the
+    /// secret.
+    ///
+    /// [sss]: https://en.wikipedia.org/wiki/Shamir%27s_Secret_Sharing
+    #[default]
+    Shamir,
+    /// Ramp (information dispersal) sharing, where every coefficient of each
+    /// polynomial is part of the secret. Each shard is only `1/threshold` of
+    /// the size of the secret, but fewer than `threshold` shards *do* reveal
+    /// information about the secret, so this should only be used where the
+    /// secrecy of every byte is not required.
+    Ramp,
+}
+
+impl Scheme {
+    // Number of chunks of the secret stored in each polynomial.
+    fn chunks_per_poly(self, threshold: u32) -> usize {
+        match self {
+            Scheme::Shamir => 1,
+            Scheme::Ramp => threshold as usize,
+        }
+    }
+
+    // Maximum length (in bytes) of a secret stored in ys_len y-values.
+    fn max_secret_len(self, threshold: u32, ys_len: usize, chunk_size: usize) -> usize {
+        ys_len
+            .saturating_mul(self.chunks_per_poly(threshold))
+            .saturating_mul(chunk_size)
+    }
+
+    fn wire_id(self) -> u32 {
+        match self {
+            Scheme::Shamir => 0,
+            Scheme::Ramp => 1,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn from_wire_id(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(Scheme::Shamir),
+            1 => Some(Scheme::Ramp),
+            _ => None,
+        }
+    }
+}
+
+/// Layout of the y-values of a serialised `Shard`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum YsLayout {
+    /// Each y-value is a varint. This is the default, and is the most compact
+    /// layout for small fields.
+    #[default]
+    Varint,
+    /// Each y-value takes exactly as many (little-endian) bytes as an element
+    /// of the field, so any y-value can be read without parsing the ones
+    /// before it (see `ShardView`).
+    FixedWidth,
+}
+
+#[cfg(feature = "std")]
+impl YsLayout {
+    fn flags(self) -> u64 {
+        match self {
+            YsLayout::Varint => 0,
+            YsLayout::FixedWidth => header::FLAG_FIXED_WIDTH_YS,
+        }
+    }
+
+    fn from_flags(flags: u64) -> Self {
+        match flags & header::FLAG_FIXED_WIDTH_YS {
+            0 => YsLayout::Varint,
+            _ => YsLayout::FixedWidth,
+        }
+    }
+}
+
+/// Piece of a secret which has been sharded with [Shamir Secret Sharing][sss]
+/// over `GF(2^32)`.
+///
+/// See [`GenericShard`] for shards over other field sizes.
+///
+/// [sss]: https://en.wikipedia.org/wiki/Shamir%27s_Secret_Sharing
+pub type Shard = GenericShard<Gf32>;
 
-/// Piece of a secret which has been sharded with [Shamir Secret Sharing][sss].
+/// Piece of a secret which has been sharded with [Shamir Secret Sharing][sss]
+/// over the field `F`.
 ///
 /// [sss]: https://en.wikipedia.org/wiki/Shamir%27s_Secret_Sharing
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Shard {
-    x: GfElem,
-    ys: Vec<GfElem>,
+pub struct GenericShard<F: GfField> {
+    x: GfElem<F>,
+    ys: Vec<GfElem<F>>,
     secret_len: usize,
-    threshold: GfElemPrimitive,
+    chunk_size: usize,
+    threshold: u32,
+    scheme: Scheme,
+    dealer_id: [u8; DEALER_ID_LENGTH],
+    mac: Option<[u8; MAC_LENGTH]>,
+}
+
+/// An opaque field element stored in a `Shard` (either its x-coordinate or
+/// one of its y-values).
+///
+/// The value can only be inspected as its little-endian byte representation,
+/// which has one byte for every 8 bits of the field.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ShardValue(Vec<u8>);
+
+impl ShardValue {
+    /// Returns the little-endian byte representation of the value.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Zeroize for ShardValue {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for ShardValue {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
 }
 
 impl Shard {
-    pub const ID_LENGTH: usize = 8;
+    /// Length of the identifier of a `Shard` in `GF(2^32)`.
+    pub const ID_LENGTH: usize = 9;
+}
 
+impl<F: GfField> GenericShard<F> {
     /// Returns the *unique* identifier for a given `Shard`.
     ///
     /// If two shards have the same identifier, they cannot be used together for
-    /// secret recovery.
+    /// secret recovery. The final character of the identifier is a check
+    /// character, so that typos can be detected with `Shard::validate_id`.
     pub fn id(&self) -> String {
-        let id = zbase32::encode_full_bytes(&self.x.to_bytes());
-        format!("h{}", id)
+        let id = encoding::zbase32(&self.x.to_bytes());
+        let check = encoding::zbase32_check(&id).expect("z-base-32 must only use its alphabet");
+        format!("h{}{}", id, check)
+    }
+
+    /// Returns whether `id` is a well-formed `Shard` identifier (in the field
+    /// `F`) with a correct check character.
+    ///
+    /// This allows a hand-copied identifier to be checked for typos when it is
+    /// entered, without needing the shard itself. Case and visually confusable
+    /// characters (such as `0` for `o`) are normalised before checking.
+    pub fn validate_id(id: &str) -> bool {
+        let len = (F::BYTES * 8).div_ceil(5) + 1;
+        match encoding::zbase32_normalize(id).strip_prefix('h') {
+            Some(id) => id.len() == len && encoding::zbase32_verify(id),
+            None => false,
+        }
     }
 
     /// Returns the number of *unique* sister `Shard`s required to recover the
@@ -54,303 +248,2815 @@ impl Shard {
     pub fn threshold(&self) -> u32 {
         self.threshold
     }
-}
 
-impl ToWire for Shard {
-    fn to_wire(&self) -> Vec<u8> {
+    /// Returns the sharing scheme used to create the `Shard`.
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    /// Returns the identifier of the `Dealer` which created the `Shard`.
+    ///
+    /// Every shard of the same secret (from the same `Dealer`) has the same
+    /// dealer identifier, so it can be used to tell which backup a shard
+    /// belongs to. Shards with different dealer identifiers cannot be used
+    /// together for secret recovery.
+    pub fn dealer_id(&self) -> String {
+        encoding::zbase32(&self.dealer_id)
+    }
+
+    #[cfg(feature = "std")]
+    pub(super) fn dealer_id_bytes(&self) -> [u8; DEALER_ID_LENGTH] {
+        self.dealer_id
+    }
+
+    #[cfg(feature = "std")]
+    pub(super) fn x_inner(&self) -> GfElemPrimitive {
+        self.x.inner()
+    }
+
+    /// Returns whether the `Shard` has a MAC attached (see
+    /// `Dealer::authenticated`).
+    pub fn is_authenticated(&self) -> bool {
+        self.mac.is_some()
+    }
+
+    /// Returns a short checksum of the `Shard`, suitable for reading aloud.
+    ///
+    /// The checksum covers the entire wire encoding of the shard, so a
+    /// custodian can read it out to confirm that their copy of the shard is
+    /// intact without having to transmit the shard itself. It is
+    /// `CHECKSUM_LENGTH` characters of z-base-32.
+    pub fn checksum(&self) -> String {
+        let hash = blake2b_simd::Params::new()
+            .hash_length(CHECKSUM_BYTES)
+            .personal(CHECKSUM_PERSONAL)
+            .hash(&self.wire_bytes());
+        encoding::zbase32(hash.as_bytes())
+    }
+
+    /// Returns the x-coordinate of the `Shard` (which is also used for its
+    /// identifier).
+    pub fn x(&self) -> ShardValue {
+        ShardValue(self.x.to_bytes())
+    }
+
+    /// Returns the number of y-values stored in the `Shard` (one for each
+    /// chunk of the secret).
+    pub fn y_count(&self) -> usize {
+        self.ys.len()
+    }
+
+    /// Returns the y-values stored in the `Shard`.
+    pub fn ys(&self) -> impl Iterator<Item = ShardValue> + '_ {
+        self.ys.iter().map(|y| ShardValue(y.to_bytes()))
+    }
+
+    /// Returns the length (in bytes) of the secret stored in the `Shard`.
+    pub fn secret_len(&self) -> usize {
+        self.secret_len
+    }
+
+    /// Returns the number of bytes of the secret stored in each y-value.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    // Wire encoding of everything covered by the MAC (that is, everything
+    // except for the MAC itself).
+    // The MAC always covers the varint layout, so it is the same whichever
+    // layout the shard is serialised with.
+    fn authenticated_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![];
+        self.write_authenticated_bytes(YsLayout::Varint, &mut bytes);
+        bytes
+    }
 
+    fn write_authenticated_bytes(&self, layout: YsLayout, bytes: &mut Vec<u8>) {
         // Encode x-value.
-        encode::u32(self.x.inner(), &mut encode::u32_buffer())
-            .iter()
-            .for_each(|b| bytes.push(*b));
+        encoding::varint(self.x.inner(), bytes);
 
         // Encode y-values (length-prefixed).
-        encode::usize(self.ys.len(), &mut encode::usize_buffer())
-            .iter()
-            .copied()
-            .chain(
-                self.ys
-                    .iter()
-                    .flat_map(|y| encode::u32(y.inner(), &mut encode::u32_buffer()).to_owned()),
-            )
-            .for_each(|b| bytes.push(b));
+        encoding::varint(self.ys.len() as u64, bytes);
+        match layout {
+            YsLayout::Varint => self
+                .ys
+                .iter()
+                .for_each(|y| encoding::varint(y.inner(), bytes)),
+            YsLayout::FixedWidth => self
+                .ys
+                .iter()
+                .for_each(|y| bytes.extend_from_slice(&y.inner().to_le_bytes()[..F::BYTES])),
+        }
 
         // Encode threshold.
-        encode::u32(self.threshold, &mut encode::u32_buffer())
-            .iter()
-            .for_each(|b| bytes.push(*b));
+        encoding::varint(self.threshold.into(), bytes);
 
         // Encode secret length.
-        encode::usize(self.secret_len, &mut encode::usize_buffer())
-            .iter()
-            .for_each(|b| bytes.push(*b));
+        encoding::varint(self.secret_len as u64, bytes);
+
+        // Encode chunk size.
+        encoding::varint(self.chunk_size as u64, bytes);
+
+        // Encode field size.
+        encoding::varint(F::BITS.into(), bytes);
+
+        // Encode sharing scheme.
+        encoding::varint(self.scheme.wire_id().into(), bytes);
+
+        // Encode dealer identifier (fixed-length).
+        bytes.extend_from_slice(&self.dealer_id);
+    }
 
+    // Complete wire encoding of the shard, without the header (see ToWire).
+    pub(crate) fn wire_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.wire_bytes_len());
+        self.write_wire_bytes(&mut bytes);
         bytes
     }
-}
 
-impl FromWire for Shard {
-    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), String> {
-        use crate::nom_helpers;
-        use nom::{combinator::complete, multi::many_m_n, IResult};
+    // Append the wire encoding of the shard (without the header) to bytes.
+    pub(crate) fn write_wire_bytes(&self, bytes: &mut Vec<u8>) {
+        self.write_wire_bytes_with_layout(YsLayout::Varint, bytes)
+    }
 
-        fn parse(input: &[u8]) -> IResult<&[u8], Shard> {
-            let (input, x) = nom_helpers::u32(input)?;
-            let x = GfElem::from_inner(x);
+    // Append the legacy wire encoding of the shard to bytes (see
+    // from_legacy_wire_body). Everything after the secret length is dropped, so
+    // this is only lossless for shards which were parsed from that layout.
+    #[cfg(feature = "std")]
+    pub(crate) fn write_legacy_wire_bytes(&self, bytes: &mut Vec<u8>) {
+        encoding::varint(self.x.inner(), bytes);
+        encoding::varint(self.ys.len() as u64, bytes);
+        self.ys
+            .iter()
+            .for_each(|y| encoding::varint(y.inner(), bytes));
+        encoding::varint(self.threshold.into(), bytes);
+        encoding::varint(self.secret_len as u64, bytes);
+    }
 
-            let (input, ys_length) = nom_helpers::usize(input)?;
-            let (input, ys) = many_m_n(ys_length, ys_length, nom_helpers::u32)(input)?;
-            let ys = ys
-                .iter()
-                .copied()
-                .map(GfElem::from_inner)
-                .collect::<Vec<_>>();
+    // Append the wire encoding of the shard (without the header) to bytes, with
+    // the y-values in the given layout.
+    fn write_wire_bytes_with_layout(&self, layout: YsLayout, bytes: &mut Vec<u8>) {
+        self.write_authenticated_bytes(layout, bytes);
 
-            let (input, threshold) = nom_helpers::u32(input)?;
-            let (input, secret_len) = nom_helpers::usize(input)?;
-
-            Ok((
-                input,
-                Shard {
-                    x,
-                    ys,
-                    threshold,
-                    secret_len,
-                },
-            ))
-        }
-        let parse = complete(parse);
+        // Encode MAC (length-prefixed, zero length if there is no MAC).
+        let mac = self.mac.as_ref().map(|mac| &mac[..]).unwrap_or_default();
+        encoding::varint(mac.len() as u64, bytes);
+        bytes.extend_from_slice(mac);
+    }
 
-        let (remain, shard) = parse(input).map_err(|err| format!("{:?}", err))?;
+    // Length (in bytes) of wire_bytes.
+    pub(crate) fn wire_bytes_len(&self) -> usize {
+        self.wire_bytes_len_with_layout(YsLayout::Varint)
+    }
 
-        Ok((shard, remain))
+    // Length (in bytes) of the wire encoding with the y-values in the given
+    // layout.
+    fn wire_bytes_len_with_layout(&self, layout: YsLayout) -> usize {
+        let mac_len = self.mac.as_ref().map_or(0, |mac| mac.len());
+        let ys_len = match layout {
+            YsLayout::Varint => self
+                .ys
+                .iter()
+                .map(|y| encoding::varint_len(y.inner()))
+                .sum(),
+            YsLayout::FixedWidth => self.ys.len() * F::BYTES,
+        };
+        [
+            self.x.inner(),
+            self.ys.len() as u64,
+            self.threshold.into(),
+            self.secret_len as u64,
+            self.chunk_size as u64,
+            F::BITS.into(),
+            self.scheme.wire_id().into(),
+            mac_len as u64,
+        ]
+        .iter()
+        .copied()
+        .map(encoding::varint_len)
+        .sum::<usize>()
+            + ys_len
+            + self.dealer_id.len()
+            + mac_len
     }
-}
 
-#[cfg(test)]
-impl quickcheck::Arbitrary for Shard {
-    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
-        Self {
-            x: GfElem::new_rand(g),
-            ys: (0..g.size()).map(|_| GfElem::new_rand(g)).collect(),
-            secret_len: g.next_u32() as usize,
-            threshold: g.next_u32(),
+    fn compute_mac(&self, key: &MacKey) -> blake2b_simd::Hash {
+        blake2b_simd::Params::new()
+            .hash_length(MAC_LENGTH)
+            .key(key)
+            .hash(&self.authenticated_bytes())
+    }
+
+    /// Verify the MAC of the `Shard` using `key`.
+    ///
+    /// Returns `ShardError::MissingMac` if the shard is not authenticated, and
+    /// `ShardError::InvalidMac` if the MAC does not match.
+    pub fn verify_mac(&self, key: &MacKey) -> Result<(), ShardError> {
+        match &self.mac {
+            None => Err(ShardError::MissingMac { id: self.id() }),
+            // blake2b_simd::Hash comparisons are constant-time.
+            Some(mac) if self.compute_mac(key) != mac[..] => {
+                Err(ShardError::InvalidMac { id: self.id() })
+            }
+            Some(_) => Ok(()),
         }
     }
 }
 
-/// Factory to share a secret using [Shamir Secret Sharing][sss].
-///
-/// [sss]: https://en.wikipedia.org/wiki/Shamir%27s_Secret_Sharing
-#[derive(Clone, Debug)]
-pub struct Dealer {
-    polys: Vec<GfPolynomial>,
-    secret_len: usize,
-    threshold: GfElemPrimitive,
+impl<F: GfField> Zeroize for GenericShard<F> {
+    fn zeroize(&mut self) {
+        // The y-values are derived from the secret, and the MAC is a hash of
+        // them (under a key which need not be secret).
+        self.ys.zeroize();
+        self.mac.zeroize();
+    }
 }
 
-impl Dealer {
-    /// Returns the number of *unique* `Shard`s generated by this `Dealer`
-    /// required to recover the stored secret.
-    #[allow(dead_code)]
-    pub fn threshold(&self) -> u32 {
-        self.threshold
+impl<F: GfField> Drop for GenericShard<F> {
+    fn drop(&mut self) {
+        self.zeroize();
     }
+}
 
-    /// Construct a new `Dealer` to shard the `secret`, requiring at least
-    /// `threshold` shards to reconstruct the secret.
-    pub fn new<B: AsRef<[u8]>>(threshold: u32, secret: B) -> Self {
-        assert!(threshold > 0, "must at least have a threshold of one");
-        let k = threshold - 1;
-        let secret = secret.as_ref();
-        let polys = secret
-            // Generate &[u32] from &[u8], by chunking into sets of four.
-            .chunks(mem::size_of::<GfElemPrimitive>())
-            .map(GfElem::from_bytes)
-            // Generate a random polynomial with the value as the constant.
-            .map(|x0| {
-                let mut poly = GfPolynomial::new_rand(k, &mut OsRng);
-                *poly.constant_mut() = x0;
-                poly
-            })
-            .collect::<Vec<_>>();
-        Dealer {
-            polys,
-            threshold,
-            secret_len: secret.len(),
-        }
+#[cfg(feature = "std")]
+impl<F: GfField> ToWire for GenericShard<F> {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.wire_len());
+        self.to_wire_into(&mut bytes);
+        bytes
     }
 
-    /// Get the secret value stored by the `Dealer`.
-    pub fn secret(&self) -> Vec<u8> {
-        self.polys
-            .iter()
-            .map(GfPolynomial::constant)
-            .flat_map(|x| x.to_bytes())
-            .take(self.secret_len)
-            .collect::<Vec<_>>()
+    fn wire_len(&self) -> usize {
+        header::record_len(DocumentKind::Shard, self.wire_bytes_len())
     }
 
-    /// Generate a new `Shard` for the secret.
-    ///
-    /// NOTE: The `x` value is calculated randomly, which means that there is a
-    ///       small chance that two separate calls to `Dealer::shard` will
-    ///       generate the same `Shard`. It is up to the caller to be sure that
-    ///       they have enough *unique* shards to reconstruct the secret.
-    // TODO: I'm not convinced the chances of collision are low enough...
-    pub fn next_shard(&self) -> Shard {
-        let mut x = GfElem::ZERO;
-        while x == GfElem::ZERO {
-            x = GfElem::new_rand(&mut OsRng);
-        }
-        let ys = self
-            .polys
-            .iter()
-            .map(|poly| {
-                let y = poly.evaluate(x);
-                assert!(self.threshold == 1 || y != poly.constant());
-                y
-            })
-            .collect::<Vec<_>>();
-        Shard {
-            x,
-            ys,
-            threshold: self.threshold,
-            secret_len: self.secret_len,
-        }
+    fn to_wire_into(&self, bytes: &mut Vec<u8>) {
+        header::write_record_into(bytes, DocumentKind::Shard, self.wire_bytes_len(), |bytes| {
+            self.write_wire_bytes(bytes)
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: GfField> GenericShard<F> {
+    /// Serialise the shard like `ToWire::to_wire`, but with its y-values in
+    /// the given layout. The layout is recorded in the header, and `FromWire`
+    /// accepts shards in any layout.
+    pub fn to_wire_with_layout(&self, layout: YsLayout) -> Vec<u8> {
+        let body_len = self.wire_bytes_len_with_layout(layout);
+        let mut bytes = Vec::with_capacity(header::flagged_record_len(
+            DocumentKind::Shard,
+            layout.flags(),
+            body_len,
+        ));
+        header::write_flagged_record_into(
+            &mut bytes,
+            DocumentKind::Shard,
+            layout.flags(),
+            body_len,
+            |bytes| self.write_wire_bytes_with_layout(layout, bytes),
+        );
+        bytes
     }
 
-    /// Reconstruct an entire `Dealer` from a *unique* set of `Shard`s.
+    /// Deserialise a `Shard`, rejecting any shard which declares lengths
+    /// larger than those permitted by `limits`.
     ///
-    /// The caller must pass exactly the correct number of shards.
+    /// Shards without a header are decoded with the original layout of
+    /// paperback shards, which is always in `GF(2^32)`.
     ///
-    /// This operation is significantly slower than `recover_secret`, so it
-    /// should only be used if it is necessary to construct additional shards
-    /// with `Dealer::next_shard`.
-    pub fn recover<S: AsRef<[Shard]>>(shards: S) -> Self {
-        // TODO: Add -> Result<Self, _>.
-        let shards = shards.as_ref();
-        assert!(shards.len() > 0, "must be provided at least one shard");
+    /// `FromWire` uses `WireLimits::DEFAULT`.
+    pub fn from_wire_partial_with_limits<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        header::read_flagged_record_or_legacy(
+            input,
+            DocumentKind::Shard,
+            header::FLAG_FIXED_WIDTH_YS,
+            |body| Self::from_legacy_wire_body(body, limits),
+            |body, flags| {
+                let (mut shard, ys, remain) =
+                    Self::parse_wire_body(body, limits, YsLayout::from_flags(flags))?;
+                if !ys.is_empty() {
+                    shard.ys = ys.chunks(F::BYTES).map(GfElem::from_bytes).collect();
+                }
+                Ok((shard, remain))
+            },
+        )
+    }
+
+    // Parse the layout shared by every version, without the header and framing.
+    pub(crate) fn from_wire_body<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        Self::parse_wire_body(input, limits, YsLayout::Varint)
+            .map(|(shard, _, remain)| (shard, remain))
+    }
+
+    // Parse the wire encoding of a shard with the y-values in the given layout.
+    // Fixed-width y-values are not decoded, and are instead returned as a
+    // slice of the input (leaving the y-values of the shard empty).
+    fn parse_wire_body<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+        layout: YsLayout,
+    ) -> Result<(Self, &'a [u8], &'a [u8]), WireError> {
+        use crate::nom_helpers;
+        use nom::{bytes::complete::take, combinator::complete, multi::length_data};
+
+        let fields = FieldOffsets::new(input);
+        let (input, prefix) = Self::parse_wire_prefix(&fields, input, limits, layout)?;
 
-        let threshold = shards[0].threshold;
-        let polys_len = shards[0].ys.len();
-        let secret_len = shards[0].secret_len;
+        let at = fields.field("chunk_size", input);
+        let (input, chunk_size) =
+            WireError::nom(complete(nom_helpers::usize)(input)).map_err(at)?;
+        if chunk_size == 0 || chunk_size > F::BYTES {
+            return Err(WireError::from(format!(
+                "shard chunk size must be between 1 and {} not '{}'",
+                F::BYTES,
+                chunk_size
+            ))
+            .in_field("chunk_size", fields.offset(input)));
+        }
 
-        // TODO: Implement this consistency checking more nicely.
-        for shard in shards {
-            assert!(shard.threshold == threshold, "shards must be consistent");
-            assert!(shard.ys.len() == polys_len, "shards must be consistent");
-            assert!(shard.secret_len == secret_len, "shards must be consistent");
+        let at = fields.field("field_bits", input);
+        let (input, field_bits) = WireError::nom(complete(nom_helpers::u32)(input)).map_err(at)?;
+        if field_bits != F::BITS {
+            return Err(WireError::from(format!(
+                "shard is in GF(2^{}) but expected GF(2^{})",
+                field_bits,
+                F::BITS
+            ))
+            .in_field("field_bits", fields.offset(input)));
         }
 
-        assert!(
-            shards.len() == threshold as usize,
-            "must have exactly {} shards",
-            threshold
-        );
+        let at = fields.field("scheme", input);
+        let (input, scheme) = WireError::nom(complete(nom_helpers::u32)(input)).map_err(at)?;
+        let scheme = Scheme::from_wire_id(scheme)
+            .ok_or_else(|| WireError::from(format!("unknown shard sharing scheme '{}'", scheme)))
+            .map_err(at)?;
+        prefix.check_secret_len(chunk_size, scheme)?;
+
+        let at = fields.field("dealer_id", input);
+        let (input, id) = WireError::nom(take(DEALER_ID_LENGTH)(input)).map_err(at)?;
+        let mut dealer_id = [0u8; DEALER_ID_LENGTH];
+        dealer_id.copy_from_slice(id);
+
+        let at = fields.field("mac", input);
+        let (remain, mac) =
+            WireError::nom(complete(length_data(nom_helpers::usize))(input)).map_err(at)?;
+        let mac = match mac.len() {
+            0 => None,
+            MAC_LENGTH => {
+                let mut buf = [0u8; MAC_LENGTH];
+                buf.copy_from_slice(mac);
+                Some(buf)
+            }
+            len => {
+                return Err(WireError::from(format!(
+                    "shard mac must be {} bytes long not '{}'",
+                    MAC_LENGTH, len
+                ))
+                .in_field("mac", fields.offset(input)))
+            }
+        };
+
+        let shard = GenericShard {
+            x: prefix.x,
+            ys: prefix.ys,
+            threshold: prefix.threshold,
+            secret_len: prefix.secret_len,
+            chunk_size,
+            scheme,
+            dealer_id,
+            mac,
+        };
 
-        let polys = (0..polys_len)
-            .map(|i| {
-                let xs = shards.iter().map(|s| s.x);
-                let ys = shards.iter().map(|s| s.ys[i]);
+        Ok((shard, prefix.fixed_ys, remain))
+    }
+
+    // Parse the fields at the start of every layout of a shard (everything up to
+    // and including the secret length).
+    fn parse_wire_prefix<'a>(
+        fields: &FieldOffsets<'_>,
+        input: &'a [u8],
+        limits: &WireLimits,
+        layout: YsLayout,
+    ) -> Result<(&'a [u8], WirePrefix<'a, F>), WireError> {
+        use crate::nom_helpers;
+        use nom::{bytes::complete::take, combinator::complete};
 
-                let points = xs.zip(ys).collect::<Vec<_>>();
-                GfPolynomial::lagrange(threshold - 1, points.as_slice())
+        let elem = |v| {
+            GfElem::from_inner(v).ok_or_else(|| {
+                WireError::from(format!("shard value {} is not in GF(2^{})", v, F::BITS))
             })
-            .collect::<Vec<_>>();
+        };
 
-        Self {
-            polys,
+        let at = fields.field("x", input);
+        let (input, x) = WireError::nom(complete(nom_helpers::u64)(input)).map_err(at)?;
+        let x = elem(x).map_err(at)?;
+
+        // Only allocate as much as could possibly be in the input, even if the
+        // declared length is within the limits.
+        let at = fields.field("ys length", input);
+        let (mut input, ys_length) =
+            WireError::nom(complete(nom_helpers::usize)(input)).map_err(at)?;
+        WireLimits::check("ys", limits.max_ys, ys_length).map_err(at)?;
+        let (mut ys, mut fixed_ys) = (vec![], &input[..0]);
+        match layout {
+            YsLayout::Varint => {
+                ys.reserve(ys_length.min(input.len()));
+                for i in 0..ys_length {
+                    let at =
+                        |err: WireError| err.in_field(format!("y[{}]", i), fields.offset(input));
+                    let (remain, y) =
+                        WireError::nom(complete(nom_helpers::u64)(input)).map_err(at)?;
+                    ys.push(elem(y).map_err(at)?);
+                    input = remain;
+                }
+            }
+            YsLayout::FixedWidth => {
+                let at = fields.field("ys", input);
+                let length = ys_length
+                    .checked_mul(F::BYTES)
+                    .ok_or_else(|| WireError::from(format!("{} y-values is too many", ys_length)))
+                    .map_err(at)?;
+                let (remain, raw) = WireError::nom(take(length)(input)).map_err(at)?;
+                fixed_ys = raw;
+                input = remain;
+            }
+        }
+
+        let at = fields.field("threshold", input);
+        let (input, threshold) = WireError::nom(complete(nom_helpers::u32)(input)).map_err(at)?;
+        let secret_len_offset = fields.offset(input);
+        let at = fields.field("secret_len", input);
+        let (input, secret_len) =
+            WireError::nom(complete(nom_helpers::usize)(input)).map_err(at)?;
+        WireLimits::check("secret", limits.max_secret_len, secret_len).map_err(at)?;
+
+        let prefix = WirePrefix {
+            x,
+            ys,
+            ys_len: ys_length,
+            fixed_ys,
             threshold,
             secret_len,
-        }
+            secret_len_offset,
+        };
+
+        Ok((input, prefix))
     }
-}
 
-/// Reconstruct a secret from a set of `Shard`s.
-///
-/// This operation is significantly faster than `Dealer::recover`, so it should
-/// always be used if the caller only needs to recover the secret.
-/// `Dealer::recover` should only be used if the caller needs to create
-/// additional shards with `Dealer::next_shard`.
-pub fn recover_secret<S: AsRef<[Shard]>>(shards: S) -> Vec<u8> {
-    // TODO: Add -> Result<Vec<u8>, _>.
-    let shards = shards.as_ref();
-    assert!(shards.len() > 0, "must be provided at least one shard");
+    // Parse the layout of shards written before the wire header was added,
+    // which ends after the secret length. Shards in this layout were always in
+    // GF(2^32) with four bytes packed into each element, were shared with
+    // Shamir's scheme, and have neither a dealer identifier nor a MAC.
+    pub(crate) fn from_legacy_wire_body<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        if F::BITS != Gf32::BITS {
+            return Err(WireError::from(format!(
+                "legacy shards are in GF(2^{}) but expected GF(2^{})",
+                Gf32::BITS,
+                F::BITS
+            )));
+        }
 
-    let threshold = shards[0].threshold;
-    let polys_len = shards[0].ys.len();
-    let secret_len = shards[0].secret_len;
+        let fields = FieldOffsets::new(input);
+        let (remain, prefix) = Self::parse_wire_prefix(&fields, input, limits, YsLayout::Varint)?;
+        prefix.check_secret_len(F::BYTES, Scheme::Shamir)?;
+        let shard = GenericShard {
+            x: prefix.x,
+            ys: prefix.ys,
+            threshold: prefix.threshold,
+            secret_len: prefix.secret_len,
+            chunk_size: F::BYTES,
+            scheme: Scheme::Shamir,
+            dealer_id: [0u8; DEALER_ID_LENGTH],
+            mac: None,
+        };
 
-    // TODO: Implement this consistency checking more nicely.
-    for shard in shards {
-        assert!(shard.threshold == threshold, "shards must be consistent");
-        assert!(shard.ys.len() == polys_len, "shards must be consistent");
-        assert!(shard.secret_len == secret_len, "shards must be consistent");
+        Ok((shard, remain))
     }
+}
 
-    assert!(
-        shards.len() == threshold as usize,
-        "must have exactly {} shards",
-        threshold
-    );
+// Fields at the start of every layout of a serialised shard.
+#[cfg(feature = "std")]
+struct WirePrefix<'a, F: GfField> {
+    x: GfElem<F>,
+    ys: Vec<GfElem<F>>,
+    ys_len: usize,
+    // Fixed-width y-values are returned undecoded (see parse_wire_body).
+    fixed_ys: &'a [u8],
+    threshold: u32,
+    secret_len: usize,
+    secret_len_offset: usize,
+}
 
-    (0..polys_len)
-        .map(|i| {
-            let xs = shards.iter().map(|s| s.x);
-            let ys = shards.iter().map(|s| s.ys[i]);
+#[cfg(feature = "std")]
+impl<F: GfField> WirePrefix<'_, F> {
+    // Check that the secret fits in the y-values, so that recovery never reads
+    // past the end of them.
+    fn check_secret_len(&self, chunk_size: usize, scheme: Scheme) -> Result<(), WireError> {
+        let max_secret_len = scheme.max_secret_len(self.threshold, self.ys_len, chunk_size);
+        match self.secret_len <= max_secret_len {
+            true => Ok(()),
+            false => Err(WireError::from(format!(
+                "shard secret length {} is longer than the {} bytes its y-values hold",
+                self.secret_len, max_secret_len
+            ))
+            .in_field("secret_len", self.secret_len_offset)),
+        }
+    }
+}
 
-            let points = xs.zip(ys).collect::<Vec<_>>();
-            GfPolynomial::lagrange_constant(threshold - 1, points.as_slice())
-        })
-        .flat_map(|x| x.to_bytes())
-        .take(secret_len)
-        .collect::<Vec<_>>()
+#[cfg(feature = "std")]
+impl<F: GfField> FromWire for GenericShard<F> {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        Self::from_wire_partial_with_limits(input, &WireLimits::DEFAULT)
+    }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+/// A `Shard` serialised with `YsLayout::FixedWidth`, whose y-values are only
+/// decoded when they are needed.
+///
+/// Only the metadata of the shard is parsed up front (though the checksum of
+/// the whole shard is still verified), so very large shards can be used
+/// directly from a memory-mapped file. Any y-value can be read without
+/// parsing the ones before it, and `recover_mapped` recovers the secret from
+/// a set of views one block at a time.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ShardView<'a, F: GfField = Gf32> {
+    // The shard without any y-values.
+    shard: GenericShard<F>,
+    ys: &'a [u8],
+}
 
-    use quickcheck::TestResult;
+#[cfg(feature = "std")]
+impl<'a, F: GfField> ShardView<'a, F> {
+    /// Parse a shard serialised with `YsLayout::FixedWidth`, without decoding
+    /// its y-values.
+    ///
+    /// Shards in any other layout are rejected, since their y-values cannot
+    /// be found without decoding all of them.
+    pub fn from_wire(input: &'a [u8]) -> Result<Self, WireError> {
+        Self::from_wire_with_limits(input, &WireLimits::DEFAULT)
+    }
 
-    #[quickcheck]
-    fn basic_roundtrip(n: u32, secret: Vec<u8>) -> TestResult {
-        if n < 1 {
-            return TestResult::discard();
+    /// Parse a shard like `from_wire`, rejecting any shard which declares
+    /// lengths larger than those permitted by `limits`.
+    pub fn from_wire_with_limits(input: &'a [u8], limits: &WireLimits) -> Result<Self, WireError> {
+        let (view, remain) = header::read_flagged_record(
+            input,
+            DocumentKind::Shard,
+            header::FLAG_FIXED_WIDTH_YS,
+            |body, flags| match YsLayout::from_flags(flags) {
+                YsLayout::FixedWidth => {
+                    GenericShard::parse_wire_body(body, limits, YsLayout::FixedWidth)
+                        .map(|(shard, ys, remain)| (ShardView { shard, ys }, remain))
+                }
+                YsLayout::Varint => Err(WireError::from(
+                    "shard y-values are not fixed-width".to_string(),
+                )),
+            },
+        )?;
+        match remain {
+            [] => Ok(view),
+            _ => Err(WireError::TrailingData {
+                length: remain.len(),
+            }),
         }
-        let dealer = Dealer::new(n, &secret);
-        TestResult::from_bool(secret == dealer.secret())
     }
 
-    #[quickcheck]
-    fn shard_bytes_roundtrip(shard: Shard) {
-        let shard2 = Shard::from_wire(&shard.to_wire()).unwrap();
-        assert_eq!(shard, shard2);
+    /// Returns the identifier of the shard (see `Shard::id`).
+    pub fn id(&self) -> String {
+        self.shard.id()
     }
 
-    #[quickcheck]
-    fn recover_secret_fail(n: u32, secret: Vec<u8>) -> TestResult {
-        // Invalid data. Note that large n values take a very long time to
-        // recover the secret. This is proportional to secret.len(), which is
-        // controlled by quickcheck and thus can be quite large.
-        if n < 2 || n > 32 || secret.len() < 1 {
-            return TestResult::discard();
-        }
+    /// Returns the number of shards required to reconstruct the secret.
+    pub fn threshold(&self) -> u32 {
+        self.shard.threshold
+    }
 
-        let dealer = Dealer::new(n, &secret);
-        let shards = (0..(n - 1))
-            .map(|_| {
-                let mut shard = dealer.next_shard();
-                shard.threshold -= 1;
-                // Ensure shard IDs are always ID_LENGTH.
+    /// Returns the number of y-values in the shard.
+    pub fn y_count(&self) -> usize {
+        self.ys.len() / F::BYTES
+    }
+
+    /// Returns the y-value at `idx` (see `Shard::ys`), or `None` if there is
+    /// no such y-value.
+    pub fn y(&self, idx: usize) -> Option<ShardValue> {
+        let start = idx.checked_mul(F::BYTES)?;
+        self.ys
+            .get(start..start + F::BYTES)
+            .map(|y| ShardValue(y.to_vec()))
+    }
+
+    /// Decode the entire shard.
+    pub fn to_shard(&self) -> GenericShard<F> {
+        let mut shard = self.shard.clone();
+        shard.ys = self.decode_ys(0..self.y_count());
+        shard
+    }
+
+    fn decode_ys(&self, polys: Range<usize>) -> Vec<GfElem<F>> {
+        self.ys[polys.start * F::BYTES..polys.end * F::BYTES]
+            .chunks(F::BYTES)
+            .map(GfElem::from_bytes)
+            .collect()
+    }
+
+    // Split the shard into (unauthenticated) segments, each holding the
+    // y-values needed to recover roughly block_size bytes of the secret, such
+    // that recovering each segment in turn gives the whole secret. There is
+    // always at least one segment.
+    pub(super) fn segments(&self, block_size: usize) -> impl Iterator<Item = GenericShard<F>> + '_ {
+        let shard = &self.shard;
+        let poly_size = shard.scheme.chunks_per_poly(shard.threshold) * shard.chunk_size;
+        let per_segment = (block_size / poly_size).max(1);
+        let count = self.y_count().div_ceil(per_segment).max(1);
+        (0..count).map(move |idx| {
+            let start = idx * per_segment;
+            let polys = start..(start + per_segment).min(self.y_count());
+            let bytes = polys.start * poly_size..(polys.end * poly_size).min(shard.secret_len);
+            GenericShard {
+                ys: self.decode_ys(polys),
+                secret_len: bytes.end.saturating_sub(bytes.start),
+                mac: None,
+                ..shard.clone()
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+impl<F: GfField> quickcheck::Arbitrary for GenericShard<F> {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let ys = (0..g.size())
+            .map(|_| GfElem::new_rand(g))
+            .collect::<Vec<_>>();
+        let chunk_size = 1 + g.next_u32() as usize % F::BYTES;
+        Self {
+            x: GfElem::new_rand(g),
+            // The secret must fit in the y-values (otherwise the shard would
+            // be rejected when it is parsed).
+            secret_len: (ys.len() * chunk_size).saturating_sub(g.next_u32() as usize % chunk_size),
+            ys,
+            chunk_size,
+            // With a threshold of zero, a ramp shard holds nothing.
+            threshold: g.next_u32().max(1),
+            scheme: match g.next_u32() % 2 {
+                0 => Scheme::Shamir,
+                _ => Scheme::Ramp,
+            },
+            dealer_id: {
+                let mut id = [0u8; DEALER_ID_LENGTH];
+                g.fill_bytes(&mut id);
+                id
+            },
+            mac: match g.next_u32() % 2 {
+                0 => None,
+                _ => {
+                    let mut mac = [0u8; MAC_LENGTH];
+                    g.fill_bytes(&mut mac);
+                    Some(mac)
+                }
+            },
+        }
+    }
+}
+
+/// Reasons why a `Dealer` cannot be constructed (or cannot produce a `Shard`)
+/// with the given parameters.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DealerError {
+    /// A threshold of zero was requested.
+    ZeroThreshold,
+    /// The threshold is larger than the number of shards which will be
+    /// created, so the secret could never be recovered.
+    ThresholdTooLarge { threshold: u32, max_shards: u32 },
+    /// The requested shard x-coordinate is zero (which is the secret itself)
+    /// or is not an element of the dealer's field.
+    InvalidShardX,
+}
+
+impl fmt::Display for DealerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DealerError::ZeroThreshold => write!(f, "must at least have a threshold of one"),
+            DealerError::ThresholdTooLarge {
+                threshold,
+                max_shards,
+            } => write!(
+                f,
+                "threshold of {} is larger than the {} shards to be created",
+                threshold, max_shards
+            ),
+            DealerError::InvalidShardX => write!(f, "invalid shard x-coordinate"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for DealerError {}
+
+/// Factory to share a secret using [Shamir Secret Sharing][sss] over
+/// `GF(2^32)`.
+///
+/// See [`GenericDealer`] for dealers over other field sizes.
+///
+/// [sss]: https://en.wikipedia.org/wiki/Shamir%27s_Secret_Sharing
+pub type Dealer = GenericDealer<Gf32>;
+
+/// Factory to share a secret using [Shamir Secret Sharing][sss] over the field
+/// `F`.
+///
+/// [sss]: https://en.wikipedia.org/wiki/Shamir%27s_Secret_Sharing
+#[derive(Clone, Debug)]
+pub struct GenericDealer<F: GfField> {
+    polys: GfPolynomials<F>,
+    secret_len: usize,
+    chunk_size: usize,
+    threshold: u32,
+    scheme: Scheme,
+    // x-values of every shard handed out (or known about) by this dealer.
+    issued_xs: BTreeSet<GfElemPrimitive>,
+    // Next candidate x-value for Dealer::next_shard_indexed.
+    next_index: GfElemPrimitive,
+    // Random identifier stored in every shard.
+    dealer_id: [u8; DEALER_ID_LENGTH],
+    // Record of every shard issued by this dealer.
+    issued: Vec<IssuedShard>,
+    // Key used to compute the MAC of each shard (if enabled).
+    mac_key: Option<MacKey>,
+}
+
+impl<F: GfField> Zeroize for GenericDealer<F> {
+    fn zeroize(&mut self) {
+        // The constant terms of the polynomials are the secret itself.
+        self.polys.zeroize();
+        self.mac_key.zeroize();
+    }
+}
+
+impl<F: GfField> Drop for GenericDealer<F> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl Dealer {
+    /// Construct a new `Dealer` to shard the `secret`, requiring at least
+    /// `threshold` shards to reconstruct the secret.
+    ///
+    /// Unlike `Dealer::new`, the parameters are validated and an error is
+    /// returned if they don't make sense. `max_shards` is the number of shards
+    /// the caller intends to create, which must be enough to reach the
+    /// threshold.
+    #[cfg(feature = "std")]
+    pub fn try_new<B: AsRef<[u8]>>(
+        threshold: u32,
+        max_shards: u32,
+        secret: B,
+    ) -> Result<Self, DealerError> {
+        if threshold == 0 {
+            return Err(DealerError::ZeroThreshold);
+        }
+        if threshold > max_shards {
+            return Err(DealerError::ThresholdTooLarge {
+                threshold,
+                max_shards,
+            });
+        }
+        Ok(Self::new(threshold, secret))
+    }
+
+    /// Construct a new `Dealer` to shard the `secret`, requiring at least
+    /// `threshold` shards to reconstruct the secret.
+    #[cfg(feature = "std")]
+    pub fn new<B: AsRef<[u8]>>(threshold: u32, secret: B) -> Self {
+        Self::new_with_chunk_size(threshold, Gf32::BYTES, secret)
+    }
+
+    /// Identical to `Dealer::new`, except that the random polynomial
+    /// coefficients are generated using `rng` rather than `OsRng`.
+    ///
+    /// This is mostly useful for generating reproducible test fixtures (with
+    /// a seeded RNG) or for using a hardware RNG. `rng` must be a
+    /// cryptographically secure RNG, as the coefficients are all that protect
+    /// the secret.
+    pub fn new_with_rng<B: AsRef<[u8]>, R: CryptoRng + RngCore + ?Sized>(
+        threshold: u32,
+        secret: B,
+        rng: &mut R,
+    ) -> Self {
+        Self::new_in_field_with_rng(threshold, Gf32::BYTES, secret, rng)
+    }
+
+    /// Construct a new `Dealer` to shard the `secret` using a ramp scheme
+    /// (see `Scheme::Ramp`), requiring at least `threshold` shards to
+    /// reconstruct the secret.
+    #[cfg(feature = "std")]
+    pub fn new_ramp<B: AsRef<[u8]>>(threshold: u32, secret: B) -> Self {
+        Self::new_ramp_in_field_with_rng(threshold, Gf32::BYTES, secret, &mut OsRng)
+    }
+
+    /// Construct a new `Dealer` to shard the `secret`, requiring at least
+    /// `threshold` shards to reconstruct the secret, packing `chunk_size` bytes
+    /// of the secret into each field element.
+    ///
+    /// Smaller chunk sizes result in larger shards, but may be useful to align
+    /// the shard payloads with the medium they are stored in. The chunk size is
+    /// stored in each `Shard`, so it does not need to be known at recovery.
+    #[cfg(feature = "std")]
+    pub fn new_with_chunk_size<B: AsRef<[u8]>>(
+        threshold: u32,
+        chunk_size: usize,
+        secret: B,
+    ) -> Self {
+        Self::new_in_field(threshold, chunk_size, secret)
+    }
+}
+
+impl<F: GfField> GenericDealer<F> {
+    /// Returns the number of *unique* `Shard`s generated by this `Dealer`
+    /// required to recover the stored secret.
+    #[allow(dead_code)]
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Returns the identifier stored in every `Shard` generated by this
+    /// `Dealer` (see `Shard::dealer_id`).
+    pub fn id(&self) -> String {
+        encoding::zbase32(&self.dealer_id)
+    }
+
+    /// Construct a new `Dealer` in the field `F` (rather than the default of
+    /// `GF(2^32)`) to shard the `secret`, requiring at least `threshold`
+    /// shards to reconstruct the secret, packing `chunk_size` bytes of the
+    /// secret into each field element.
+    ///
+    /// Smaller fields produce smaller shards for small secrets, while larger
+    /// fields need fewer field elements for large secrets. The field is
+    /// recorded in each `Shard`, and shards can only be parsed as a `Shard` of
+    /// the same field.
+    #[cfg(feature = "std")]
+    pub fn new_in_field<B: AsRef<[u8]>>(threshold: u32, chunk_size: usize, secret: B) -> Self {
+        Self::new_in_field_with_rng(threshold, chunk_size, secret, &mut OsRng)
+    }
+
+    /// Identical to `Dealer::new_in_field`, except that the random polynomial
+    /// coefficients are generated using `rng` rather than `OsRng`.
+    pub fn new_in_field_with_rng<B: AsRef<[u8]>, R: CryptoRng + RngCore + ?Sized>(
+        threshold: u32,
+        chunk_size: usize,
+        secret: B,
+        rng: &mut R,
+    ) -> Self {
+        assert!(threshold > 0, "must at least have a threshold of one");
+        assert!(
+            chunk_size > 0 && chunk_size <= F::BYTES,
+            "chunk size must be between 1 and {}",
+            F::BYTES
+        );
+        let k = threshold - 1;
+        let secret = secret.as_ref();
+        let mut polys = GfPolynomials::with_capacity(k, secret.len().div_ceil(chunk_size));
+        secret
+            // Generate field elements from &[u8], by chunking into sets of (at
+            // most) chunk_size.
+            .chunks(chunk_size)
+            .map(GfElem::from_bytes)
+            // Generate a random polynomial with the value as the constant.
+            .for_each(|x0| polys.push_rand(x0, rng));
+        let mut dealer_id = [0u8; DEALER_ID_LENGTH];
+        rng.fill_bytes(&mut dealer_id);
+        GenericDealer {
+            polys,
+            threshold,
+            scheme: Scheme::Shamir,
+            secret_len: secret.len(),
+            chunk_size,
+            issued_xs: BTreeSet::new(),
+            next_index: 1,
+            dealer_id,
+            issued: Vec::new(),
+            mac_key: None,
+        }
+    }
+
+    /// Construct a new `Dealer` in the field `F` to shard the `secret` using a
+    /// ramp scheme (see `Scheme::Ramp`), requiring at least `threshold` shards
+    /// to reconstruct the secret.
+    ///
+    /// Each shard is only `1/threshold` the size of a Shamir shard, but fewer
+    /// than `threshold` shards still leak information about the secret.
+    pub fn new_ramp_in_field_with_rng<B: AsRef<[u8]>, R: CryptoRng + RngCore + ?Sized>(
+        threshold: u32,
+        chunk_size: usize,
+        secret: B,
+        rng: &mut R,
+    ) -> Self {
+        assert!(threshold > 0, "must at least have a threshold of one");
+        assert!(
+            chunk_size > 0 && chunk_size <= F::BYTES,
+            "chunk size must be between 1 and {}",
+            F::BYTES
+        );
+        let secret = secret.as_ref();
+        // Every coefficient is a chunk of the secret, with the final
+        // polynomial padded with zero coefficients.
+        let mut coeffs = secret
+            .chunks(chunk_size)
+            .map(GfElem::from_bytes)
+            .collect::<Vec<_>>();
+        let stride = threshold as usize;
+        coeffs.resize(coeffs.len().div_ceil(stride) * stride, GfElem::ZERO);
+        let polys = GfPolynomials::from_coeffs(threshold - 1, coeffs)
+            .expect("coefficients must be padded to the polynomial degree");
+        let mut dealer_id = [0u8; DEALER_ID_LENGTH];
+        rng.fill_bytes(&mut dealer_id);
+        GenericDealer {
+            polys,
+            threshold,
+            scheme: Scheme::Ramp,
+            secret_len: secret.len(),
+            chunk_size,
+            issued_xs: BTreeSet::new(),
+            next_index: 1,
+            dealer_id,
+            issued: Vec::new(),
+            mac_key: None,
+        }
+    }
+
+    /// Returns the sharing scheme used by this `Dealer`.
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    /// Attach a MAC to every `Shard` generated by this `Dealer`.
+    ///
+    /// The MAC is keyed by `key`, and is verified for each shard (before any
+    /// interpolation) by `recover_secret_with_key` and `Dealer::recover_with_key`.
+    /// This means that a tampered or mis-transcribed shard is reported by name,
+    /// rather than silently producing the wrong secret.
+    ///
+    /// The key must be available to whoever recovers the secret *without*
+    /// the shards, so it must not be derived from the secret. It does not
+    /// need to be kept secret to detect damaged shards, but only custodians
+    /// who do not know it are unable to forge a MAC.
+    pub fn authenticated(mut self, key: &MacKey) -> Self {
+        self.mac_key = Some(*key);
+        self
+    }
+
+    /// Get the secret value stored by the `Dealer`.
+    pub fn secret(&self) -> Vec<u8> {
+        let chunks: Box<dyn Iterator<Item = GfElem<F>>> = match self.scheme {
+            Scheme::Shamir => Box::new(self.polys.constants()),
+            Scheme::Ramp => Box::new(self.polys.coeffs().iter().copied()),
+        };
+        chunks
+            .flat_map(|x| x.to_bytes().into_iter().take(self.chunk_size))
+            .take(self.secret_len)
+            .collect::<Vec<_>>()
+    }
+
+    /// Generate a new `Shard` for the secret.
+    ///
+    /// The `x` value is calculated randomly, but the `Dealer` keeps track of
+    /// every `x` value it has handed out and will never re-use one. Thus any
+    /// `threshold` shards returned by the same `Dealer` can be used together
+    /// to recover the secret.
+    #[cfg(feature = "std")]
+    pub fn next_shard(&mut self) -> GenericShard<F> {
+        self.next_shard_with_rng(&mut OsRng)
+    }
+
+    /// Identical to `Dealer::next_shard`, except that the `x` value is
+    /// generated using `rng` rather than `OsRng`.
+    pub fn next_shard_with_rng<R: CryptoRng + RngCore + ?Sized>(
+        &mut self,
+        rng: &mut R,
+    ) -> GenericShard<F> {
+        assert!(
+            (self.issued_xs.len() as GfElemPrimitive) < F::MASK,
+            "dealer has run out of shard x values"
+        );
+        let x = self.next_x(rng);
+        self.issue(x)
+    }
+
+    /// Generate `n` new `Shard`s for the secret.
+    ///
+    /// This is equivalent to calling `Dealer::next_shard` `n` times (and the
+    /// shards are all unique in the same way), but is much faster for large
+    /// secrets because every polynomial is evaluated at all of the new `x`
+    /// values in a single pass.
+    #[cfg(feature = "std")]
+    pub fn shards(&mut self, n: u32) -> Vec<GenericShard<F>> {
+        assert!(
+            (self.issued_xs.len() as GfElemPrimitive).saturating_add(n.into()) <= F::MASK,
+            "dealer has run out of shard x values"
+        );
+        let xs = (0..n).map(|_| self.next_x(&mut OsRng)).collect::<Vec<_>>();
+        let ys = match self.threshold {
+            // See Dealer::make_shard.
+            1 => xs
+                .iter()
+                .map(|_| self.polys.constants().collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+            _ => self.polys.evaluate_many(&xs),
+        };
+        let shards = xs
+            .into_iter()
+            .zip(ys)
+            .map(|(x, ys)| self.shard_from_ys(x, ys))
+            .collect::<Vec<_>>();
+        self.issued.extend(shards.iter().map(IssuedShard::new));
+        shards
+    }
+
+    // Pick a new random (unused) x value.
+    fn next_x<R: RngCore + ?Sized>(&mut self, rng: &mut R) -> GfElem<F> {
+        loop {
+            let x = GfElem::new_rand(rng);
+            // x = 0 is the secret itself, and re-using an x value would create
+            // a shard that cannot be used alongside the original.
+            if x != GfElem::ZERO && self.issued_xs.insert(x.inner()) {
+                break x;
+            }
+        }
+    }
+
+    /// Generate a new `Shard` for the secret, with the `x` value being the
+    /// next unused index (starting from `1`).
+    ///
+    /// This allows shards to be numbered sequentially (so that a printed shard
+    /// can be referred to as "shard #3"). Indices which have already been
+    /// issued by this `Dealer` (including randomly-chosen `x` values from
+    /// `Dealer::next_shard`) are skipped.
+    pub fn next_shard_indexed(&mut self) -> GenericShard<F> {
+        let x = loop {
+            let index = self.next_index;
+            let x = GfElem::from_inner(index)
+                .filter(|x| *x != GfElem::ZERO)
+                .expect("dealer has run out of shard indices");
+            self.next_index = index.wrapping_add(1);
+            if self.issued_xs.insert(index) {
+                break x;
+            }
+        };
+        self.issue(x)
+    }
+
+    /// Generate the `Shard` with the given x-coordinate (as returned by
+    /// `Shard::x`).
+    ///
+    /// This allows a lost shard to be re-issued exactly (for instance after
+    /// recovering the `Dealer` with `Dealer::recover`), rather than handing
+    /// out an entirely new shard. The x-coordinate is recorded as issued, so
+    /// `Dealer::next_shard` will never produce another shard with it.
+    pub fn shard_at<B: AsRef<[u8]>>(&mut self, x: B) -> Result<GenericShard<F>, DealerError> {
+        let x = x.as_ref();
+        if x.len() != F::BYTES || x.iter().all(|b| *b == 0) {
+            return Err(DealerError::InvalidShardX);
+        }
+        let x = GfElem::from_bytes(x);
+        self.issued_xs.insert(x.inner());
+        Ok(self.issue(x))
+    }
+
+    /// Returns a record of every `Shard` issued by this `Dealer` (in the order
+    /// they were issued), including re-issued shards.
+    ///
+    /// A `Dealer` recovered with `Dealer::recover` starts with an empty log.
+    pub fn issued(&self) -> impl Iterator<Item = &IssuedShard> {
+        self.issued.iter()
+    }
+
+    /// Returns an exportable copy of the record of issued `Shard`s (see
+    /// `Dealer::issued`).
+    pub fn issued_log(&self) -> IssuedLog {
+        IssuedLog::new(self.issued.clone())
+    }
+
+    // Generate the shard at x, and record that it was issued.
+    fn issue(&mut self, x: GfElem<F>) -> GenericShard<F> {
+        let shard = self.make_shard(x);
+        self.issued.push(IssuedShard::new(&shard));
+        shard
+    }
+
+    #[cfg(feature = "std")]
+    pub(super) fn dealer_id_bytes(&self) -> [u8; DEALER_ID_LENGTH] {
+        self.dealer_id
+    }
+
+    // Every shard issued by (or known to) this dealer.
+    #[cfg(feature = "std")]
+    pub(super) fn issued_shards(&self) -> impl Iterator<Item = GenericShard<F>> + '_ {
+        self.issued_xs
+            .iter()
+            .filter_map(|x| GfElem::from_inner(*x))
+            .map(move |x| self.make_shard(x))
+    }
+
+    pub(super) fn make_shard(&self, x: GfElem<F>) -> GenericShard<F> {
+        let ys = match self.threshold {
+            // With a threshold of one, every polynomial is just a constant so
+            // each y-value is the corresponding chunk of the secret.
+            1 => self.polys.constants().collect::<Vec<_>>(),
+            _ => self.polys.evaluate(x).collect::<Vec<_>>(),
+        };
+        self.shard_from_ys(x, ys)
+    }
+
+    fn shard_from_ys(&self, x: GfElem<F>, ys: Vec<GfElem<F>>) -> GenericShard<F> {
+        if self.threshold > 1 && self.scheme == Scheme::Shamir {
+            // In small fields a shard value can legitimately collide with the
+            // secret chunk, so only check in larger fields.
+            assert!(F::BITS < 32 || ys.iter().zip(self.polys.constants()).all(|(y, c)| *y != c));
+        }
+        let mut shard = GenericShard {
+            x,
+            ys,
+            threshold: self.threshold,
+            secret_len: self.secret_len,
+            chunk_size: self.chunk_size,
+            scheme: self.scheme,
+            dealer_id: self.dealer_id,
+            mac: None,
+        };
+        if let Some(key) = &self.mac_key {
+            let mut mac = [0u8; MAC_LENGTH];
+            mac.copy_from_slice(shard.compute_mac(key).as_bytes());
+            shard.mac = Some(mac);
+        }
+        shard
+    }
+
+    /// Reconstruct an entire `Dealer` from a set of `Shard`s.
+    ///
+    /// Any number of shards may be provided, so long as there are at least
+    /// `threshold` unique shards. Only the first `threshold` unique shards are
+    /// used to recover the polynomials.
+    ///
+    /// The MACs of authenticated shards are **not** checked, and the recovered
+    /// `Dealer` generates unauthenticated shards. Use `Dealer::recover_with_key`
+    /// for authenticated shards.
+    ///
+    /// This operation is significantly slower than `recover_secret`, so it
+    /// should only be used if it is necessary to construct additional shards
+    /// with `Dealer::next_shard`.
+    pub fn recover<S: AsRef<[GenericShard<F>]>>(shards: S) -> Result<Self, ShardError> {
+        Self::recover_inner(shards.as_ref(), None)
+    }
+
+    /// Reconstruct an entire `Dealer` from a set of authenticated `Shard`s.
+    ///
+    /// This is like `Dealer::recover`, except that the MAC of every shard is
+    /// verified with `key` before it is used, and the recovered `Dealer` will
+    /// also generate authenticated shards.
+    pub fn recover_with_key<S: AsRef<[GenericShard<F>]>>(
+        shards: S,
+        key: &MacKey,
+    ) -> Result<Self, ShardError> {
+        Ok(Self::recover_inner(shards.as_ref(), Some(key))?.authenticated(key))
+    }
+
+    fn recover_inner(shards: &[GenericShard<F>], key: Option<&MacKey>) -> Result<Self, ShardError> {
+        let shards = unique_shards(shards, key)?;
+        let first = shards[0];
+
+        let threshold = first.threshold;
+        let polys_len = first.ys.len();
+        let secret_len = first.secret_len;
+        let chunk_size = first.chunk_size;
+        // The shards used for recovery have obviously already been issued.
+        let issued_xs = shards.iter().map(|s| s.x.inner()).collect::<BTreeSet<_>>();
+        let shards = &shards[..threshold as usize];
+
+        // With a threshold of one, the single shard's y-values are the
+        // constant terms of the polynomials -- no interpolation needed.
+        if threshold == 1 {
+            return Ok(Self {
+                polys: GfPolynomials::from_constants(first.ys.iter().copied()),
+                threshold,
+                scheme: first.scheme,
+                secret_len,
+                chunk_size,
+                issued_xs,
+                next_index: 1,
+                dealer_id: first.dealer_id,
+                issued: Vec::new(),
+                mac_key: None,
+            });
+        }
+
+        let mut polys = GfPolynomials::with_capacity(threshold - 1, polys_len);
+        map_chunks(polys_len, |i| {
+            let xs = shards.iter().map(|s| s.x);
+            let ys = shards.iter().map(|s| s.ys[i]);
+
+            let points = xs.zip(ys).collect::<Vec<_>>();
+            GfPolynomial::lagrange(threshold - 1, points.as_slice())
+        })
+        .into_iter()
+        .for_each(|poly| polys.push(poly));
+
+        Ok(Self {
+            polys,
+            threshold,
+            scheme: first.scheme,
+            secret_len,
+            chunk_size,
+            issued_xs,
+            next_index: 1,
+            dealer_id: first.dealer_id,
+            issued: Vec::new(),
+            mac_key: None,
+        })
+    }
+}
+
+/// Reasons why a set of `Shard`s cannot be used to recover a secret.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ShardError {
+    /// No shards were provided.
+    NoShards,
+    /// More than one (different) shard has the same x-value (and thus the
+    /// same id).
+    DuplicateShard { id: String },
+    /// Fewer shards were provided than the threshold requires.
+    NotEnoughShards { threshold: u32, count: usize },
+    /// The shards claim to have a threshold of zero.
+    ZeroThreshold,
+    /// None of the shards beyond the threshold agree with any other subset of
+    /// the shards, so it is not possible to tell which shards are correct.
+    NoConsensus,
+    /// Too many of the shards were corrupted for the errors to be corrected.
+    TooManyErrors,
+    /// The shard was created by a different `Dealer` to the other shards (so
+    /// it is from a different backup).
+    MixedDealers {
+        id: String,
+        dealer_id: String,
+        expected: String,
+    },
+    /// The shards disagree about the threshold.
+    InconsistentThreshold,
+    /// The shards disagree about the length of the secret.
+    InconsistentSecretLength,
+    /// The shards disagree about the number of bytes in each field element.
+    InconsistentChunkSize,
+    /// The shards disagree about the sharing scheme.
+    InconsistentScheme,
+    /// The shards have different numbers of y-values.
+    MismatchedLengths,
+    /// Some of the shards are authenticated but this one is not.
+    MissingMac { id: String },
+    /// The compartmented shards disagree about the number of groups.
+    InconsistentCompartments,
+    /// Shards from fewer groups were provided than the secret was shared
+    /// between.
+    NotEnoughCompartments { compartments: u32, count: usize },
+    /// The shards from a group could not be used to recover that group's share
+    /// of the secret.
+    Compartment { group: String, err: Box<ShardError> },
+    /// The shard's MAC did not match the recovered secret, so either this
+    /// shard or one of the shards used to recover the secret has been
+    /// tampered with (or mis-transcribed).
+    InvalidMac { id: String },
+    /// The packed shards disagree about the lengths of the packed secrets.
+    InconsistentPacking,
+    /// The requested packed secret does not exist.
+    NoSuchSecret { index: usize, count: usize },
+    /// The requested range of the secret is not within the secret.
+    RangeOutOfBounds {
+        start: usize,
+        end: usize,
+        secret_len: usize,
+    },
+    /// The shards claim a secret longer than their y-values can hold.
+    SecretTooLong {
+        secret_len: usize,
+        max_secret_len: usize,
+    },
+    /// A streamed block is too short to contain its header.
+    MalformedStreamBlock { index: u64 },
+    /// A streamed block is from a different stream to the first block.
+    MixedStreams { index: u64 },
+    /// A streamed block is not the block expected at its position in the
+    /// stream (blocks were dropped or reordered).
+    StreamBlockOutOfOrder { index: u64, found: u64 },
+    /// The stream ended before its last block.
+    TruncatedStream { blocks: u64 },
+    /// More blocks were provided after the last block of the stream.
+    TrailingStreamBlocks { blocks: u64 },
+}
+
+/// Error returned by `recover_secret`.
+pub type RecoverError = ShardError;
+
+impl fmt::Display for ShardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShardError::NoShards => write!(f, "must be provided at least one shard"),
+            ShardError::DuplicateShard { id } => write!(f, "duplicate shard {}", id),
+            ShardError::NotEnoughShards { threshold, count } => write!(
+                f,
+                "not enough shards: need {} unique shards but only had {}",
+                threshold, count
+            ),
+            ShardError::ZeroThreshold => write!(f, "shards have a threshold of zero"),
+            ShardError::NoConsensus => write!(f, "shards do not agree with each other"),
+            ShardError::TooManyErrors => write!(f, "too many corrupted shards to correct"),
+            ShardError::MixedDealers {
+                id,
+                dealer_id,
+                expected,
+            } => write!(
+                f,
+                "shard {} is from a different backup (dealer {} not {})",
+                id, dealer_id, expected
+            ),
+            ShardError::InconsistentThreshold => write!(f, "shards have inconsistent thresholds"),
+            ShardError::InconsistentSecretLength => {
+                write!(f, "shards have inconsistent secret lengths")
+            }
+            ShardError::InconsistentChunkSize => {
+                write!(f, "shards have inconsistent chunk sizes")
+            }
+            ShardError::InconsistentScheme => write!(f, "shards have inconsistent schemes"),
+            ShardError::MismatchedLengths => {
+                write!(f, "shards have differing numbers of y-values")
+            }
+            ShardError::InconsistentCompartments => {
+                write!(f, "shards have inconsistent numbers of groups")
+            }
+            ShardError::NotEnoughCompartments {
+                compartments,
+                count,
+            } => write!(
+                f,
+                "not enough groups: need shards from {} groups but only had {}",
+                compartments, count
+            ),
+            ShardError::Compartment { group, err } => write!(f, "group {}: {}", group, err),
+            ShardError::MissingMac { id } => write!(f, "shard {} is missing its mac", id),
+            ShardError::InvalidMac { id } => write!(f, "shard {} failed mac verification", id),
+            ShardError::InconsistentPacking => {
+                write!(f, "shards have inconsistent packed secret lengths")
+            }
+            ShardError::NoSuchSecret { index, count } => write!(
+                f,
+                "no packed secret {}: shards only contain {} secrets",
+                index, count
+            ),
+            ShardError::RangeOutOfBounds {
+                start,
+                end,
+                secret_len,
+            } => write!(
+                f,
+                "range {}..{} is outside the {} byte secret",
+                start, end, secret_len
+            ),
+            ShardError::SecretTooLong {
+                secret_len,
+                max_secret_len,
+            } => write!(
+                f,
+                "shards claim a {} byte secret but only hold {} bytes",
+                secret_len, max_secret_len
+            ),
+            ShardError::MalformedStreamBlock { index } => {
+                write!(f, "stream block {} is missing its header", index)
+            }
+            ShardError::MixedStreams { index } => {
+                write!(f, "stream block {} is from a different stream", index)
+            }
+            ShardError::StreamBlockOutOfOrder { index, found } => write!(
+                f,
+                "stream block {} is out of order (found block {})",
+                index, found
+            ),
+            ShardError::TruncatedStream { blocks } => write!(
+                f,
+                "stream is truncated: the last of its {} blocks is not its last block",
+                blocks
+            ),
+            ShardError::TrailingStreamBlocks { blocks } => {
+                write!(
+                    f,
+                    "stream has blocks after its last block (block {})",
+                    blocks
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for ShardError {}
+
+/// Check that the `Shard`s are consistent with each other and that there are
+/// enough of them, returning the *unique* shards (in the order they were
+/// given). Exact copies of a shard are ignored, but two different shards with
+/// the same x-value are treated as an error.
+///
+/// If a MAC key is given, the MAC of every shard is also verified, so a bad
+/// shard is rejected by name before it can affect the interpolation.
+fn unique_shards<'a, F: GfField>(
+    shards: &'a [GenericShard<F>],
+    key: Option<&MacKey>,
+) -> Result<Vec<&'a GenericShard<F>>, ShardError> {
+    let first = shards.first().ok_or(ShardError::NoShards)?;
+    if first.threshold == 0 {
+        return Err(ShardError::ZeroThreshold);
+    }
+    // The secret must fit in the y-values, so that recovery never reads past
+    // the end of them (every shard must agree with the first one).
+    let max_secret_len =
+        first
+            .scheme
+            .max_secret_len(first.threshold, first.ys.len(), first.chunk_size);
+    if first.secret_len > max_secret_len {
+        return Err(ShardError::SecretTooLong {
+            secret_len: first.secret_len,
+            max_secret_len,
+        });
+    }
+
+    let mut unique = Vec::with_capacity(shards.len());
+    let mut xs = BTreeMap::new();
+    for shard in shards {
+        if shard.dealer_id != first.dealer_id {
+            return Err(ShardError::MixedDealers {
+                id: shard.id(),
+                dealer_id: shard.dealer_id(),
+                expected: first.dealer_id(),
+            });
+        }
+        if shard.threshold != first.threshold {
+            return Err(ShardError::InconsistentThreshold);
+        }
+        if shard.ys.len() != first.ys.len() {
+            return Err(ShardError::MismatchedLengths);
+        }
+        if shard.secret_len != first.secret_len {
+            return Err(ShardError::InconsistentSecretLength);
+        }
+        if shard.chunk_size != first.chunk_size {
+            return Err(ShardError::InconsistentChunkSize);
+        }
+        if shard.scheme != first.scheme {
+            return Err(ShardError::InconsistentScheme);
+        }
+        if let Some(key) = key {
+            shard.verify_mac(key)?;
+        }
+        match xs.get(&shard.x.inner()) {
+            Some(&other) if other == shard => continue,
+            Some(_) => return Err(ShardError::DuplicateShard { id: shard.id() }),
+            None => {
+                xs.insert(shard.x.inner(), shard);
+                unique.push(shard);
+            }
+        }
+    }
+
+    let (threshold, count) = (first.threshold, unique.len());
+    if count < threshold as usize {
+        return Err(ShardError::NotEnoughShards { threshold, count });
+    }
+
+    Ok(unique)
+}
+
+// Compute f(i) for every chunk index. The chunks of a secret are entirely
+// independent, so with the "parallel" feature they are computed concurrently.
+#[cfg(feature = "parallel")]
+fn map_chunks<T: Send, M: Fn(usize) -> T + Send + Sync>(len: usize, f: M) -> Vec<T> {
+    (0..len).into_par_iter().map(f).collect::<Vec<_>>()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn map_chunks<T: Send, M: Fn(usize) -> T + Send + Sync>(len: usize, f: M) -> Vec<T> {
+    (0..len).map(f).collect::<Vec<_>>()
+}
+
+/// Reconstruct a secret from a set of `Shard`s.
+///
+/// This operation is significantly faster than `Dealer::recover`, so it should
+/// always be used if the caller only needs to recover the secret.
+/// `Dealer::recover` should only be used if the caller needs to create
+/// additional shards with `Dealer::next_shard`.
+///
+/// Any number of shards may be provided, so long as there are at least
+/// `threshold` unique shards. Only the first `threshold` unique shards are
+/// used to recover the secret.
+///
+/// The MACs of authenticated shards are **not** checked (there is no key to
+/// check them with). Use `recover_secret_with_key` for authenticated shards.
+pub fn recover_secret<F: GfField, S: AsRef<[GenericShard<F>]>>(
+    shards: S,
+) -> Result<Vec<u8>, RecoverError> {
+    recover_bytes(shards.as_ref(), None, None)
+}
+
+/// Reconstruct a secret from a set of authenticated `Shard`s.
+///
+/// This is like `recover_secret`, except that the MAC of every shard is
+/// verified with `key` before any interpolation is done. If a shard is not
+/// authenticated or its MAC does not match, `ShardError::MissingMac` or
+/// `ShardError::InvalidMac` (naming that shard) is returned.
+pub fn recover_secret_with_key<F: GfField, S: AsRef<[GenericShard<F>]>>(
+    shards: S,
+    key: &MacKey,
+) -> Result<Vec<u8>, RecoverError> {
+    recover_bytes(shards.as_ref(), Some(key), None)
+}
+
+/// Reconstruct only the bytes of the secret within `range` from a set of
+/// `Shard`s.
+///
+/// Only the chunks of the secret overlapping `range` are interpolated, so this
+/// is much faster than `recover_secret` when only a small part of a large
+/// secret is needed (such as a file header). The requirements on `shards` are
+/// the same as `recover_secret`.
+///
+/// Like `recover_secret`, the MACs of authenticated shards are **not**
+/// checked. Use `recover_secret_with_key` if the recovered data needs to be
+/// authenticated.
+pub fn recover_secret_range<F: GfField, S: AsRef<[GenericShard<F>]>>(
+    shards: S,
+    range: Range<usize>,
+) -> Result<Vec<u8>, RecoverError> {
+    recover_bytes(shards.as_ref(), None, Some(range))
+}
+
+// Recover the bytes of the secret within range (or the whole secret).
+fn recover_bytes<F: GfField>(
+    shards: &[GenericShard<F>],
+    key: Option<&MacKey>,
+    range: Option<Range<usize>>,
+) -> Result<Vec<u8>, RecoverError> {
+    let shards = unique_shards(shards, key)?;
+    let first = shards[0];
+
+    let threshold = first.threshold;
+    let secret_len = first.secret_len;
+    let chunk_size = first.chunk_size;
+    let shards = &shards[..threshold as usize];
+
+    let range = range.unwrap_or(0..secret_len);
+    if range.start > range.end || range.end > secret_len {
+        return Err(ShardError::RangeOutOfBounds {
+            start: range.start,
+            end: range.end,
+            secret_len,
+        });
+    }
+    // Indices of the polynomials which overlap with the range.
+    let per_poly = first.scheme.chunks_per_poly(threshold);
+    let polys =
+        range.start / chunk_size / per_poly..range.end.div_ceil(chunk_size).div_ceil(per_poly);
+    let offset = range.start - polys.start * per_poly * chunk_size;
+
+    let mut constants = interpolate_chunks(shards, polys);
+    let secret = constants
+        .iter()
+        .flat_map(|x| x.to_bytes().into_iter().take(chunk_size))
+        .skip(offset)
+        .take(range.len())
+        .collect::<Vec<_>>();
+    constants.zeroize();
+    Ok(secret)
+}
+
+// Interpolate the secret chunks stored in the given polynomials, from exactly
+// threshold unique shards.
+fn interpolate_chunks<F: GfField>(
+    shards: &[&GenericShard<F>],
+    polys: Range<usize>,
+) -> Vec<GfElem<F>> {
+    let first = shards[0];
+    let threshold = first.threshold;
+    match (threshold, first.scheme) {
+        // With a threshold of one, the single shard's y-values are the secret.
+        (1, _) => first.ys[polys].to_vec(),
+        // Every chunk shares the same x-values, so the Lagrange basis
+        // polynomials (evaluated at zero) only need to be computed once. Each
+        // chunk's secret is then just a weighted sum of its y-values.
+        (_, Scheme::Shamir) => {
+            let xs = shards.iter().map(|s| s.x).collect::<Vec<_>>();
+            let weights = GfPolynomial::lagrange_weights(&xs, GfElem::ZERO)
+                .into_iter()
+                .map(|w| GfMultiplier::new(w, polys.len()))
+                .collect::<Vec<_>>();
+            map_chunks(polys.len(), |i| {
+                shards
+                    .iter()
+                    .zip(&weights)
+                    .fold(GfElem::ZERO, |acc, (s, w)| {
+                        acc + w.mul(s.ys[polys.start + i])
+                    })
+            })
+        }
+        // Every coefficient is part of the secret, so each polynomial must be
+        // interpolated in full.
+        (_, Scheme::Ramp) => map_chunks(polys.len(), |i| {
+            let points = shards
+                .iter()
+                .map(|s| (s.x, s.ys[polys.start + i]))
+                .collect::<Vec<_>>();
+            GfPolynomial::lagrange(threshold - 1, points.as_slice())
+        })
+        .iter()
+        .flat_map(|poly| poly.coeffs().iter().copied())
+        .collect::<Vec<_>>(),
+    }
+}
+
+/// Reconstruct a secret from a set of `Shard`s, writing it to `writer` as it
+/// is recovered. Returns the number of bytes written.
+///
+/// Unlike `recover_secret`, the secret is interpolated in small batches of
+/// chunks and each batch is written (and zeroed) before the next is computed,
+/// so the full secret is never held in memory. The requirements on `shards`
+/// are the same as `recover_secret`.
+///
+/// If the shards cannot be used to recover the secret, an
+/// `io::ErrorKind::InvalidData` error is returned (wrapping the `ShardError`)
+/// before anything is written. Like `recover_secret`, the MACs of
+/// authenticated shards are **not** checked; use `recover_secret_to_with_key`
+/// for authenticated shards.
+#[cfg(feature = "std")]
+pub fn recover_secret_to<F, S, W>(shards: S, writer: &mut W) -> io::Result<u64>
+where
+    F: GfField,
+    S: AsRef<[GenericShard<F>]>,
+    W: Write + ?Sized,
+{
+    write_secret(shards.as_ref(), None, writer)
+}
+
+/// Reconstruct a secret from a set of authenticated `Shard`s, writing it to
+/// `writer` as it is recovered. Returns the number of bytes written.
+///
+/// This is like `recover_secret_to`, except that the MAC of every shard is
+/// verified with `key` before anything is written to `writer`.
+#[cfg(feature = "std")]
+pub fn recover_secret_to_with_key<F, S, W>(
+    shards: S,
+    key: &MacKey,
+    writer: &mut W,
+) -> io::Result<u64>
+where
+    F: GfField,
+    S: AsRef<[GenericShard<F>]>,
+    W: Write + ?Sized,
+{
+    write_secret(shards.as_ref(), Some(key), writer)
+}
+
+#[cfg(feature = "std")]
+fn write_secret<F: GfField, W: Write + ?Sized>(
+    shards: &[GenericShard<F>],
+    key: Option<&MacKey>,
+    writer: &mut W,
+) -> io::Result<u64> {
+    // Number of polynomials interpolated at a time.
+    const BATCH_POLYS: usize = 1024;
+
+    let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+
+    let unique = unique_shards(shards, key).map_err(invalid)?;
+    let first = unique[0];
+    let unique = &unique[..first.threshold as usize];
+
+    let mut remaining = first.secret_len;
+    let mut start = 0;
+    while remaining > 0 && start < first.ys.len() {
+        let polys = start..(start + BATCH_POLYS).min(first.ys.len());
+        start = polys.end;
+
+        let mut constants = interpolate_chunks(unique, polys);
+        let mut batch = constants
+            .iter()
+            .flat_map(|x| x.to_bytes().into_iter().take(first.chunk_size))
+            .take(remaining)
+            .collect::<Vec<_>>();
+        constants.zeroize();
+        remaining -= batch.len();
+
+        let result = writer.write_all(&batch);
+        batch.zeroize();
+        result?;
+    }
+
+    Ok((first.secret_len - remaining) as u64)
+}
+
+/// Create a new `Dealer` for the secret stored in a set of `Shard`s, but with a
+/// different `threshold`.
+///
+/// This allows an existing backup to be converted (for instance) from a 2-of-3
+/// scheme to a 3-of-5 scheme. The secret is only ever held in memory, and is
+/// zeroed once the new `Dealer` has been constructed. The chunk size and
+/// sharing scheme are carried over from the old shards, but MACs are neither
+/// checked nor attached (use `Dealer::authenticated` on the result, after
+/// checking the old shards with `recover_secret_with_key` if needed).
+///
+/// The new `Dealer` uses entirely new polynomials, so its shards cannot be
+/// combined with the old shards (which should be destroyed, as they can still
+/// be used to recover the secret with the old threshold).
+///
+/// Panics if `threshold` is zero (like `Dealer::new`).
+#[cfg(feature = "std")]
+pub fn reshard<F: GfField, S: AsRef<[GenericShard<F>]>>(
+    shards: S,
+    threshold: u32,
+) -> Result<GenericDealer<F>, ShardError> {
+    let first = shards.as_ref().first().ok_or(ShardError::NoShards)?;
+    let chunk_size = first.chunk_size;
+
+    let mut secret = recover_secret(shards.as_ref())?;
+    let dealer = match first.scheme {
+        Scheme::Shamir => GenericDealer::new_in_field(threshold, chunk_size, &secret),
+        Scheme::Ramp => {
+            GenericDealer::new_ramp_in_field_with_rng(threshold, chunk_size, &secret, &mut OsRng)
+        }
+    };
+    secret.zeroize();
+
+    Ok(dealer)
+}
+
+/// Reconstruct a secret from a set of `Shard`s, some of which may have been
+/// corrupted.
+///
+/// If `threshold + 2t` unique shards are provided, up to `t` of them can have
+/// incorrect y-values and the secret will still be recovered correctly (using
+/// the [Berlekamp-Welch algorithm][berlekamp-welch]). If there are too many
+/// errors to correct, `ShardError::TooManyErrors` is returned.
+///
+/// This is much slower than `recover_secret` if any of the shards are
+/// actually corrupted, so it should only be used if `recover_secret` has
+/// produced an incorrect secret.
+///
+/// [berlekamp-welch]: https://en.wikipedia.org/wiki/Berlekamp%E2%80%93Welch_algorithm
+pub fn recover_secret_corrected<F: GfField, S: AsRef<[GenericShard<F>]>>(
+    shards: S,
+) -> Result<Vec<u8>, ShardError> {
+    let shards = unique_shards(shards.as_ref(), None)?;
+    let first = shards[0];
+
+    let threshold = first.threshold;
+    let polys_len = first.ys.len();
+    let secret_len = first.secret_len;
+    let chunk_size = first.chunk_size;
+    let max_errors = (shards.len() - threshold as usize) / 2;
+
+    // In the common case most chunks will not be corrupted, so we first check
+    // if the extra shards agree with the polynomial interpolated from the
+    // first threshold shards (the weights for which are shared by all chunks).
+    let (base, extra) = shards.split_at(threshold as usize);
+    let base_xs = base.iter().map(|s| s.x).collect::<Vec<_>>();
+    let multipliers = |weights: Vec<GfElem<F>>| {
+        weights
+            .into_iter()
+            .map(|w| GfMultiplier::new(w, polys_len))
+            .collect::<Vec<_>>()
+    };
+    let extra_weights = extra
+        .iter()
+        .map(|s| multipliers(GfPolynomial::lagrange_weights(&base_xs, s.x)))
+        .collect::<Vec<_>>();
+    let zero_weights = multipliers(GfPolynomial::lagrange_weights(&base_xs, GfElem::ZERO));
+
+    let mut points = Vec::with_capacity(shards.len());
+    let mut secret = Vec::with_capacity(secret_len);
+    if first.scheme == Scheme::Ramp {
+        // Every coefficient is part of the secret, so the polynomials must be
+        // interpolated in full.
+        for i in 0..polys_len {
+            points.clear();
+            points.extend(shards.iter().map(|s| (s.x, s.ys[i])));
+            let poly = match max_errors {
+                0 => Some(GfPolynomial::lagrange(
+                    threshold - 1,
+                    &points[..threshold as usize],
+                )),
+                _ => GfPolynomial::berlekamp_welch(threshold - 1, max_errors, points.as_slice()),
+            };
+            match poly {
+                Some(poly) => poly
+                    .coeffs()
+                    .iter()
+                    .for_each(|c| secret.extend(c.to_bytes().into_iter().take(chunk_size))),
+                None => {
+                    secret.zeroize();
+                    return Err(ShardError::TooManyErrors);
+                }
+            }
+        }
+        secret.truncate(secret_len);
+        return Ok(secret);
+    }
+    for i in 0..polys_len {
+        let interpolate = |weights: &[GfMultiplier<F>]| {
+            base.iter()
+                .zip(weights)
+                .fold(GfElem::ZERO, |acc, (s, w)| acc + w.mul(s.ys[i]))
+        };
+
+        let clean = extra
+            .iter()
+            .zip(&extra_weights)
+            .all(|(s, weights)| interpolate(weights) == s.ys[i]);
+        let constant = match clean {
+            true => interpolate(&zero_weights),
+            false => {
+                points.clear();
+                points.extend(shards.iter().map(|s| (s.x, s.ys[i])));
+                match GfPolynomial::berlekamp_welch(threshold - 1, max_errors, points.as_slice()) {
+                    Some(poly) => poly.constant(),
+                    None => {
+                        // Don't leave a partially-recovered secret around.
+                        secret.zeroize();
+                        return Err(ShardError::TooManyErrors);
+                    }
+                }
+            }
+        };
+        secret.extend(constant.to_bytes().into_iter().take(chunk_size));
+    }
+    secret.truncate(secret_len);
+
+    Ok(secret)
+}
+
+/// Check whether `shard` lies on the polynomials interpolated from `base`.
+#[cfg(feature = "std")]
+fn agrees_with<F: GfField>(base: &[&GenericShard<F>], shard: &GenericShard<F>) -> bool {
+    let xs = base.iter().map(|s| s.x).collect::<Vec<_>>();
+    let weights = GfPolynomial::lagrange_weights(&xs, shard.x)
+        .into_iter()
+        .map(|w| GfMultiplier::new(w, shard.ys.len()))
+        .collect::<Vec<_>>();
+    shard.ys.iter().enumerate().all(|(chunk, y)| {
+        let expected = base
+            .iter()
+            .zip(&weights)
+            .fold(GfElem::ZERO, |acc, (s, w)| acc + w.mul(s.ys[chunk]));
+        expected == *y
+    })
+}
+
+/// Report produced by `recover_secret_with_report`, describing how the secret
+/// was recovered.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg(feature = "std")]
+pub struct RecoveryReport {
+    used: Vec<String>,
+    duplicates: usize,
+    cross_checked: Vec<String>,
+    mismatched: Vec<String>,
+    shard_count: usize,
+    threshold: u32,
+    secret_len: usize,
+    elapsed: Duration,
+}
+
+#[cfg(feature = "std")]
+impl RecoveryReport {
+    /// Identifiers of the shards which were used to recover the secret.
+    pub fn used_ids(&self) -> &[String] {
+        &self.used
+    }
+
+    /// Number of shards which were ignored because they were exact copies of
+    /// another shard.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    /// Identifiers of the extra shards (beyond the threshold) which agree
+    /// with the shards used to recover the secret.
+    pub fn cross_checked_ids(&self) -> &[String] {
+        &self.cross_checked
+    }
+
+    /// Identifiers of the extra shards (beyond the threshold) which do *not*
+    /// agree with the shards used to recover the secret.
+    pub fn mismatched_ids(&self) -> &[String] {
+        &self.mismatched
+    }
+
+    /// Returns whether every extra shard agreed with the recovered secret, or
+    /// `None` if there were no extra shards to check against.
+    pub fn cross_check_passed(&self) -> Option<bool> {
+        match (self.cross_checked.len(), self.mismatched.len()) {
+            (0, 0) => None,
+            (_, mismatched) => Some(mismatched == 0),
+        }
+    }
+
+    /// Total number of shards provided (including duplicates).
+    pub fn shard_count(&self) -> usize {
+        self.shard_count
+    }
+
+    /// Number of shards required to recover the secret.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Length (in bytes) of the recovered secret.
+    pub fn secret_len(&self) -> usize {
+        self.secret_len
+    }
+
+    /// Time taken to recover (and cross-check) the secret.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// Reconstruct a secret from a set of `Shard`s (like `recover_secret`), also
+/// returning a `RecoveryReport` describing the recovery.
+///
+/// Any unique shards beyond the threshold are checked against the shards used
+/// to recover the secret. Shards which do not agree are listed in the report
+/// rather than causing recovery to fail.
+#[cfg(feature = "std")]
+pub fn recover_secret_with_report<F: GfField, S: AsRef<[GenericShard<F>]>>(
+    shards: S,
+) -> Result<(Vec<u8>, RecoveryReport), RecoverError> {
+    let start = Instant::now();
+    let shards = shards.as_ref();
+    let mut secret = recover_secret(shards)?;
+
+    let unique = match unique_shards(shards, None) {
+        Ok(unique) => unique,
+        Err(err) => {
+            secret.zeroize();
+            return Err(err);
+        }
+    };
+    let first = unique[0];
+    let (used, extra) = unique.split_at(first.threshold as usize);
+    let (cross_checked, mismatched): (Vec<&GenericShard<F>>, Vec<_>) =
+        extra.iter().partition(|shard| agrees_with(used, shard));
+
+    let report = RecoveryReport {
+        used: used.iter().map(|s| s.id()).collect(),
+        duplicates: shards.len() - unique.len(),
+        cross_checked: cross_checked.iter().map(|s| s.id()).collect(),
+        mismatched: mismatched.iter().map(|s| s.id()).collect(),
+        shard_count: shards.len(),
+        threshold: first.threshold,
+        secret_len: first.secret_len,
+        elapsed: start.elapsed(),
+    };
+    Ok((secret, report))
+}
+
+/// Report produced by `check_consistency`, listing which `Shard`s agree with
+/// the majority and which do not.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg(feature = "std")]
+pub struct ConsistencyReport {
+    consistent: Vec<String>,
+    inconsistent: Vec<String>,
+}
+
+#[cfg(feature = "std")]
+impl ConsistencyReport {
+    /// Returns whether every shard agreed with the majority.
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistent.is_empty()
+    }
+
+    /// Identifiers of the shards which agree with the majority.
+    pub fn consistent_ids(&self) -> &[String] {
+        &self.consistent
+    }
+
+    /// Identifiers of the shards which disagree with the majority (and are
+    /// thus either corrupted, mis-transcribed or forged).
+    pub fn inconsistent_ids(&self) -> &[String] {
+        &self.inconsistent
+    }
+}
+
+/// Cross-check a set of `Shard`s against each other, to figure out which (if
+/// any) shards are inconsistent with the rest.
+///
+/// Every subset of `threshold` unique shards is used to interpolate the
+/// secret's polynomials, and the remaining shards are checked against them.
+/// The subset which the most shards agree with is taken to be correct, and any
+/// shards which disagree with it are reported as inconsistent. Note that this
+/// requires checking every subset, so it is only practical for small numbers
+/// of shards.
+///
+/// If only `threshold` unique shards are given, there is nothing to compare
+/// them against and so they are all reported as consistent. If more shards
+/// were given but none of the extra shards agree with any subset, there is no
+/// majority and `ShardError::NoConsensus` is returned.
+#[cfg(feature = "std")]
+pub fn check_consistency<F: GfField, S: AsRef<[GenericShard<F>]>>(
+    shards: S,
+) -> Result<ConsistencyReport, ShardError> {
+    let shards = unique_shards(shards.as_ref(), None)?;
+    let threshold = shards[0].threshold as usize;
+
+    // Indices of the shards which agree with the polynomials interpolated from
+    // the given subset of shards.
+    let supporters = |subset: &[usize]| {
+        let base = subset.iter().map(|i| shards[*i]).collect::<Vec<_>>();
+        (0..shards.len())
+            .filter(|i| subset.contains(i) || agrees_with(&base, shards[*i]))
+            .collect::<Vec<_>>()
+    };
+
+    let majority = (0..shards.len())
+        .combinations(threshold)
+        .map(|subset| supporters(&subset))
+        .max_by_key(Vec::len)
+        .expect("must be at least one subset of shards");
+
+    if majority.len() == threshold && shards.len() > threshold {
+        return Err(ShardError::NoConsensus);
+    }
+
+    let (consistent, inconsistent): (Vec<_>, Vec<_>) =
+        (0..shards.len()).partition(|i| majority.contains(i));
+    Ok(ConsistencyReport {
+        consistent: consistent.iter().map(|i| shards[*i].id()).collect(),
+        inconsistent: inconsistent.iter().map(|i| shards[*i].id()).collect(),
+    })
+}
+
+/// CBOR support for `Shard` (see `crate::cbor`).
+///
+/// `Shard`s are encoded as a map of their fields, with a version of its own
+/// (since the CBOR encoding doesn't share the framing of the wire format).
+#[cfg(feature = "cbor")]
+mod cbor {
+    use super::*;
+
+    use crate::cbor::{FromCbor, MapReader, ToCbor, Value};
+
+    use core::convert::TryFrom;
+
+    // Version of the CBOR layout of a shard. This is versioned separately from
+    // the wire format, since the CBOR encoding does not share its framing.
+    const CBOR_VERSION: u32 = 1;
+
+    impl<F: GfField> ToCbor for GenericShard<F> {
+        fn to_cbor(&self) -> Vec<u8> {
+            let mut fields = vec![
+                ("version".into(), Value::Uint(u64::from(CBOR_VERSION))),
+                ("x".into(), Value::Uint(self.x.inner())),
+                (
+                    "ys".into(),
+                    Value::Array(self.ys.iter().map(|y| Value::Uint(y.inner())).collect()),
+                ),
+                ("threshold".into(), Value::Uint(u64::from(self.threshold))),
+                ("secret_len".into(), Value::Uint(self.secret_len as u64)),
+                ("chunk_size".into(), Value::Uint(self.chunk_size as u64)),
+                ("field_bits".into(), Value::Uint(u64::from(F::BITS))),
+                (
+                    "scheme".into(),
+                    Value::Uint(u64::from(self.scheme.wire_id())),
+                ),
+                ("dealer_id".into(), Value::Bytes(self.dealer_id.to_vec())),
+            ];
+            if let Some(mac) = &self.mac {
+                fields.push(("mac".into(), Value::Bytes(mac.to_vec())));
+            }
+            Value::Map(fields).encode()
+        }
+    }
+
+    impl<F: GfField> FromCbor for GenericShard<F> {
+        fn from_cbor<B: AsRef<[u8]>>(input: B) -> Result<Self, WireError> {
+            let mut map = MapReader::new(Value::decode(input.as_ref())?)?;
+
+            match map.take_u32("version")? {
+                CBOR_VERSION => (),
+                version => return Err(WireError::UnsupportedVersion { version }),
+            }
+            let field_bits = map.take_u32("field_bits")?;
+            if field_bits != F::BITS {
+                return Err(format!(
+                    "shard is in GF(2^{}) but expected GF(2^{})",
+                    field_bits,
+                    F::BITS
+                )
+                .into());
+            }
+
+            let elem = |v| {
+                GfElem::from_inner(v).ok_or_else(|| {
+                    WireError::from(format!("shard value {} is not in GF(2^{})", v, F::BITS))
+                })
+            };
+            let x = elem(map.take_uint("x")?)?;
+            let ys = match map.take("ys")? {
+                Value::Array(ys) => ys
+                    .into_iter()
+                    .map(|y| match y {
+                        Value::Uint(y) => elem(y),
+                        _ => Err(String::from("shard y-values must be integers").into()),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                _ => return Err(String::from("shard y-values must be an array").into()),
+            };
+            let threshold = map.take_u32("threshold")?;
+            let secret_len = map.take_usize("secret_len")?;
+            WireLimits::check("secret", WireLimits::DEFAULT.max_secret_len, secret_len)?;
+            let chunk_size = map.take_usize("chunk_size")?;
+            if chunk_size == 0 || chunk_size > F::BYTES {
+                return Err(format!(
+                    "shard chunk size must be between 1 and {} not '{}'",
+                    F::BYTES,
+                    chunk_size
+                )
+                .into());
+            }
+            let scheme = map.take_u32("scheme")?;
+            let scheme = Scheme::from_wire_id(scheme)
+                .ok_or_else(|| format!("unknown shard sharing scheme '{}'", scheme))?;
+            let max_secret_len = scheme.max_secret_len(threshold, ys.len(), chunk_size);
+            if secret_len > max_secret_len {
+                return Err(format!(
+                    "shard secret length {} is longer than the {} bytes its y-values hold",
+                    secret_len, max_secret_len
+                )
+                .into());
+            }
+
+            let dealer_id = map.take_bytes("dealer_id")?;
+            let dealer_id = <[u8; DEALER_ID_LENGTH]>::try_from(dealer_id.as_slice())
+                .map_err(|_| format!("shard dealer id must be {} bytes", DEALER_ID_LENGTH))?;
+            let mac = match map.take_optional("mac") {
+                None => None,
+                Some(Value::Bytes(mac)) => Some(
+                    <[u8; MAC_LENGTH]>::try_from(mac.as_slice())
+                        .map_err(|_| format!("shard mac must be {} bytes", MAC_LENGTH))?,
+                ),
+                Some(_) => return Err(String::from("shard mac must be a byte string").into()),
+            };
+            map.finish()?;
+
+            Ok(GenericShard {
+                x,
+                ys,
+                threshold,
+                secret_len,
+                chunk_size,
+                scheme,
+                dealer_id,
+                mac,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        use crate::shamir::gf::Gf16;
+
+        #[quickcheck]
+        fn shard_cbor_roundtrip(shard: Shard) -> bool {
+            let bytes = shard.to_cbor();
+            Shard::from_cbor(&bytes).as_ref() == Ok(&shard)
+                && GenericShard::<Gf16>::from_cbor(&bytes).is_err()
+        }
+
+        #[test]
+        fn shard_cbor_secret_too_long() {
+            let mut shard = Dealer::new(1, [0x42; 29]).next_shard();
+            shard.ys.truncate(1);
+            assert!(Shard::from_cbor(shard.to_cbor()).is_err());
+        }
+    }
+}
+
+/// `serde` support for `Shard` (and, with the `serde-dealer` feature,
+/// `Dealer`).
+///
+/// `Shard`s are serialised as their wire encoding (see `ToWire`), so that the
+/// same validation is done as with `FromWire`.
+#[cfg(feature = "serde")]
+mod serialize {
+    use super::*;
+
+    use serde::{
+        de::{self, Deserializer, SeqAccess, Visitor},
+        Deserialize, Serialize, Serializer,
+    };
+
+    impl<F: GfField> Serialize for GenericShard<F> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.to_wire())
+        }
+    }
+
+    struct ShardVisitor<F: GfField>(std::marker::PhantomData<F>);
+
+    impl<'de, F: GfField> Visitor<'de> for ShardVisitor<F> {
+        type Value = GenericShard<F>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a paperback shard in wire format")
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            GenericShard::from_wire(v).map_err(E::custom)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+            while let Some(b) = seq.next_element::<u8>()? {
+                bytes.push(b);
+            }
+            self.visit_bytes(&bytes)
+        }
+    }
+
+    impl<'de, F: GfField> Deserialize<'de> for GenericShard<F> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_bytes(ShardVisitor(std::marker::PhantomData))
+        }
+    }
+
+    // Serialised form of a Dealer. This contains the secret (and everything
+    // needed to create new shards for it) in plaintext.
+    #[cfg(feature = "serde-dealer")]
+    #[derive(Serialize, Deserialize)]
+    struct RawDealer {
+        field_bits: u32,
+        threshold: u32,
+        scheme: u32,
+        secret_len: usize,
+        chunk_size: usize,
+        coeffs: Vec<GfElemPrimitive>,
+        issued_xs: Vec<GfElemPrimitive>,
+        next_index: GfElemPrimitive,
+        dealer_id: [u8; DEALER_ID_LENGTH],
+        #[serde(default)]
+        issued: Vec<IssuedShard>,
+        mac_key: Option<Vec<u8>>,
+    }
+
+    #[cfg(feature = "serde-dealer")]
+    impl Drop for RawDealer {
+        fn drop(&mut self) {
+            self.coeffs.zeroize();
+            self.mac_key.zeroize();
+        }
+    }
+
+    /// **DANGER**: The serialised form of a `Dealer` contains the secret *in
+    /// plaintext*, as well as everything needed to create new shards. It must
+    /// be protected at least as well as the secret itself. This is only
+    /// available with the `serde-dealer` feature.
+    #[cfg(feature = "serde-dealer")]
+    impl<F: GfField> Serialize for GenericDealer<F> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut issued_xs = self.issued_xs.iter().copied().collect::<Vec<_>>();
+            issued_xs.sort_unstable();
+            RawDealer {
+                field_bits: F::BITS,
+                threshold: self.threshold,
+                scheme: self.scheme.wire_id(),
+                secret_len: self.secret_len,
+                chunk_size: self.chunk_size,
+                coeffs: self.polys.coeffs().iter().map(|c| c.inner()).collect(),
+                issued_xs,
+                next_index: self.next_index,
+                dealer_id: self.dealer_id,
+                issued: self.issued.clone(),
+                mac_key: self.mac_key.map(|key| key.to_vec()),
+            }
+            .serialize(serializer)
+        }
+    }
+
+    /// See the `Serialize` implementation for why this is dangerous.
+    #[cfg(feature = "serde-dealer")]
+    impl<'de, F: GfField> Deserialize<'de> for GenericDealer<F> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let raw = RawDealer::deserialize(deserializer)?;
+            if raw.field_bits != F::BITS {
+                return Err(de::Error::custom(format!(
+                    "dealer is in GF(2^{}) not GF(2^{})",
+                    raw.field_bits,
+                    F::BITS
+                )));
+            }
+            if raw.threshold == 0 {
+                return Err(de::Error::custom("dealer must have a non-zero threshold"));
+            }
+            if raw.chunk_size == 0 || raw.chunk_size > F::BYTES {
+                return Err(de::Error::custom("dealer has an invalid chunk size"));
+            }
+            let scheme = Scheme::from_wire_id(raw.scheme)
+                .ok_or_else(|| de::Error::custom("dealer has an unknown sharing scheme"))?;
+            let coeffs = raw
+                .coeffs
+                .iter()
+                .map(|c| GfElem::from_inner(*c))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| de::Error::custom("dealer coefficient outside field"))?;
+            let polys = GfPolynomials::from_coeffs(raw.threshold - 1, coeffs)
+                .ok_or_else(|| de::Error::custom("dealer has partial polynomial"))?;
+            let chunks = raw.secret_len.div_ceil(raw.chunk_size);
+            if polys.iter().count() != chunks.div_ceil(scheme.chunks_per_poly(raw.threshold)) {
+                return Err(de::Error::custom("dealer secret length is inconsistent"));
+            }
+            let mac_key = match &raw.mac_key {
+                None => None,
+                Some(key) if key.len() == MAC_KEY_LENGTH => {
+                    let mut mac_key = [0u8; MAC_KEY_LENGTH];
+                    mac_key.copy_from_slice(key);
+                    Some(mac_key)
+                }
+                Some(_) => return Err(de::Error::custom("dealer has invalid mac key length")),
+            };
+            Ok(GenericDealer {
+                polys,
+                threshold: raw.threshold,
+                scheme,
+                secret_len: raw.secret_len,
+                chunk_size: raw.chunk_size,
+                issued_xs: raw.issued_xs.iter().copied().collect(),
+                next_index: raw.next_index,
+                dealer_id: raw.dealer_id,
+                issued: raw.issued.clone(),
+                mac_key,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        use serde::de::value::{BytesDeserializer, Error, SeqDeserializer};
+
+        #[quickcheck]
+        fn shard_deserialize(shard: Shard) -> bool {
+            let bytes = shard.to_wire();
+            let from_bytes = Shard::deserialize(BytesDeserializer::<Error>::new(&bytes));
+            let from_seq =
+                Shard::deserialize(SeqDeserializer::<_, Error>::new(bytes.iter().copied()));
+            from_bytes == Ok(shard.clone()) && from_seq == Ok(shard)
+        }
+
+        #[quickcheck]
+        fn shard_deserialize_invalid(shard: Shard) -> bool {
+            let mut bytes = shard.to_wire();
+            bytes.push(0);
+            Shard::deserialize(BytesDeserializer::<Error>::new(&bytes)).is_err()
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    use crate::shamir::{
+        gf::{Gf16, Gf64},
+        header::{CRC_LENGTH, WIRE_MAGIC, WIRE_VERSION},
+    };
+
+    use quickcheck::TestResult;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const MAC_KEY: MacKey = [0x5a; MAC_KEY_LENGTH];
+
+    #[quickcheck]
+    fn basic_roundtrip(n: u32, secret: Vec<u8>) -> TestResult {
+        if n < 1 {
+            return TestResult::discard();
+        }
+        let dealer = Dealer::new(n, &secret);
+        TestResult::from_bool(secret == dealer.secret())
+    }
+
+    #[quickcheck]
+    fn try_new_validation(n: u32, max_shards: u32, secret: Vec<u8>) -> TestResult {
+        if n > 32 {
+            return TestResult::discard();
+        }
+        let dealer = Dealer::try_new(n, max_shards, &secret);
+        TestResult::from_bool(match dealer {
+            Err(DealerError::ZeroThreshold) => n == 0,
+            Err(DealerError::ThresholdTooLarge { .. }) => n != 0 && n > max_shards,
+            Err(DealerError::InvalidShardX) => false,
+            Ok(dealer) => n != 0 && n <= max_shards && dealer.secret() == secret,
+        })
+    }
+
+    #[quickcheck]
+    fn shard_bytes_roundtrip(shard: Shard) {
+        let shard2 = Shard::from_wire(&shard.to_wire()).unwrap();
+        assert_eq!(shard, shard2);
+    }
+
+    // Shards of b"baseline secret!!" with a threshold of 2, serialised by the
+    // first version of paperback (before the layout of shards was recorded).
+    const BASELINE_SHARDS: [&[u8]; 2] = [
+        &[
+            0xc1, 0xb5, 0xc2, 0xaf, 0x02, 0x05, 0xc8, 0xb2, 0xe0, 0xb9, 0x0b, 0x92, 0xab, 0xa2,
+            0xa7, 0x01, 0xf0, 0xb2, 0x94, 0xef, 0x04, 0xe0, 0xc7, 0x93, 0xe4, 0x0b, 0xc7, 0x96,
+            0x99, 0xb6, 0x0b, 0x02, 0x11,
+        ],
+        &[
+            0xc1, 0xb1, 0x83, 0xb9, 0x01, 0x05, 0x8f, 0xf5, 0xb1, 0xff, 0x0e, 0x8a, 0x8e, 0xf4,
+            0xf2, 0x0e, 0xe8, 0xfb, 0xbb, 0x99, 0x09, 0xe9, 0xd8, 0xe6, 0xb3, 0x05, 0x9c, 0x9d,
+            0xe0, 0xea, 0x0a, 0x02, 0x11,
+        ],
+    ];
+
+    #[test]
+    fn shard_legacy_layout() {
+        let shards = BASELINE_SHARDS
+            .iter()
+            .map(|bytes| {
+                let (shard, remain) =
+                    Shard::from_legacy_wire_body(bytes, &WireLimits::DEFAULT).unwrap();
+                assert!(remain.is_empty());
+                assert_eq!(shard.chunk_size(), 4);
+                assert_eq!(shard.scheme, Scheme::Shamir);
+                assert!(!shard.is_authenticated());
+
+                let mut rewritten = vec![];
+                shard.write_legacy_wire_bytes(&mut rewritten);
+                assert_eq!(&rewritten[..], *bytes);
+                shard
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(recover_secret(&shards).unwrap(), b"baseline secret!!");
+        // The current layout has more fields, so legacy shards are rejected.
+        assert!(Shard::from_wire_body(BASELINE_SHARDS[0], &WireLimits::DEFAULT).is_err());
+        assert!(GenericShard::<Gf16>::from_legacy_wire_body(
+            BASELINE_SHARDS[0],
+            &WireLimits::DEFAULT
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn shard_wire_baseline() {
+        // Shards without a header are decoded with the legacy layout.
+        let shards = BASELINE_SHARDS
+            .iter()
+            .map(|bytes| Shard::from_wire(bytes).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(recover_secret(&shards).unwrap(), b"baseline secret!!");
+
+        // They are written back with the current version, and stay the same.
+        for shard in &shards {
+            let bytes = shard.to_wire();
+            assert!(bytes.starts_with(&WIRE_MAGIC));
+            assert_eq!(Shard::from_wire(&bytes).as_ref(), Ok(shard));
+        }
+        assert!(GenericShard::<Gf16>::from_wire(BASELINE_SHARDS[0]).is_err());
+    }
+
+    #[test]
+    fn default_field_inference() {
+        // The original API (without any field annotations) keeps working.
+        let mut dealer = Dealer::new(2, b"default field");
+        let shards = vec![dealer.next_shard(), dealer.next_shard()];
+        let shard = Shard::from_wire(shards[0].to_wire()).unwrap();
+        assert_eq!(shard, shards[0]);
+        assert_eq!(recover_secret(&shards).unwrap(), b"default field");
+        assert_eq!(Dealer::recover(&shards).unwrap().secret(), b"default field");
+    }
+
+    #[test]
+    fn shard_wire_secret_too_long() {
+        // A shard whose secret doesn't fit in its y-values.
+        let mut shard = Dealer::new(1, [0x42; 29]).next_shard();
+        shard.ys.truncate(1);
+
+        let mut legacy = vec![];
+        shard.write_legacy_wire_bytes(&mut legacy);
+        for bytes in &[shard.to_wire(), legacy] {
+            assert!(matches!(
+                Shard::from_wire(bytes),
+                Err(WireError::InField { field, .. }) if field == "secret_len"
+            ));
+        }
+
+        // Ramp shards hold threshold chunks in each y-value.
+        let mut shard = Dealer::new_ramp(2, [0x42; 29]).next_shard();
+        assert_eq!(shard.ys.len(), 4);
+        assert!(Shard::from_wire(shard.to_wire()).is_ok());
+        shard.ys.truncate(3);
+        assert!(Shard::from_wire(shard.to_wire()).is_err());
+    }
+
+    // The shard with everything the legacy layout cannot store reset to the
+    // defaults of that layout, along with its legacy encoding.
+    fn legacy_shard(shard: &Shard) -> (Shard, Vec<u8>) {
+        let mut shard = shard.clone();
+        shard.chunk_size = Gf32::BYTES;
+        shard.scheme = Scheme::Shamir;
+        shard.dealer_id = [0u8; DEALER_ID_LENGTH];
+        shard.mac = None;
+        let mut bytes = vec![];
+        shard.write_legacy_wire_bytes(&mut bytes);
+        (shard, bytes)
+    }
+
+    #[quickcheck]
+    fn shard_wire_errors(shard: Shard) -> bool {
+        let bytes = shard.to_wire();
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+
+        // A varint which never terminates.
+        let mut overflow = bytes[..WIRE_MAGIC.len() + 1].to_vec();
+        overflow.extend_from_slice(&[0xff; 16]);
+
+        // Any damage to the shard itself is caught by the checksum.
+        let mut damaged = bytes.clone();
+        damaged[bytes.len() - CRC_LENGTH - 1] ^= 1;
+
+        let cause = |bytes: &[u8]| Shard::from_wire(bytes).map_err(|err| err.cause().clone());
+
+        cause(&bytes[..bytes.len() - 1]) == Err(WireError::UnexpectedEof)
+            && cause(&trailing) == Err(WireError::TrailingData { length: 1 })
+            && cause(&overflow) == Err(WireError::InvalidVarint)
+            && cause(&damaged) == Err(WireError::Corrupted)
+    }
+
+    #[quickcheck]
+    fn shard_wire_error_fields(shard: Shard) -> bool {
+        // Offsets are relative to the whole input, including the framing
+        // before the shard itself.
+        let frame = |body: &[u8]| {
+            let bytes = header::write_record(DocumentKind::Shard, body);
+            let body_offset = bytes.len() - CRC_LENGTH - body.len();
+            (bytes, body_offset)
+        };
+
+        // The second y-value is not an element of GF(2^32).
+        let mut bad_y = vec![];
+        encoding::varint(shard.x.inner(), &mut bad_y);
+        encoding::varint(2, &mut bad_y);
+        let y_offset = bad_y.len() + 1;
+        encoding::varint(1, &mut bad_y);
+        encoding::varint(u64::MAX, &mut bad_y);
+        let (bad_y, body_offset) = frame(&bad_y);
+        let bad_y = Shard::from_wire(&bad_y).unwrap_err();
+
+        // The shard ends part-way through the MAC (or its length).
+        let body = shard.wire_bytes();
+        let (truncated, truncated_offset) = frame(&body[..body.len() - 1]);
+        let truncated = Shard::from_wire(&truncated).unwrap_err();
+        let mac_len = shard.mac.map_or(0, |mac| mac.len());
+
+        bad_y.field() == Some("y[1]")
+            && bad_y.offset() == Some(body_offset + y_offset)
+            && matches!(bad_y.cause(), WireError::Malformed(_))
+            && truncated.field() == Some("mac")
+            && truncated.offset() == Some(truncated_offset + body.len() - mac_len - 1)
+    }
+
+    #[quickcheck]
+    fn shard_wire_canonical(shard: Shard) -> bool {
+        let bytes = shard.to_wire();
+        let body = shard.wire_bytes();
+
+        // Encode x with a redundant trailing zero byte.
+        let mut x = vec![];
+        encoding::varint(shard.x.inner(), &mut x);
+        let mut overlong = x[..x.len() - 1].to_vec();
+        overlong.extend_from_slice(&[x[x.len() - 1] | 0x80, 0x00]);
+        overlong.extend_from_slice(&body[x.len()..]);
+        let overlong = header::write_record(DocumentKind::Shard, &overlong);
+
+        Shard::from_wire(&bytes).map(|shard| shard.to_wire()) == Ok(bytes)
+            && Shard::from_wire(&overlong).map_err(|err| err.cause().clone())
+                == Err(WireError::InvalidVarint)
+    }
+
+    #[quickcheck]
+    fn shard_to_wire_into(shard: Shard, other: Shard, prefix: Vec<u8>) -> bool {
+        let mut bytes = prefix.clone();
+        shard.to_wire_into(&mut bytes);
+        other.to_wire_into(&mut bytes);
+
+        shard.wire_len() == shard.to_wire().len()
+            && bytes == [prefix, shard.to_wire(), other.to_wire()].concat()
+    }
+
+    #[quickcheck]
+    fn shard_from_reader(shard: Shard, other: Shard) -> bool {
+        use std::io::{Cursor, ErrorKind};
+
+        // Shards of every version can be read back-to-back from one stream.
+        let (other, legacy) = legacy_shard(&other);
+        let mut stream = shard.to_wire();
+        stream.extend(legacy);
+        stream.extend(shard.to_wire());
+        let mut reader = Cursor::new(&stream);
+
+        // The final shard is cut short.
+        let mut truncated = Cursor::new(&stream[..stream.len() - 1]);
+        let complete = (0..2).all(|_| Shard::from_reader(&mut truncated).is_ok());
+
+        Shard::from_reader(&mut reader).as_ref().ok() == Some(&shard)
+            && Shard::from_reader(&mut reader).as_ref().ok() == Some(&other)
+            && Shard::from_reader(&mut reader).as_ref().ok() == Some(&shard)
+            && matches!(
+                Shard::from_reader(&mut reader),
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof
+            )
+            && complete
+            && matches!(
+                Shard::from_reader(&mut truncated),
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof
+            )
+    }
+
+    #[quickcheck]
+    fn shard_wire_layouts(shard: Shard) -> bool {
+        let fixed = shard.to_wire_with_layout(YsLayout::FixedWidth);
+        // Elements of smaller fields take fewer bytes.
+        let shard16 = GenericDealer::<Gf16>::new_in_field(2, 2, &fixed).next_shard();
+        let fixed16 = shard16.to_wire_with_layout(YsLayout::FixedWidth);
+
+        shard.to_wire_with_layout(YsLayout::Varint) == shard.to_wire()
+            && Shard::from_wire(&fixed).as_ref() == Ok(&shard)
+            && GenericShard::<Gf16>::from_wire(&fixed16).as_ref() == Ok(&shard16)
+            && fixed.len()
+                == shard.wire_len() + shard.ys.len() * Gf32::BYTES
+                    - shard
+                        .ys
+                        .iter()
+                        .map(|y| encoding::varint_len(y.inner()))
+                        .sum::<usize>()
+    }
+
+    #[quickcheck]
+    fn shard_view(shard: Shard) -> bool {
+        let fixed = shard.to_wire_with_layout(YsLayout::FixedWidth);
+        let view = ShardView::<Gf32>::from_wire(&fixed).unwrap();
+
+        view.id() == shard.id()
+            && view.y_count() == shard.y_count()
+            && (0..=shard.y_count())
+                .map(|idx| view.y(idx))
+                .eq(shard.ys().map(Some).chain(Some(None)))
+            && view.to_shard() == shard
+            && matches!(
+                ShardView::<Gf32>::from_wire(&shard.to_wire()),
+                Err(WireError::Malformed(_))
+            )
+    }
+
+    #[quickcheck]
+    fn shard_wire_versions(shard: Shard) -> bool {
+        let bytes = shard.to_wire();
+
+        // Shards written before the header was added must still be readable.
+        // Shards without a header are in the legacy layout.
+        let (legacy_shard, headerless) = legacy_shard(&shard);
+        let legacy = shard.wire_bytes();
+
+        let mut future = WIRE_MAGIC.to_vec();
+        encoding::varint(u64::from(WIRE_VERSION) + 1, &mut future);
+        future.extend_from_slice(&legacy);
+
+        bytes.starts_with(&WIRE_MAGIC)
+            && Shard::from_wire(&bytes).as_ref() == Ok(&shard)
+            && Shard::from_wire(&headerless).as_ref() == Ok(&legacy_shard)
+            && Shard::from_wire(&legacy).is_err()
+            && header::detect(&bytes) == DocumentKind::Shard
+            && matches!(
+                Shard::from_wire_partial_with_limits(&future, &WireLimits::DEFAULT),
+                Err(WireError::UnsupportedVersion { .. })
+            )
+    }
+
+    #[quickcheck]
+    fn shard_wire_limits(shard: Shard) -> bool {
+        let bytes = shard.to_wire();
+        let ys_limit = WireLimits {
+            max_ys: shard.ys.len().saturating_sub(1),
+            ..WireLimits::DEFAULT
+        };
+        let secret_limit = WireLimits {
+            max_secret_len: shard.secret_len.saturating_sub(1),
+            ..WireLimits::DEFAULT
+        };
+
+        // A shard which claims an enormous number of y-values.
+        let mut huge = vec![];
+        encoding::varint(shard.x.inner(), &mut huge);
+        encoding::varint(u64::MAX >> 1, &mut huge);
+
+        matches!(
+            Shard::from_wire_partial_with_limits(&bytes, &WireLimits::DEFAULT),
+            Ok((ref shard2, [])) if shard2 == &shard
+        ) && (shard.ys.is_empty()
+            || matches!(
+                Shard::from_wire_partial_with_limits(&bytes, &ys_limit)
+                    .map_err(|err| err.cause().clone()),
+                Err(WireError::LimitExceeded { field: "ys", .. })
+            ))
+            && (shard.secret_len == 0
+                || matches!(
+                    Shard::from_wire_partial_with_limits(&bytes, &secret_limit)
+                        .map_err(|err| err.cause().clone()),
+                    Err(WireError::LimitExceeded {
+                        field: "secret",
+                        ..
+                    })
+                ))
+            && matches!(
+                Shard::from_wire_partial_with_limits(&huge, &WireLimits::DEFAULT)
+                    .map_err(|err| err.cause().clone()),
+                Err(WireError::LimitExceeded { field: "ys", .. })
+            )
+    }
+
+    #[quickcheck]
+    fn recover_secret_fail(n: u32, secret: Vec<u8>) -> TestResult {
+        // Invalid data. Note that large n values take a very long time to
+        // recover the secret. This is proportional to secret.len(), which is
+        // controlled by quickcheck and thus can be quite large.
+        if n < 2 || n > 32 || secret.len() < 1 {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret);
+        let shards = (0..(n - 1))
+            .map(|_| {
+                let mut shard = dealer.next_shard();
+                shard.threshold -= 1;
+                // Ensure shard IDs are always ID_LENGTH.
                 assert_eq!(shard.id().len(), Shard::ID_LENGTH);
                 shard
             })
             .collect::<Vec<_>>();
 
-        TestResult::from_bool(recover_secret(shards) != secret)
+        TestResult::from_bool(recover_secret(shards).unwrap() != secret)
+    }
+
+    #[quickcheck]
+    fn recover_secret_errors(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(2..=32).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret);
+        let mut shards = (0..n).map(|_| dealer.next_shard()).collect::<Vec<_>>();
+
+        let mut bad_shards = shards.clone();
+        bad_shards[0].threshold += 1;
+        let inconsistent = recover_secret(bad_shards);
+
+        let extra = dealer.next_shard();
+        shards.push(extra);
+        let superset = recover_secret(&shards);
+
+        shards.truncate(n as usize - 1);
+        let not_enough = recover_secret(&shards);
+
+        TestResult::from_bool(
+            recover_secret(Vec::<Shard>::new()) == Err(ShardError::NoShards)
+                && inconsistent == Err(ShardError::InconsistentThreshold)
+                && superset == Ok(secret)
+                && not_enough
+                    == Err(ShardError::NotEnoughShards {
+                        threshold: n,
+                        count: n as usize - 1,
+                    }),
+        )
     }
 
     #[quickcheck]
@@ -362,7 +3068,7 @@ mod test {
             return TestResult::discard();
         }
 
-        let dealer = Dealer::new(n, &secret);
+        let mut dealer = Dealer::new(n, &secret);
         let shards = (0..n)
             .map(|_| {
                 let shard = dealer.next_shard();
@@ -372,7 +3078,567 @@ mod test {
             })
             .collect::<Vec<_>>();
 
-        TestResult::from_bool(recover_secret(shards) == secret)
+        TestResult::from_bool(recover_secret(shards).unwrap() == secret)
+    }
+
+    #[quickcheck]
+    fn recover_secret_chunk_size(n: u32, chunk_size: usize, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) || !(1..=Gf32::BYTES).contains(&chunk_size) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new_with_chunk_size(n, chunk_size, &secret);
+        let shards = (0..n)
+            .map(|_| {
+                let shard = dealer.next_shard();
+                // Each field element must only hold chunk_size bytes.
+                assert_eq!(shard.ys.len(), secret.len().div_ceil(chunk_size));
+                Shard::from_wire(shard.to_wire()).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        TestResult::from_bool(
+            dealer.secret() == secret && recover_secret(shards).unwrap() == secret,
+        )
+    }
+
+    #[quickcheck]
+    fn threshold_one_roundtrip(secret: Vec<u8>) -> bool {
+        let mut dealer = Dealer::new(1, &secret);
+        let shard = dealer.next_shard();
+
+        // The fast-path must produce the same shards as the general case.
+        let ys = dealer.polys.evaluate(shard.x).collect::<Vec<_>>();
+
+        shard.ys == ys
+            && recover_secret(vec![shard.clone()]).unwrap() == secret
+            && Dealer::recover(vec![shard]).unwrap().polys == dealer.polys
+    }
+
+    #[quickcheck]
+    fn recover_duplicate_shards(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(2..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret);
+        let mut shards = (0..n).map(|_| dealer.next_shard()).collect::<Vec<_>>();
+        let id = shards[0].id();
+
+        // Exact copies of a shard are ignored.
+        let mut copies = shards.clone();
+        copies.insert(1, shards[0].clone());
+        if recover_secret(&copies) != Ok(secret) {
+            return TestResult::failed();
+        }
+
+        // But differing shards with the same x-value are an error.
+        let mut conflict = shards[0].clone();
+        match conflict.ys.first_mut() {
+            Some(y) => *y += GfElem::ONE,
+            None => return TestResult::discard(),
+        }
+        shards[n as usize - 1] = conflict;
+
+        TestResult::from_bool(
+            Dealer::recover(&shards).unwrap_err() == ShardError::DuplicateShard { id: id.clone() }
+                && recover_secret(&shards) == Err(ShardError::DuplicateShard { id }),
+        )
+    }
+
+    #[quickcheck]
+    fn check_consistency_finds_bad_shard(n: u32, bad: usize, secret: Vec<u8>) -> TestResult {
+        if !(1..=4).contains(&n) || secret.is_empty() {
+            return TestResult::discard();
+        }
+
+        // Two extra shards, so that one bad shard is still outvoted.
+        let mut dealer = Dealer::new(n, &secret);
+        let mut shards = (0..n + 2).map(|_| dealer.next_shard()).collect::<Vec<_>>();
+        if !check_consistency(&shards).unwrap().is_consistent() {
+            return TestResult::failed();
+        }
+
+        let bad = bad % shards.len();
+        shards[bad].ys[0] += GfElem::ONE;
+        let report = check_consistency(&shards).unwrap();
+
+        TestResult::from_bool(
+            report.inconsistent_ids() == [shards[bad].id()]
+                && report.consistent_ids().len() == shards.len() - 1,
+        )
+    }
+
+    #[quickcheck]
+    fn recover_secret_corrected_success(n: u32, bad: Vec<usize>, secret: Vec<u8>) -> TestResult {
+        if !(1..=4).contains(&n) || secret.is_empty() {
+            return TestResult::discard();
+        }
+
+        // Two extra shards, so that one corrupted shard can be corrected.
+        let mut dealer = Dealer::new(n, &secret);
+        let mut shards = (0..n + 2).map(|_| dealer.next_shard()).collect::<Vec<_>>();
+        if recover_secret_corrected(&shards) != Ok(secret.clone()) {
+            return TestResult::failed();
+        }
+
+        // Corrupt some of the chunks of one shard.
+        let bad_shard = bad.first().copied().unwrap_or(0) % shards.len();
+        for i in bad.iter() {
+            let ys = &mut shards[bad_shard].ys;
+            let len = ys.len();
+            ys[i % len] += GfElem::ONE;
+        }
+
+        TestResult::from_bool(recover_secret_corrected(&shards) == Ok(secret))
+    }
+
+    #[quickcheck]
+    fn recover_secret_authenticated(n: u32, bad: usize, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) || secret.is_empty() {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret).authenticated(&MAC_KEY);
+        let mut shards = (0..n + 1).map(|_| dealer.next_shard()).collect::<Vec<_>>();
+        if !shards.iter().all(Shard::is_authenticated) {
+            return TestResult::failed();
+        }
+        if recover_secret_with_key(&shards, &MAC_KEY) != Ok(secret.clone())
+            || Dealer::recover_with_key(&shards, &MAC_KEY)
+                .unwrap()
+                .next_shard()
+                .verify_mac(&MAC_KEY)
+                != Ok(())
+        {
+            return TestResult::failed();
+        }
+
+        // Tampering with any shard (even one that isn't needed to recover the
+        // secret) must be detected and blamed on that shard.
+        let bad = bad % shards.len();
+        let bad_mac = Err(ShardError::InvalidMac {
+            id: shards[bad].id(),
+        });
+        shards[bad].ys[0] += GfElem::ONE;
+        let tampered = recover_secret_with_key(&shards, &MAC_KEY);
+        let tampered_dealer = Dealer::recover_with_key(&shards, &MAC_KEY).map(|d| d.secret());
+        shards[bad].ys[0] -= GfElem::ONE;
+
+        // The MACs cannot be verified with any other key.
+        let wrong_key = recover_secret_with_key(&shards, &[0u8; MAC_KEY_LENGTH]);
+
+        // Stripping the MAC from a shard must also be detected.
+        shards[1].mac = None;
+        let missing_mac = Err(ShardError::MissingMac { id: shards[1].id() });
+
+        TestResult::from_bool(
+            tampered == bad_mac
+                && tampered_dealer == bad_mac
+                && matches!(wrong_key, Err(ShardError::InvalidMac { .. }))
+                && recover_secret_with_key(&shards, &MAC_KEY) == missing_mac
+                && recover_secret(&shards) == Ok(secret),
+        )
+    }
+
+    #[quickcheck]
+    fn recover_secret_range_success(
+        n: u32,
+        chunk_size: usize,
+        start: usize,
+        end: usize,
+        secret: Vec<u8>,
+    ) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+        let chunk_size = 1 + chunk_size % Gf32::BYTES;
+        let (start, end) = match secret.len() {
+            0 => (0, 0),
+            len => {
+                let (a, b) = (start % (len + 1), end % (len + 1));
+                (a.min(b), a.max(b))
+            }
+        };
+
+        let mut dealer = Dealer::new_with_chunk_size(n, chunk_size, &secret);
+        let shards = dealer.shards(n);
+
+        TestResult::from_bool(
+            recover_secret_range(&shards, start..end) == Ok(secret[start..end].to_vec())
+                && recover_secret_range(&shards, 0..secret.len()) == Ok(secret.clone())
+                && matches!(
+                    recover_secret_range(&shards, 0..secret.len() + 1),
+                    Err(ShardError::RangeOutOfBounds { .. })
+                ),
+        )
+    }
+
+    #[test]
+    fn recover_secret_too_long() {
+        // The secret claims more bytes than the shard's y-values hold.
+        let mut shard = Dealer::new(1, [0x42; 29]).next_shard();
+        shard.ys.truncate(1);
+        let shards = [shard];
+
+        let too_long = Err(ShardError::SecretTooLong {
+            secret_len: 29,
+            max_secret_len: 4,
+        });
+        assert_eq!(recover_secret_range(&shards, 10..12), too_long);
+        assert_eq!(recover_secret(&shards), too_long);
+        assert!(recover_secret_to(&shards, &mut vec![]).is_err());
+    }
+
+    #[quickcheck]
+    fn recover_secret_to_success(n: u32, ramp: bool, len: u16, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) || secret.is_empty() {
+            return TestResult::discard();
+        }
+        // Make sure the secret is large enough to span several batches.
+        let secret = secret
+            .iter()
+            .copied()
+            .cycle()
+            .take(len as usize)
+            .collect::<Vec<_>>();
+
+        let mut dealer = match ramp {
+            true => Dealer::new_ramp(n, &secret),
+            false => Dealer::new(n, &secret),
+        }
+        .authenticated(&MAC_KEY);
+        let shards = dealer.shards(n);
+        let mut output = vec![];
+        let written = recover_secret_to_with_key(&shards, &MAC_KEY, &mut output).unwrap();
+
+        // A bad MAC must be detected before anything is written.
+        let mut bad_shards = shards.clone();
+        bad_shards[0].mac.as_mut().unwrap()[0] ^= 1;
+        let mut bad_output = vec![];
+        let bad = recover_secret_to_with_key(&bad_shards, &MAC_KEY, &mut bad_output);
+
+        TestResult::from_bool(
+            output == secret
+                && written == secret.len() as u64
+                && bad.map_err(|err| err.kind()).err() == Some(io::ErrorKind::InvalidData)
+                && bad_output.is_empty(),
+        )
+    }
+
+    #[quickcheck]
+    fn recover_secret_mixed_dealers(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(2..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        // Two backups of the same secret with the same threshold.
+        let mut dealer1 = Dealer::new(n, &secret);
+        let mut dealer2 = Dealer::new(n, &secret);
+        let mut shards = dealer1.shards(n - 1);
+        shards.push(dealer2.next_shard());
+
+        let recovered = Dealer::recover(dealer1.shards(n)).unwrap();
+
+        TestResult::from_bool(
+            dealer1.id() != dealer2.id()
+                && shards[0].dealer_id() == dealer1.id()
+                && recovered.id() == dealer1.id()
+                && matches!(
+                    recover_secret(&shards),
+                    Err(ShardError::MixedDealers { ref dealer_id, .. }) if *dealer_id == dealer2.id()
+                ),
+        )
+    }
+
+    #[quickcheck]
+    fn recover_secret_report(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) || secret.is_empty() {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret);
+        let mut shards = (0..n + 2).map(|_| dealer.next_shard()).collect::<Vec<_>>();
+        shards.push(shards[0].clone());
+
+        let (recovered, report) = recover_secret_with_report(&shards).unwrap();
+        let ok = recovered == secret
+            && report.used_ids().len() == n as usize
+            && report.used_ids()[0] == shards[0].id()
+            && report.duplicates() == 1
+            && report.cross_checked_ids().len() == 2
+            && report.cross_check_passed() == Some(true)
+            && report.shard_count() == shards.len()
+            && report.threshold() == n
+            && report.secret_len() == secret.len();
+
+        // A corrupted extra shard is reported, but doesn't prevent recovery.
+        let bad = shards[n as usize + 1].id();
+        shards[n as usize + 1].ys[0] += GfElem::ONE;
+        let (recovered, report) = recover_secret_with_report(&shards).unwrap();
+
+        // With no extra shards, there is nothing to cross-check.
+        let (_, exact) = recover_secret_with_report(&shards[..n as usize]).unwrap();
+
+        TestResult::from_bool(
+            ok && recovered == secret
+                && report.mismatched_ids() == [bad]
+                && report.cross_check_passed() == Some(false)
+                && exact.cross_check_passed().is_none(),
+        )
+    }
+
+    #[quickcheck]
+    fn ramp_roundtrip(n: u32, start: usize, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) || secret.is_empty() {
+            return TestResult::discard();
+        }
+        let start = start % secret.len();
+
+        let mut dealer = Dealer::new_ramp(n, &secret).authenticated(&MAC_KEY);
+        let mut shards = dealer.shards(n + 2);
+        let chunks = secret.len().div_ceil(Gf32::BYTES);
+        if !shards
+            .iter()
+            .all(|s| s.scheme() == Scheme::Ramp && s.y_count() == chunks.div_ceil(n as usize))
+        {
+            return TestResult::failed();
+        }
+        let parsed = shards
+            .iter()
+            .map(|s| Shard::from_wire(s.to_wire()).unwrap())
+            .collect::<Vec<_>>();
+
+        let ok = dealer.secret() == secret
+            && recover_secret_with_key(&parsed, &MAC_KEY) == Ok(secret.clone())
+            && Dealer::recover_with_key(&shards[..n as usize], &MAC_KEY)
+                .unwrap()
+                .secret()
+                == secret
+            && recover_secret_range(&shards, start..secret.len()) == Ok(secret[start..].to_vec());
+
+        // Mixing schemes is not allowed.
+        let mut mixed = shards[1..n as usize].to_vec();
+        mixed.push(Dealer::new(n, &secret).next_shard());
+        let mixed_ok = n == 1
+            || matches!(
+                recover_secret(&mixed),
+                Err(ShardError::MixedDealers { .. }) | Err(ShardError::InconsistentScheme)
+            );
+
+        shards[0].ys[0] += GfElem::ONE;
+        TestResult::from_bool(ok && mixed_ok && recover_secret_corrected(&shards) == Ok(secret))
+    }
+
+    #[quickcheck]
+    fn reshard_roundtrip(n: u32, m: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) || !(1..=8).contains(&m) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new_with_chunk_size(n, 3, &secret);
+        let shards = (0..n).map(|_| dealer.next_shard()).collect::<Vec<_>>();
+
+        let mut dealer = reshard(&shards, m).unwrap().authenticated(&MAC_KEY);
+        let new_shards = (0..m).map(|_| dealer.next_shard()).collect::<Vec<_>>();
+
+        TestResult::from_bool(
+            dealer.threshold() == m
+                && new_shards
+                    .iter()
+                    .all(|s| s.chunk_size == 3 && s.is_authenticated())
+                && recover_secret_with_key(&new_shards, &MAC_KEY) == Ok(secret),
+        )
+    }
+
+    fn field_roundtrip<F: GfField>(n: u32, secret: &[u8]) -> bool {
+        let mut dealer = GenericDealer::<F>::new_in_field(n, F::BYTES, secret);
+        let shards = (0..n)
+            .map(|_| GenericShard::<F>::from_wire(dealer.next_shard().to_wire()).unwrap())
+            .collect::<Vec<_>>();
+        shards
+            .iter()
+            .all(|s| s.ys.len() == secret.len().div_ceil(F::BYTES))
+            && recover_secret(&shards) == Ok(secret.to_vec())
+            && GenericDealer::recover(&shards).unwrap().secret() == secret
+    }
+
+    #[quickcheck]
+    fn recover_secret_fields(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+        TestResult::from_bool(
+            field_roundtrip::<Gf16>(n, &secret)
+                && field_roundtrip::<Gf32>(n, &secret)
+                && field_roundtrip::<Gf64>(n, &secret),
+        )
+    }
+
+    #[quickcheck]
+    fn shard_wrong_field(shard: Shard) -> bool {
+        let bytes = shard.to_wire();
+        GenericShard::<Gf16>::from_wire(&bytes).is_err()
+            && GenericShard::<Gf64>::from_wire(&bytes).is_err()
+    }
+
+    #[quickcheck]
+    fn shard_checksum(shard: Shard) -> bool {
+        let mut modified = shard.clone();
+        modified.secret_len = modified.secret_len.wrapping_add(1);
+
+        shard.checksum().len() == CHECKSUM_LENGTH
+            && shard.checksum() == shard.clone().checksum()
+            && shard.checksum() != modified.checksum()
+    }
+
+    #[quickcheck]
+    fn shard_validate_id(shard: Shard) -> bool {
+        let id = shard.id();
+        let mut typo = id.clone().into_bytes();
+        typo[1] = match typo[1] {
+            b'y' => b'b',
+            _ => b'y',
+        };
+        let typo = String::from_utf8(typo).unwrap();
+
+        Shard::validate_id(&id)
+            && Shard::validate_id(&id.to_ascii_uppercase().replace('O', "0"))
+            && !Shard::validate_id(&typo)
+            && !Shard::validate_id(&id[1..])
+            && !GenericShard::<Gf16>::validate_id(&id)
+    }
+
+    #[quickcheck]
+    fn shard_accessors(shard: Shard) -> bool {
+        shard.x().as_bytes() == &shard.x.to_bytes()[..]
+            && shard.y_count() == shard.ys.len()
+            && shard
+                .ys()
+                .zip(&shard.ys)
+                .all(|(v, y)| v.as_bytes() == &y.to_bytes()[..] && v.as_bytes().len() == 4)
+            && shard.secret_len() == shard.secret_len
+            && shard.chunk_size() == shard.chunk_size
+    }
+
+    #[quickcheck]
+    fn dealer_with_rng(n: u32, seed: u64, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let shards = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut dealer = Dealer::new_with_rng(n, &secret, &mut rng);
+            (0..n)
+                .map(|_| dealer.next_shard_with_rng(&mut rng))
+                .collect::<Vec<_>>()
+        };
+
+        // The same seed must produce the same shards.
+        let shards1 = shards(seed);
+        let shards2 = shards(seed);
+        TestResult::from_bool(shards1 == shards2 && recover_secret(&shards1) == Ok(secret.clone()))
+    }
+
+    #[quickcheck]
+    fn dealer_zeroize(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret).authenticated(&MAC_KEY);
+        let mut shard = dealer.next_shard();
+        dealer.zeroize();
+        shard.zeroize();
+
+        TestResult::from_bool(
+            dealer.secret().iter().all(|b| *b == 0)
+                && shard.ys.iter().all(|y| *y == GfElem::ZERO)
+                && !shard.is_authenticated(),
+        )
+    }
+
+    #[quickcheck]
+    fn shards_bulk(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret).authenticated(&MAC_KEY);
+        let mut shards = dealer.shards(n);
+        shards.push(dealer.next_shard());
+        let xs = shards.iter().map(|s| s.x.inner()).collect::<BTreeSet<_>>();
+
+        TestResult::from_bool(
+            xs.len() == shards.len()
+                && shards.iter().all(|s| dealer.make_shard(s.x) == *s)
+                && recover_secret_with_key(&shards[..n as usize], &MAC_KEY) == Ok(secret.clone())
+                && recover_secret_with_key(&shards[1..], &MAC_KEY) == Ok(secret),
+        )
+    }
+
+    #[quickcheck]
+    fn dealer_shard_at(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret).authenticated(&MAC_KEY);
+        let lost = dealer.next_shard();
+        let shards = dealer.shards(n);
+
+        // Re-issue the lost shard from a recovered dealer.
+        let mut recovered = Dealer::recover_with_key(&shards, &MAC_KEY).unwrap();
+        let reissued = recovered.shard_at(lost.x().as_bytes());
+
+        TestResult::from_bool(
+            reissued == Ok(lost.clone())
+                && recovered.shard_at([0u8; 4]) == Err(DealerError::InvalidShardX)
+                && recovered.shard_at([1u8; 3]) == Err(DealerError::InvalidShardX)
+                && (0..8).all(|_| recovered.next_shard().x != lost.x),
+        )
+    }
+
+    #[quickcheck]
+    fn next_shard_unique(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, secret);
+        let shards = (0..n).map(|_| dealer.next_shard()).collect::<Vec<_>>();
+        let xs = shards.iter().map(|s| s.x.inner()).collect::<BTreeSet<_>>();
+
+        // A recovered dealer must know about the shards used to recover it.
+        let recovered_dealer = Dealer::recover(&shards).unwrap();
+
+        TestResult::from_bool(
+            xs.len() == shards.len() && dealer.issued_xs == xs && recovered_dealer.issued_xs == xs,
+        )
+    }
+
+    #[quickcheck]
+    fn next_shard_indexed_sequential(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret);
+        let shards = (0..n)
+            .map(|_| dealer.next_shard_indexed())
+            .collect::<Vec<_>>();
+        let xs = shards.iter().map(|s| s.x.inner()).collect::<Vec<_>>();
+
+        // A recovered dealer must skip the indices already used.
+        let mut recovered_dealer = Dealer::recover(&shards).unwrap();
+        let next = recovered_dealer.next_shard_indexed();
+
+        TestResult::from_bool(
+            xs == (1..=u64::from(n)).collect::<Vec<_>>()
+                && next.x.inner() == u64::from(n) + 1
+                && recover_secret(&shards).unwrap() == secret,
+        )
     }
 
     #[quickcheck]
@@ -383,7 +3649,7 @@ mod test {
             return TestResult::discard();
         }
 
-        let dealer = Dealer::new(n, secret);
+        let mut dealer = Dealer::new(n, secret);
         let shards = (0..n)
             .map(|_| {
                 let shard = dealer.next_shard();
@@ -392,7 +3658,7 @@ mod test {
                 shard
             })
             .collect::<Vec<_>>();
-        let recovered_dealer = Dealer::recover(shards);
+        let recovered_dealer = Dealer::recover(shards).unwrap();
 
         TestResult::from_bool(dealer.polys == recovered_dealer.polys)
     }
@@ -0,0 +1,532 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::shamir::{
+    gf::{GfElem, GfField},
+    shamir::{
+        recover_secret, recover_secret_with_key, Dealer, MacKey, Shard, ShardError, ShardView,
+    },
+};
+
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    io::{self, Read, Write},
+};
+
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroize;
+
+/// Default number of bytes of the secret which are sharded at a time by a
+/// `StreamDealer`.
+pub const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+const STREAM_ID_LENGTH: usize = 16;
+
+// Each block's secret is prefixed with the stream id, the block index (as a
+// big-endian u64) and whether it is the last block.
+const BLOCK_HEADER_LENGTH: usize = STREAM_ID_LENGTH + 8 + 1;
+
+/// Factory to share a secret of arbitrary size, read from an `io::Read`.
+///
+/// A `Dealer` holds a polynomial for every field element of the secret, which
+/// is not practical for very large secrets (such as disk images). Instead, a
+/// `StreamDealer` reads the secret one block at a time and shards each block
+/// with its own (independent) `Dealer`, so only a single block is ever held in
+/// memory.
+///
+/// Like the [STREAM][stream] construction, each block is sharded together
+/// with a random stream id, its index and whether it is the last block, so
+/// `recover_stream` can detect blocks which were dropped, reordered or taken
+/// from a different stream.
+///
+/// Each block produces one `Shard` (a "segment") for each of the shard streams
+/// requested. Every segment of a given shard stream has the same id, and the
+/// corresponding segments of each block must be passed to `recover_stream`
+/// in order to recover the secret.
+///
+/// [stream]: https://eprint.iacr.org/2015/189.pdf
+pub struct StreamDealer<R: Read> {
+    reader: R,
+    threshold: u32,
+    block_size: usize,
+    mac_key: Option<MacKey>,
+    // x-values of each shard stream, which are shared by every block.
+    xs: Vec<GfElem>,
+    stream_id: [u8; STREAM_ID_LENGTH],
+    index: u64,
+    // The first byte of the next block, which was read to find out whether
+    // the previous block was the last one.
+    lookahead: Option<u8>,
+    done: bool,
+}
+
+impl<R: Read> Drop for StreamDealer<R> {
+    fn drop(&mut self) {
+        self.lookahead.zeroize();
+        self.mac_key.zeroize();
+    }
+}
+
+impl<R: Read> StreamDealer<R> {
+    /// Construct a new `StreamDealer` to shard the secret read from `reader`
+    /// into `shards` shard streams, requiring at least `threshold` of them to
+    /// reconstruct the secret.
+    ///
+    /// Panics if `threshold` is zero or `shards` is smaller than `threshold`.
+    pub fn new(reader: R, threshold: u32, shards: u32) -> Self {
+        assert!(threshold > 0, "must at least have a threshold of one");
+        assert!(
+            shards >= threshold,
+            "must have at least as many shards as the threshold"
+        );
+
+        let mut issued_xs = HashSet::new();
+        let xs = (0..shards)
+            .map(|_| loop {
+                let x = GfElem::new_rand(&mut OsRng);
+                if x != GfElem::ZERO && issued_xs.insert(x.inner()) {
+                    break x;
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut stream_id = [0; STREAM_ID_LENGTH];
+        OsRng.fill_bytes(&mut stream_id);
+
+        Self {
+            reader,
+            threshold,
+            block_size: DEFAULT_BLOCK_SIZE,
+            mac_key: None,
+            xs,
+            stream_id,
+            index: 0,
+            lookahead: None,
+            done: false,
+        }
+    }
+
+    /// Use blocks of `block_size` bytes (rather than `DEFAULT_BLOCK_SIZE`).
+    ///
+    /// Panics if `block_size` is zero.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        assert!(block_size > 0, "block size must be non-zero");
+        self.block_size = block_size;
+        self
+    }
+
+    /// Attach a MAC to every segment (see `Dealer::authenticated`), which is
+    /// verified by `recover_stream_with_key`.
+    pub fn authenticated(mut self, key: &MacKey) -> Self {
+        self.mac_key = Some(*key);
+        self
+    }
+
+    /// Returns the number of shard streams required to recover the secret.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Read and shard the next block of the secret, returning one segment for
+    /// each shard stream (in the same order every time).
+    ///
+    /// Returns `None` once the reader has been exhausted.
+    pub fn next_block(&mut self) -> io::Result<Option<Vec<Shard>>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        // Read one byte past the end of the block, so we know whether this is
+        // the last block. An empty secret still has a single (empty) block.
+        let mut block = Vec::with_capacity(BLOCK_HEADER_LENGTH + self.block_size + 1);
+        block.resize(BLOCK_HEADER_LENGTH, 0);
+        block.extend(self.lookahead.take());
+        let wanted = BLOCK_HEADER_LENGTH + self.block_size + 1 - block.len();
+        if let Err(err) = self
+            .reader
+            .by_ref()
+            .take(wanted as u64)
+            .read_to_end(&mut block)
+        {
+            block.zeroize();
+            return Err(err);
+        }
+        let last = block.len() <= BLOCK_HEADER_LENGTH + self.block_size;
+        if !last {
+            self.lookahead = block.pop();
+        }
+
+        let (id, header) = block[..BLOCK_HEADER_LENGTH].split_at_mut(STREAM_ID_LENGTH);
+        id.copy_from_slice(&self.stream_id);
+        header[..8].copy_from_slice(&self.index.to_be_bytes());
+        header[8] = last as u8;
+
+        let mut dealer = Dealer::new(self.threshold, &block);
+        block.zeroize();
+        if let Some(key) = &self.mac_key {
+            dealer = dealer.authenticated(key);
+        }
+        self.index += 1;
+        self.done = last;
+        Ok(Some(
+            self.xs.iter().map(|x| dealer.make_shard(*x)).collect(),
+        ))
+    }
+}
+
+impl<R: Read> Iterator for StreamDealer<R> {
+    type Item = io::Result<Vec<Shard>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_block().transpose()
+    }
+}
+
+/// Reconstruct a secret sharded by a `StreamDealer`, writing it to `writer`
+/// one block at a time.
+///
+/// Each item of `blocks` is the set of segments for one block (at least
+/// `threshold` of them, from different shard streams), and the blocks must be
+/// in the order they were produced. Returns the number of bytes written.
+///
+/// If any block cannot be recovered (or blocks are missing, out of order, or
+/// from a different stream), an `io::ErrorKind::InvalidData` error is returned
+/// (wrapping the `ShardError`). Note that the preceding blocks will have
+/// already been written to `writer`.
+///
+/// Like `recover_secret`, the MACs of authenticated segments are **not**
+/// checked. Use `recover_stream_with_key` for authenticated streams.
+pub fn recover_stream<I, S, W>(blocks: I, writer: &mut W) -> io::Result<u64>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<[Shard]>,
+    W: Write + ?Sized,
+{
+    recover_blocks(blocks, None, writer)
+}
+
+/// Reconstruct a secret sharded by an authenticated `StreamDealer`, writing
+/// it to `writer` one block at a time.
+///
+/// This is like `recover_stream`, except that the MAC of every segment of a
+/// block is verified with `key` before that block is recovered.
+pub fn recover_stream_with_key<I, S, W>(blocks: I, key: &MacKey, writer: &mut W) -> io::Result<u64>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<[Shard]>,
+    W: Write + ?Sized,
+{
+    recover_blocks(blocks, Some(key), writer)
+}
+
+fn recover_blocks<I, S, W>(blocks: I, key: Option<&MacKey>, writer: &mut W) -> io::Result<u64>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<[Shard]>,
+    W: Write + ?Sized,
+{
+    let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+
+    let (mut written, mut index) = (0, 0);
+    let mut stream_id = None;
+    let mut last = false;
+    for segments in blocks {
+        if last {
+            return Err(invalid(ShardError::TrailingStreamBlocks { blocks: index }));
+        }
+        let mut block = match key {
+            Some(key) => recover_secret_with_key(segments, key),
+            None => recover_secret(segments),
+        }
+        .map_err(invalid)?;
+        let result = check_block_header(&block, index, &mut stream_id);
+        let data = match result {
+            Ok(is_last) => {
+                last = is_last;
+                &block[BLOCK_HEADER_LENGTH..]
+            }
+            Err(err) => {
+                block.zeroize();
+                return Err(invalid(err));
+            }
+        };
+        let result = writer.write_all(data);
+        written += data.len() as u64;
+        block.zeroize();
+        result?;
+        index += 1;
+    }
+    match last {
+        true => Ok(written),
+        false => Err(invalid(ShardError::TruncatedStream { blocks: index })),
+    }
+}
+
+// Check that a recovered block is block `index` of the stream (whose id is
+// taken from the first block), returning whether it is the last block.
+fn check_block_header(
+    block: &[u8],
+    index: u64,
+    stream_id: &mut Option<[u8; STREAM_ID_LENGTH]>,
+) -> Result<bool, ShardError> {
+    if block.len() < BLOCK_HEADER_LENGTH {
+        return Err(ShardError::MalformedStreamBlock { index });
+    }
+    let (id, header) = block[..BLOCK_HEADER_LENGTH].split_at(STREAM_ID_LENGTH);
+    let id: [u8; STREAM_ID_LENGTH] = id.try_into().expect("stream id has a fixed length");
+    if *stream_id.get_or_insert(id) != id {
+        return Err(ShardError::MixedStreams { index });
+    }
+    let found = u64::from_be_bytes(header[..8].try_into().expect("index has a fixed length"));
+    if found != index {
+        return Err(ShardError::StreamBlockOutOfOrder { index, found });
+    }
+    match header[8] {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(ShardError::MalformedStreamBlock { index }),
+    }
+}
+
+/// Reconstruct the secret of a set of `ShardView`s, writing it to `writer`
+/// one block at a time.
+///
+/// Only the y-values needed for each block are decoded, so the secret of very
+/// large (memory-mapped) shards can be recovered without holding all of their
+/// y-values in memory. The requirements on `views` are the same as for
+/// `recover_secret`. Returns the number of bytes written.
+///
+/// Note that MACs can only be verified against the entire secret, so the MACs
+/// of authenticated shards are **not** checked.
+///
+/// If the secret cannot be recovered, an `io::ErrorKind::InvalidData` error is
+/// returned (wrapping the `ShardError`). Note that some of the secret may have
+/// already been written to `writer`.
+pub fn recover_mapped<F, W>(views: &[ShardView<F>], writer: &mut W) -> io::Result<u64>
+where
+    F: GfField,
+    W: Write + ?Sized,
+{
+    let mut segments = views
+        .iter()
+        .map(|view| view.segments(DEFAULT_BLOCK_SIZE))
+        .collect::<Vec<_>>();
+    let mut next_block = || {
+        segments
+            .iter_mut()
+            .filter_map(Iterator::next)
+            .collect::<Vec<_>>()
+    };
+
+    // Every view has at least one segment, so the first block is always
+    // recovered (and checked), even if there are no views.
+    let (mut written, mut block) = (0, next_block());
+    loop {
+        let mut secret = recover_secret(&block)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        writer.write_all(&secret)?;
+        written += secret.len() as u64;
+        secret.zeroize();
+
+        block = next_block();
+        if block.is_empty() {
+            break;
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::shamir::{gf::Gf32, shamir::YsLayout, MAC_KEY_LENGTH};
+
+    use quickcheck::TestResult;
+
+    #[quickcheck]
+    fn mapped_roundtrip(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let mut dealer = Dealer::new(n, &secret).authenticated(&[0x5a; MAC_KEY_LENGTH]);
+        let shards = dealer
+            .shards(n + 1)
+            .iter()
+            .map(|shard| shard.to_wire_with_layout(YsLayout::FixedWidth))
+            .collect::<Vec<_>>();
+        let views = shards
+            .iter()
+            .map(|bytes| ShardView::<Gf32>::from_wire(bytes).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut recovered = vec![];
+        let written = recover_mapped(&views[1..], &mut recovered).unwrap();
+        TestResult::from_bool(written == secret.len() as u64 && recovered == secret)
+    }
+
+    #[test]
+    fn mapped_many_blocks() {
+        // Large enough to need several blocks, and not a whole number of them.
+        let secret = (0..3 * DEFAULT_BLOCK_SIZE + 1234)
+            .map(|i| (i * 7) as u8)
+            .collect::<Vec<_>>();
+        for dealer in &mut [Dealer::new(3, &secret), Dealer::new_ramp(3, &secret)] {
+            let shards = dealer
+                .shards(3)
+                .iter()
+                .map(|shard| shard.to_wire_with_layout(YsLayout::FixedWidth))
+                .collect::<Vec<_>>();
+            let views = shards
+                .iter()
+                .map(|bytes| ShardView::<Gf32>::from_wire(bytes).unwrap())
+                .collect::<Vec<_>>();
+
+            let mut recovered = vec![];
+            recover_mapped(&views, &mut recovered).unwrap();
+            assert_eq!(recovered, secret);
+
+            let err = recover_mapped(&views[1..], &mut vec![]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        let err = recover_mapped::<Gf32, _>(&[], &mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[quickcheck]
+    fn stream_roundtrip(n: u32, block_size: u8, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) || block_size == 0 {
+            return TestResult::discard();
+        }
+
+        let blocks = StreamDealer::new(secret.as_slice(), n, n + 1)
+            .with_block_size(block_size.into())
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        let expected_blocks = std::cmp::max(1, secret.len().div_ceil(block_size.into()));
+
+        // Only use the last n segments of each block.
+        let mut recovered = vec![];
+        let written = recover_stream(blocks.iter().map(|b| &b[1..]), &mut recovered).unwrap();
+
+        TestResult::from_bool(
+            blocks.len() == expected_blocks
+                && blocks.iter().all(|b| b.len() == (n + 1) as usize)
+                && written == secret.len() as u64
+                && recovered == secret,
+        )
+    }
+
+    #[test]
+    fn stream_tampered_blocks() {
+        let secret = (0..100).collect::<Vec<u8>>();
+        let stream = |secret: &[u8]| {
+            StreamDealer::new(secret, 2, 2)
+                .with_block_size(16)
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap()
+        };
+        let blocks = stream(&secret);
+        let other = stream(&secret);
+        assert_eq!(blocks.len(), 7);
+
+        let recover = |blocks: Vec<&Vec<Shard>>| {
+            recover_stream(blocks, &mut vec![]).map_err(|err| {
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+                err.into_inner()
+                    .and_then(|err| err.downcast::<ShardError>().ok())
+                    .map(|err| *err)
+            })
+        };
+        let all = blocks.iter().collect::<Vec<_>>();
+        assert_eq!(recover(all.clone()), Ok(100));
+
+        // Dropped trailing blocks (or no blocks at all).
+        assert_eq!(
+            recover(all[..5].to_vec()),
+            Err(Some(ShardError::TruncatedStream { blocks: 5 }))
+        );
+        assert_eq!(
+            recover(vec![]),
+            Err(Some(ShardError::TruncatedStream { blocks: 0 }))
+        );
+        // Dropped or reordered blocks.
+        let mut dropped = all.clone();
+        dropped.remove(2);
+        assert_eq!(
+            recover(dropped),
+            Err(Some(ShardError::StreamBlockOutOfOrder {
+                index: 2,
+                found: 3
+            }))
+        );
+        let mut reordered = all.clone();
+        reordered.swap(0, 1);
+        assert_eq!(
+            recover(reordered),
+            Err(Some(ShardError::StreamBlockOutOfOrder {
+                index: 0,
+                found: 1
+            }))
+        );
+        // Blocks from a different stream (of the same secret).
+        let mut mixed = all.clone();
+        mixed[3] = &other[3];
+        assert_eq!(
+            recover(mixed),
+            Err(Some(ShardError::MixedStreams { index: 3 }))
+        );
+        // Blocks after the last block.
+        let mut trailing = all.clone();
+        trailing.push(&other[0]);
+        assert_eq!(
+            recover(trailing),
+            Err(Some(ShardError::TrailingStreamBlocks { blocks: 7 }))
+        );
+    }
+
+    #[quickcheck]
+    fn stream_segment_ids(n: u32, secret: Vec<u8>) -> TestResult {
+        if !(1..=8).contains(&n) {
+            return TestResult::discard();
+        }
+
+        let key = [0x5a; MAC_KEY_LENGTH];
+        let blocks = StreamDealer::new(secret.as_slice(), n, n)
+            .with_block_size(7)
+            .authenticated(&key)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        let mut recovered = vec![];
+        recover_stream_with_key(&blocks, &key, &mut recovered).unwrap();
+        let wrong_key = recover_stream_with_key(&blocks, &[0; MAC_KEY_LENGTH], &mut vec![]);
+
+        // Every block must use the same ids (in the same order).
+        let ids = blocks[0].iter().map(Shard::id).collect::<Vec<_>>();
+        TestResult::from_bool(
+            blocks
+                .iter()
+                .all(|b| b.iter().map(Shard::id).collect::<Vec<_>>() == ids)
+                && blocks.iter().flatten().all(Shard::is_authenticated)
+                && recovered == secret
+                && wrong_key.map_err(|err| err.kind()).err() == Some(io::ErrorKind::InvalidData),
+        )
+    }
+}
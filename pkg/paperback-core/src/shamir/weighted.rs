@@ -0,0 +1,263 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::{
+        encoding,
+        header::{self, DocumentKind},
+        limits::{FieldOffsets, WireError, WireLimits},
+        shamir::{recover_secret, Dealer, Shard, ShardError},
+    },
+    v0::{FromWire, ToWire},
+};
+
+/// Bundle of `Shard`s which counts as `weight` shards towards the threshold.
+///
+/// This allows some custodians to be given more say in recovery than others
+/// ("my lawyer's shard counts as two"). Internally a `WeightedShard` is just
+/// `weight` ordinary shards from the same `Dealer`, so the weight is enforced
+/// by the threshold of the underlying polynomials.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WeightedShard {
+    shards: Vec<Shard>,
+}
+
+impl WeightedShard {
+    /// Returns the identifier of the `WeightedShard`, which is the identifier
+    /// of the first `Shard` it contains.
+    pub fn id(&self) -> String {
+        self.shards[0].id()
+    }
+
+    /// Returns the number of shards this `WeightedShard` counts as.
+    pub fn weight(&self) -> u32 {
+        self.shards.len() as u32
+    }
+
+    /// Returns the total weight of the `WeightedShard`s required to recover
+    /// the stored secret.
+    pub fn threshold(&self) -> u32 {
+        self.shards[0].threshold()
+    }
+
+    /// Returns the underlying `Shard`s.
+    pub fn shards(&self) -> &[Shard] {
+        &self.shards
+    }
+}
+
+impl ToWire for WeightedShard {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.wire_len());
+        self.to_wire_into(&mut bytes);
+        bytes
+    }
+
+    fn wire_len(&self) -> usize {
+        header::record_len(DocumentKind::WeightedShard, self.wire_body_len())
+    }
+
+    fn to_wire_into(&self, bytes: &mut Vec<u8>) {
+        header::write_record_into(
+            bytes,
+            DocumentKind::WeightedShard,
+            self.wire_body_len(),
+            |bytes| self.write_wire_body(bytes),
+        )
+    }
+}
+
+impl WeightedShard {
+    // Append the wire encoding (without the header) to bytes.
+    fn write_wire_body(&self, bytes: &mut Vec<u8>) {
+        // Encode the number of shards, followed by each shard.
+        encoding::varint(self.shards.len() as u64, bytes);
+        self.shards
+            .iter()
+            .for_each(|shard| shard.write_wire_bytes(bytes));
+    }
+
+    // Length (in bytes) of the wire encoding, without the header.
+    fn wire_body_len(&self) -> usize {
+        encoding::varint_len(self.shards.len() as u64)
+            + self.shards.iter().map(Shard::wire_bytes_len).sum::<usize>()
+    }
+}
+
+impl WeightedShard {
+    /// Deserialise a `WeightedShard`, rejecting any shard which declares
+    /// lengths larger than those permitted by `limits`.
+    pub fn from_wire_partial_with_limits<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        header::read_record(input, DocumentKind::WeightedShard, |body| {
+            Self::from_wire_body(body, limits)
+        })
+    }
+
+    // Parse the layout shared by every version, without the header and framing.
+    fn from_wire_body<'a>(
+        input: &'a [u8],
+        limits: &WireLimits,
+    ) -> Result<(Self, &'a [u8]), WireError> {
+        use crate::nom_helpers;
+        use nom::combinator::complete;
+
+        let fields = FieldOffsets::new(input);
+
+        let at = fields.field("weight", input);
+        let (mut input, weight) =
+            WireError::nom(complete(nom_helpers::usize)(input)).map_err(at)?;
+        if weight == 0 {
+            return Err(at(String::from(
+                "weighted shard must contain at least one shard",
+            )
+            .into()));
+        }
+        WireLimits::check("weight", limits.max_weight, weight).map_err(at)?;
+
+        let mut shards = Vec::with_capacity(weight.min(input.len()));
+        for i in 0..weight {
+            let offset = fields.offset(input);
+            let (shard, remain) = Shard::from_wire_body(input, limits)
+                .map_err(|err| err.in_field(format!("shards[{}]", i), offset))?;
+            shards.push(shard);
+            input = remain;
+        }
+
+        Ok((WeightedShard { shards }, input))
+    }
+}
+
+impl FromWire for WeightedShard {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        Self::from_wire_partial_with_limits(input, &WireLimits::DEFAULT)
+    }
+}
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for WeightedShard {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        Self {
+            shards: (0..1 + g.next_u32() % 4)
+                .map(|_| Shard::arbitrary(g))
+                .collect(),
+        }
+    }
+}
+
+impl Dealer {
+    /// Generate a new `WeightedShard` for the secret, which counts as `weight`
+    /// shards towards the threshold.
+    ///
+    /// Panics if `weight` is zero.
+    pub fn next_weighted_shard(&mut self, weight: u32) -> WeightedShard {
+        assert!(
+            weight > 0,
+            "weighted shard must have a weight of at least one"
+        );
+        WeightedShard {
+            shards: (0..weight).map(|_| self.next_shard()).collect(),
+        }
+    }
+}
+
+/// Reconstruct a secret from a set of `WeightedShard`s.
+///
+/// The combined weight of the unique `WeightedShard`s must be at least the
+/// threshold. See `recover_secret` for more details.
+pub fn recover_weighted_secret<S: AsRef<[WeightedShard]>>(
+    shards: S,
+) -> Result<Vec<u8>, ShardError> {
+    let shards = shards
+        .as_ref()
+        .iter()
+        .flat_map(|s| s.shards.iter().cloned())
+        .collect::<Vec<_>>();
+    recover_secret(shards)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::shamir::{header::CRC_LENGTH, shamir::MAC_LENGTH};
+
+    use quickcheck::TestResult;
+
+    #[quickcheck]
+    fn weighted_shard_bytes_roundtrip(shard: WeightedShard) {
+        let shard2 = WeightedShard::from_wire(shard.to_wire()).unwrap();
+        assert_eq!(shard, shard2);
+    }
+
+    #[quickcheck]
+    fn weighted_shard_error_fields(shard: WeightedShard) -> bool {
+        let bytes = shard.to_wire();
+        let (body, _) = header::read_record(&bytes, DocumentKind::WeightedShard, |body| {
+            Ok((body, &[][..]))
+        })
+        .unwrap();
+        let last = shard.shards.len() - 1;
+        let mac_len = match shard.shards[last].is_authenticated() {
+            true => MAC_LENGTH,
+            false => 0,
+        };
+
+        // The object ends part-way through the final shard's MAC.
+        let truncated = header::write_record(DocumentKind::WeightedShard, &body[..body.len() - 1]);
+        let err = WeightedShard::from_wire(&truncated).unwrap_err();
+
+        err.field().map(String::from) == Some(format!("shards[{}].mac", last))
+            && err.offset() == Some(truncated.len() - CRC_LENGTH - mac_len)
+            && err.cause() == &WireError::UnexpectedEof
+    }
+
+    #[quickcheck]
+    fn recover_weighted_secret_success(weights: Vec<u8>, secret: Vec<u8>) -> TestResult {
+        let weights = weights
+            .iter()
+            .map(|w| 1 + u32::from(*w) % 3)
+            .take(6)
+            .collect::<Vec<_>>();
+        let total = weights.iter().sum::<u32>();
+        if weights.len() < 2 {
+            return TestResult::discard();
+        }
+
+        // The shards together only just reach the threshold, so removing any
+        // of them must make recovery fail.
+        let mut dealer = Dealer::new(total, &secret);
+        let shards = weights
+            .iter()
+            .map(|w| dealer.next_weighted_shard(*w))
+            .collect::<Vec<_>>();
+        if shards.iter().map(WeightedShard::weight).sum::<u32>() != total {
+            return TestResult::failed();
+        }
+
+        TestResult::from_bool(
+            recover_weighted_secret(&shards) == Ok(secret)
+                && matches!(
+                    recover_weighted_secret(&shards[1..]),
+                    Err(ShardError::NotEnoughShards { .. })
+                ),
+        )
+    }
+}
@@ -0,0 +1,855 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Compatibility with [SLIP-0039][slip39] Shamir mnemonic shares, as used by
+//! Trezor and other hardware wallets.
+//!
+//! A secret already split into SLIP-0039 shares can be recovered with
+//! `combine` (or backed up directly with `Backup::from_slip39`) so that it can
+//! be re-printed as a paperback backup, and a small secret recovered from a
+//! paperback backup can be split into SLIP-0039 shares with `split` (or
+//! `Quorum::recover_slip39`).
+//!
+//! Shares are produced with the "extendable backup" flag set and an
+//! iteration exponent of `DEFAULT_ITERATION_EXPONENT`, but shares without the
+//! flag (produced by older tooling) can still be combined.
+//!
+//! [slip39]: https://github.com/satoshilabs/slips/blob/master/slip-0039.md
+
+mod wordlist;
+
+use crate::v0::{Backup, BackupOptions, Quorum};
+
+use std::{error, fmt};
+
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, Rng, RngCore};
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+use wordlist::WORDLIST;
+
+/// Minimum length (in bytes) of a secret which can be split into shares. The
+/// length must also be even.
+pub const MIN_SECRET_LENGTH: usize = 16;
+
+/// Maximum number of groups, and of members within each group.
+pub const MAX_SHARE_COUNT: u8 = 16;
+
+/// Iteration exponent used by `split`. Each of the four Feistel rounds uses
+/// `2500 << exponent` iterations of PBKDF2-HMAC-SHA256.
+pub const DEFAULT_ITERATION_EXPONENT: u8 = 1;
+
+const RADIX_BITS: usize = 10;
+const ID_BITS: usize = 15;
+const HEADER_WORDS: usize = 4;
+const CHECKSUM_WORDS: usize = 3;
+const MIN_MNEMONIC_WORDS: usize = HEADER_WORDS + CHECKSUM_WORDS + 13;
+const ROUND_COUNT: u8 = 4;
+const BASE_ITERATION_COUNT: u32 = 10000;
+const DIGEST_LENGTH: usize = 4;
+const DIGEST_INDEX: u8 = 254;
+const SECRET_INDEX: u8 = 255;
+
+const CUSTOMIZATION: &[u8] = b"shamir";
+const CUSTOMIZATION_EXTENDABLE: &[u8] = b"shamir_extendable";
+
+/// Reasons why SLIP-0039 shares could not be produced or combined.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Slip39Error {
+    /// Word `word` (counted from 1) of mnemonic `mnemonic` (counted from 1) is
+    /// not in the SLIP-0039 word list.
+    InvalidWord { mnemonic: usize, word: usize },
+    /// Mnemonic `mnemonic` has an invalid number of words.
+    InvalidLength { mnemonic: usize },
+    /// The checksum of mnemonic `mnemonic` does not match (a word was
+    /// mistyped).
+    ChecksumMismatch { mnemonic: usize },
+    /// Mnemonic `mnemonic` has invalid padding or sharing parameters.
+    InvalidShare { mnemonic: usize },
+    /// The passphrase contains characters other than printable ASCII.
+    InvalidPassphrase,
+    /// The secret is too short or has an odd length.
+    InvalidSecretLength,
+    /// The requested group or member thresholds and counts are invalid.
+    InvalidThreshold,
+    /// No mnemonics were given.
+    NoShares,
+    /// The mnemonics are from different sets of shares.
+    MismatchedShares,
+    /// There are not enough shares (or groups of shares) to recover the
+    /// secret.
+    InsufficientShares,
+    /// The recovered secret does not match its digest, so some of the shares
+    /// are invalid.
+    DigestMismatch,
+}
+
+impl fmt::Display for Slip39Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Slip39Error::InvalidWord { mnemonic, word } => write!(
+                f,
+                "word {} of mnemonic {} is not in the SLIP-0039 word list",
+                word, mnemonic
+            ),
+            Slip39Error::InvalidLength { mnemonic } => {
+                write!(f, "mnemonic {} has an invalid length", mnemonic)
+            }
+            Slip39Error::ChecksumMismatch { mnemonic } => {
+                write!(f, "mnemonic {} was mistyped (checksum mismatch)", mnemonic)
+            }
+            Slip39Error::InvalidShare { mnemonic } => {
+                write!(f, "mnemonic {} is not a valid SLIP-0039 share", mnemonic)
+            }
+            Slip39Error::InvalidPassphrase => {
+                write!(f, "passphrase must only contain printable ASCII")
+            }
+            Slip39Error::InvalidSecretLength => write!(
+                f,
+                "secret must be an even number of bytes, and at least {} bytes long",
+                MIN_SECRET_LENGTH
+            ),
+            Slip39Error::InvalidThreshold => write!(f, "invalid share threshold or count"),
+            Slip39Error::NoShares => write!(f, "no mnemonics given"),
+            Slip39Error::MismatchedShares => {
+                write!(f, "mnemonics are not from the same set of shares")
+            }
+            Slip39Error::InsufficientShares => {
+                write!(f, "not enough shares to recover the secret")
+            }
+            Slip39Error::DigestMismatch => {
+                write!(f, "shares are invalid (recovered secret digest mismatch)")
+            }
+        }
+    }
+}
+
+impl error::Error for Slip39Error {}
+
+// Reed-Solomon code over GF(1024), used as the checksum of each mnemonic.
+fn rs1024_polymod(values: impl Iterator<Item = u32>) -> u32 {
+    const GENERATORS: [u32; 10] = [
+        0x00e0_e040,
+        0x01c1_c080,
+        0x0383_8100,
+        0x0707_0200,
+        0x0e0e_0009,
+        0x1c0c_2412,
+        0x3808_6c24,
+        0x3090_fc48,
+        0x21b1_f890,
+        0x03f3_f120,
+    ];
+    values.fold(1, |chk, value| {
+        let top = chk >> 20;
+        GENERATORS
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| (top >> idx) & 1 == 1)
+            .fold(((chk & 0xf_ffff) << 10) ^ value, |chk, (_, g)| chk ^ g)
+    })
+}
+
+fn customization(extendable: bool) -> &'static [u8] {
+    match extendable {
+        true => CUSTOMIZATION_EXTENDABLE,
+        false => CUSTOMIZATION,
+    }
+}
+
+// Logarithm and exponent tables for GF(256) (with the Rijndael polynomial),
+// using 3 as the generator.
+struct Gf256 {
+    log: [u8; 256],
+    exp: [u8; 255],
+}
+
+impl Gf256 {
+    fn new() -> Self {
+        let (mut log, mut exp) = ([0u8; 256], [0u8; 255]);
+        let mut value = 1u8;
+        for (power, entry) in exp.iter_mut().enumerate() {
+            *entry = value;
+            log[value as usize] = power as u8;
+            // Multiply by the generator (x + 1).
+            value ^= (value << 1) ^ ((value >> 7) * 0x1b);
+        }
+        Self { log, exp }
+    }
+
+    // Evaluate the polynomial through the given points at x.
+    fn interpolate(&self, shares: &[(u8, Vec<u8>)], x: u8) -> Vec<u8> {
+        if let Some((_, value)) = shares.iter().find(|(index, _)| *index == x) {
+            return value.clone();
+        }
+        let log = |value: u8| i64::from(self.log[value as usize]);
+        let log_product = shares.iter().map(|(index, _)| log(index ^ x)).sum::<i64>();
+
+        let mut result = vec![0u8; shares[0].1.len()];
+        for (index, value) in shares {
+            let log_basis = log_product
+                - log(index ^ x)
+                - shares
+                    .iter()
+                    .filter(|(other, _)| other != index)
+                    .map(|(other, _)| log(index ^ other))
+                    .sum::<i64>();
+            for (r, v) in result.iter_mut().zip(value) {
+                if *v != 0 {
+                    *r ^= self.exp[(log(*v) + log_basis).rem_euclid(255) as usize];
+                }
+            }
+        }
+        result
+    }
+}
+
+fn digest(random: &[u8], secret: &[u8]) -> [u8; DIGEST_LENGTH] {
+    let mut mac = Hmac::<Sha256>::new_varkey(random).expect("HMAC accepts any key length");
+    mac.input(secret);
+    let mut digest = [0u8; DIGEST_LENGTH];
+    digest.copy_from_slice(&mac.result().code()[..DIGEST_LENGTH]);
+    digest
+}
+
+// Split the secret into count shares, any threshold of which can recover it.
+fn split_secret(threshold: u8, count: u8, secret: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    if threshold == 1 {
+        return (0..count).map(|index| (index, secret.to_vec())).collect();
+    }
+    let gf = Gf256::new();
+    let mut shares = (0..threshold - 2)
+        .map(|index| {
+            let mut value = vec![0u8; secret.len()];
+            OsRng.fill_bytes(&mut value);
+            (index, value)
+        })
+        .collect::<Vec<_>>();
+    let mut digest_share = vec![0u8; secret.len()];
+    OsRng.fill_bytes(&mut digest_share[DIGEST_LENGTH..]);
+    let digest = digest(&digest_share[DIGEST_LENGTH..], secret);
+    digest_share[..DIGEST_LENGTH].copy_from_slice(&digest);
+
+    let mut base = shares.clone();
+    base.push((DIGEST_INDEX, digest_share));
+    base.push((SECRET_INDEX, secret.to_vec()));
+    shares.extend((threshold - 2..count).map(|index| (index, gf.interpolate(&base, index))));
+    base.iter_mut().for_each(|(_, value)| value.zeroize());
+    shares
+}
+
+// Recover a secret split with split_secret from threshold of its shares.
+fn recover_secret(threshold: u8, shares: &[(u8, Vec<u8>)]) -> Result<Vec<u8>, Slip39Error> {
+    if threshold == 1 {
+        return Ok(shares[0].1.clone());
+    }
+    let gf = Gf256::new();
+    let secret = gf.interpolate(shares, SECRET_INDEX);
+    let digest_share = gf.interpolate(shares, DIGEST_INDEX);
+    if digest(&digest_share[DIGEST_LENGTH..], &secret)[..] != digest_share[..DIGEST_LENGTH] {
+        return Err(Slip39Error::DigestMismatch);
+    }
+    Ok(secret)
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), Slip39Error> {
+    match passphrase.bytes().all(|c| (32..=126).contains(&c)) {
+        true => Ok(()),
+        false => Err(Slip39Error::InvalidPassphrase),
+    }
+}
+
+fn salt(id: u16, extendable: bool) -> Vec<u8> {
+    match extendable {
+        true => vec![],
+        false => [CUSTOMIZATION, &id.to_be_bytes()[..]].concat(),
+    }
+}
+
+// The four-round Feistel network used to encrypt the master secret with the
+// passphrase. Decryption is the same with the rounds in reverse order.
+fn feistel(
+    input: &[u8],
+    passphrase: &str,
+    exponent: u8,
+    salt: &[u8],
+    rounds: impl Iterator<Item = u8>,
+) -> Vec<u8> {
+    let half = input.len() / 2;
+    let (mut left, mut right) = (input[..half].to_vec(), input[half..].to_vec());
+    let iterations = (BASE_ITERATION_COUNT << exponent) / u32::from(ROUND_COUNT);
+    for round in rounds {
+        let mut password = vec![round];
+        password.extend_from_slice(passphrase.as_bytes());
+        let mut round_salt = salt.to_vec();
+        round_salt.extend_from_slice(&right);
+
+        let mut f = vec![0u8; half];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(&password, &round_salt, iterations as usize, &mut f);
+        left.iter_mut().zip(&f).for_each(|(l, f)| *l ^= f);
+        std::mem::swap(&mut left, &mut right);
+        password.zeroize();
+        f.zeroize();
+    }
+    right.extend_from_slice(&left);
+    left.zeroize();
+    right
+}
+
+// A single decoded SLIP-0039 share.
+#[derive(Clone, Debug)]
+struct Share {
+    id: u16,
+    extendable: bool,
+    exponent: u8,
+    group_index: u8,
+    group_threshold: u8,
+    group_count: u8,
+    member_index: u8,
+    member_threshold: u8,
+    value: Vec<u8>,
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+impl Share {
+    fn to_mnemonic(&self) -> String {
+        let header = (u64::from(self.id) << 25)
+            | (u64::from(self.extendable) << 24)
+            | (u64::from(self.exponent) << 20)
+            | (u64::from(self.group_index) << 16)
+            | (u64::from(self.group_threshold - 1) << 12)
+            | (u64::from(self.group_count - 1) << 8)
+            | (u64::from(self.member_index) << 4)
+            | u64::from(self.member_threshold - 1);
+        let mut values = (0..HEADER_WORDS)
+            .rev()
+            .map(|idx| ((header >> (RADIX_BITS * idx)) & 0x3ff) as u32)
+            .collect::<Vec<_>>();
+
+        // The value is left-padded with zero bits to a whole number of words.
+        let value_words = (self.value.len() * 8).div_ceil(RADIX_BITS);
+        let (mut acc, mut bits) = (0u32, value_words * RADIX_BITS - self.value.len() * 8);
+        for byte in &self.value {
+            acc = (acc << 8) | u32::from(*byte);
+            bits += 8;
+            if bits >= RADIX_BITS {
+                bits -= RADIX_BITS;
+                values.push((acc >> bits) & 0x3ff);
+            }
+        }
+
+        let customization = customization(self.extendable);
+        let checksum = rs1024_polymod(
+            customization
+                .iter()
+                .map(|c| u32::from(*c))
+                .chain(values.iter().copied())
+                .chain(std::iter::repeat_n(0, CHECKSUM_WORDS)),
+        ) ^ 1;
+        values.extend(
+            (0..CHECKSUM_WORDS)
+                .rev()
+                .map(|idx| (checksum >> (RADIX_BITS * idx)) & 0x3ff),
+        );
+        values
+            .iter()
+            .map(|value| WORDLIST[*value as usize])
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // Parse a mnemonic, with position being its (1-based) position in the
+    // list of mnemonics for error reporting.
+    fn from_mnemonic(mnemonic: &str, position: usize) -> Result<Self, Slip39Error> {
+        let values = mnemonic
+            .split_whitespace()
+            .enumerate()
+            .map(|(idx, word)| {
+                WORDLIST
+                    .binary_search(&word.to_lowercase().as_str())
+                    .map(|value| value as u32)
+                    .map_err(|_| Slip39Error::InvalidWord {
+                        mnemonic: position,
+                        word: idx + 1,
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.len() < MIN_MNEMONIC_WORDS {
+            return Err(Slip39Error::InvalidLength { mnemonic: position });
+        }
+
+        let header = values[..HEADER_WORDS]
+            .iter()
+            .fold(0u64, |acc, value| (acc << RADIX_BITS) | u64::from(*value));
+        let extendable = (header >> 24) & 1 == 1;
+        let customization = customization(extendable);
+        let checksum = rs1024_polymod(
+            customization
+                .iter()
+                .map(|c| u32::from(*c))
+                .chain(values.iter().copied()),
+        );
+        if checksum != 1 {
+            return Err(Slip39Error::ChecksumMismatch { mnemonic: position });
+        }
+
+        // The padding must be shorter than a byte, and zero.
+        let value_words = &values[HEADER_WORDS..values.len() - CHECKSUM_WORDS];
+        let padding = (value_words.len() * RADIX_BITS) % 16;
+        if padding > 8 {
+            return Err(Slip39Error::InvalidLength { mnemonic: position });
+        }
+        let mut value = Vec::with_capacity(value_words.len() * RADIX_BITS / 8);
+        let (mut acc, mut bits, mut skip) = (0u32, 0, padding);
+        for word in value_words {
+            acc = (acc << RADIX_BITS) | word;
+            bits += RADIX_BITS;
+            if skip > 0 {
+                if acc >> (bits - skip) != 0 {
+                    return Err(Slip39Error::InvalidShare { mnemonic: position });
+                }
+                bits -= skip;
+                skip = 0;
+            }
+            while bits >= 8 {
+                bits -= 8;
+                value.push((acc >> bits) as u8);
+            }
+            acc &= (1 << bits) - 1;
+        }
+        acc.zeroize();
+
+        let share = Self {
+            id: (header >> 25) as u16,
+            extendable,
+            exponent: ((header >> 20) & 0xf) as u8,
+            group_index: ((header >> 16) & 0xf) as u8,
+            group_threshold: ((header >> 12) & 0xf) as u8 + 1,
+            group_count: ((header >> 8) & 0xf) as u8 + 1,
+            member_index: ((header >> 4) & 0xf) as u8,
+            member_threshold: (header & 0xf) as u8 + 1,
+            value,
+        };
+        if share.group_threshold > share.group_count {
+            return Err(Slip39Error::InvalidShare { mnemonic: position });
+        }
+        Ok(share)
+    }
+}
+
+fn check_secret(secret: &[u8]) -> Result<(), Slip39Error> {
+    match secret.len() >= MIN_SECRET_LENGTH && secret.len().is_multiple_of(2) {
+        true => Ok(()),
+        false => Err(Slip39Error::InvalidSecretLength),
+    }
+}
+
+fn split_with_exponent(
+    secret: &[u8],
+    passphrase: &str,
+    group_threshold: u8,
+    groups: &[(u8, u8)],
+    exponent: u8,
+) -> Result<Vec<Vec<String>>, Slip39Error> {
+    check_secret(secret)?;
+    check_passphrase(passphrase)?;
+    let group_count = groups.len();
+    if group_threshold == 0
+        || usize::from(group_threshold) > group_count
+        || group_count > usize::from(MAX_SHARE_COUNT)
+    {
+        return Err(Slip39Error::InvalidThreshold);
+    }
+    // Splitting a group into several identical member shares is not allowed
+    // (a 1-of-1 group should be used instead).
+    if groups.iter().any(|(threshold, count)| {
+        *threshold == 0
+            || threshold > count
+            || *count > MAX_SHARE_COUNT
+            || (*threshold == 1 && *count > 1)
+    }) {
+        return Err(Slip39Error::InvalidThreshold);
+    }
+
+    let id = OsRng.gen_range(0, 1 << ID_BITS) as u16;
+    let mut encrypted = feistel(
+        secret,
+        passphrase,
+        exponent,
+        &salt(id, true),
+        0..ROUND_COUNT,
+    );
+    let group_secrets = split_secret(group_threshold, group_count as u8, &encrypted);
+    encrypted.zeroize();
+
+    Ok(group_secrets
+        .iter()
+        .zip(groups)
+        .map(
+            |((group_index, group_secret), (member_threshold, member_count))| {
+                split_secret(*member_threshold, *member_count, group_secret)
+                    .into_iter()
+                    .map(|(member_index, value)| {
+                        Share {
+                            id,
+                            extendable: true,
+                            exponent,
+                            group_index: *group_index,
+                            group_threshold,
+                            group_count: group_count as u8,
+                            member_index,
+                            member_threshold: *member_threshold,
+                            value,
+                        }
+                        .to_mnemonic()
+                    })
+                    .collect()
+            },
+        )
+        .collect())
+}
+
+/// Split `secret` into SLIP-0039 mnemonics, protected with `passphrase`
+/// (which may be empty).
+///
+/// Each entry of `groups` is the `(threshold, count)` of the members of that
+/// group, and `group_threshold` groups are needed to recover the secret. The
+/// mnemonics are returned in the same order as `groups`. For simple
+/// `threshold`-of-`count` sharing, use a single group:
+///
+/// ```
+/// # use paperback_core::slip39;
+/// let mnemonics = slip39::split(&[0x42; 16], "", 1, &[(2, 3)]).unwrap();
+/// assert_eq!(mnemonics[0].len(), 3);
+/// ```
+pub fn split(
+    secret: &[u8],
+    passphrase: &str,
+    group_threshold: u8,
+    groups: &[(u8, u8)],
+) -> Result<Vec<Vec<String>>, Slip39Error> {
+    split_with_exponent(
+        secret,
+        passphrase,
+        group_threshold,
+        groups,
+        DEFAULT_ITERATION_EXPONENT,
+    )
+}
+
+/// Recover the secret from enough SLIP-0039 mnemonics (from any of its
+/// groups), decrypting it with `passphrase`.
+///
+/// Note that (as with SLIP-0039 itself) an incorrect passphrase does not
+/// result in an error, but in a different secret.
+pub fn combine<S: AsRef<str>>(mnemonics: &[S], passphrase: &str) -> Result<Vec<u8>, Slip39Error> {
+    check_passphrase(passphrase)?;
+    let shares = mnemonics
+        .iter()
+        .enumerate()
+        .map(|(idx, mnemonic)| Share::from_mnemonic(mnemonic.as_ref(), idx + 1))
+        .collect::<Result<Vec<_>, _>>()?;
+    let first = shares.first().ok_or(Slip39Error::NoShares)?;
+    if shares.iter().any(|share| {
+        (share.id, share.extendable, share.exponent) != (first.id, first.extendable, first.exponent)
+            || (share.group_threshold, share.group_count)
+                != (first.group_threshold, first.group_count)
+            || share.value.len() != first.value.len()
+    }) {
+        return Err(Slip39Error::MismatchedShares);
+    }
+
+    // Recover the secret of every group with enough members.
+    let mut group_secrets = vec![];
+    for group_index in 0..first.group_count {
+        let mut members = Vec::<(u8, Vec<u8>)>::new();
+        let mut threshold = None;
+        for share in shares.iter().filter(|s| s.group_index == group_index) {
+            if *threshold.get_or_insert(share.member_threshold) != share.member_threshold {
+                return Err(Slip39Error::MismatchedShares);
+            }
+            match members
+                .iter()
+                .find(|(index, _)| *index == share.member_index)
+            {
+                Some((_, value)) if *value != share.value => {
+                    return Err(Slip39Error::MismatchedShares)
+                }
+                Some(_) => continue,
+                None => members.push((share.member_index, share.value.clone())),
+            }
+        }
+        match threshold {
+            Some(threshold) if members.len() >= usize::from(threshold) => {
+                members.truncate(threshold.into());
+                group_secrets.push((group_index, recover_secret(threshold, &members)?));
+            }
+            _ => (),
+        }
+        members.iter_mut().for_each(|(_, value)| value.zeroize());
+    }
+    if group_secrets.len() < usize::from(first.group_threshold) {
+        return Err(Slip39Error::InsufficientShares);
+    }
+    group_secrets.truncate(first.group_threshold.into());
+
+    let mut encrypted = recover_secret(first.group_threshold, &group_secrets)?;
+    group_secrets
+        .iter_mut()
+        .for_each(|(_, value)| value.zeroize());
+    let secret = feistel(
+        &encrypted,
+        passphrase,
+        first.exponent,
+        &salt(first.id, first.extendable),
+        (0..ROUND_COUNT).rev(),
+    );
+    encrypted.zeroize();
+    Ok(secret)
+}
+
+impl Backup {
+    /// Create a new backup of a secret recovered from SLIP-0039 mnemonics (see
+    /// `combine`), so that a secret split with SLIP-0039 tooling can be
+    /// re-printed as a paperback backup.
+    pub fn from_slip39<S: AsRef<str>>(
+        quorum_size: u32,
+        mnemonics: &[S],
+        passphrase: &str,
+        options: BackupOptions,
+    ) -> Result<Self, String> {
+        let mut secret = combine(mnemonics, passphrase).map_err(|err| err.to_string())?;
+        let backup = Self::with_options(quorum_size, &secret, options);
+        secret.zeroize();
+        backup
+    }
+}
+
+impl Quorum {
+    /// Recover the secret and split it into SLIP-0039 mnemonics (see
+    /// `split`), so that it can be used with SLIP-0039 tooling. The secret
+    /// must be an even number of bytes, and at least `MIN_SECRET_LENGTH` bytes
+    /// long.
+    pub fn recover_slip39(
+        &self,
+        passphrase: &str,
+        group_threshold: u8,
+        groups: &[(u8, u8)],
+    ) -> Result<Vec<Vec<String>>, String> {
+        let mut secret = self.recover_document()?;
+        let mnemonics = split(&secret, passphrase, group_threshold, groups);
+        secret.zeroize();
+        mnemonics.map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{test::hex, UntrustedQuorum};
+
+    #[test]
+    fn slip39_vectors() {
+        // Test vectors from the SLIP-0039 reference implementation.
+        assert_eq!(
+            combine(
+                &["duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard"],
+                "TREZOR"
+            ),
+            Ok(hex("bb54aac4b89dc868ba37d9cc21b2cece"))
+        );
+        assert_eq!(
+            combine(
+                &[
+                    "shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed",
+                    "shadow pistol academic acid actress prayer class unknown daughter sweater depict flip twice unkind craft early superior advocate guest smoking",
+                ],
+                "TREZOR"
+            ),
+            Ok(hex("b43ceb7e57a0ea8766221624d01b0864"))
+        );
+    }
+
+    // Cheaper than DEFAULT_ITERATION_EXPONENT, to keep the tests quick.
+    const TEST_EXPONENT: u8 = 0;
+
+    #[quickcheck]
+    fn slip39_split_secret(secret: Vec<u8>, threshold: u8, count: u8) -> bool {
+        let count = 1 + count % MAX_SHARE_COUNT;
+        let threshold = 1 + threshold % count;
+        let mut secret = secret;
+        secret.resize(secret.len().max(DIGEST_LENGTH), 0xa5);
+        let shares = split_secret(threshold, count, &secret);
+
+        shares.len() == count.into()
+            && recover_secret(threshold, &shares[shares.len() - usize::from(threshold)..])
+                == Ok(secret.clone())
+            && (threshold == 1
+                || recover_secret(
+                    threshold,
+                    &[&shares[1..], &shares[..1]].concat()[..threshold.into()],
+                ) == Ok(secret))
+    }
+
+    #[test]
+    fn slip39_roundtrip() {
+        for (length, threshold, count) in &[(16usize, 1, 1), (18, 2, 2), (32, 3, 5), (64, 16, 16)] {
+            let secret = (0..*length).map(|i| i as u8).collect::<Vec<_>>();
+            let mnemonics = split_with_exponent(
+                &secret,
+                "passphrase",
+                1,
+                &[(*threshold, *count)],
+                TEST_EXPONENT,
+            )
+            .unwrap()
+            .remove(0);
+            let used = &mnemonics[mnemonics.len() - usize::from(*threshold)..];
+            assert_eq!(mnemonics.len(), usize::from(*count));
+            assert!(mnemonics.iter().all(|mnemonic| mnemonic.split(' ').count()
+                == HEADER_WORDS + CHECKSUM_WORDS + (length * 8).div_ceil(RADIX_BITS)));
+            assert_eq!(combine(used, "passphrase"), Ok(secret.clone()));
+            assert_ne!(combine(used, "wrong passphrase").unwrap(), secret);
+            if *threshold > 1 {
+                assert_eq!(
+                    combine(&used[1..], "passphrase"),
+                    Err(Slip39Error::InsufficientShares)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn slip39_groups() {
+        let secret = hex("00112233445566778899aabbccddeeff0011223344556677");
+        let groups = [(1, 1), (2, 3), (3, 5)];
+        let mnemonics = split_with_exponent(&secret, "", 2, &groups, TEST_EXPONENT).unwrap();
+        assert_eq!(
+            mnemonics.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![1, 3, 5]
+        );
+
+        let combined = |shares: &[&String]| combine(shares, "");
+        let (a, b, c) = (&mnemonics[0], &mnemonics[1], &mnemonics[2]);
+        assert_eq!(combined(&[&a[0], &b[2], &b[0]]), Ok(secret.clone()));
+        assert_eq!(
+            combined(&[&c[4], &b[1], &c[0], &b[2], &c[3]]),
+            Ok(secret.clone())
+        );
+        // Extra (and repeated) shares are ignored.
+        assert_eq!(
+            combined(&[&a[0], &a[0], &b[0], &c[0], &c[1], &b[1]]),
+            Ok(secret.clone())
+        );
+        // Only one complete group.
+        assert_eq!(
+            combined(&[&a[0], &b[0], &c[0], &c[1]]),
+            Err(Slip39Error::InsufficientShares)
+        );
+        // Shares from a different split.
+        let other = split_with_exponent(&secret, "", 2, &groups, TEST_EXPONENT).unwrap();
+        assert_eq!(
+            combined(&[&a[0], &other[1][0], &other[1][1]]),
+            Err(Slip39Error::MismatchedShares)
+        );
+    }
+
+    #[test]
+    fn slip39_errors() {
+        let secret = [0x42; MIN_SECRET_LENGTH];
+        let split = |secret: &[u8], passphrase, group_threshold, groups: &[(u8, u8)]| {
+            split_with_exponent(secret, passphrase, group_threshold, groups, TEST_EXPONENT)
+        };
+        assert_eq!(
+            split(&secret[1..], "", 1, &[(1, 1)]),
+            Err(Slip39Error::InvalidSecretLength)
+        );
+        assert_eq!(
+            split(&[0x42; MIN_SECRET_LENGTH + 1], "", 1, &[(1, 1)]),
+            Err(Slip39Error::InvalidSecretLength)
+        );
+        assert_eq!(
+            split(&secret, "caf\u{e9}", 1, &[(1, 1)]),
+            Err(Slip39Error::InvalidPassphrase)
+        );
+        for (group_threshold, groups) in &[
+            (1, &[(1, 2)][..]),
+            (1, &[(3, 2)][..]),
+            (1, &[(0, 2)][..]),
+            (1, &[(2, 17)][..]),
+            (2, &[(2, 3)][..]),
+            (0, &[(2, 3)][..]),
+        ] {
+            assert_eq!(
+                split(&secret, "", *group_threshold, groups),
+                Err(Slip39Error::InvalidThreshold)
+            );
+        }
+
+        let mnemonics = split(&secret, "", 1, &[(2, 3)]).unwrap().remove(0);
+        let mut words = mnemonics[1].split(' ').collect::<Vec<_>>();
+        assert_eq!(combine::<&str>(&[], ""), Err(Slip39Error::NoShares));
+        assert_eq!(
+            combine(&[&mnemonics[0], &words[..19].join(" ")], ""),
+            Err(Slip39Error::InvalidLength { mnemonic: 2 })
+        );
+        words[7] = if words[7] == "zero" { "yoga" } else { "zero" };
+        assert_eq!(
+            combine(&[&mnemonics[0], &words.join(" ")], ""),
+            Err(Slip39Error::ChecksumMismatch { mnemonic: 2 })
+        );
+        words[7] = "paperback";
+        assert_eq!(
+            combine(&[&mnemonics[0], &words.join(" ")], ""),
+            Err(Slip39Error::InvalidWord {
+                mnemonic: 2,
+                word: 8
+            })
+        );
+    }
+
+    #[test]
+    fn slip39_backup() {
+        let secret = hex("bb54aac4b89dc868ba37d9cc21b2cece");
+        let mut backup = Backup::from_slip39(
+            2,
+            &["duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard"],
+            "TREZOR",
+            Default::default(),
+        )
+        .unwrap();
+
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(backup.main_document().clone());
+        for _ in 0..2 {
+            quorum.push_shard(backup.next_shard().unwrap());
+        }
+        let quorum = quorum.validate().unwrap();
+        assert_eq!(quorum.recover_document().unwrap(), secret);
+
+        let mnemonics = quorum.recover_slip39("TREZOR", 1, &[(2, 3)]).unwrap();
+        assert_eq!(combine(&mnemonics[0][1..], "TREZOR"), Ok(secret));
+    }
+}
@@ -0,0 +1,222 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Objects can be written down using several text encodings (see the `armor`,
+//! `lines`, `multibase`, `bech32` and `words` modules). Each of them is recognisable
+//! from the text alone, so recovery does not need to be told which encoding
+//! was chosen when the object was written down.
+
+use crate::{
+    armor::{self, ArmorError},
+    bech32::{self, Bech32Error},
+    lines::{self, LineError},
+    multibase::{self, Base, MultibaseError},
+    v0::{FromWire, WireError},
+    words::{self, WordError},
+};
+
+use std::{error, fmt};
+
+/// Text encodings which can be detected by `detect`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextEncoding {
+    /// ASCII armor, from the `armor` module.
+    Armor,
+    /// Numbered lines of z-base-32, from the `lines` module.
+    Lines,
+    /// A multibase-prefixed string in the given base.
+    Multibase(Base),
+    /// A Bech32m string with one of paperback's human-readable parts.
+    Bech32m,
+    /// A list of dictionary words, from the `words` module.
+    Words,
+}
+
+/// Reasons why text could not be decoded by `decode`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TextError {
+    /// The text does not look like any supported encoding.
+    Unknown,
+    /// The text looks like armor, but could not be decoded.
+    Armor(ArmorError),
+    /// The text looks like numbered lines, but could not be decoded.
+    Lines(LineError),
+    /// The text looks like a multibase string, but could not be decoded.
+    Multibase(MultibaseError),
+    /// The text looks like a Bech32m string, but could not be decoded.
+    Bech32(Bech32Error),
+    /// The text looks like a list of words, but could not be decoded.
+    Words(WordError),
+    /// The decoded data could not be deserialised.
+    Wire(WireError),
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextError::Unknown => write!(f, "unrecognised text encoding"),
+            TextError::Armor(err) => write!(f, "invalid armor: {}", err),
+            TextError::Lines(err) => write!(f, "invalid lines: {}", err),
+            TextError::Multibase(err) => write!(f, "invalid multibase string: {}", err),
+            TextError::Bech32(err) => write!(f, "invalid bech32m string: {}", err),
+            TextError::Words(err) => write!(f, "invalid word list: {}", err),
+            TextError::Wire(err) => write!(f, "failed to deserialise data: {}", err),
+        }
+    }
+}
+
+impl error::Error for TextError {}
+
+impl From<WireError> for TextError {
+    fn from(err: WireError) -> Self {
+        TextError::Wire(err)
+    }
+}
+
+const BECH32_HRPS: [&str; 3] = [
+    bech32::SHARD_HRP,
+    bech32::MAIN_DOCUMENT_HRP,
+    bech32::KEY_SHARD_HRP,
+];
+
+/// Guess which encoding `input` was written in, without checking that it is
+/// valid in that encoding.
+pub fn detect(input: &str) -> Option<TextEncoding> {
+    let input = input.trim();
+    if input
+        .lines()
+        .any(|line| line.trim().starts_with("-----BEGIN "))
+    {
+        return Some(TextEncoding::Armor);
+    }
+    // Both multibase codes and Bech32m human-readable parts start with a
+    // letter, while numbered lines start with a digit.
+    if input.starts_with(|c: char| c.is_ascii_digit()) {
+        return Some(TextEncoding::Lines);
+    }
+    // The other encodings are a single string, while word lists are several
+    // words made up only of letters.
+    if input.contains(char::is_whitespace)
+        && input
+            .split_whitespace()
+            .all(|word| word.chars().all(|c| c.is_ascii_alphabetic()))
+    {
+        return Some(TextEncoding::Words);
+    }
+    let hrp = input
+        .rfind('1')
+        .map(|idx| input[..idx].to_ascii_lowercase());
+    if hrp.is_some_and(|hrp| BECH32_HRPS.contains(&hrp.as_str())) {
+        return Some(TextEncoding::Bech32m);
+    }
+    input
+        .chars()
+        .next()
+        .and_then(Base::from_code)
+        .map(TextEncoding::Multibase)
+}
+
+/// Decode text written in any supported encoding, returning the encoding
+/// that was detected.
+pub fn decode(input: &str) -> Result<(TextEncoding, Vec<u8>), TextError> {
+    let encoding = detect(input).ok_or(TextError::Unknown)?;
+    let data = match encoding {
+        TextEncoding::Armor => armor::dearmor_bytes(input).map_err(TextError::Armor)?.1,
+        TextEncoding::Lines => lines::decode(input).map_err(TextError::Lines)?,
+        TextEncoding::Multibase(_) => {
+            multibase::decode(input.trim())
+                .map_err(TextError::Multibase)?
+                .1
+        }
+        TextEncoding::Bech32m => bech32::decode(input.trim()).map_err(TextError::Bech32)?.1,
+        TextEncoding::Words => words::decode(input).map_err(TextError::Words)?,
+    };
+    Ok((encoding, data))
+}
+
+/// Decode an object from text written in any supported encoding, returning
+/// the encoding that was detected.
+pub fn decode_wire<T: FromWire>(input: &str) -> Result<(TextEncoding, T), TextError> {
+    let (encoding, data) = decode(input)?;
+    Ok((encoding, T::from_wire(data)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{shamir::Shard, v0::MainDocument};
+
+    #[quickcheck]
+    fn text_detect_shard(shard: Shard) -> bool {
+        let mut encoded = vec![
+            (
+                TextEncoding::Armor,
+                armor::armor(armor::SHARD_LABEL, &shard),
+            ),
+            (TextEncoding::Lines, lines::encode_wire(&shard)),
+            (
+                TextEncoding::Bech32m,
+                bech32::encode_wire(bech32::SHARD_HRP, &shard),
+            ),
+            (TextEncoding::Words, words::encode_wire(&shard)),
+        ];
+        for base in Base::ALL.iter() {
+            encoded.push((
+                TextEncoding::Multibase(*base),
+                multibase::encode_wire(*base, &shard),
+            ));
+        }
+        encoded.iter().all(|(encoding, text)| {
+            decode_wire::<Shard>(text).as_ref() == Ok(&(*encoding, shard.clone()))
+        })
+    }
+
+    #[quickcheck]
+    fn text_detect_main_document(main: MainDocument) -> bool {
+        let encoded = bech32::encode_wire(bech32::MAIN_DOCUMENT_HRP, &main).to_ascii_uppercase();
+        decode_wire::<MainDocument>(&encoded) == Ok((TextEncoding::Bech32m, main))
+    }
+
+    #[test]
+    fn text_errors() {
+        assert_eq!(detect(""), None);
+        assert_eq!(decode("paperback"), Err(TextError::Unknown));
+        assert_eq!(detect("pbshard1"), Some(TextEncoding::Bech32m));
+        assert!(matches!(decode("pbshard1"), Err(TextError::Bech32(_))));
+        assert!(matches!(decode("01 abcd"), Err(TextError::Lines(_))));
+        assert!(matches!(
+            decode("abandon paperback"),
+            Err(TextError::Words(_))
+        ));
+        assert!(matches!(
+            decode("-----BEGIN PAPERBACK SHARD-----"),
+            Err(TextError::Armor(_))
+        ));
+        // An unknown human-readable part is not detected as Bech32m.
+        assert_eq!(
+            detect("zzzz1abcdef"),
+            Some(TextEncoding::Multibase(Base::Base58Btc))
+        );
+        // Base45 strings are upper-case, so aren't mistaken for Bech32m.
+        assert_eq!(
+            detect("RPBSHARD1"),
+            Some(TextEncoding::Multibase(Base::Base45))
+        );
+    }
+}
@@ -0,0 +1,144 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Interoperability with [age][age], so that a bulky payload can be encrypted
+//! (and kept digitally) with existing age tooling while paperback only backs
+//! up the small age identity needed to decrypt it.
+//!
+//! `Backup::new_age` backs up an age X25519 identity (as the contents of an
+//! age identity file, so that the recovered document can be passed straight to
+//! `age --decrypt -i`), and `Quorum::recover_age_identity` recovers it.
+//! Encrypting and decrypting payloads is left to the [age crate][crate].
+//!
+//! [age]: https://age-encryption.org
+//! [crate]: https://docs.rs/age
+
+use crate::v0::{Backup, BackupOptions, Quorum};
+
+use ::age::{secrecy::ExposeSecret, x25519::Identity};
+use zeroize::Zeroize;
+
+impl Backup {
+    /// Back up an age identity, so that files encrypted to its recipient (and
+    /// kept digitally) can be decrypted after the identity is recovered with
+    /// `Quorum::recover_age_identity`. The secret of the backup is an age
+    /// identity file, so the recovered document can also be used directly
+    /// with age tooling.
+    pub fn new_age(
+        quorum_size: u32,
+        identity: &Identity,
+        options: BackupOptions,
+    ) -> Result<Self, String> {
+        // Same format as age-keygen.
+        let mut secret = format!(
+            "# public key: {}\n{}\n",
+            identity.to_public(),
+            identity.to_string().expose_secret()
+        );
+        let backup = Self::with_options(quorum_size, &secret, options);
+        secret.zeroize();
+        backup
+    }
+}
+
+impl Quorum {
+    /// Recover an age identity backed up with `Backup::new_age`.
+    pub fn recover_age_identity(&self) -> Result<Identity, String> {
+        let mut contents = self.recover_document()?;
+        let identity = std::str::from_utf8(&contents)
+            .map_err(|_| String::from("recovered document is not an age identity file"))
+            .and_then(|contents| {
+                // Identity files can contain comments and blank lines.
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty() && !line.starts_with('#'))
+                    .ok_or_else(|| String::from("age identity file contains no identities"))?
+                    .parse::<Identity>()
+                    .map_err(|err| format!("invalid age identity: {}", err))
+            });
+        contents.zeroize();
+        identity
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{test::quorum, KeyShard};
+
+    fn backup_quorum(identity: &Identity) -> Quorum {
+        let mut backup = Backup::new_age(2, identity, Default::default()).unwrap();
+        let main = backup.main_document().clone();
+        let shards = (0..2)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<KeyShard>>();
+        quorum(&main, &shards)
+    }
+
+    #[test]
+    fn age_backup() {
+        let identity = Identity::generate();
+        let quorum = backup_quorum(&identity);
+
+        let recovered = quorum.recover_age_identity().unwrap();
+        assert_eq!(
+            recovered.to_string().expose_secret(),
+            identity.to_string().expose_secret()
+        );
+
+        // Files encrypted to the original identity can be decrypted with the
+        // recovered one.
+        let payload = b"a payload which is too large to back up on paper";
+        let encrypted = ::age::encrypt(&identity.to_public(), payload).unwrap();
+        assert_eq!(::age::decrypt(&recovered, &encrypted).unwrap(), payload);
+    }
+
+    #[test]
+    fn age_identity_file() {
+        // Example identity from the age specification.
+        let identity = "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX"
+            .parse::<Identity>()
+            .unwrap();
+        let quorum = backup_quorum(&identity);
+        assert_eq!(
+            String::from_utf8(quorum.recover_document().unwrap()).unwrap(),
+            "# public key: age1zvkyg2lqzraa2lnjvqej32nkuu0ues2s82hzrye869xeexvn73equnujwj\n\
+             AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX\n"
+        );
+        assert_eq!(
+            quorum
+                .recover_age_identity()
+                .unwrap()
+                .to_public()
+                .to_string(),
+            "age1zvkyg2lqzraa2lnjvqej32nkuu0ues2s82hzrye869xeexvn73equnujwj"
+        );
+    }
+
+    #[test]
+    fn age_not_identity_file() {
+        let mut backup = Backup::new(2, b"# just a comment\n").unwrap();
+        let main = backup.main_document().clone();
+        let shards = (0..2)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<KeyShard>>();
+        assert!(quorum(&main, &shards).recover_age_identity().is_err());
+    }
+}
@@ -0,0 +1,183 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Parameters for the Argon2id password hash ([RFC 9106][rfc]), used to derive
+//! keys from passphrases. The hash itself is computed by the `argon2` crate.
+//!
+//! [rfc]: https://www.rfc-editor.org/rfc/rfc9106.html
+
+use ::argon2::{Algorithm, Argon2, AssociatedData, ParamsBuilder, Version};
+
+/// Cost parameters of Argon2id.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Argon2Params {
+    /// Memory used (in KiB). Must be at least `8 * lanes`.
+    pub memory_kib: u32,
+    /// Number of passes over the memory. Must be at least 1.
+    pub iterations: u32,
+    /// Degree of parallelism. Must be at least 1.
+    pub lanes: u32,
+}
+
+impl Argon2Params {
+    /// Default parameters used to protect key shards (the "second
+    /// recommended option" from RFC 9106).
+    pub const DEFAULT: Argon2Params = Argon2Params {
+        memory_kib: 64 * 1024,
+        iterations: 3,
+        lanes: 4,
+    };
+
+    /// Upper bound on the memory that will be used when deriving a key for a
+    /// serialised shard, so that a malicious shard can't exhaust memory.
+    pub const MAX_MEMORY_KIB: u32 = 1 << 20;
+
+    /// Upper bound on the number of passes for a serialised shard.
+    pub const MAX_ITERATIONS: u32 = 1 << 4;
+
+    /// Upper bound on the total work (memory multiplied by passes, in KiB)
+    /// for a serialised shard, so that the memory and pass bounds can't both
+    /// be maxed out at once. This is about ten times the default cost.
+    pub const MAX_WORK_KIB: u64 = 1 << 21;
+
+    /// Upper bound on the number of lanes for a serialised shard.
+    pub const MAX_LANES: u32 = 1 << 6;
+
+    // Check that the parameters are usable (and within the bounds above).
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.lanes == 0 || self.lanes > Self::MAX_LANES {
+            Err(format!(
+                "argon2 lanes must be between 1 and {} not {}",
+                Self::MAX_LANES,
+                self.lanes
+            ))
+        } else if self.iterations == 0 || self.iterations > Self::MAX_ITERATIONS {
+            Err(format!(
+                "argon2 iterations must be between 1 and {} not {}",
+                Self::MAX_ITERATIONS,
+                self.iterations
+            ))
+        } else if self.memory_kib < 8 * self.lanes || self.memory_kib > Self::MAX_MEMORY_KIB {
+            Err(format!(
+                "argon2 memory must be between {} and {} KiB not {}",
+                8 * self.lanes,
+                Self::MAX_MEMORY_KIB,
+                self.memory_kib
+            ))
+        } else if u64::from(self.memory_kib) * u64::from(self.iterations) > Self::MAX_WORK_KIB {
+            Err(format!(
+                "argon2 memory times iterations must be at most {} KiB not {}",
+                Self::MAX_WORK_KIB,
+                u64::from(self.memory_kib) * u64::from(self.iterations)
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Compute the Argon2id hash of `password` (with the given salt, optional
+/// secret key and associated data), writing `out.len()` bytes of output to
+/// `out`. The parameters must be valid (see `Argon2Params::validate`), `salt`
+/// must be at least 8 bytes long and `out` must be at least 4 bytes long.
+pub(crate) fn argon2id(
+    params: &Argon2Params,
+    password: &[u8],
+    salt: &[u8],
+    secret: &[u8],
+    ad: &[u8],
+    out: &mut [u8],
+) {
+    let params = ParamsBuilder::new()
+        .m_cost(params.memory_kib)
+        .t_cost(params.iterations)
+        .p_cost(params.lanes)
+        .data(AssociatedData::new(ad).expect("associated data must be short"))
+        .output_len(out.len())
+        .build()
+        .expect("argon2 parameters must be validated");
+    Argon2::new_with_secret(secret, Algorithm::Argon2id, Version::V0x13, params)
+        .and_then(|argon2| argon2.hash_password_into(password, salt, out))
+        .expect("argon2 inputs must be within the documented bounds");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn argon2id_rfc9106_vector() {
+        // Test vector from RFC 9106, section 5.3.
+        let params = Argon2Params {
+            memory_kib: 32,
+            iterations: 3,
+            lanes: 4,
+        };
+        let mut tag = [0u8; 32];
+        argon2id(
+            &params,
+            &[0x01; 32],
+            &[0x02; 16],
+            &[0x03; 8],
+            &[0x04; 12],
+            &mut tag,
+        );
+        assert_eq!(
+            tag,
+            [
+                0x0d, 0x64, 0x0d, 0xf5, 0x8d, 0x78, 0x76, 0x6c, 0x08, 0xc0, 0x37, 0xa3, 0x4a, 0x8b,
+                0x53, 0xc9, 0xd0, 0x1e, 0xf0, 0x45, 0x2d, 0x75, 0xb6, 0x5e, 0xb5, 0x25, 0x20, 0xe9,
+                0x6b, 0x01, 0xe6, 0x59,
+            ]
+        );
+    }
+
+    #[test]
+    fn argon2_params_validate() {
+        assert_eq!(Argon2Params::DEFAULT.validate(), Ok(()));
+        let params = |memory_kib, iterations, lanes| Argon2Params {
+            memory_kib,
+            iterations,
+            lanes,
+        };
+        assert!(params(8, 1, 1).validate().is_ok());
+        assert!(params(7, 1, 1).validate().is_err());
+        assert!(params(8, 0, 1).validate().is_err());
+        assert!(params(8, 1, 0).validate().is_err());
+        assert!(params(Argon2Params::MAX_MEMORY_KIB + 1, 1, 1)
+            .validate()
+            .is_err());
+        assert!(params(8, Argon2Params::MAX_ITERATIONS + 1, 1)
+            .validate()
+            .is_err());
+        // The memory and pass bounds can't both be maxed out.
+        assert!(params(
+            Argon2Params::MAX_MEMORY_KIB,
+            Argon2Params::MAX_ITERATIONS,
+            1
+        )
+        .validate()
+        .is_err());
+    }
+}
@@ -19,15 +19,143 @@
 use crate::{
     shamir::Dealer,
     v0::{
-        ChaChaPolyKey, ChaChaPolyNonce, KeyShard, KeyShardBuilder, MainDocument,
-        MainDocumentBuilder, MainDocumentMeta, ShardSecret, ToWire,
+        decoy, revoke::RevocationListBuilder, shard_mac_key, AeadAlgorithm, Argon2Params,
+        ChaChaPolyKey, Compression, Custodian, KeyShard, KeyShardBuilder, MainDocument,
+        MainDocumentBuilder, MainDocumentMeta, MainDocumentPage, Metadata, RevocationList,
+        RevocationReason, ShardId, ShardSecret, TimeLock, TimeLockParams, ToWire, Watermark,
+        WatermarkKey, CHACHAPOLY_KEY_LENGTH,
     },
 };
 
-use aead::{Aead, NewAead, Payload};
-use chacha20poly1305::ChaCha20Poly1305;
-use ed25519_dalek::{Keypair, SecretKey};
+use aead::Payload;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
 use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroize;
+
+/// Options controlling how a `Backup` is created (see `Backup::with_options`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BackupOptions {
+    /// Whether the identity private key is omitted from the shards, so that
+    /// no new shards can be created after recovery.
+    pub sealed: bool,
+    /// Compression applied to the secret before it is encrypted. If the
+    /// secret doesn't compress, it is stored uncompressed.
+    pub compression: Compression,
+    /// Algorithm used to encrypt the secret.
+    pub algorithm: AeadAlgorithm,
+    /// Descriptive metadata stored (unencrypted) in the main document.
+    pub metadata: Metadata,
+    /// Number of random bytes appended to the secret before it is encrypted,
+    /// which hides the length of the secret (and makes room for the hidden
+    /// secret of a decoy backup, see `Backup::with_decoy`). If zero, the
+    /// secret isn't padded. Defaults to `BackupOptions::DEFAULT_PADDING`, so
+    /// that a decoy backup created with the default options cannot be told
+    /// apart from any other backup.
+    pub padding: usize,
+    /// Whether the main document (and thus its ID) is derived from the secret
+    /// and the other options rather than from fresh randomness, so that
+    /// backing up the same secret with the same options always results in
+    /// the same main document. This lets archival pipelines deduplicate
+    /// backups, but it also lets anyone who can guess the secret confirm
+    /// their guess from the main document alone -- only use it for secrets
+    /// with plenty of entropy. The shards are still random.
+    ///
+    /// The identity keypair is derived from the secret as well, so anyone who
+    /// knows the secret (such as any past quorum) can sign documents for the
+    /// backup. Unsealed backups already give that key to every quorum, but it
+    /// would defeat sealing, so deterministic backups cannot be sealed (or
+    /// contain a hidden secret). Their padding is zeroed rather than random.
+    pub deterministic: bool,
+    /// Maximum number of bytes of the main document stored on each printed
+    /// page (see `MainDocument::to_chunked_pages`), which is recorded in the
+    /// main document. Larger chunks mean fewer pages but denser codes, so this
+    /// can be tuned to what a particular printer and scanner can handle. It
+    /// must be between `MainDocumentPage::MIN_CHUNK_SIZE` and
+    /// `MainDocumentPage::MAX_CHUNK_SIZE`.
+    pub chunk_size: Option<u32>,
+    /// Whether the document key stored in the shards is masked by a time lock
+    /// (see `TimeLock`), so that even a complete quorum has to spend a fixed
+    /// amount of computation before the secret can be recovered. Creating the
+    /// lock takes a fraction of that time (depending on the number of chains).
+    /// Deterministic backups cannot be time-locked.
+    pub time_lock: Option<TimeLockParams>,
+}
+
+impl BackupOptions {
+    /// Default amount of padding, which leaves room for a hidden secret of
+    /// about 70 bytes (depending on the algorithm).
+    pub const DEFAULT_PADDING: usize = 128;
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self {
+            sealed: false,
+            compression: Default::default(),
+            algorithm: Default::default(),
+            metadata: Default::default(),
+            padding: Self::DEFAULT_PADDING,
+            deterministic: false,
+            chunk_size: None,
+            time_lock: None,
+        }
+    }
+}
+
+// Personalisation strings for the keys of deterministic backups.
+const DETERMINISTIC_SEED_PERSONAL: &[u8] = b"paperback-seed";
+const DETERMINISTIC_ID_KEY_PERSONAL: &[u8] = b"paperback-idkey";
+const DETERMINISTIC_DOC_KEY_PERSONAL: &[u8] = b"paperback-dockey";
+const DETERMINISTIC_NONCE_PERSONAL: &[u8] = b"paperback-nonce";
+
+// Derive the identity keypair, document key and nonce of a deterministic
+// backup from the secret, the amount of padding and the (requested) metadata
+// of the main document.
+fn derive_keys(
+    meta: &MainDocumentMeta,
+    sealed: bool,
+    padding: usize,
+    secret: &[u8],
+) -> (Keypair, ChaChaPolyKey, Vec<u8>) {
+    let seed = blake2b_simd::Params::new()
+        .personal(DETERMINISTIC_SEED_PERSONAL)
+        .to_state()
+        .update(&meta.to_wire())
+        .update(&[sealed as u8])
+        .update(&(padding as u64).to_le_bytes())
+        .update(secret)
+        .finalize();
+    let derive = |personal: &[u8], len: usize| {
+        blake2b_simd::Params::new()
+            .hash_length(len)
+            .key(seed.as_bytes())
+            .personal(personal)
+            .hash(&[])
+    };
+
+    let id_secret = SecretKey::from_bytes(
+        derive(
+            DETERMINISTIC_ID_KEY_PERSONAL,
+            ed25519_dalek::SECRET_KEY_LENGTH,
+        )
+        .as_bytes(),
+    )
+    .expect("ed25519 secret keys can be any 32 bytes");
+    let id_keypair = Keypair {
+        public: PublicKey::from(&id_secret),
+        secret: id_secret,
+    };
+
+    let mut doc_key = ChaChaPolyKey::default();
+    doc_key
+        .copy_from_slice(derive(DETERMINISTIC_DOC_KEY_PERSONAL, CHACHAPOLY_KEY_LENGTH).as_bytes());
+
+    let nonce_len = meta.algorithm.nonce_len();
+    let doc_nonce =
+        derive(DETERMINISTIC_NONCE_PERSONAL, nonce_len).as_bytes()[..nonce_len].to_vec();
+
+    (id_keypair, doc_key, doc_nonce)
+}
 
 pub struct Backup {
     main_document: MainDocument,
@@ -36,23 +164,92 @@ pub struct Backup {
 }
 
 impl Backup {
-    // XXX: This internal API is a bit ugly...
-    fn inner_new(quorum_size: u32, secret: &[u8], sealed: bool) -> Result<Self, String> {
-        // Generate identity keypair.
-        let id_keypair = Keypair::generate(&mut OsRng);
+    pub fn with_options<B: AsRef<[u8]>>(
+        quorum_size: u32,
+        secret: B,
+        options: BackupOptions,
+    ) -> Result<Self, String> {
+        Self::inner_with_options(quorum_size, secret.as_ref(), None, options)
+    }
 
-        // Generate key and nonce.
-        let mut doc_key = ChaChaPolyKey::default();
-        OsRng.fill_bytes(&mut doc_key);
-        let mut doc_nonce = ChaChaPolyNonce::default();
-        OsRng.fill_bytes(&mut doc_nonce);
+    // Create a backup, with an optional hidden secret (and the passphrase and
+    // parameters used to protect it) stored in the padding.
+    pub(super) fn inner_with_options(
+        quorum_size: u32,
+        secret: &[u8],
+        hidden: Option<(&[u8], &[u8], Argon2Params)>,
+        options: BackupOptions,
+    ) -> Result<Self, String> {
+        let BackupOptions {
+            sealed,
+            compression,
+            algorithm,
+            metadata,
+            padding,
+            deterministic,
+            chunk_size,
+            time_lock,
+        } = options;
+        metadata.validate()?;
+        if let Some(chunk_size) = chunk_size {
+            MainDocumentPage::check_chunk_size(chunk_size)?;
+        }
+        if padding == 0 && hidden.is_some() {
+            return Err("a hidden secret requires the document to be padded".into());
+        }
+        if hidden.is_some() && deterministic {
+            return Err("deterministic backups cannot contain a hidden secret".into());
+        }
+        if sealed && deterministic {
+            return Err("deterministic backups cannot be sealed".into());
+        }
+        if time_lock.is_some() && deterministic {
+            return Err("deterministic backups cannot be time-locked".into());
+        }
+
+        // Generate identity keypair, key and nonce (or derive them from the
+        // contents, for deterministic backups).
+        let (id_keypair, doc_key, doc_nonce) = match deterministic {
+            false => {
+                let mut doc_key = ChaChaPolyKey::default();
+                OsRng.fill_bytes(&mut doc_key);
+                (
+                    Keypair::generate(&mut OsRng),
+                    doc_key,
+                    algorithm.generate_nonce(),
+                )
+            }
+            true => {
+                let meta = MainDocumentMeta {
+                    version: 0u32,
+                    quorum_size,
+                    compression,
+                    algorithm,
+                    metadata: metadata.clone(),
+                    padded: padding > 0,
+                    chunk_size,
+                    time_lock: None,
+                };
+                derive_keys(&meta, sealed, padding, secret)
+            }
+        };
+
+        // Create the time lock (if requested), which masks the document key
+        // stored in the shards.
+        let (time_lock, mut shard_doc_key) = match time_lock {
+            None => (None, doc_key),
+            Some(params) => {
+                let (lock, key) = TimeLock::create(&params, &doc_key)?;
+                (Some(lock), key)
+            }
+        };
 
         // Construct shard secret and serialise it.
         let shard_secret = {
             let id_private_key = SecretKey::from_bytes(id_keypair.secret.as_bytes())
                 .expect("round-trip of ed25519 key to get around non-Copy must never fail");
             ShardSecret {
-                doc_key,
+                doc_key: shard_doc_key,
                 id_private_key: match sealed {
                     false => Some(id_private_key),
                     true => None,
@@ -60,22 +257,69 @@ impl Backup {
             }
             .to_wire()
         };
+        shard_doc_key.zeroize();
+
+        // Compress the contents, but only keep the result if it actually saved
+        // some space (and can be inflated again on recovery).
+        let compression = compression.for_length(secret.len());
+        let mut compressed = compression.compress(secret);
+        let (compression, plaintext) = match compression {
+            Compression::None => (Compression::None, secret),
+            _ if compressed.len() >= secret.len() => (Compression::None, secret),
+            _ => (compression, compressed.as_slice()),
+        };
 
         // Construct the MainDocument.
         let main_document_meta = MainDocumentMeta {
             version: 0u32,
             quorum_size,
+            compression,
+            algorithm,
+            metadata,
+            padded: padding > 0,
+            chunk_size,
+            time_lock,
+        };
+        let aad = main_document_meta.aad(&id_keypair.public);
+
+        // Pad the contents (hiding the hidden secret in the padding, if there
+        // is one). The padding of deterministic backups can't be random.
+        let mut padded = match (padding, deterministic) {
+            (0, _) => vec![],
+            (_, false) => decoy::pad(plaintext, padding),
+            (_, true) => decoy::pad_zeroed(plaintext, padding),
+        };
+        if let Some((hidden, passphrase, params)) = hidden {
+            let offset = padded.len() - padding;
+            let result = decoy::hide(
+                &mut padded[offset..],
+                hidden,
+                passphrase,
+                &params,
+                &doc_key,
+                algorithm,
+                &aad,
+            );
+            if let Err(err) = result {
+                compressed.zeroize();
+                padded.zeroize();
+                return Err(err);
+            }
+        }
+        let plaintext = match padding {
+            0 => plaintext,
+            _ => padded.as_slice(),
         };
 
         // Encrypt the contents.
-        let aead = ChaCha20Poly1305::new(doc_key);
         let payload = Payload {
-            msg: secret,
-            aad: &main_document_meta.aad(&id_keypair.public),
+            msg: plaintext,
+            aad: &aad,
         };
-        let ciphertext = aead
-            .encrypt(&doc_nonce, payload)
-            .map_err(|err| format!("{:?}", err))?; // XXX: Ugly, fix this.
+        let ciphertext = algorithm.encrypt(&doc_key, &doc_nonce, payload);
+        compressed.zeroize();
+        padded.zeroize();
+        let ciphertext = ciphertext?;
 
         // Continue MainDocument construction.
         let main_document = MainDocumentBuilder {
@@ -85,8 +329,11 @@ impl Backup {
         }
         .sign(&id_keypair);
 
-        // Construct SSS dealer.
-        let dealer = Dealer::new(quorum_size, shard_secret);
+        // Construct SSS dealer. The shards are authenticated (keyed by the
+        // main document's checksum) so that a damaged shard is detected, and
+        // named, before it is used during recovery.
+        let mac_key = shard_mac_key(&main_document.checksum());
+        let dealer = Dealer::new(quorum_size, shard_secret).authenticated(&mac_key);
 
         Ok(Backup {
             main_document,
@@ -96,23 +343,95 @@ impl Backup {
     }
 
     pub fn new<B: AsRef<[u8]>>(quorum_size: u32, secret: B) -> Result<Self, String> {
-        Self::inner_new(quorum_size, secret.as_ref(), false)
+        Self::with_options(quorum_size, secret, BackupOptions::default())
     }
 
     pub fn new_sealed<B: AsRef<[u8]>>(quorum_size: u32, secret: B) -> Result<Self, String> {
-        Self::inner_new(quorum_size, secret.as_ref(), true)
+        let options = BackupOptions {
+            sealed: true,
+            ..Default::default()
+        };
+        Self::with_options(quorum_size, secret, options)
+    }
+
+    /// Like `new`, but compresses the secret before it is encrypted. If the
+    /// secret doesn't compress, it is stored uncompressed.
+    pub fn new_compressed<B: AsRef<[u8]>>(quorum_size: u32, secret: B) -> Result<Self, String> {
+        let options = BackupOptions {
+            compression: Compression::Deflate,
+            ..Default::default()
+        };
+        Self::with_options(quorum_size, secret, options)
+    }
+
+    /// Like `new_sealed`, but compresses the secret before it is encrypted.
+    pub fn new_sealed_compressed<B: AsRef<[u8]>>(
+        quorum_size: u32,
+        secret: B,
+    ) -> Result<Self, String> {
+        let options = BackupOptions {
+            sealed: true,
+            compression: Compression::Deflate,
+            ..Default::default()
+        };
+        Self::with_options(quorum_size, secret, options)
     }
 
     pub fn main_document(&self) -> &MainDocument {
         &self.main_document
     }
 
-    pub fn next_shard(&self) -> Result<KeyShard, String> {
+    /// Create a revocation list for shards of this backup.
+    pub fn revoke(&self, revoked: Vec<(ShardId, RevocationReason)>) -> RevocationList {
+        RevocationListBuilder {
+            doc_chksum: self.main_document.checksum(),
+            revoked,
+        }
+        .sign(&self.id_keypair)
+    }
+
+    pub fn next_shard(&mut self) -> Result<KeyShard, String> {
+        self.inner_next_shard(None, None)
+    }
+
+    /// Like `next_shard`, but records which custodian the shard is given to.
+    pub fn next_shard_for(&mut self, custodian: Custodian) -> Result<KeyShard, String> {
+        custodian.validate()?;
+        self.inner_next_shard(Some(custodian), None)
+    }
+
+    /// Like `next_shard` (or `next_shard_for`, if `custodian` is given), but
+    /// embeds a watermark identifying the shard as the copy issued to the
+    /// custodian with the given index (see `Watermark`). The owner of the
+    /// backup must keep `key` (and which custodian has which index) to be able
+    /// to identify the shard later.
+    pub fn next_shard_watermarked(
+        &mut self,
+        custodian: Option<Custodian>,
+        index: u32,
+        key: &WatermarkKey,
+    ) -> Result<KeyShard, String> {
+        if let Some(custodian) = &custodian {
+            custodian.validate()?;
+        }
+        let watermark = key.watermark(&self.main_document.checksum(), index);
+        self.inner_next_shard(custodian, Some(watermark))
+    }
+
+    fn inner_next_shard(
+        &mut self,
+        custodian: Option<Custodian>,
+        watermark: Option<Watermark>,
+    ) -> Result<KeyShard, String> {
         // Extend new shard.
         Ok(KeyShardBuilder {
             version: self.main_document.inner.meta.version,
             doc_chksum: self.main_document.checksum(),
             shard: self.dealer.next_shard(),
+            review_by: self.main_document.metadata().review_by,
+            custodian,
+            watermark,
+            legacy: false,
         }
         .sign(&self.id_keypair))
     }
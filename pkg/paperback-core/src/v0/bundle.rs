@@ -0,0 +1,209 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Bundles of several named files (such as an SSH key, a GPG key and some 2FA
+//! recovery codes) which are backed up together as a single secret, so that
+//! they share one quorum. The bundle is serialised with `ToWire` and used as
+//! the secret of a `Backup`, and `Quorum::recover_bundle` recovers it.
+
+use std::{error, fmt};
+
+use zeroize::Zeroize;
+
+/// A named file stored in a `Bundle`. Its contents are zeroed when it is
+/// dropped.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BundleEntry {
+    pub(super) name: String,
+    pub(super) data: Vec<u8>,
+}
+
+impl BundleEntry {
+    /// Name of the file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Contents of the file.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Drop for BundleEntry {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+/// Reasons why an entry could not be added to a `Bundle`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BundleError {
+    /// The name is empty or longer than `Bundle::MAX_NAME_LENGTH` bytes.
+    InvalidName(String),
+    /// The bundle already has an entry with the same name.
+    DuplicateName(String),
+    /// The bundle already has `Bundle::MAX_ENTRIES` entries.
+    TooManyEntries,
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BundleError::InvalidName(name) => write!(f, "invalid bundle entry name {:?}", name),
+            BundleError::DuplicateName(name) => {
+                write!(f, "bundle already contains an entry named {:?}", name)
+            }
+            BundleError::TooManyEntries => write!(
+                f,
+                "bundle cannot contain more than {} entries",
+                Bundle::MAX_ENTRIES
+            ),
+        }
+    }
+}
+
+impl error::Error for BundleError {}
+
+/// Several named files backed up together as one secret. Entries are kept in
+/// the order they were added, and their names are unique.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Bundle {
+    pub(super) entries: Vec<BundleEntry>,
+}
+
+impl Bundle {
+    /// Maximum length (in bytes) of the name of an entry.
+    pub const MAX_NAME_LENGTH: usize = 255;
+
+    /// Maximum number of entries in a bundle.
+    pub const MAX_ENTRIES: usize = 1 << 16;
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub(super) fn check_name(&self, name: &str) -> Result<(), BundleError> {
+        if name.is_empty() || name.len() > Self::MAX_NAME_LENGTH {
+            return Err(BundleError::InvalidName(name.into()));
+        }
+        if self.get(name).is_some() {
+            return Err(BundleError::DuplicateName(name.into()));
+        }
+        if self.entries.len() >= Self::MAX_ENTRIES {
+            return Err(BundleError::TooManyEntries);
+        }
+        Ok(())
+    }
+
+    /// Add a file to the bundle.
+    pub fn add<N, B>(&mut self, name: N, data: B) -> Result<&mut Self, BundleError>
+    where
+        N: Into<String>,
+        B: AsRef<[u8]>,
+    {
+        let name = name.into();
+        self.check_name(&name)?;
+        self.entries.push(BundleEntry {
+            name,
+            data: data.as_ref().to_vec(),
+        });
+        Ok(self)
+    }
+
+    /// Returns the contents of the named file, if it is in the bundle.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(BundleEntry::data)
+    }
+
+    /// Returns the names of the files in the bundle.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(BundleEntry::name)
+    }
+
+    pub fn entries(&self) -> &[BundleEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for Bundle {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let mut bundle = Bundle::new();
+        for (name, data) in Vec::<(String, Vec<u8>)>::arbitrary(g) {
+            // Skip any entries with invalid or duplicate names.
+            let _ = bundle.add(name, data);
+        }
+        bundle
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[quickcheck]
+    fn bundle_get(bundle: Bundle) -> bool {
+        bundle.len() == bundle.names().count()
+            && bundle
+                .entries()
+                .iter()
+                .all(|entry| bundle.get(entry.name()) == Some(entry.data()))
+    }
+
+    #[test]
+    fn bundle_names() {
+        let mut bundle = Bundle::new();
+        bundle
+            .add("id_ed25519", b"ssh key")
+            .unwrap()
+            .add("2fa.txt", b"recovery codes")
+            .unwrap();
+
+        assert_eq!(
+            bundle.names().collect::<Vec<_>>(),
+            ["id_ed25519", "2fa.txt"]
+        );
+        assert_eq!(bundle.get("2fa.txt"), Some(&b"recovery codes"[..]));
+        assert_eq!(bundle.get("gpg.key"), None);
+        assert_eq!(
+            bundle.add("2fa.txt", b"").map(|_| ()),
+            Err(BundleError::DuplicateName("2fa.txt".into()))
+        );
+        assert_eq!(
+            bundle.add("", b"").map(|_| ()),
+            Err(BundleError::InvalidName("".into()))
+        );
+        let long = "x".repeat(Bundle::MAX_NAME_LENGTH + 1);
+        assert_eq!(
+            bundle.add(long.clone(), b"").map(|_| ()),
+            Err(BundleError::InvalidName(long))
+        );
+    }
+}
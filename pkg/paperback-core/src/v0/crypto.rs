@@ -0,0 +1,192 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Registry of the AEAD algorithms which can be used to encrypt the contents
+//! of a document. The algorithm used is recorded in the document, so new
+//! algorithms can be added without affecting the decryption of older backups
+//! (which all use ChaCha20-Poly1305).
+
+use crate::v0::ChaChaPolyKey;
+
+use aead::{generic_array::GenericArray, Aead, NewAead, Payload};
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305};
+use rand::{rngs::OsRng, RngCore};
+
+/// AEAD algorithms which can be used to encrypt a document. All of them use
+/// 256-bit keys.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AeadAlgorithm {
+    /// ChaCha20-Poly1305 (RFC 8439), with a 96-bit nonce.
+    #[default]
+    ChaCha20Poly1305,
+    /// XChaCha20-Poly1305, with a 192-bit nonce.
+    XChaCha20Poly1305,
+    /// AES-256-GCM (NIST SP 800-38D), with a 96-bit nonce.
+    Aes256Gcm,
+}
+
+impl AeadAlgorithm {
+    /// All supported algorithms.
+    pub const ALL: [AeadAlgorithm; 3] = [
+        AeadAlgorithm::ChaCha20Poly1305,
+        AeadAlgorithm::XChaCha20Poly1305,
+        AeadAlgorithm::Aes256Gcm,
+    ];
+
+    // Identifier stored in the document. The default algorithm is implied by
+    // the identifier being omitted (as it was before it could be chosen).
+    pub(crate) fn wire_id(self) -> Option<u32> {
+        match self {
+            AeadAlgorithm::ChaCha20Poly1305 => None,
+            AeadAlgorithm::XChaCha20Poly1305 => Some(1),
+            AeadAlgorithm::Aes256Gcm => Some(2),
+        }
+    }
+
+    pub(crate) fn from_wire_id(id: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|algorithm| algorithm.wire_id() == Some(id))
+    }
+
+    /// Length (in bytes) of the nonces used by the algorithm.
+    pub fn nonce_len(self) -> usize {
+        match self {
+            AeadAlgorithm::ChaCha20Poly1305 => 12,
+            AeadAlgorithm::XChaCha20Poly1305 => 24,
+            AeadAlgorithm::Aes256Gcm => 12,
+        }
+    }
+
+    pub(crate) fn generate_nonce(self) -> Vec<u8> {
+        let mut nonce = vec![0u8; self.nonce_len()];
+        OsRng.fill_bytes(&mut nonce);
+        nonce
+    }
+
+    pub(crate) fn encrypt(
+        self,
+        key: &ChaChaPolyKey,
+        nonce: &[u8],
+        payload: Payload,
+    ) -> Result<Vec<u8>, String> {
+        self.check_nonce(nonce)?;
+        match self {
+            AeadAlgorithm::ChaCha20Poly1305 => {
+                ChaCha20Poly1305::new(*key).encrypt(GenericArray::from_slice(nonce), payload)
+            }
+            AeadAlgorithm::XChaCha20Poly1305 => {
+                XChaCha20Poly1305::new(*key).encrypt(GenericArray::from_slice(nonce), payload)
+            }
+            AeadAlgorithm::Aes256Gcm => {
+                Aes256Gcm::new(*key).encrypt(GenericArray::from_slice(nonce), payload)
+            }
+        }
+        .map_err(|_| format!("{:?} encryption failed", self))
+    }
+
+    pub(crate) fn decrypt(
+        self,
+        key: &ChaChaPolyKey,
+        nonce: &[u8],
+        payload: Payload,
+    ) -> Result<Vec<u8>, String> {
+        self.check_nonce(nonce)?;
+        match self {
+            AeadAlgorithm::ChaCha20Poly1305 => {
+                ChaCha20Poly1305::new(*key).decrypt(GenericArray::from_slice(nonce), payload)
+            }
+            AeadAlgorithm::XChaCha20Poly1305 => {
+                XChaCha20Poly1305::new(*key).decrypt(GenericArray::from_slice(nonce), payload)
+            }
+            AeadAlgorithm::Aes256Gcm => {
+                Aes256Gcm::new(*key).decrypt(GenericArray::from_slice(nonce), payload)
+            }
+        }
+        // The only failure is an authentication failure, which means the key
+        // is wrong or the ciphertext has been modified.
+        .map_err(|_| format!("{:?} decryption failed", self))
+    }
+
+    fn check_nonce(self, nonce: &[u8]) -> Result<(), String> {
+        match nonce.len() == self.nonce_len() {
+            true => Ok(()),
+            false => Err(format!(
+                "{:?} nonce must be {} bytes not {}",
+                self,
+                self.nonce_len(),
+                nonce.len()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::test::hex;
+
+    #[quickcheck]
+    fn aead_roundtrip(msg: Vec<u8>, aad: Vec<u8>) -> bool {
+        let mut key = ChaChaPolyKey::default();
+        OsRng.fill_bytes(&mut key);
+        AeadAlgorithm::ALL.iter().all(|algorithm| {
+            let nonce = algorithm.generate_nonce();
+            let payload = |msg| Payload { msg, aad: &aad };
+            let ciphertext = algorithm.encrypt(&key, &nonce, payload(&msg)).unwrap();
+            algorithm.decrypt(&key, &nonce, payload(&ciphertext)) == Ok(msg.clone())
+                && AeadAlgorithm::ALL
+                    .iter()
+                    .filter(|other| *other != algorithm)
+                    .all(|other| {
+                        let nonce = &other.generate_nonce();
+                        other.decrypt(&key, nonce, payload(&ciphertext)).is_err()
+                    })
+        })
+    }
+
+    #[test]
+    fn aes256gcm_vector() {
+        // Test case 14 from the GCM specification (all-zero key and nonce).
+        let key = ChaChaPolyKey::default();
+        let ciphertext = AeadAlgorithm::Aes256Gcm
+            .encrypt(&key, &[0; 12], Payload::from(&[0; 16][..]))
+            .unwrap();
+        assert_eq!(
+            ciphertext,
+            hex("cea7403d4d606b6e074ec5d3baf39d18d0d1c8a799996bf0265b98b5d48ab919")
+        );
+    }
+
+    #[test]
+    fn aead_wire_ids() {
+        for algorithm in AeadAlgorithm::ALL.iter() {
+            match algorithm.wire_id() {
+                None => assert_eq!(*algorithm, AeadAlgorithm::default()),
+                Some(id) => assert_eq!(AeadAlgorithm::from_wire_id(id), Some(*algorithm)),
+            }
+        }
+        assert_eq!(AeadAlgorithm::from_wire_id(0), None);
+        assert!(AeadAlgorithm::ChaCha20Poly1305
+            .encrypt(&ChaChaPolyKey::default(), &[0; 24], Payload::from(&[][..]))
+            .is_err());
+    }
+}
@@ -0,0 +1,314 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Decoy backups, for plausible deniability. The main document of a padded
+//! backup (see `BackupOptions::padding`) contains the secret followed by
+//! random padding. A decoy backup is a padded backup whose padding is instead
+//! a hidden secret, encrypted with a key derived from both the document key
+//! and a passphrase. Without the passphrase the hidden secret cannot be told
+//! apart from random padding, so someone coerced into a recovery can give up
+//! the decoy (which is all `Quorum::recover_document` returns) without
+//! revealing that there is anything else in the backup.
+
+use crate::v0::{
+    argon2, AeadAlgorithm, Argon2Params, Backup, BackupOptions, ChaChaPolyKey, Quorum,
+    ARGON2_SALT_LENGTH,
+};
+
+use aead::Payload;
+use rand::{rngs::OsRng, RngCore};
+use unsigned_varint::{decode, encode};
+use zeroize::Zeroize;
+
+// Length of the authentication tag appended by both AEAD algorithms.
+const AEAD_TAG_LENGTH: usize = 16;
+
+// Prefix the contents with their length and append `padding` zero bytes.
+pub(super) fn pad_zeroed(contents: &[u8], padding: usize) -> Vec<u8> {
+    let mut plaintext = Vec::with_capacity(contents.len() + padding + 10);
+    plaintext.extend_from_slice(encode::usize(contents.len(), &mut encode::usize_buffer()));
+    plaintext.extend_from_slice(contents);
+    plaintext.resize(plaintext.len() + padding, 0);
+    plaintext
+}
+
+// Prefix the contents with their length and append `padding` random bytes.
+pub(super) fn pad(contents: &[u8], padding: usize) -> Vec<u8> {
+    let mut plaintext = pad_zeroed(contents, padding);
+    let offset = plaintext.len() - padding;
+    OsRng.fill_bytes(&mut plaintext[offset..]);
+    plaintext
+}
+
+// Split padded plaintext into the contents and the padding.
+pub(super) fn unpad(plaintext: &[u8]) -> Result<(&[u8], &[u8]), String> {
+    let (len, remain) =
+        decode::usize(plaintext).map_err(|_| "padded document has invalid length")?;
+    if len > remain.len() {
+        return Err("padded document is truncated".into());
+    }
+    Ok(remain.split_at(len))
+}
+
+// Key used to encrypt the hidden secret. Both the document key (which needs a
+// quorum of shards) and the passphrase are needed to derive it.
+fn hidden_key(
+    params: &Argon2Params,
+    doc_key: &ChaChaPolyKey,
+    passphrase: &[u8],
+    salt: &[u8],
+) -> ChaChaPolyKey {
+    let mut key = ChaChaPolyKey::default();
+    argon2::argon2id(params, passphrase, salt, doc_key, &[], &mut key);
+    key
+}
+
+// Overhead of the hidden secret in the padding (not counting its length).
+fn hidden_overhead(algorithm: AeadAlgorithm) -> usize {
+    ARGON2_SALT_LENGTH + algorithm.nonce_len() + AEAD_TAG_LENGTH
+}
+
+// Fill the padding with the hidden secret (which must fit) in place of random
+// bytes. The whole padding is used, so that it is indistinguishable from
+// random padding.
+pub(super) fn hide(
+    padding: &mut [u8],
+    hidden: &[u8],
+    passphrase: &[u8],
+    params: &Argon2Params,
+    doc_key: &ChaChaPolyKey,
+    algorithm: AeadAlgorithm,
+    aad: &[u8],
+) -> Result<(), String> {
+    let overhead = hidden_overhead(algorithm);
+    let capacity = padding.len().saturating_sub(overhead);
+    let mut plaintext = pad(hidden, 0);
+    if plaintext.len() > capacity {
+        plaintext.zeroize();
+        return Err(format!(
+            "padding of {} bytes is too small for hidden secret (need {} bytes)",
+            padding.len(),
+            plaintext.len() + overhead
+        ));
+    }
+    let offset = plaintext.len();
+    plaintext.resize(capacity, 0);
+    OsRng.fill_bytes(&mut plaintext[offset..]);
+
+    let (salt, rest) = padding.split_at_mut(ARGON2_SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at_mut(algorithm.nonce_len());
+    OsRng.fill_bytes(salt);
+    nonce.copy_from_slice(&algorithm.generate_nonce());
+
+    let key = hidden_key(params, doc_key, passphrase, salt);
+    let payload = Payload {
+        msg: &plaintext,
+        aad,
+    };
+    let encrypted = algorithm.encrypt(&key, nonce, payload);
+    plaintext.zeroize();
+    ciphertext.copy_from_slice(&encrypted?);
+    Ok(())
+}
+
+// Recover the hidden secret from the padding.
+pub(super) fn reveal(
+    padding: &[u8],
+    passphrase: &[u8],
+    params: &Argon2Params,
+    doc_key: &ChaChaPolyKey,
+    algorithm: AeadAlgorithm,
+    aad: &[u8],
+) -> Result<Vec<u8>, String> {
+    // Wrong passphrases and backups without a hidden secret are deliberately
+    // indistinguishable.
+    const NOT_FOUND: &str = "no hidden document found (or wrong passphrase)";
+
+    if padding.len() < hidden_overhead(algorithm) {
+        return Err(NOT_FOUND.into());
+    }
+    let (salt, rest) = padding.split_at(ARGON2_SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(algorithm.nonce_len());
+
+    let key = hidden_key(params, doc_key, passphrase, salt);
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+    let mut plaintext = algorithm
+        .decrypt(&key, nonce, payload)
+        .map_err(|_| NOT_FOUND)?;
+    let hidden = unpad(&plaintext).map(|(hidden, _)| hidden.to_vec());
+    plaintext.zeroize();
+    hidden
+}
+
+impl Backup {
+    /// Create a decoy backup, which contains a hidden secret as well as the
+    /// (decoy) secret.
+    ///
+    /// The hidden secret is stored in the padding of the main document, so
+    /// `options.padding` must be large enough to hold it. With the default
+    /// padding, the main document has the same layout and size as that of a
+    /// backup of the decoy created with the default options. Recovering the
+    /// backup normally only returns the decoy -- the hidden secret can only be
+    /// recovered with `Quorum::recover_hidden_document` given the same
+    /// passphrase and Argon2id parameters (which are not stored anywhere).
+    pub fn with_decoy<D, S, P>(
+        quorum_size: u32,
+        decoy: D,
+        secret: S,
+        passphrase: P,
+        params: Argon2Params,
+        options: BackupOptions,
+    ) -> Result<Self, String>
+    where
+        D: AsRef<[u8]>,
+        S: AsRef<[u8]>,
+        P: AsRef<[u8]>,
+    {
+        params.validate()?;
+        Self::inner_with_options(
+            quorum_size,
+            decoy.as_ref(),
+            Some((secret.as_ref(), passphrase.as_ref(), params)),
+            options,
+        )
+    }
+}
+
+impl Quorum {
+    /// Recover the hidden secret of a decoy backup (see `Backup::with_decoy`).
+    ///
+    /// The error is the same whether the passphrase is wrong or the backup
+    /// simply has no hidden secret.
+    pub fn recover_hidden_document<P: AsRef<[u8]>>(
+        &self,
+        passphrase: P,
+        params: Argon2Params,
+    ) -> Result<Vec<u8>, String> {
+        params.validate()?;
+        self.with_plaintext(|secret, main, aad, plaintext| {
+            let (_, padding) = match main.padded() {
+                true => unpad(plaintext)?,
+                false => (plaintext, &[][..]),
+            };
+            reveal(
+                padding,
+                passphrase.as_ref(),
+                &params,
+                &secret.doc_key,
+                main.algorithm(),
+                aad,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{test::backup_quorum, Compression, ToWire};
+
+    // Cheap parameters, so the tests don't take forever.
+    const PARAMS: Argon2Params = Argon2Params {
+        memory_kib: 8,
+        iterations: 1,
+        lanes: 1,
+    };
+
+    #[quickcheck]
+    fn padding_roundtrip(contents: Vec<u8>, padding: u8) -> bool {
+        let padded = pad(&contents, padding.into());
+        padded.len() > contents.len() + usize::from(padding)
+            && unpad(&padded).map(|(contents, padding)| (contents.to_vec(), padding.len()))
+                == Ok((contents, padding.into()))
+    }
+
+    #[quickcheck]
+    fn padded_backup(secret: Vec<u8>, padding: u16, compress: bool) -> bool {
+        let options = BackupOptions {
+            padding: padding.into(),
+            compression: match compress {
+                false => Compression::None,
+                true => Compression::Deflate,
+            },
+            ..Default::default()
+        };
+        let mut backup = Backup::with_options(2, &secret, options).unwrap();
+        let quorum = backup_quorum(&mut backup);
+
+        quorum.recover_document().as_ref() == Ok(&secret)
+            && quorum.recover_hidden_document("", PARAMS).is_err()
+    }
+
+    #[quickcheck]
+    fn decoy_backup(decoy: Vec<u8>, secret: Vec<u8>, passphrase: String) -> bool {
+        let options = BackupOptions {
+            padding: secret.len() + 64,
+            ..Default::default()
+        };
+        let mut backup =
+            Backup::with_decoy(2, &decoy, &secret, &passphrase, PARAMS, options.clone()).unwrap();
+        let mut plain = Backup::with_options(2, &decoy, options).unwrap();
+        let quorum = backup_quorum(&mut backup);
+
+        // The main document is the same size as that of a padded backup with
+        // no hidden secret.
+        backup.main_document().inner.ciphertext.len()
+            == plain.main_document().inner.ciphertext.len()
+            && quorum.recover_document().as_ref() == Ok(&decoy)
+            && quorum.recover_hidden_document(&passphrase, PARAMS).as_ref() == Ok(&secret)
+            && quorum
+                .recover_hidden_document(format!("{}!", passphrase), PARAMS)
+                .is_err()
+            && self::backup_quorum(&mut plain)
+                .recover_hidden_document(&passphrase, PARAMS)
+                .is_err()
+    }
+
+    #[quickcheck]
+    fn decoy_backup_like_default(decoy: Vec<u8>, secret: Vec<u8>) -> bool {
+        // Small enough to fit in the default padding.
+        let secret = &secret[..secret.len().min(32)];
+        let mut backup =
+            Backup::with_decoy(2, &decoy, secret, "hunter2", PARAMS, Default::default()).unwrap();
+        let plain = Backup::with_options(2, &decoy, Default::default()).unwrap();
+        let (main, plain) = (backup.main_document(), plain.main_document());
+
+        main.padded()
+            && main.padded() == plain.padded()
+            && main.inner.meta == plain.inner.meta
+            && main.to_wire().len() == plain.to_wire().len()
+            && backup_quorum(&mut backup)
+                .recover_hidden_document("hunter2", PARAMS)
+                .as_deref()
+                == Ok(secret)
+    }
+
+    #[test]
+    fn decoy_padding_too_small() {
+        let options = BackupOptions {
+            padding: 32,
+            ..Default::default()
+        };
+        let backup = Backup::with_decoy(1, b"decoy", b"secret", "hunter2", PARAMS, options);
+        assert!(backup.is_err());
+    }
+}
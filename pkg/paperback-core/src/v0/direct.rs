@@ -0,0 +1,147 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Direct sharding of small secrets. A normal `Backup` encrypts the secret
+//! with a document key and only shards the key, which means a main document
+//! has to be printed (and kept) alongside the key shards. A `DirectBackup`
+//! instead shards the secret itself, so that the shards are the only printed
+//! documents and recovery needs nothing but a quorum of them.
+//!
+//! This has none of the extras of a `Backup` (no metadata, custodians or
+//! signatures, and no way of issuing more shards after recovery), and every
+//! shard is as large as the secret, so it is only suitable for small secrets.
+
+use crate::shamir::{self, Dealer, MacKey, Shard};
+
+// There is no main document to derive a MAC key from, so every direct backup
+// uses the same (public) key.
+const MAC_KEY: MacKey = *b"paperback direct backup mac key!";
+
+/// A backup where the secret is sharded directly, without a main document.
+/// The shards are ordinary `shamir::Shard`s, and can be serialised and printed
+/// in the same ways.
+pub struct DirectBackup {
+    dealer: Dealer,
+}
+
+impl DirectBackup {
+    /// Maximum length (in bytes) of a directly-sharded secret.
+    pub const MAX_SECRET_LENGTH: usize = 256;
+
+    /// Create a new direct backup of `secret`, which can be recovered from
+    /// any `quorum_size` of its shards.
+    ///
+    /// The shards are authenticated (see `shamir::Dealer::authenticated`) so
+    /// that a damaged shard is detected (and named) during recovery. The MAC
+    /// key is public, so unlike the signed shards of a `Backup` this does not
+    /// stop a custodian from forging a shard.
+    pub fn new<B: AsRef<[u8]>>(quorum_size: u32, secret: B) -> Result<Self, String> {
+        let secret = secret.as_ref();
+        if quorum_size == 0 {
+            return Err("quorum size must be at least 1".into());
+        }
+        if secret.is_empty() {
+            return Err("secret must not be empty".into());
+        }
+        if secret.len() > Self::MAX_SECRET_LENGTH {
+            return Err(format!(
+                "secret must be at most {} bytes to be sharded directly",
+                Self::MAX_SECRET_LENGTH
+            ));
+        }
+        Ok(Self {
+            dealer: Dealer::new(quorum_size, secret).authenticated(&MAC_KEY),
+        })
+    }
+
+    /// The number of shards needed to recover the secret.
+    pub fn quorum_size(&self) -> u32 {
+        self.dealer.threshold()
+    }
+
+    /// Create a new shard of the secret. Any `quorum_size` distinct shards of
+    /// the same backup can be used to recover it.
+    pub fn next_shard(&mut self) -> Shard {
+        self.dealer.next_shard()
+    }
+
+    /// Recover the secret from (at least `quorum_size`) shards of a direct
+    /// backup.
+    pub fn recover<S: AsRef<[Shard]>>(shards: S) -> Result<Vec<u8>, String> {
+        shamir::recover_secret_with_key(shards, &MAC_KEY).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        shamir::{header, DocumentKind},
+        v0::{FromWire, ToWire},
+    };
+
+    use quickcheck::TestResult;
+
+    #[quickcheck]
+    fn direct_backup_roundtrip(quorum_size: u8, secret: Vec<u8>) -> TestResult {
+        let quorum_size = u32::from(quorum_size % 8) + 1;
+        if secret.is_empty() || secret.len() > DirectBackup::MAX_SECRET_LENGTH {
+            return TestResult::discard();
+        }
+
+        let mut backup = DirectBackup::new(quorum_size, &secret).unwrap();
+        let shards = (0..quorum_size)
+            .map(|_| backup.next_shard())
+            .map(|shard| Shard::from_wire(shard.to_wire()).unwrap())
+            .collect::<Vec<_>>();
+
+        let too_few = quorum_size == 1 || DirectBackup::recover(&shards[1..]).is_err();
+        TestResult::from_bool(too_few && DirectBackup::recover(&shards).unwrap() == secret)
+    }
+
+    #[test]
+    fn direct_backup_rejects_bad_shards() {
+        let mut backup = DirectBackup::new(2, b"correct horse battery staple").unwrap();
+        let shards = [backup.next_shard(), backup.next_shard()];
+
+        // Shards of another backup.
+        let mut other = DirectBackup::new(2, b"correct horse battery staple").unwrap();
+        let mixed = [shards[0].clone(), other.next_shard()];
+        assert!(DirectBackup::recover(&mixed).is_err());
+
+        // Unauthenticated shards.
+        let mut dealer = Dealer::new(2, b"correct horse battery staple");
+        let unauthenticated = [dealer.next_shard(), dealer.next_shard()];
+        assert!(DirectBackup::recover(&unauthenticated).is_err());
+
+        // Damaged shards are named.
+        let mut bytes = shards[1].wire_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let damaged = Shard::from_wire(header::write_record(DocumentKind::Shard, &bytes)).unwrap();
+        let damaged = [shards[0].clone(), damaged];
+        assert_eq!(
+            DirectBackup::recover(&damaged),
+            Err(format!("shard {} failed mac verification", shards[1].id()))
+        );
+
+        assert!(DirectBackup::new(0, b"secret").is_err());
+        assert!(DirectBackup::new(2, b"").is_err());
+        assert!(DirectBackup::new(2, vec![0; DirectBackup::MAX_SECRET_LENGTH + 1]).is_err());
+    }
+}
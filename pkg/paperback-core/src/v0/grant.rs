@@ -0,0 +1,261 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Grants recording which shard holders took part in creating new shards
+//! from a recovered quorum (see `Quorum::extend_shards_with_grant`).
+//!
+//! Each key shard has an Ed25519 key derived from its secret contents, so
+//! only someone holding the shard can sign with it. The grant is signed with
+//! the key of every shard in the quorum, which lets each custodian check
+//! whether their shard was used (and an auditor check that enough shards
+//! took part), so that shards created by a lone thief can be told apart from
+//! shards created legitimately.
+
+use crate::v0::{
+    document_id, DocumentId, KeyShard, MainDocument, Quorum, ShardId, ToWire, VerificationError,
+};
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature};
+use multihash::Multihash;
+use zeroize::Zeroize;
+
+const GRANT_KEY_PERSONAL: &[u8] = b"paperback-grant";
+
+impl KeyShard {
+    // Ed25519 keypair derived from the (secret) contents of the shard.
+    pub(super) fn grant_keypair(&self) -> Keypair {
+        let mut bytes = self.inner.to_wire();
+        let hash = blake2b_simd::Params::new()
+            .hash_length(ed25519_dalek::SECRET_KEY_LENGTH)
+            .personal(GRANT_KEY_PERSONAL)
+            .hash(&bytes);
+        bytes.zeroize();
+
+        let secret = SecretKey::from_bytes(hash.as_bytes())
+            .expect("ed25519 secret keys can be any 32 bytes");
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    /// Whether the holder of this shard signed `grant` (that is, whether this
+    /// shard was one of those used to create the shards in the grant).
+    pub fn participated_in(&self, grant: &ExpansionGrant) -> bool {
+        let public_key = self.grant_keypair().public;
+        grant.participants.iter().any(|participant| {
+            participant.shard_id == self.id()
+                && participant.public_key == public_key
+                && public_key
+                    .verify_strict(&grant.inner.to_wire(), &participant.signature)
+                    .is_ok()
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct ExpansionGrantBuilder {
+    pub(super) doc_chksum: Multihash,
+    pub(super) new_shard_ids: Vec<ShardId>,
+}
+
+impl ExpansionGrantBuilder {
+    fn sign(self, shards: &[KeyShard]) -> ExpansionGrant {
+        let bytes = self.to_wire();
+        let mut participants = Vec::<Participant>::new();
+        for shard in shards {
+            let shard_id = shard.id();
+            if participants.iter().any(|p| p.shard_id == shard_id) {
+                continue;
+            }
+            let keypair = shard.grant_keypair();
+            participants.push(Participant {
+                shard_id,
+                public_key: keypair.public,
+                signature: keypair.sign(&bytes),
+            });
+        }
+        ExpansionGrant {
+            inner: self,
+            participants,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct Participant {
+    pub(super) shard_id: ShardId,
+    pub(super) public_key: PublicKey,
+    pub(super) signature: Signature,
+}
+
+/// Record of new key shards created from a recovered quorum, signed by every
+/// shard of that quorum.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExpansionGrant {
+    pub(super) inner: ExpansionGrantBuilder,
+    pub(super) participants: Vec<Participant>,
+}
+
+impl ExpansionGrant {
+    /// Maximum number of shards created by (or participating in) a grant.
+    pub const MAX_SHARDS: usize = 1 << 16;
+
+    /// Identifier of the main document the shards belong to.
+    pub fn document_id(&self) -> DocumentId {
+        document_id(&self.inner.doc_chksum)
+    }
+
+    /// Identifiers of the shards which were created.
+    pub fn new_shard_ids(&self) -> &[ShardId] {
+        &self.inner.new_shard_ids
+    }
+
+    /// Identifiers of the shards which were used to create them.
+    pub fn participant_ids(&self) -> Vec<&str> {
+        self.participants
+            .iter()
+            .map(|participant| participant.shard_id.as_str())
+            .collect()
+    }
+
+    /// Whether `shard` was created by this grant.
+    pub fn covers(&self, shard: &KeyShard) -> bool {
+        shard.inner.doc_chksum == self.inner.doc_chksum
+            && self.inner.new_shard_ids.contains(&shard.id())
+    }
+
+    /// Verify that this grant is for shards of `main`, and that it was signed
+    /// by at least a quorum of distinct shards.
+    ///
+    /// This cannot tell *which* shards signed the grant (only their holders
+    /// can, with `KeyShard::participated_in`).
+    pub fn verify(&self, main: &MainDocument) -> Result<(), VerificationError> {
+        if self.inner.doc_chksum != main.checksum() {
+            return Err(VerificationError::ChecksumMismatch);
+        }
+        let bytes = self.inner.to_wire();
+        for participant in &self.participants {
+            participant
+                .public_key
+                .verify_strict(&bytes, &participant.signature)
+                .map_err(|_| VerificationError::InvalidSignature)?;
+        }
+        let mut public_keys = self
+            .participants
+            .iter()
+            .map(|participant| participant.public_key.to_bytes())
+            .collect::<Vec<_>>();
+        public_keys.sort_unstable();
+        public_keys.dedup();
+        if public_keys.len() < main.quorum_size() as usize {
+            return Err(VerificationError::TooFewSignatures {
+                required: main.quorum_size(),
+                found: public_keys.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Quorum {
+    /// Like `extend_shards`, but also returns a grant signed by each shard of
+    /// the quorum, recording that they were used to create the new shards.
+    pub fn extend_shards_with_grant(
+        &self,
+        n: u32,
+    ) -> Result<(Vec<KeyShard>, ExpansionGrant), String> {
+        let shards = self.extend_shards(n)?;
+        let grant = ExpansionGrantBuilder {
+            doc_chksum: self.main_document().checksum(),
+            new_shard_ids: shards.iter().map(KeyShard::id).collect(),
+        }
+        .sign(self.shards());
+        Ok((shards, grant))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{Backup, UntrustedQuorum};
+
+    #[quickcheck]
+    fn expansion_grant(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let main = backup.main_document().clone();
+        let shards = (0..3)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(main.clone());
+        quorum
+            .push_shard(shards[0].clone())
+            .push_shard(shards[1].clone());
+        let (new_shards, grant) = quorum
+            .validate()
+            .unwrap()
+            .extend_shards_with_grant(2)
+            .unwrap();
+
+        grant.verify(&main) == Ok(())
+            && grant.document_id() == main.id()
+            && new_shards.iter().all(|shard| grant.covers(shard))
+            && !grant.covers(&shards[2])
+            && shards[0].participated_in(&grant)
+            && shards[1].participated_in(&grant)
+            && !shards[2].participated_in(&grant)
+            && grant.participant_ids() == vec![shards[0].id(), shards[1].id()]
+    }
+
+    #[quickcheck]
+    fn expansion_grant_forged(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let main = backup.main_document().clone();
+        let shards = (0..2)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(main.clone());
+        shards.iter().cloned().for_each(|shard| {
+            quorum.push_shard(shard);
+        });
+        let (_, grant) = quorum
+            .validate()
+            .unwrap()
+            .extend_shards_with_grant(1)
+            .unwrap();
+
+        // Signed by fewer shards than the quorum size.
+        let mut short = grant.clone();
+        short.participants.pop();
+
+        // Claims to create a different shard to the one that was signed.
+        let mut tampered = grant.clone();
+        tampered.inner.new_shard_ids = vec![shards[0].id()];
+
+        short.verify(&main)
+            == Err(VerificationError::TooFewSignatures {
+                required: 2,
+                found: 1,
+            })
+            && tampered.verify(&main) == Err(VerificationError::InvalidSignature)
+            && !shards[0].participated_in(&tampered)
+    }
+}
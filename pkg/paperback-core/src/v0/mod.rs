@@ -16,7 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::{shamir::Shard, v0::wire::prefixes::*};
+use crate::{
+    shamir::{MacKey, Shard, MAC_KEY_LENGTH},
+    v0::wire::prefixes::*,
+};
 
 use aead::{generic_array::GenericArray, Aead, NewAead};
 use bip39::{Language, Mnemonic};
@@ -25,6 +28,7 @@ use ed25519_dalek::{Keypair, PublicKey, Signature};
 use multihash::{Blake2b256, Multihash, MultihashDigest};
 use rand::{rngs::OsRng, RngCore};
 use unsigned_varint::encode;
+use zeroize::Zeroize;
 
 pub type ShardId = String;
 pub type DocumentId = String;
@@ -46,6 +50,24 @@ fn check_length_consts() {
 
 const CHECKSUM_ALGORITHM: Blake2b256 = Blake2b256;
 
+const SHARD_MAC_KEY_PERSONAL: &[u8] = b"paperback-mac";
+
+// Current time (in seconds since the Unix epoch), or None if the clock is set
+// before the epoch.
+fn now() -> Option<u64> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+// Whether a review-by date (in seconds since the Unix epoch) has passed.
+fn review_overdue(review_by: Option<u64>, now: u64) -> bool {
+    review_by.is_some_and(|review_by| now >= review_by)
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 struct Identity {
     id_public_key: PublicKey,
@@ -78,6 +100,10 @@ struct KeyShardBuilder {
     version: u32, // must be 0 for this version
     doc_chksum: Multihash,
     shard: Shard,
+    review_by: Option<u64>, // copied from the main document
+    custodian: Option<Custodian>,
+    watermark: Option<Watermark>,
+    legacy: bool, // shard is in the layout used before the wire header
 }
 
 impl KeyShardBuilder {
@@ -112,6 +138,91 @@ impl quickcheck::Arbitrary for KeyShardBuilder {
             version: 0,
             doc_chksum: CHECKSUM_ALGORITHM.digest(&bytes[..]),
             shard: Shard::arbitrary(g),
+            review_by: Option::<u64>::arbitrary(g),
+            custodian: Option::<Custodian>::arbitrary(g),
+            watermark: Option::<Watermark>::arbitrary(g),
+            legacy: false,
+        }
+    }
+}
+
+/// The person a key shard was given to, so that when shards are returned it
+/// is clear which shard belongs to whom (and who is still missing one).
+///
+/// The custodian is covered by the signature of the key shard, and is also
+/// stored (unencrypted) in the encrypted key shard so that it can be printed
+/// on the shard.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Custodian {
+    /// Name of the custodian.
+    pub name: String,
+    /// How to contact the custodian (such as an email address).
+    pub contact: Option<String>,
+}
+
+impl Custodian {
+    /// Maximum length (in bytes) of the name.
+    pub const MAX_NAME_LENGTH: usize = 256;
+
+    /// Maximum length (in bytes) of the contact details.
+    pub const MAX_CONTACT_LENGTH: usize = 256;
+
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        Self {
+            name: name.into(),
+            contact: None,
+        }
+    }
+
+    pub fn with_contact<C: Into<String>>(self, contact: C) -> Self {
+        Self {
+            contact: Some(contact.into()),
+            ..self
+        }
+    }
+
+    /// Check that the name is non-empty, and that the name and contact
+    /// details are within their length limits.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("custodian name must not be empty".into());
+        }
+        if self.name.len() > Self::MAX_NAME_LENGTH {
+            return Err(format!(
+                "custodian name must be at most {} bytes",
+                Self::MAX_NAME_LENGTH
+            ));
+        }
+        match &self.contact {
+            Some(contact) if contact.len() > Self::MAX_CONTACT_LENGTH => Err(format!(
+                "custodian contact must be at most {} bytes",
+                Self::MAX_CONTACT_LENGTH
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for Custodian {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let text = |g: &mut G, max| {
+            let mut text = String::arbitrary(g);
+            while text.len() > max {
+                text.pop();
+            }
+            text
+        };
+        let name = match text(g, Self::MAX_NAME_LENGTH) {
+            name if name.is_empty() => "custodian".into(),
+            name => name,
+        };
+        Self {
+            name,
+            contact: match bool::arbitrary(g) {
+                false => None,
+                true => Some(text(g, Self::MAX_CONTACT_LENGTH)),
+            },
         }
     }
 }
@@ -119,6 +230,49 @@ impl quickcheck::Arbitrary for KeyShardBuilder {
 const CODEWORD_LANGUAGE: Language = Language::English;
 pub type KeyShardCodewords = [String; 24];
 
+const ARGON2_SALT_LENGTH: usize = 16;
+
+// Parameters used to derive the key of a passphrase-protected key shard.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct PassphraseKdf {
+    params: Argon2Params,
+    salt: [u8; ARGON2_SALT_LENGTH],
+}
+
+impl PassphraseKdf {
+    // The shard key (from the codewords) is used as the Argon2id secret, so
+    // both the codewords and the passphrase are needed to decrypt the shard.
+    fn derive_key(&self, shard_key: &ChaChaPolyKey, passphrase: &[u8]) -> ChaChaPolyKey {
+        let mut key = ChaChaPolyKey::default();
+        argon2::argon2id(
+            &self.params,
+            passphrase,
+            &self.salt,
+            shard_key,
+            &[],
+            &mut key,
+        );
+        key
+    }
+}
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for PassphraseKdf {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let mut salt = [0u8; ARGON2_SALT_LENGTH];
+        g.fill_bytes(&mut salt);
+        let lanes = 1 + g.next_u32() % 4;
+        Self {
+            params: Argon2Params {
+                memory_kib: 8 * lanes + g.next_u32() % 64,
+                iterations: 1 + g.next_u32() % 4,
+                lanes,
+            },
+            salt,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 pub struct KeyShard {
@@ -141,7 +295,54 @@ impl KeyShard {
         self.inner.shard.id()
     }
 
+    /// The date (in seconds since the Unix epoch) by which the backup this
+    /// shard belongs to should be reviewed, copied from its main document.
+    pub fn review_by(&self) -> Option<u64> {
+        self.inner.review_by
+    }
+
+    /// The person this shard was given to (if recorded).
+    pub fn custodian(&self) -> Option<&Custodian> {
+        self.inner.custodian.as_ref()
+    }
+
+    /// The watermark identifying which custodian this shard was issued to (if
+    /// it was issued with `Backup::next_shard_watermarked`).
+    pub fn watermark(&self) -> Option<&Watermark> {
+        self.inner.watermark.as_ref()
+    }
+
+    /// Whether the review-by date of this shard has passed.
+    pub fn review_overdue(&self) -> bool {
+        now().is_some_and(|now| review_overdue(self.review_by(), now))
+    }
+
     pub fn encrypt(self) -> Result<(EncryptedKeyShard, KeyShardCodewords), String> {
+        self.inner_encrypt(None)
+    }
+
+    /// Like `encrypt`, but the shard can only be decrypted if the passphrase
+    /// is given as well as the codewords. The key is derived from the
+    /// passphrase with Argon2id using the given parameters (which are stored
+    /// in the encrypted shard).
+    pub fn encrypt_with_passphrase<P: AsRef<[u8]>>(
+        self,
+        passphrase: P,
+        params: Argon2Params,
+    ) -> Result<(EncryptedKeyShard, KeyShardCodewords), String> {
+        params.validate()?;
+        let mut salt = [0u8; ARGON2_SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        self.inner_encrypt(Some((PassphraseKdf { params, salt }, passphrase.as_ref())))
+    }
+
+    fn inner_encrypt(
+        self,
+        kdf: Option<(PassphraseKdf, &[u8])>,
+    ) -> Result<(EncryptedKeyShard, KeyShardCodewords), String> {
+        let custodian = self.inner.custodian.clone();
+        let watermark = self.inner.watermark.clone();
+
         // Serialise.
         let wire_shard = self.to_wire();
 
@@ -151,8 +352,17 @@ impl KeyShard {
         let mut shard_nonce = ChaChaPolyNonce::default();
         OsRng.fill_bytes(&mut shard_nonce);
 
+        // Mix in the passphrase (if any).
+        let (kdf, encryption_key) = match kdf {
+            None => (None, shard_key),
+            Some((kdf, passphrase)) => {
+                let key = kdf.derive_key(&shard_key, passphrase);
+                (Some(kdf), key)
+            }
+        };
+
         // Encrypt the contents.
-        let aead = ChaCha20Poly1305::new(shard_key);
+        let aead = ChaCha20Poly1305::new(encryption_key);
         let wire_shard = aead
             .encrypt(&shard_nonce, wire_shard.as_slice())
             .map_err(|err| format!("{:?}", err))?; // XXX: Ugly, fix this.
@@ -172,6 +382,9 @@ impl KeyShard {
 
         // Create wrapper shard.
         let shard = EncryptedKeyShard {
+            custodian,
+            watermark,
+            kdf,
             nonce: shard_nonce,
             ciphertext: wire_shard,
         };
@@ -183,12 +396,79 @@ impl KeyShard {
 #[derive(Clone, Debug)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 pub struct EncryptedKeyShard {
+    custodian: Option<Custodian>, // must match the (signed) decrypted shard
+    watermark: Option<Watermark>, // must match the (signed) decrypted shard
+    kdf: Option<PassphraseKdf>,
     nonce: ChaChaPolyNonce,
     ciphertext: Vec<u8>,
 }
 
 impl EncryptedKeyShard {
+    /// Returns whether a passphrase is needed (as well as the codewords) to
+    /// decrypt the shard.
+    pub fn needs_passphrase(&self) -> bool {
+        self.kdf.is_some()
+    }
+
+    /// The person this shard was given to (if recorded). This is not
+    /// authenticated until the shard is decrypted.
+    pub fn custodian(&self) -> Option<&Custodian> {
+        self.custodian.as_ref()
+    }
+
+    /// The watermark identifying which custodian this shard was issued to (if
+    /// any). This is not authenticated until the shard is decrypted, but a
+    /// genuine watermark can be checked without decrypting the shard (see
+    /// `Watermark::verify`).
+    pub fn watermark(&self) -> Option<&Watermark> {
+        self.watermark.as_ref()
+    }
+
     pub fn decrypt(self, codewords: &KeyShardCodewords) -> Result<KeyShard, String> {
+        if self.needs_passphrase() {
+            return Err("key shard is protected by a passphrase")?;
+        }
+        self.inner_decrypt(codewords, &[])
+    }
+
+    /// Decrypt a shard created by `KeyShard::encrypt_with_passphrase`.
+    pub fn decrypt_with_passphrase<P: AsRef<[u8]>>(
+        self,
+        codewords: &KeyShardCodewords,
+        passphrase: P,
+    ) -> Result<KeyShard, String> {
+        if !self.needs_passphrase() {
+            return Err("key shard is not protected by a passphrase")?;
+        }
+        self.inner_decrypt(codewords, passphrase.as_ref())
+    }
+
+    /// Decrypt a shard, calling `prompt` to ask the user for the passphrase
+    /// only if the shard is protected by one.
+    pub fn decrypt_with_prompt<F>(
+        self,
+        codewords: &KeyShardCodewords,
+        prompt: F,
+    ) -> Result<KeyShard, String>
+    where
+        F: FnOnce() -> Result<String, String>,
+    {
+        match self.needs_passphrase() {
+            false => self.decrypt(codewords),
+            true => {
+                let mut passphrase = prompt()?;
+                let shard = self.decrypt_with_passphrase(codewords, &passphrase);
+                passphrase.zeroize();
+                shard
+            }
+        }
+    }
+
+    fn inner_decrypt(
+        self,
+        codewords: &KeyShardCodewords,
+        passphrase: &[u8],
+    ) -> Result<KeyShard, String> {
         // Convert BIP-39 mnemonic to a key.
         let phrase = codewords[..].join(" ").to_lowercase();
         let mnemonic =
@@ -196,6 +476,9 @@ impl EncryptedKeyShard {
 
         let mut shard_key = ChaChaPolyKey::default();
         shard_key.copy_from_slice(mnemonic.entropy());
+        if let Some(kdf) = &self.kdf {
+            shard_key = kdf.derive_key(&shard_key, passphrase);
+        }
 
         // Decrypt the contents.
         let aead = ChaCha20Poly1305::new(shard_key);
@@ -204,7 +487,16 @@ impl EncryptedKeyShard {
             .map_err(|err| format!("{:?}", err))?; // XXX: Ugly, fix this.
 
         // Deserialise.
-        KeyShard::from_wire(wire_shard)
+        let shard = KeyShard::from_wire(wire_shard).map_err(|err| err.to_string())?;
+
+        // The custodian printed on the shard must be the signed one.
+        if shard.custodian() != self.custodian() {
+            return Err("key shard custodian doesn't match its contents")?;
+        }
+        if shard.watermark() != self.watermark() {
+            return Err("key shard watermark doesn't match its contents")?;
+        }
+        Ok(shard)
     }
 }
 
@@ -214,7 +506,239 @@ impl quickcheck::Arbitrary for EncryptedKeyShard {
         let mut nonce = ChaChaPolyNonce::default();
         g.fill_bytes(&mut nonce);
         let ciphertext = Vec::<u8>::arbitrary(g);
-        Self { nonce, ciphertext }
+        Self {
+            custodian: Option::<Custodian>::arbitrary(g),
+            watermark: Option::<Watermark>::arbitrary(g),
+            kdf: Option::<PassphraseKdf>::arbitrary(g),
+            nonce,
+            ciphertext,
+        }
+    }
+}
+
+/// Compression applied to the contents of a document before they are
+/// encrypted.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Compression {
+    /// The contents are stored as-is.
+    #[default]
+    None,
+    /// The contents are compressed with raw DEFLATE (RFC 1951).
+    Deflate,
+}
+
+impl Compression {
+    // Maximum size we will inflate a document to, so that a malicious document
+    // can't be used as a decompression bomb.
+    const MAX_INFLATED_LENGTH: usize = 64 << 20;
+
+    fn wire_id(self) -> Option<u32> {
+        match self {
+            Compression::None => None,
+            Compression::Deflate => Some(1),
+        }
+    }
+
+    fn from_wire_id(id: u32) -> Option<Self> {
+        match id {
+            1 => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+
+    // Compression to use for contents of the given length. Contents too large
+    // to be inflated again are never compressed.
+    fn for_length(self, length: usize) -> Self {
+        match length > Self::MAX_INFLATED_LENGTH {
+            true => Compression::None,
+            false => self,
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => data.to_vec(),
+            Compression::Deflate => miniz_oxide::deflate::compress_to_vec(data, 9),
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Deflate => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(data, Self::MAX_INFLATED_LENGTH)
+                    .map_err(|err| format!("failed to decompress document: {:?}", err.status))
+            }
+        }
+    }
+}
+
+/// Descriptive metadata about a backup, stored in its main document so that
+/// a printed backup can say what it contains.
+///
+/// The metadata is covered by the signature of the main document, but it is
+/// *not* encrypted.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Metadata {
+    /// Short label describing the contents (such as "GPG master key").
+    pub label: Option<String>,
+    /// Free-text comment.
+    pub comment: Option<String>,
+    /// When the backup was created, in seconds since the Unix epoch.
+    pub created: Option<u64>,
+    /// When the backup should next be reviewed (re-tested and possibly
+    /// re-issued), in seconds since the Unix epoch. The date is also stored
+    /// in every key shard.
+    pub review_by: Option<u64>,
+    /// MIME type of the secret (such as "application/pgp-keys" or
+    /// "text/plain; charset=utf-8"), so that it can be handled sensibly once
+    /// recovered.
+    pub content_type: Option<String>,
+}
+
+impl Metadata {
+    /// Maximum length (in bytes) of the label.
+    pub const MAX_LABEL_LENGTH: usize = 256;
+
+    /// Maximum length (in bytes) of the comment.
+    pub const MAX_COMMENT_LENGTH: usize = 4096;
+
+    /// Maximum length (in bytes) of the content type.
+    pub const MAX_CONTENT_TYPE_LENGTH: usize = 128;
+
+    /// Check that the label, comment and content type are within their length
+    /// limits, and that the content type looks like a MIME type.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(content_type) = &self.content_type {
+            let essence = mime_essence(content_type);
+            let mut parts = essence.splitn(2, '/');
+            let valid_part = |part: Option<&str>| {
+                part.is_some_and(|part| {
+                    !part.is_empty()
+                        && part
+                            .bytes()
+                            .all(|b| b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?=".contains(&b))
+                })
+            };
+            if !content_type.is_ascii() || !valid_part(parts.next()) || !valid_part(parts.next()) {
+                return Err(format!(
+                    "content type '{}' is not a MIME type",
+                    content_type
+                ));
+            }
+        }
+        let fields = [
+            ("label", &self.label, Self::MAX_LABEL_LENGTH),
+            ("comment", &self.comment, Self::MAX_COMMENT_LENGTH),
+            (
+                "content type",
+                &self.content_type,
+                Self::MAX_CONTENT_TYPE_LENGTH,
+            ),
+        ];
+        for (name, value, max) in fields.iter() {
+            match value {
+                Some(value) if value.len() > *max => {
+                    return Err(format!("{} must be at most {} bytes", name, max))
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the review-by date has passed at `now` (in seconds since the
+    /// Unix epoch).
+    pub fn review_overdue_at(&self, now: u64) -> bool {
+        review_overdue(self.review_by, now)
+    }
+
+    /// Whether the review-by date has passed.
+    pub fn review_overdue(&self) -> bool {
+        now().is_some_and(|now| self.review_overdue_at(now))
+    }
+
+    /// Whether the secret is text according to its content type (`None` if
+    /// there is no content type). Secrets which aren't text shouldn't be
+    /// written to a terminal.
+    pub fn is_text(&self) -> Option<bool> {
+        let content_type = self.content_type.as_ref()?;
+        let essence = mime_essence(content_type);
+        Some(
+            essence.starts_with("text/")
+                || essence.ends_with("+json")
+                || essence.ends_with("+xml")
+                || TEXT_CONTENT_TYPES.contains(&essence.as_str())
+                || content_type.to_ascii_lowercase().contains("charset="),
+        )
+    }
+
+    /// A conventional file extension (without the leading dot) for the
+    /// secret according to its content type, if one is known.
+    pub fn file_extension(&self) -> Option<&'static str> {
+        let essence = mime_essence(self.content_type.as_ref()?);
+        FILE_EXTENSIONS
+            .iter()
+            .find(|(content_type, _)| *content_type == essence)
+            .map(|(_, extension)| *extension)
+    }
+}
+
+// Content types (other than text/*) of secrets which are text.
+const TEXT_CONTENT_TYPES: &[&str] = &[
+    "application/json",
+    "application/pgp-keys",
+    "application/x-pem-file",
+    "application/xml",
+];
+
+// Conventional file extensions of common content types.
+const FILE_EXTENSIONS: &[(&str, &str)] = &[
+    ("application/json", "json"),
+    ("application/octet-stream", "bin"),
+    ("application/pdf", "pdf"),
+    ("application/pgp-keys", "asc"),
+    ("application/pkcs8", "p8"),
+    ("application/x-pem-file", "pem"),
+    ("application/x-pkcs12", "p12"),
+    ("application/zip", "zip"),
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("text/csv", "csv"),
+    ("text/plain", "txt"),
+];
+
+// The type and subtype of a MIME type (without any parameters), in lowercase.
+fn mime_essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for Metadata {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let text = |g: &mut G, max| {
+            Option::<String>::arbitrary(g).map(|mut text| {
+                while text.len() > max {
+                    text.pop();
+                }
+                text
+            })
+        };
+        Self {
+            label: text(g, Self::MAX_LABEL_LENGTH),
+            comment: text(g, Self::MAX_COMMENT_LENGTH),
+            created: Option::<u64>::arbitrary(g),
+            review_by: Option::<u64>::arbitrary(g),
+            content_type: Option::<usize>::arbitrary(g).map(|n| {
+                let (content_type, _) = FILE_EXTENSIONS[n % FILE_EXTENSIONS.len()];
+                content_type.to_string()
+            }),
+        }
     }
 }
 
@@ -222,6 +746,12 @@ impl quickcheck::Arbitrary for EncryptedKeyShard {
 struct MainDocumentMeta {
     version: u32, // must be 0 for this version
     quorum_size: u32,
+    compression: Compression,
+    algorithm: AeadAlgorithm,
+    metadata: Metadata,
+    padded: bool,            // whether the plaintext is length-prefixed and padded
+    chunk_size: Option<u32>, // bytes of the document on each printed page
+    time_lock: Option<TimeLock>,
 }
 
 impl MainDocumentMeta {
@@ -243,6 +773,23 @@ impl quickcheck::Arbitrary for MainDocumentMeta {
         Self {
             version: 0,
             quorum_size: g.next_u32(),
+            compression: match bool::arbitrary(g) {
+                false => Compression::None,
+                true => Compression::Deflate,
+            },
+            algorithm: AeadAlgorithm::ALL[g.next_u32() as usize % AeadAlgorithm::ALL.len()],
+            metadata: Metadata::arbitrary(g),
+            padded: bool::arbitrary(g),
+            chunk_size: match bool::arbitrary(g) {
+                false => None,
+                true => Some(
+                    MainDocumentPage::MIN_CHUNK_SIZE
+                        + g.next_u32()
+                            % (MainDocumentPage::MAX_CHUNK_SIZE - MainDocumentPage::MIN_CHUNK_SIZE
+                                + 1),
+                ),
+            },
+            time_lock: Option::<TimeLock>::arbitrary(g),
         }
     }
 }
@@ -250,7 +797,7 @@ impl quickcheck::Arbitrary for MainDocumentMeta {
 #[derive(Clone, Debug, Eq, PartialEq)]
 struct MainDocumentBuilder {
     meta: MainDocumentMeta,
-    nonce: ChaChaPolyNonce,
+    nonce: Vec<u8>, // must be meta.algorithm.nonce_len() bytes
     ciphertext: Vec<u8>,
 }
 
@@ -281,10 +828,11 @@ impl MainDocumentBuilder {
 #[cfg(test)]
 impl quickcheck::Arbitrary for MainDocumentBuilder {
     fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
-        let mut nonce = ChaChaPolyNonce::default();
+        let meta = MainDocumentMeta::arbitrary(g);
+        let mut nonce = vec![0u8; meta.algorithm.nonce_len()];
         g.fill_bytes(&mut nonce);
         Self {
-            meta: MainDocumentMeta::arbitrary(g),
+            meta,
             nonce,
             ciphertext: Vec::<u8>::arbitrary(g),
         }
@@ -302,21 +850,73 @@ impl MainDocument {
     pub const ID_LENGTH: usize = 8;
 
     pub fn checksum(&self) -> Multihash {
-        CHECKSUM_ALGORITHM.digest(&self.to_wire())
+        // The checksum must not depend on the header, so that shards continue
+        // to match documents written with older versions of the wire format.
+        CHECKSUM_ALGORITHM.digest(&self.wire_body())
     }
 
     pub fn id(&self) -> DocumentId {
-        let doc_chksum = self.checksum();
-        let encoded_chksum = zbase32::encode_full_bytes(doc_chksum.as_bytes());
-        // The *suffix* is the ID.
-        let short_id = &encoded_chksum[encoded_chksum.len() - Self::ID_LENGTH..];
-
-        short_id.to_string()
+        document_id(&self.checksum())
     }
 
     pub fn quorum_size(&self) -> u32 {
         self.inner.meta.quorum_size
     }
+
+    pub fn compression(&self) -> Compression {
+        self.inner.meta.compression
+    }
+
+    pub fn algorithm(&self) -> AeadAlgorithm {
+        self.inner.meta.algorithm
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.inner.meta.metadata
+    }
+
+    /// Maximum number of bytes of the document stored on each printed page
+    /// (see `MainDocument::to_chunked_pages`), if one was chosen when the
+    /// backup was created.
+    pub fn chunk_size(&self) -> Option<u32> {
+        self.inner.meta.chunk_size
+    }
+
+    /// The time lock which has to be opened to recover the backup (if it was
+    /// created with one). Recovery tooling should warn the user how long this
+    /// will take (see `TimeLock::estimated_duration`).
+    pub fn time_lock(&self) -> Option<&TimeLock> {
+        self.inner.meta.time_lock.as_ref()
+    }
+
+    fn padded(&self) -> bool {
+        self.inner.meta.padded
+    }
+}
+
+// Identifier of the main document with the given checksum.
+fn document_id(doc_chksum: &Multihash) -> DocumentId {
+    let encoded_chksum = zbase32::encode_full_bytes(doc_chksum.as_bytes());
+    // The *suffix* is the ID.
+    let short_id = &encoded_chksum[encoded_chksum.len() - MainDocument::ID_LENGTH..];
+
+    short_id.to_string()
+}
+
+// Key for the MACs of the key shards of the main document with the given
+// checksum. It must be known before the shard secret is recovered, so that
+// each shard is checked (and a bad one named) before it is used. Key shards
+// are also signed, so the MACs only have to catch damaged shards.
+fn shard_mac_key(doc_chksum: &Multihash) -> MacKey {
+    let mut key = [0u8; MAC_KEY_LENGTH];
+    key.copy_from_slice(
+        blake2b_simd::Params::new()
+            .hash_length(MAC_KEY_LENGTH)
+            .personal(SHARD_MAC_KEY_PERSONAL)
+            .hash(doc_chksum.as_bytes())
+            .as_bytes(),
+    );
+    key
 }
 
 #[cfg(test)]
@@ -327,6 +927,60 @@ impl quickcheck::Arbitrary for MainDocument {
     }
 }
 
+mod age;
+
+mod argon2;
+pub use argon2::Argon2Params;
+
+mod crypto;
+pub use crypto::AeadAlgorithm;
+
+mod bundle;
+pub use bundle::{Bundle, BundleEntry, BundleError};
+
+mod decoy;
+
+mod direct;
+pub use direct::DirectBackup;
+
+mod grant;
+pub use grant::ExpansionGrant;
+
+mod openpgp;
+
+mod pages;
+pub use pages::MainDocumentPage;
+
+mod pile;
+pub use pile::{Inconsistency, Pile, PileGroup, PileReport};
+
+mod possession;
+pub use possession::{PossessionChallenge, PossessionResponse, PossessionVerifier};
+
+mod record;
+pub use record::{RecoveryRecord, SOFTWARE_VERSION};
+
+mod revoke;
+pub use revoke::{RevocationList, RevocationReason};
+
+mod signature;
+pub use signature::{DetachedSignature, SignedKind, VerificationError};
+
+mod stream;
+pub use stream::DEFAULT_CHUNK_SIZE;
+
+mod supersede;
+pub use supersede::SupersededNotice;
+
+mod timelock;
+pub use timelock::{TimeLock, TimeLockParams};
+
+mod unlinkable;
+pub use unlinkable::GroupingHint;
+
+mod watermark;
+pub use watermark::{Watermark, WatermarkKey};
+
 mod wire;
 pub use wire::*;
 
@@ -337,11 +991,38 @@ mod backup;
 pub use backup::*;
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use super::*;
 
     use quickcheck::TestResult;
 
+    // Helpers shared by the tests of other modules.
+
+    pub(crate) fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    pub(crate) fn quorum(main: &MainDocument, shards: &[KeyShard]) -> Quorum {
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(main.clone());
+        for shard in shards {
+            quorum.push_shard(shard.clone());
+        }
+        quorum.validate().unwrap()
+    }
+
+    // A quorum of the main document and the next quorum_size shards of backup.
+    pub(crate) fn backup_quorum(backup: &mut Backup) -> Quorum {
+        let main = backup.main_document().clone();
+        let shards = (0..main.quorum_size())
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+        quorum(&main, &shards)
+    }
+
     #[quickcheck]
     fn paperback_roundtrip_smoke(quorum_size: u32, secret: Vec<u8>) -> TestResult {
         if quorum_size < 2 || quorum_size > 20 {
@@ -349,7 +1030,7 @@ mod test {
         }
 
         // Construct a backup.
-        let backup = Backup::new(quorum_size, &secret).unwrap();
+        let mut backup = Backup::new(quorum_size, &secret).unwrap();
         let main_document = backup.main_document().clone();
         let shards = (0..quorum_size)
             .map(|_| backup.next_shard().unwrap())
@@ -385,6 +1066,434 @@ mod test {
         TestResult::from_bool(recovered_secret == secret)
     }
 
+    // A backup of b"a paper backup from the baseline" with a quorum size of 2,
+    // created by the first version of paperback.
+    const BASELINE_MAIN_DOCUMENT: &str = "\
+            000285a680d1acd93f99cef6285f643f88f909706785a680d1ac993f304142a8698cb60526ea4cd2\
+            a914b6609dfba401a3e3bf20170f4b65a260ab87c5344e15e2016ad3f8d3b2d703804a47fbed013c\
+            3176f8a82ed52bf7d768ca29b851cb80f2489a44cb7fa03f389bc59fae9c71ef010305fd07e9b57c\
+            ce553290246cf4acab069db5394e9ac3b210d309e91850b65966a3debf1ca2ab1f2ecfad809faf35\
+            9f1fe24b437aeebae39e86321be64dd601";
+    const BASELINE_KEY_SHARDS: [(&str, &str); 2] = [
+        (
+            "85a680d1acd93f021c64f96925222c999120e685a680d1ac993f8402b7987ced80aeb56b1f5d4ade\
+            e6dea66183a198e33efb9aee153b23f0f661375b0a4a5ddb8020c91a5683b584b7e068744b9ae2ad\
+            4f638c06ec863f84350b1ae29cf587de0598567e510db9a2c2785710593df1cca26b15615f165a92\
+            218116c14a65f014002a945376b295c089538ea3b72bc208b0cf1a2d5e3c5ab0160ac5a2c1d70654\
+            d975762c7312219739239eaa8a986f23057b29a726cc87fccd41ad1d6e9a4bc8e8883e8ed0f5a28d\
+            cb991fa3d14d81fcb6813a13a1e7d2431fecd918e515fab2f101c2dc8c1ba60aaebe3a77b0991906\
+            a6d3a3dfc66330d6ecfc92c5ffe83f96e5f8b64e7291a85ec3a18d95db0ca2d09c89077fc1a889e6\
+            d52576b197a2c885",
+            "throw pizza pony cube elite glimpse mango birth submit punch exit aspect \
+            rain cart ability ten fence nominee nest tackle suit actress crouch simple",
+        ),
+        (
+            "85a680d1acd93f659f1f6d0380e0e584b2a88785a680d1ac993f8402c1082035d0aa7391fca4b1bd\
+            ceac380556bda476a80ef3666403b8c5a790bcb5b30c8e7d1ed19baf4ade9aa80fea453d8742ac27\
+            fc903287939e4d3bdc8dfd6548788b027a93b889b86356b5e6260832a061ddc9d3d3266875a815d4\
+            47e93b17e1b5d8877de1973113de2a5bd21298e6d128b65ba724a4ba58e5acd301d71f8b6e0c2f0a\
+            bbd77c35f05c7c1009d5011490e6070759f3cde29fb94f72d748db640c19f3e76fa024919d3d55c8\
+            876fce5ee012879463097d5ecf5639e797b3fe4cdde2b33380019b067ff1073ec4ac466fede50c5f\
+            ad7b03efb642b7aac36ba3e4af517e095e57d58c8c2e91285c40123c91e4b4ee1a06cc8b3cacd150\
+            ee4bce8a5b83209d",
+            "ethics actual kingdom stool mass seminar primary rifle language sure tortoise damage \
+            energy amateur sugar corn risk laptop atom chair lady body prepare stove",
+        ),
+    ];
+
+    #[test]
+    fn paperback_baseline_recover() {
+        let main_document = MainDocument::from_wire(hex(BASELINE_MAIN_DOCUMENT)).unwrap();
+
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(main_document);
+        for (shard, codewords) in BASELINE_KEY_SHARDS.iter() {
+            let mut words = KeyShardCodewords::default();
+            words
+                .iter_mut()
+                .zip(codewords.split_whitespace())
+                .for_each(|(word, codeword)| *word = codeword.to_string());
+
+            let shard = EncryptedKeyShard::from_wire(hex(shard)).unwrap();
+            let shard = shard.decrypt(&words).unwrap();
+            assert!(shard.inner.legacy);
+            // The shard is written back in the layout it was signed in.
+            let decoded = KeyShard::from_wire(shard.to_wire()).unwrap();
+            assert_eq!(decoded, shard);
+            quorum.push_shard(shard);
+        }
+        let quorum = quorum.validate().unwrap();
+
+        assert_eq!(
+            quorum.recover_document().unwrap(),
+            b"a paper backup from the baseline"
+        );
+    }
+
+    #[quickcheck]
+    fn paperback_compressed_roundtrip(quorum_size: u32, secret: Vec<u8>) -> TestResult {
+        if !(2..=20).contains(&quorum_size) {
+            return TestResult::discard();
+        }
+
+        // Make the secret compressible.
+        let secret = secret.repeat(8);
+
+        let mut backup = Backup::new_compressed(quorum_size, &secret).unwrap();
+        let main_document = backup.main_document().clone();
+        let shards = (0..quorum_size)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+
+        if secret.len() > 64 && main_document.compression() != Compression::Deflate {
+            return TestResult::error("compressible secret was not compressed");
+        }
+
+        let main_document = MainDocument::from_wire(main_document.to_wire()).unwrap();
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(main_document);
+        for shard in shards {
+            quorum.push_shard(shard);
+        }
+        let quorum = quorum.validate().unwrap();
+
+        // Recovery must transparently decompress the secret.
+        let recovered_secret = quorum.recover_document().unwrap();
+
+        TestResult::from_bool(recovered_secret == secret)
+    }
+
+    #[quickcheck]
+    fn paperback_algorithm_roundtrip(secret: Vec<u8>) -> bool {
+        AeadAlgorithm::ALL.iter().all(|algorithm| {
+            let options = BackupOptions {
+                algorithm: *algorithm,
+                ..Default::default()
+            };
+            let mut backup = Backup::with_options(2, &secret, options).unwrap();
+            let main_document = backup.main_document().clone();
+            let shards = (0..2)
+                .map(|_| backup.next_shard().unwrap())
+                .collect::<Vec<_>>();
+
+            let main_document = MainDocument::from_wire(main_document.to_wire()).unwrap();
+            let mut quorum = UntrustedQuorum::new();
+            quorum.main_document(main_document.clone());
+            shards.into_iter().for_each(|shard| {
+                quorum.push_shard(shard);
+            });
+            let quorum = quorum.validate().unwrap();
+
+            main_document.algorithm() == *algorithm
+                && quorum.recover_document().as_ref() == Ok(&secret)
+        })
+    }
+
+    #[quickcheck]
+    fn paperback_metadata_roundtrip(secret: Vec<u8>, metadata: Metadata) -> bool {
+        let options = BackupOptions {
+            metadata: metadata.clone(),
+            ..Default::default()
+        };
+        let mut backup = Backup::with_options(2, &secret, options).unwrap();
+        let main_document = MainDocument::from_wire(backup.main_document().to_wire()).unwrap();
+        let shards = (0..2)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+
+        // The metadata is covered by the signature.
+        let mut forged = main_document.clone();
+        forged.inner.meta.metadata.label = Some(format!("{:?} (forged)", metadata.label));
+
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(main_document.clone());
+        shards.into_iter().for_each(|shard| {
+            quorum.push_shard(shard);
+        });
+        let quorum = quorum.validate().unwrap();
+
+        main_document.metadata() == &metadata
+            && quorum.recover_document().as_ref() == Ok(&secret)
+            && forged.detached_signature().verify_main_document(&forged)
+                == Err(VerificationError::InvalidSignature)
+    }
+
+    #[quickcheck]
+    fn paperback_deterministic_roundtrip(secret: Vec<u8>) -> bool {
+        let options = BackupOptions {
+            deterministic: true,
+            ..Default::default()
+        };
+        let mut backup = Backup::with_options(2, &secret, options.clone()).unwrap();
+        let again = Backup::with_options(2, &secret, options.clone()).unwrap();
+        let other = Backup::with_options(3, &secret, options.clone()).unwrap();
+        let random = Backup::new(2, &secret).unwrap();
+
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(backup.main_document().clone());
+        for _ in 0..2 {
+            quorum.push_shard(backup.next_shard().unwrap());
+        }
+        let quorum = quorum.validate().unwrap();
+
+        // Identical backups have identical main documents, but any difference
+        // in the options results in a different document.
+        backup.main_document() == again.main_document()
+            && backup.main_document().id() != other.main_document().id()
+            && backup.main_document().id() != random.main_document().id()
+            && quorum.recover_document().as_ref() == Ok(&secret)
+            && quorum.extend_shards(1).is_ok()
+    }
+
+    #[quickcheck]
+    fn deterministic_padded(secret: Vec<u8>, padding: u8) -> bool {
+        let options = |padding| BackupOptions {
+            deterministic: true,
+            padding,
+            ..Default::default()
+        };
+        let padding = usize::from(padding);
+        let mut backup = Backup::with_options(2, &secret, options(padding)).unwrap();
+        let again = Backup::with_options(2, &secret, options(padding)).unwrap();
+        let other = Backup::with_options(2, &secret, options(padding + 1)).unwrap();
+
+        // The padding is part of what the document is derived from, so that a
+        // different amount of padding doesn't reuse the key and nonce.
+        backup.main_document() == again.main_document()
+            && backup.main_document().id() != other.main_document().id()
+            && backup.main_document().padded() == (padding > 0)
+            && backup_quorum(&mut backup).recover_document().as_ref() == Ok(&secret)
+    }
+
+    #[test]
+    fn deterministic_hidden_is_rejected() {
+        let options = BackupOptions {
+            deterministic: true,
+            ..Default::default()
+        };
+        let params = Argon2Params::DEFAULT;
+        assert!(Backup::with_decoy(2, b"decoy", b"secret", "hunter2", params, options).is_err());
+    }
+
+    #[test]
+    fn deterministic_sealed_is_rejected() {
+        // The identity key of a deterministic backup can be derived from the
+        // secret, so it cannot be kept from past quorums.
+        let options = BackupOptions {
+            deterministic: true,
+            sealed: true,
+            ..Default::default()
+        };
+        assert!(Backup::with_options(2, b"secret", options).is_err());
+    }
+
+    #[quickcheck]
+    fn paperback_review_by(secret: Vec<u8>, review_by: Option<u64>) -> bool {
+        // Either in the past or far enough in the future.
+        let review_by = review_by.map(|t| if t > now().unwrap() { u64::MAX } else { t });
+        let options = BackupOptions {
+            metadata: Metadata {
+                review_by,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut backup = Backup::with_options(2, &secret, options).unwrap();
+        let main_document = backup.main_document().clone();
+        let shards = (0..2)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(main_document.clone());
+        shards.iter().cloned().for_each(|shard| {
+            quorum.push_shard(shard);
+        });
+        let quorum = quorum.validate().unwrap();
+        let extended = quorum.extend_shards(1).unwrap();
+
+        let overdue = review_by.is_some_and(|t| t != u64::MAX);
+        shards
+            .iter()
+            .chain(&extended)
+            .all(|shard| shard.review_by() == review_by && shard.review_overdue() == overdue)
+            && main_document.metadata().review_overdue() == overdue
+            && quorum.review_overdue() == overdue
+            && quorum.recover_document().as_ref() == Ok(&secret)
+    }
+
+    #[quickcheck]
+    fn paperback_bundle_roundtrip(bundle: Bundle) -> bool {
+        let mut backup = Backup::new(2, bundle.to_wire()).unwrap();
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(backup.main_document().clone());
+        for _ in 0..2 {
+            quorum.push_shard(backup.next_shard().unwrap());
+        }
+        let quorum = quorum.validate().unwrap();
+
+        quorum.recover_bundle() == Ok(bundle)
+    }
+
+    #[quickcheck]
+    fn paperback_custodians(secret: Vec<u8>, custodians: (Custodian, Custodian)) -> bool {
+        let (alice, bob) = custodians;
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let (shard1, codewords1) = backup
+            .next_shard_for(alice.clone())
+            .unwrap()
+            .encrypt()
+            .unwrap();
+        let (shard2, codewords2) = backup
+            .next_shard_for(bob.clone())
+            .unwrap()
+            .encrypt()
+            .unwrap();
+        let shard1 = EncryptedKeyShard::from_wire(shard1.to_wire()).unwrap();
+
+        // The printed custodian must match the signed one.
+        let mut swapped = shard1.clone();
+        swapped.custodian = Some(Custodian::new(format!("{} (forged)", alice.name)));
+
+        let printed = shard1.custodian() == Some(&alice) && shard2.custodian() == Some(&bob);
+        let forged = swapped.decrypt(&codewords1).is_err();
+
+        let shard1 = shard1.decrypt(&codewords1).unwrap();
+        let shard2 = shard2.decrypt(&codewords2).unwrap();
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(backup.main_document().clone());
+        quorum.push_shard(shard1.clone()).push_shard(shard2);
+        let quorum = quorum.validate().unwrap();
+
+        printed
+            && forged
+            && shard1.custodian() == Some(&alice)
+            && quorum.custodians() == vec![&alice, &bob]
+            && quorum.recover_document().as_ref() == Ok(&secret)
+    }
+
+    #[test]
+    fn invalid_custodian_is_rejected() {
+        let mut backup = Backup::new(2, b"secret").unwrap();
+        assert!(backup.next_shard_for(Custodian::new("")).is_err());
+        let long = "x".repeat(Custodian::MAX_CONTACT_LENGTH + 1);
+        assert!(backup
+            .next_shard_for(Custodian::new("alice").with_contact(long))
+            .is_err());
+        assert!(backup
+            .next_shard_for(Custodian::new("alice").with_contact("alice@example.com"))
+            .is_ok());
+    }
+
+    #[test]
+    fn metadata_review_overdue_at() {
+        let metadata = Metadata {
+            review_by: Some(1000),
+            ..Default::default()
+        };
+        assert!(!metadata.review_overdue_at(999));
+        assert!(metadata.review_overdue_at(1000));
+        assert!(!Metadata::default().review_overdue_at(u64::MAX));
+    }
+
+    #[test]
+    fn oversized_metadata_is_rejected() {
+        let options = BackupOptions {
+            metadata: Metadata {
+                comment: Some("x".repeat(Metadata::MAX_COMMENT_LENGTH + 1)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(Backup::with_options(2, b"secret", options).is_err());
+    }
+
+    #[test]
+    fn metadata_content_type() {
+        let metadata = |content_type: &str| Metadata {
+            content_type: Some(content_type.into()),
+            ..Default::default()
+        };
+
+        let text = metadata("text/plain; charset=utf-8");
+        assert_eq!(text.validate(), Ok(()));
+        assert_eq!(text.is_text(), Some(true));
+        assert_eq!(text.file_extension(), Some("txt"));
+
+        let keys = metadata("Application/PGP-Keys");
+        assert_eq!(keys.is_text(), Some(true));
+        assert_eq!(keys.file_extension(), Some("asc"));
+
+        let binary = metadata("application/octet-stream");
+        assert_eq!(binary.is_text(), Some(false));
+        assert_eq!(binary.file_extension(), Some("bin"));
+
+        assert_eq!(metadata("application/x-unknown").file_extension(), None);
+        assert_eq!(Metadata::default().is_text(), None);
+
+        for invalid in &["", "text", "text/", "/plain", "text/pla in", "tëxt/plain"] {
+            assert!(metadata(invalid).validate().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn incompressible_secret_is_stored_uncompressed() {
+        let backup = Backup::new_compressed(2, b"x").unwrap();
+        assert_eq!(backup.main_document().compression(), Compression::None);
+    }
+
+    #[test]
+    fn oversized_secret_is_stored_uncompressed() {
+        let max = Compression::MAX_INFLATED_LENGTH;
+        assert_eq!(Compression::Deflate.for_length(max), Compression::Deflate);
+        assert_eq!(Compression::Deflate.for_length(max + 1), Compression::None);
+
+        // Contents right at the limit can still be inflated.
+        let secret = vec![0u8; max];
+        let compressed = Compression::Deflate.compress(&secret);
+        assert!(Compression::Deflate.decompress(&compressed).unwrap() == secret);
+    }
+
+    #[quickcheck]
+    fn key_shard_passphrase_roundtrip(shard: KeyShard, passphrase: String) {
+        let params = Argon2Params {
+            memory_kib: 32,
+            iterations: 1,
+            lanes: 2,
+        };
+        let (enc_shard, codewords) = shard
+            .clone()
+            .encrypt_with_passphrase(&passphrase, params)
+            .unwrap();
+        assert!(enc_shard.needs_passphrase());
+
+        // The shard can't be decrypted without the right passphrase.
+        assert!(enc_shard.clone().decrypt(&codewords).is_err());
+        let wrong = format!("{}!", passphrase);
+        assert!(enc_shard
+            .clone()
+            .decrypt_with_passphrase(&codewords, wrong)
+            .is_err());
+
+        // Only the KDF parameters and salt (not the passphrase) are stored
+        // alongside the encrypted shard, so they must survive the wire format.
+        let enc_shard = EncryptedKeyShard::from_wire(enc_shard.to_wire()).unwrap();
+        let shard2 = enc_shard
+            .decrypt_with_prompt(&codewords, || Ok(passphrase.clone()))
+            .unwrap();
+        assert_eq!(shard, shard2);
+    }
+
+    #[quickcheck]
+    fn key_shard_prompt_unprotected(shard: KeyShard) {
+        let (enc_shard, codewords) = shard.clone().encrypt().unwrap();
+        assert!(!enc_shard.needs_passphrase());
+        let shard2 = enc_shard
+            .decrypt_with_prompt(&codewords, || panic!("unexpected passphrase prompt"))
+            .unwrap();
+        assert_eq!(shard, shard2);
+    }
+
     #[quickcheck]
     fn key_shard_encryption_roundtrip(shard: KeyShard) {
         let (enc_shard, codewords) = shard.clone().encrypt().unwrap();
@@ -0,0 +1,312 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Erasure-coded pages of large main documents. A main document which is too
+//! big to fit on a single sheet of paper can be split across several printed
+//! pages, any `threshold` of which suffice to restore it (see
+//! `shamir::erasure_encode`), so that losing or ruining a page does not
+//! destroy the backup.
+
+use crate::{
+    shamir::{self, ErasureError},
+    v0::{document_id, DocumentId, FromWire, MainDocument, ToWire},
+};
+
+use multihash::Multihash;
+
+/// One page of an erasure-coded `MainDocument` (see
+/// `MainDocument::to_pages`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MainDocumentPage {
+    pub(super) doc_chksum: Multihash,
+    pub(super) index: u32,
+    pub(super) threshold: u32,
+    pub(super) page_count: u32,
+    pub(super) length: usize,
+    pub(super) data: Vec<u8>,
+}
+
+impl MainDocumentPage {
+    /// Maximum number of pages a main document can be split into.
+    pub const MAX_PAGES: u32 = shamir::MAX_ERASURE_PIECES;
+
+    /// Maximum length of the main document a page is part of.
+    pub const MAX_DOCUMENT_LENGTH: usize = 64 << 20;
+
+    /// Smallest chunk size (the number of bytes of the main document stored
+    /// on each page) which can be chosen for a backup.
+    pub const MIN_CHUNK_SIZE: u32 = 32;
+
+    /// Largest chunk size which can be chosen for a backup.
+    pub const MAX_CHUNK_SIZE: u32 = 1 << 16;
+
+    /// Chunk size used by `MainDocument::to_chunked_pages` for documents
+    /// which don't record one.
+    pub const DEFAULT_CHUNK_SIZE: u32 = 1024;
+
+    pub(super) fn check_chunk_size(chunk_size: u32) -> Result<(), String> {
+        match chunk_size {
+            Self::MIN_CHUNK_SIZE..=Self::MAX_CHUNK_SIZE => Ok(()),
+            _ => Err(format!(
+                "chunk size must be between {} and {} bytes",
+                Self::MIN_CHUNK_SIZE,
+                Self::MAX_CHUNK_SIZE
+            )),
+        }
+    }
+
+    /// Identifier of the main document this page is part of.
+    pub fn document_id(&self) -> DocumentId {
+        document_id(&self.doc_chksum)
+    }
+
+    /// Index of this page (starting from one).
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Number of distinct pages needed to restore the main document.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Total number of pages the main document was split into.
+    pub fn page_count(&self) -> u32 {
+        self.page_count
+    }
+}
+
+impl MainDocument {
+    /// Split this document into `page_count` pages, any `threshold` of which
+    /// can be passed to `MainDocument::from_pages` to restore it.
+    ///
+    /// The first `threshold` pages hold the document itself and the rest hold
+    /// parity data, so `threshold == page_count` just splits the document up
+    /// without any redundancy.
+    pub fn to_pages(
+        &self,
+        threshold: u32,
+        page_count: u32,
+    ) -> Result<Vec<MainDocumentPage>, String> {
+        if threshold == 0 {
+            return Err("must at least have a threshold of one".into());
+        }
+        if page_count < threshold {
+            return Err(format!(
+                "threshold of {} is larger than the {} pages to be created",
+                threshold, page_count
+            ));
+        }
+        if page_count > MainDocumentPage::MAX_PAGES {
+            return Err(format!(
+                "cannot split a main document into more than {} pages",
+                MainDocumentPage::MAX_PAGES
+            ));
+        }
+        let bytes = self.to_wire();
+        if bytes.len() > MainDocumentPage::MAX_DOCUMENT_LENGTH {
+            return Err("main document is too large to be split into pages".into());
+        }
+
+        let doc_chksum = self.checksum();
+        Ok(shamir::erasure_encode(&bytes, threshold, page_count)
+            .into_iter()
+            .zip(1..)
+            .map(|(data, index)| MainDocumentPage {
+                doc_chksum: doc_chksum.clone(),
+                index,
+                threshold,
+                page_count,
+                length: bytes.len(),
+                data,
+            })
+            .collect())
+    }
+
+    /// Split this document into pages holding at most `chunk_size()` bytes of
+    /// the document each (or `MainDocumentPage::DEFAULT_CHUNK_SIZE` if the
+    /// document doesn't record a chunk size), plus `redundancy` extra pages
+    /// so that up to `redundancy` pages can be lost.
+    pub fn to_chunked_pages(&self, redundancy: u32) -> Result<Vec<MainDocumentPage>, String> {
+        let chunk_size = self
+            .chunk_size()
+            .unwrap_or(MainDocumentPage::DEFAULT_CHUNK_SIZE);
+        // Pages hold a whole number of field elements, so a page may need to
+        // be a little shorter than the chunk size.
+        let length = self.wire_len();
+        let mut threshold = length.div_ceil(chunk_size as usize) as u32;
+        while shamir::erasure_piece_len(length, threshold) > chunk_size as usize {
+            threshold += 1;
+        }
+        let page_count = threshold
+            .checked_add(redundancy)
+            .filter(|page_count| *page_count <= MainDocumentPage::MAX_PAGES)
+            .ok_or_else(|| {
+                format!(
+                    "main document needs {} pages at a chunk size of {} bytes (plus {} redundant pages), but at most {} are supported",
+                    threshold,
+                    chunk_size,
+                    redundancy,
+                    MainDocumentPage::MAX_PAGES
+                )
+            })?;
+        self.to_pages(threshold, page_count)
+    }
+
+    /// Restore a document from at least `threshold` distinct pages produced by
+    /// `MainDocument::to_pages`. Duplicate pages are ignored.
+    pub fn from_pages(pages: &[MainDocumentPage]) -> Result<MainDocument, String> {
+        let first = pages.first().ok_or("no main document pages given")?;
+        if pages.iter().any(|page| {
+            page.doc_chksum != first.doc_chksum
+                || page.threshold != first.threshold
+                || page.page_count != first.page_count
+                || page.length != first.length
+        }) {
+            return Err("pages are not all from the same main document".into());
+        }
+        if let Some(page) = pages.iter().find(|page| page.index > page.page_count) {
+            return Err(ErasureError::InvalidIndex(page.index).to_string());
+        }
+
+        let pieces = pages
+            .iter()
+            .map(|page| (page.index, page.data.as_slice()))
+            .collect::<Vec<_>>();
+        let bytes = shamir::erasure_decode(&pieces, first.threshold, first.length)
+            .map_err(|err| err.to_string())?;
+        let main = MainDocument::from_wire(bytes)
+            .map_err(|err| format!("restored main document is corrupted: {}", err))?;
+        if main.checksum() != first.doc_chksum {
+            return Err("restored main document does not match its pages".into());
+        }
+        Ok(main)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{Backup, BackupOptions};
+
+    use quickcheck::TestResult;
+
+    #[quickcheck]
+    fn pages_any_threshold(secret: Vec<u8>, threshold: u8, extra: u8, keep: Vec<u8>) -> TestResult {
+        let threshold = (threshold % 8) as u32 + 1;
+        let page_count = threshold + (extra % 4) as u32;
+        let backup = Backup::new(2, &secret).unwrap();
+        let main = backup.main_document();
+        let pages = main.to_pages(threshold, page_count).unwrap();
+
+        // Pick an arbitrary subset of threshold pages.
+        let mut remaining = pages.clone();
+        let mut chosen = vec![];
+        for i in keep.into_iter().chain(0..) {
+            if chosen.len() == threshold as usize {
+                break;
+            }
+            chosen.push(remaining.remove(i as usize % remaining.len()));
+        }
+
+        TestResult::from_bool(
+            pages.iter().all(|page| page.document_id() == main.id())
+                && MainDocument::from_pages(&chosen).as_ref() == Ok(main)
+                && (threshold == 1 || MainDocument::from_pages(&chosen[1..]).is_err()),
+        )
+    }
+
+    #[quickcheck]
+    fn pages_lost_page(secret: Vec<u8>) -> bool {
+        let backup = Backup::new(2, &secret).unwrap();
+        let main = backup.main_document();
+        let mut pages = main.to_pages(3, 4).unwrap();
+
+        // Ruin one of the data pages.
+        pages.remove(1);
+        MainDocument::from_pages(&pages).as_ref() == Ok(main)
+    }
+
+    #[quickcheck]
+    fn pages_chunked(secret: Vec<u8>, chunk_size: u16, redundancy: u8) -> bool {
+        let chunk_size = MainDocumentPage::MIN_CHUNK_SIZE + chunk_size as u32;
+        let redundancy = (redundancy % 4) as u32;
+        let options = BackupOptions {
+            chunk_size: Some(chunk_size),
+            ..Default::default()
+        };
+        let main = Backup::with_options(2, &secret, options)
+            .unwrap()
+            .main_document()
+            .clone();
+        let pages = main.to_chunked_pages(redundancy).unwrap();
+        let threshold = pages[0].threshold();
+
+        main.chunk_size() == Some(chunk_size)
+            && pages.len() as u32 == threshold + redundancy
+            && pages
+                .iter()
+                .all(|page| page.data.len() <= chunk_size as usize)
+            && (threshold == 1
+                || shamir::erasure_piece_len(main.wire_len(), threshold - 1) > chunk_size as usize)
+            && MainDocument::from_pages(&pages[redundancy as usize..]).as_ref() == Ok(&main)
+    }
+
+    #[test]
+    fn pages_chunk_size_limits() {
+        for chunk_size in &[
+            0,
+            MainDocumentPage::MIN_CHUNK_SIZE - 1,
+            MainDocumentPage::MAX_CHUNK_SIZE + 1,
+        ] {
+            let options = BackupOptions {
+                chunk_size: Some(*chunk_size),
+                ..Default::default()
+            };
+            assert!(Backup::with_options(2, b"secret", options).is_err());
+        }
+
+        // A large document can't be split into more than MAX_PAGES pages.
+        let options = BackupOptions {
+            chunk_size: Some(MainDocumentPage::MIN_CHUNK_SIZE),
+            ..Default::default()
+        };
+        let secret = vec![0u8; 64 * MainDocumentPage::MAX_PAGES as usize];
+        let backup = Backup::with_options(2, &secret, options).unwrap();
+        assert!(backup.main_document().to_chunked_pages(0).is_err());
+    }
+
+    #[quickcheck]
+    fn pages_mismatched(secret: Vec<u8>) -> bool {
+        let main = Backup::new(2, &secret).unwrap().main_document().clone();
+        let other = Backup::new(2, &secret).unwrap().main_document().clone();
+        let pages = main.to_pages(2, 3).unwrap();
+        let other_pages = other.to_pages(2, 3).unwrap();
+
+        // Corrupted page data is caught by the checksum.
+        let mut corrupted = pages.clone();
+        corrupted[0].data[0] ^= 1;
+
+        MainDocument::from_pages(&[pages[0].clone(), other_pages[1].clone()]).is_err()
+            && MainDocument::from_pages(&corrupted[..2]).is_err()
+            && MainDocument::from_pages(&[]).is_err()
+            && main.to_pages(3, 2).is_err()
+            && main.to_pages(0, 2).is_err()
+    }
+}
@@ -0,0 +1,497 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Sorting out a pile of documents and key shards which may belong to several
+//! different backups (such as the contents of a safe, or a directory of
+//! scans). The pile is split up by backup, and each backup is checked for
+//! whether it can be recovered with what is in the pile. Documents which
+//! contradict the rest of their backup (or are forged) are flagged rather than
+//! causing the whole check to fail.
+
+use crate::v0::{
+    document_id,
+    recover::{Type, Untrusted},
+    DocumentId, EncryptedKeyShard, KeyShard, KeyShardCodewords, MainDocument, ShardId,
+    UntrustedQuorum,
+};
+
+use std::{error, fmt};
+
+use multihash::Multihash;
+
+/// Reasons why a document in a pile is inconsistent with the rest of its
+/// backup. Inconsistent documents are not used for recovery.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Inconsistency {
+    /// The signature of the document is invalid.
+    Forged,
+    /// The document was signed by a different identity to the rest of the
+    /// backup.
+    IdentityMismatch,
+    /// The document claims a different paperback version to the rest of the
+    /// backup.
+    VersionMismatch { expected: u32, found: u32 },
+    /// The document claims a different quorum size to the rest of the backup.
+    QuorumSizeMismatch { expected: u32, found: u32 },
+    /// The review-by date copied into the key shard doesn't match the main
+    /// document.
+    ReviewByMismatch,
+    /// Another key shard in the pile has the same identifier but different
+    /// contents.
+    ConflictingShard(ShardId),
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inconsistency::Forged => write!(f, "signature is invalid"),
+            Inconsistency::IdentityMismatch => {
+                write!(
+                    f,
+                    "signed by a different identity to the rest of the backup"
+                )
+            }
+            Inconsistency::VersionMismatch { expected, found } => write!(
+                f,
+                "paperback version is {} but the rest of the backup has {}",
+                found, expected
+            ),
+            Inconsistency::QuorumSizeMismatch { expected, found } => write!(
+                f,
+                "quorum size is {} but the rest of the backup has {}",
+                found, expected
+            ),
+            Inconsistency::ReviewByMismatch => {
+                write!(f, "review-by date doesn't match the main document")
+            }
+            Inconsistency::ConflictingShard(id) => {
+                write!(f, "conflicts with another key shard with id {}", id)
+            }
+        }
+    }
+}
+
+impl error::Error for Inconsistency {}
+
+/// The documents in a pile which belong to a single backup, as found by
+/// `Pile::check`.
+#[derive(Clone, Debug)]
+pub struct PileGroup {
+    doc_chksum: Multihash,
+    quorum_size: u32,
+    main_documents: Vec<(String, MainDocument)>,
+    shards: Vec<(String, KeyShard)>,
+    inconsistent: Vec<(String, Inconsistency)>,
+}
+
+impl PileGroup {
+    /// The identifier of the backup (as printed on its documents).
+    pub fn document_id(&self) -> DocumentId {
+        document_id(&self.doc_chksum)
+    }
+
+    /// The number of distinct key shards needed to recover the backup.
+    pub fn quorum_size(&self) -> u32 {
+        self.quorum_size
+    }
+
+    /// Labels of the (consistent) copies of the main document in the pile.
+    pub fn main_documents(&self) -> Vec<&str> {
+        self.main_documents
+            .iter()
+            .map(|(label, _)| label.as_str())
+            .collect()
+    }
+
+    /// Labels of the (consistent) key shards in the pile. Several copies of
+    /// the same key shard are all listed, but only count once towards the
+    /// quorum.
+    pub fn shards(&self) -> Vec<&str> {
+        self.shards
+            .iter()
+            .map(|(label, _)| label.as_str())
+            .collect()
+    }
+
+    /// Labels of the documents which are inconsistent with the rest of the
+    /// backup, and why.
+    pub fn inconsistent(&self) -> &[(String, Inconsistency)] {
+        &self.inconsistent
+    }
+
+    /// The number of distinct key shards in the pile.
+    pub fn unique_shards(&self) -> u32 {
+        let mut ids = self
+            .shards
+            .iter()
+            .map(|(_, shard)| shard.id())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        ids.len() as u32
+    }
+
+    /// The number of key shards which still need to be found before the
+    /// backup can be recovered.
+    pub fn missing_shards(&self) -> u32 {
+        self.quorum_size.saturating_sub(self.unique_shards())
+    }
+
+    /// Whether the pile has a main document for this backup.
+    pub fn has_main_document(&self) -> bool {
+        !self.main_documents.is_empty()
+    }
+
+    /// Whether the backup can be recovered from the (consistent) documents in
+    /// the pile.
+    pub fn is_viable(&self) -> bool {
+        self.has_main_document() && self.missing_shards() == 0
+    }
+
+    /// An `UntrustedQuorum` containing the consistent documents of this
+    /// backup, for recovering it.
+    pub fn untrusted_quorum(&self) -> UntrustedQuorum {
+        let mut quorum = UntrustedQuorum::new();
+        if let Some((_, main_document)) = self.main_documents.first() {
+            quorum.main_document(main_document.clone());
+        }
+        let mut seen = vec![];
+        for (_, shard) in &self.shards {
+            let id = shard.id();
+            if !seen.contains(&id) {
+                seen.push(id);
+                quorum.push_shard(shard.clone());
+            }
+        }
+        quorum
+    }
+}
+
+/// The result of `Pile::check`.
+#[derive(Clone, Debug)]
+pub struct PileReport {
+    groups: Vec<PileGroup>,
+    unreadable: Vec<(String, String)>,
+}
+
+impl PileReport {
+    /// The backups found in the pile, in the order they first appeared.
+    pub fn groups(&self) -> &[PileGroup] {
+        &self.groups
+    }
+
+    /// Labels of the documents which couldn't be read at all (and so couldn't
+    /// be assigned to a backup), and why.
+    pub fn unreadable(&self) -> &[(String, String)] {
+        &self.unreadable
+    }
+
+    /// Whether every document in the pile was readable and consistent.
+    pub fn is_consistent(&self) -> bool {
+        self.unreadable.is_empty() && self.groups.iter().all(|g| g.inconsistent.is_empty())
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    MainDocument(MainDocument),
+    KeyShard(KeyShard),
+    Unreadable(String),
+}
+
+/// A pile of documents from (possibly) several different backups. Each
+/// document is given a label (such as the name of the file it was scanned
+/// from) which is used to refer to it in the `PileReport`.
+#[derive(Clone, Debug, Default)]
+pub struct Pile {
+    items: Vec<(String, Item)>,
+}
+
+// The properties all documents of a backup must agree on.
+struct Reference {
+    version: u32,
+    quorum_size: u32,
+    id_public_key: [u8; 32],
+    // Only known if there is a main document.
+    review_by: Option<Option<u64>>,
+}
+
+impl Pile {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn push_main_document<L: Into<String>>(
+        &mut self,
+        label: L,
+        main: MainDocument,
+    ) -> &mut Self {
+        self.items.push((label.into(), Item::MainDocument(main)));
+        self
+    }
+
+    pub fn push_shard<L: Into<String>>(&mut self, label: L, shard: KeyShard) -> &mut Self {
+        self.items.push((label.into(), Item::KeyShard(shard)));
+        self
+    }
+
+    /// Add an encrypted key shard to the pile. If it can't be decrypted with
+    /// the codewords, it is reported as unreadable.
+    pub fn push_encrypted_shard<L: Into<String>>(
+        &mut self,
+        label: L,
+        shard: EncryptedKeyShard,
+        codewords: &KeyShardCodewords,
+    ) -> &mut Self {
+        let item = match shard.decrypt(codewords) {
+            Ok(shard) => Item::KeyShard(shard),
+            Err(err) => Item::Unreadable(err),
+        };
+        self.items.push((label.into(), item));
+        self
+    }
+
+    /// Record a document which couldn't be read (such as a scan which failed
+    /// to decode), so that it is listed in the report.
+    pub fn push_unreadable<L: Into<String>, E: ToString>(&mut self, label: L, err: E) -> &mut Self {
+        self.items
+            .push((label.into(), Item::Unreadable(err.to_string())));
+        self
+    }
+
+    /// Split the pile up by backup and check each backup for consistency.
+    pub fn check(&self) -> PileReport {
+        let mut labels = vec![];
+        let mut documents = vec![];
+        let mut unreadable = vec![];
+        for (label, item) in &self.items {
+            match item {
+                Item::MainDocument(main) => {
+                    labels.push(label.clone());
+                    documents.push(Untrusted::MainDocument(main.clone()));
+                }
+                Item::KeyShard(shard) => {
+                    labels.push(label.clone());
+                    documents.push(Untrusted::KeyShard(shard.clone()));
+                }
+                Item::Unreadable(err) => unreadable.push((label.clone(), err.clone())),
+            }
+        }
+
+        let mut grouped: Vec<(Multihash, Vec<(String, Type)>)> = vec![];
+        let documents = Untrusted::verify_documents(documents);
+        for (label, (doc_chksum, document)) in labels.into_iter().zip(documents) {
+            match grouped.iter_mut().find(|(chksum, _)| *chksum == doc_chksum) {
+                Some((_, group)) => group.push((label, document)),
+                None => grouped.push((doc_chksum, vec![(label, document)])),
+            }
+        }
+
+        PileReport {
+            groups: grouped
+                .into_iter()
+                .map(|(doc_chksum, documents)| Self::check_group(doc_chksum, documents))
+                .collect(),
+            unreadable,
+        }
+    }
+
+    fn check_group(doc_chksum: Multihash, documents: Vec<(String, Type)>) -> PileGroup {
+        // Genuine main documents are the most trustworthy description of the
+        // backup, otherwise go with the first genuine key shard.
+        let reference = documents
+            .iter()
+            .find_map(|(_, document)| document.main_document())
+            .map(|main| Reference {
+                version: main.inner.meta.version,
+                quorum_size: main.quorum_size(),
+                id_public_key: main.identity.id_public_key.to_bytes(),
+                review_by: Some(main.metadata().review_by),
+            })
+            .or_else(|| {
+                documents
+                    .iter()
+                    .find_map(|(_, document)| document.key_shard())
+                    .map(|shard| Reference {
+                        version: shard.inner.version,
+                        quorum_size: shard.inner.shard.threshold(),
+                        id_public_key: shard.identity.id_public_key.to_bytes(),
+                        review_by: None,
+                    })
+            });
+
+        let mut group = PileGroup {
+            doc_chksum,
+            quorum_size: reference.as_ref().map_or(0, |r| r.quorum_size),
+            main_documents: vec![],
+            shards: vec![],
+            inconsistent: vec![],
+        };
+        for (label, document) in documents {
+            let (version, quorum_size, id_public_key) = match &document {
+                Type::ForgedMainDocument(_) | Type::ForgedKeyShard(_) => {
+                    group.inconsistent.push((label, Inconsistency::Forged));
+                    continue;
+                }
+                Type::MainDocument(main) => (
+                    main.inner.meta.version,
+                    main.quorum_size(),
+                    main.identity.id_public_key.to_bytes(),
+                ),
+                Type::KeyShard(shard) => (
+                    shard.inner.version,
+                    shard.inner.shard.threshold(),
+                    shard.identity.id_public_key.to_bytes(),
+                ),
+            };
+            // There must be a reference if there's a genuine document.
+            let reference = reference
+                .as_ref()
+                .expect("genuine document without reference");
+
+            let inconsistency = if id_public_key != reference.id_public_key {
+                Some(Inconsistency::IdentityMismatch)
+            } else if version != reference.version {
+                Some(Inconsistency::VersionMismatch {
+                    expected: reference.version,
+                    found: version,
+                })
+            } else if quorum_size != reference.quorum_size {
+                Some(Inconsistency::QuorumSizeMismatch {
+                    expected: reference.quorum_size,
+                    found: quorum_size,
+                })
+            } else {
+                match &document {
+                    Type::KeyShard(shard) => {
+                        if reference
+                            .review_by
+                            .is_some_and(|review_by| review_by != shard.review_by())
+                        {
+                            Some(Inconsistency::ReviewByMismatch)
+                        } else {
+                            group
+                                .shards
+                                .iter()
+                                .map(|(_, other)| other)
+                                .find(|other| {
+                                    other.id() == shard.id()
+                                        && other.inner.shard != shard.inner.shard
+                                })
+                                .map(|_| Inconsistency::ConflictingShard(shard.id()))
+                        }
+                    }
+                    _ => None,
+                }
+            };
+
+            match (inconsistency, document) {
+                (Some(inconsistency), _) => group.inconsistent.push((label, inconsistency)),
+                (None, Type::MainDocument(main)) => group.main_documents.push((label, main)),
+                (None, Type::KeyShard(shard)) => group.shards.push((label, shard)),
+                (None, _) => unreachable!("forged documents are handled above"),
+            }
+        }
+        group
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::Backup;
+
+    fn backup(quorum_size: u32, n: u32) -> (MainDocument, Vec<KeyShard>) {
+        let mut backup = Backup::new(quorum_size, b"secret").unwrap();
+        let shards = (0..n).map(|_| backup.next_shard().unwrap()).collect();
+        (backup.main_document().clone(), shards)
+    }
+
+    #[test]
+    fn pile_groups_by_backup() {
+        let (main_a, shards_a) = backup(3, 4);
+        let (main_b, shards_b) = backup(2, 2);
+        let (_, shards_c) = backup(2, 1);
+
+        let mut pile = Pile::new();
+        pile.push_shard("a1", shards_a[0].clone())
+            .push_main_document("b", main_b.clone())
+            .push_shard("a2", shards_a[1].clone())
+            .push_main_document("a", main_a.clone())
+            .push_shard("b1", shards_b[0].clone())
+            .push_shard("a2 (copy)", shards_a[1].clone())
+            .push_shard("c1", shards_c[0].clone())
+            .push_shard("a3", shards_a[2].clone());
+        let report = pile.check();
+
+        assert!(report.is_consistent());
+        let groups = report.groups();
+        assert_eq!(groups.len(), 3);
+
+        assert_eq!(groups[0].document_id(), main_a.id());
+        assert_eq!(groups[0].main_documents(), vec!["a"]);
+        assert_eq!(groups[0].shards(), vec!["a1", "a2", "a2 (copy)", "a3"]);
+        assert_eq!(groups[0].unique_shards(), 3);
+        assert!(groups[0].is_viable());
+        let quorum = groups[0].untrusted_quorum().validate().unwrap();
+        assert_eq!(quorum.recover_document().unwrap(), b"secret");
+
+        assert_eq!(groups[1].document_id(), main_b.id());
+        assert_eq!(groups[1].missing_shards(), 1);
+        assert!(!groups[1].is_viable());
+
+        assert!(!groups[2].has_main_document());
+        assert_eq!(groups[2].quorum_size(), 2);
+        assert_eq!(groups[2].missing_shards(), 1);
+    }
+
+    #[test]
+    fn pile_flags_inconsistent() {
+        let (main, shards) = backup(2, 3);
+        let (encrypted, _) = shards[2].clone().encrypt().unwrap();
+        let (_, wrong_codewords) = shards[1].clone().encrypt().unwrap();
+
+        let mut forged = shards[0].clone();
+        forged.inner.review_by = Some(1);
+
+        let mut pile = Pile::new();
+        pile.push_main_document("main", main)
+            .push_shard("forged", forged)
+            .push_shard("1", shards[1].clone())
+            .push_shard("2", shards[2].clone())
+            .push_encrypted_shard("encrypted", encrypted, &wrong_codewords)
+            .push_unreadable("scan", "qr code not found");
+        let report = pile.check();
+
+        assert!(!report.is_consistent());
+        let unreadable = report
+            .unreadable()
+            .iter()
+            .map(|(label, _)| label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(unreadable, vec!["encrypted", "scan"]);
+
+        let group = &report.groups()[0];
+        assert_eq!(
+            group.inconsistent(),
+            &[("forged".to_string(), Inconsistency::Forged)]
+        );
+        assert_eq!(group.shards(), vec!["1", "2"]);
+        assert!(group.is_viable());
+    }
+}
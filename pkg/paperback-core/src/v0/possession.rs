@@ -0,0 +1,238 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Proof that a custodian still holds an intact key shard, without the shard
+//! leaving the custodian. When the shards are created, the owner keeps a
+//! `PossessionVerifier` for each of them. Later, the owner sends a random
+//! `PossessionChallenge` to a custodian, who answers it with a short
+//! `PossessionResponse` computed from their shard, which the owner checks
+//! with the verifier.
+//!
+//! A verifier contains a key derived from the (secret) contents of the
+//! shard. It doesn't reveal anything about the shard itself, but it can be
+//! used to forge responses, so verifiers should be kept private.
+
+use crate::v0::{document_id, DocumentId, KeyShard, ShardId, ToWire};
+
+use std::{fmt, str::FromStr};
+
+use multihash::Multihash;
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroize;
+
+const POSSESSION_KEY_PERSONAL: &[u8] = b"paperback-pokey";
+const POSSESSION_RESPONSE_PERSONAL: &[u8] = b"paperback-pop";
+
+pub(super) const POSSESSION_KEY_LENGTH: usize = 32;
+
+// Parse a fixed-length z-base-32 code.
+pub(super) fn parse_code<const N: usize>(code: &str, what: &str) -> Result<[u8; N], String> {
+    use crate::shamir::encoding;
+
+    let bytes = zbase32::decode_full_bytes_str(&encoding::zbase32_normalize(code))
+        .map_err(|_| format!("{} must be z-base-32", what))?;
+    if bytes.len() != N {
+        return Err(format!("{} must be {} bytes", what, N));
+    }
+    let mut code = [0u8; N];
+    code.copy_from_slice(&bytes);
+    Ok(code)
+}
+
+/// Random challenge sent by the owner of a backup to a custodian. A fresh
+/// challenge must be used every time, so that old responses can't be
+/// replayed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PossessionChallenge([u8; PossessionChallenge::LENGTH]);
+
+impl PossessionChallenge {
+    /// Length of a challenge.
+    pub const LENGTH: usize = 10;
+
+    /// Generate a new random challenge.
+    pub fn generate() -> Self {
+        let mut challenge = [0u8; Self::LENGTH];
+        OsRng.fill_bytes(&mut challenge);
+        PossessionChallenge(challenge)
+    }
+}
+
+/// The challenge is written down as z-base-32.
+impl fmt::Display for PossessionChallenge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", zbase32::encode_full_bytes(&self.0))
+    }
+}
+
+impl FromStr for PossessionChallenge {
+    type Err = String;
+
+    fn from_str(challenge: &str) -> Result<Self, Self::Err> {
+        parse_code(challenge, "challenge").map(PossessionChallenge)
+    }
+}
+
+/// Response of a custodian to a `PossessionChallenge`, computed from their
+/// key shard.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PossessionResponse([u8; PossessionResponse::LENGTH]);
+
+impl PossessionResponse {
+    /// Length of a response.
+    pub const LENGTH: usize = 10;
+}
+
+/// The response is written down as z-base-32.
+impl fmt::Display for PossessionResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", zbase32::encode_full_bytes(&self.0))
+    }
+}
+
+impl FromStr for PossessionResponse {
+    type Err = String;
+
+    fn from_str(response: &str) -> Result<Self, Self::Err> {
+        parse_code(response, "response").map(PossessionResponse)
+    }
+}
+
+// Compute the response to a challenge with the possession key of a shard.
+fn respond(
+    key: &[u8; POSSESSION_KEY_LENGTH],
+    challenge: &PossessionChallenge,
+) -> blake2b_simd::Hash {
+    blake2b_simd::Params::new()
+        .hash_length(PossessionResponse::LENGTH)
+        .key(key)
+        .personal(POSSESSION_RESPONSE_PERSONAL)
+        .hash(&challenge.0)
+}
+
+/// Kept by the owner of a backup to check the responses of the custodian of
+/// a key shard to a `PossessionChallenge` (see `KeyShard::possession_verifier`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PossessionVerifier {
+    pub(super) doc_chksum: Multihash,
+    pub(super) shard_id: ShardId,
+    pub(super) key: [u8; POSSESSION_KEY_LENGTH],
+}
+
+impl Drop for PossessionVerifier {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl PossessionVerifier {
+    /// Identifier of the main document the shard belongs to.
+    pub fn document_id(&self) -> DocumentId {
+        document_id(&self.doc_chksum)
+    }
+
+    /// Identifier of the shard.
+    pub fn shard_id(&self) -> ShardId {
+        self.shard_id.clone()
+    }
+
+    /// Returns whether `response` is the correct response to `challenge`,
+    /// meaning that the custodian holds the shard (and that it is intact).
+    pub fn verify(&self, challenge: &PossessionChallenge, response: &PossessionResponse) -> bool {
+        // blake2b_simd compares hashes in constant time.
+        respond(&self.key, challenge) == response.0[..]
+    }
+}
+
+impl KeyShard {
+    // Key derived from the complete (secret) contents of the shard, so that a
+    // damaged shard can't answer challenges correctly.
+    fn possession_key(&self) -> [u8; POSSESSION_KEY_LENGTH] {
+        let mut bytes = self.to_wire();
+        let hash = blake2b_simd::Params::new()
+            .hash_length(POSSESSION_KEY_LENGTH)
+            .personal(POSSESSION_KEY_PERSONAL)
+            .hash(&bytes);
+        bytes.zeroize();
+
+        let mut key = [0u8; POSSESSION_KEY_LENGTH];
+        key.copy_from_slice(hash.as_bytes());
+        key
+    }
+
+    /// Create the verifier for this shard, which the owner of the backup
+    /// should keep (privately) when handing the shard to its custodian.
+    pub fn possession_verifier(&self) -> PossessionVerifier {
+        PossessionVerifier {
+            doc_chksum: self.inner.doc_chksum.clone(),
+            shard_id: self.id(),
+            key: self.possession_key(),
+        }
+    }
+
+    /// Answer a challenge from the owner of the backup, proving that this
+    /// shard is still held (and intact).
+    pub fn prove_possession(&self, challenge: &PossessionChallenge) -> PossessionResponse {
+        let mut key = self.possession_key();
+        let hash = respond(&key, challenge);
+        key.zeroize();
+
+        let mut response = [0u8; PossessionResponse::LENGTH];
+        response.copy_from_slice(hash.as_bytes());
+        PossessionResponse(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::Backup;
+
+    #[test]
+    fn possession_codes_roundtrip() {
+        let challenge = PossessionChallenge::generate();
+        let encoded = challenge.to_string();
+        assert_eq!(encoded.parse(), Ok(challenge));
+        assert_eq!(encoded.to_uppercase().parse(), Ok(challenge));
+        assert!("ybndrfg8".parse::<PossessionChallenge>().is_err());
+        assert!("not z-base-32!".parse::<PossessionResponse>().is_err());
+    }
+
+    #[quickcheck]
+    fn possession_challenge(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let shard = backup.next_shard().unwrap();
+        let other = backup.next_shard().unwrap();
+        let verifier = shard.possession_verifier();
+
+        let challenge = PossessionChallenge::generate();
+        let response = shard.prove_possession(&challenge);
+        let response = response.to_string().parse().unwrap();
+
+        // A damaged shard can't answer the challenge.
+        let mut damaged = shard.clone();
+        damaged.inner.review_by = Some(0);
+
+        verifier.shard_id() == shard.id()
+            && verifier.document_id() == backup.main_document().id()
+            && verifier.verify(&challenge, &response)
+            && !verifier.verify(&PossessionChallenge::generate(), &response)
+            && !verifier.verify(&challenge, &other.prove_possession(&challenge))
+            && !verifier.verify(&challenge, &damaged.prove_possession(&challenge))
+    }
+}
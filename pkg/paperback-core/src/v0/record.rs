@@ -0,0 +1,246 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Signed records of completed recoveries, for processes (such as estate
+//! administration or corporate key ceremonies) which need an evidence trail.
+//! A record states which shards took part in a recovery, when it happened and
+//! which software performed it, and is signed with the key of every shard
+//! that took part (see `grant` for how those keys are derived), so it can be
+//! made for sealed backups too.
+
+use crate::v0::{
+    document_id, grant::Participant, now, DocumentId, KeyShard, MainDocument, Quorum, ShardId,
+    ToWire, VerificationError,
+};
+
+use multihash::Multihash;
+
+/// Version of the software which performed a recovery, as recorded in
+/// `RecoveryRecord`s.
+pub const SOFTWARE_VERSION: &str = concat!("paperback-core ", env!("CARGO_PKG_VERSION"));
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct RecoveryRecordBuilder {
+    pub(super) doc_chksum: Multihash,
+    pub(super) participant_ids: Vec<ShardId>,
+    pub(super) software: String,
+    pub(super) recovered_at: Option<u64>,
+}
+
+impl RecoveryRecordBuilder {
+    fn sign(mut self, shards: &[KeyShard]) -> RecoveryRecord {
+        let mut unique = Vec::<&KeyShard>::new();
+        for shard in shards {
+            if !unique.iter().any(|other| other.id() == shard.id()) {
+                unique.push(shard);
+            }
+        }
+        let shards = unique;
+        self.participant_ids = shards.iter().map(|shard| shard.id()).collect();
+
+        let bytes = self.to_wire();
+        let participants = shards
+            .iter()
+            .map(|shard| {
+                let keypair = shard.grant_keypair();
+                Participant {
+                    shard_id: shard.id(),
+                    public_key: keypair.public,
+                    signature: keypair.sign(&bytes),
+                }
+            })
+            .collect();
+        RecoveryRecord {
+            inner: self,
+            participants,
+        }
+    }
+}
+
+/// Record of a completed recovery, signed by every shard which took part in
+/// it (see `Quorum::recover_document_with_record`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecoveryRecord {
+    pub(super) inner: RecoveryRecordBuilder,
+    // The participant shard IDs are part of the signed record.
+    pub(super) participants: Vec<Participant>,
+}
+
+impl RecoveryRecord {
+    /// Maximum number of shards which can take part in a recovery.
+    pub const MAX_SHARDS: usize = 1 << 16;
+
+    /// Maximum length of the software version.
+    pub const MAX_SOFTWARE_LENGTH: usize = 256;
+
+    /// Identifier of the main document which was recovered.
+    pub fn document_id(&self) -> DocumentId {
+        document_id(&self.inner.doc_chksum)
+    }
+
+    /// Identifiers of the shards which took part in the recovery.
+    pub fn participant_ids(&self) -> &[ShardId] {
+        &self.inner.participant_ids
+    }
+
+    /// The software which performed the recovery.
+    pub fn software(&self) -> &str {
+        &self.inner.software
+    }
+
+    /// When the recovery happened (in seconds since the Unix epoch), if
+    /// known.
+    pub fn recovered_at(&self) -> Option<u64> {
+        self.inner.recovered_at
+    }
+
+    /// Whether `shard` signed this record (that is, whether it took part in
+    /// the recovery).
+    pub fn signed_by(&self, shard: &KeyShard) -> bool {
+        let public_key = shard.grant_keypair().public;
+        shard.inner.doc_chksum == self.inner.doc_chksum
+            && self.participants.iter().any(|participant| {
+                participant.shard_id == shard.id()
+                    && participant.public_key == public_key
+                    && public_key
+                        .verify_strict(&self.inner.to_wire(), &participant.signature)
+                        .is_ok()
+            })
+    }
+
+    /// Verify that this record is for a recovery of `main`, and that it was
+    /// signed by every listed shard (of which there must be at least a
+    /// quorum).
+    ///
+    /// As with `ExpansionGrant::verify`, this cannot tell whether the keys
+    /// which signed the record belong to real shards of `main` -- the holder
+    /// of a shard can check that with `signed_by`.
+    pub fn verify(&self, main: &MainDocument) -> Result<(), VerificationError> {
+        if self.inner.doc_chksum != main.checksum() {
+            return Err(VerificationError::ChecksumMismatch);
+        }
+        let signed_ids = self
+            .participants
+            .iter()
+            .map(|participant| &participant.shard_id);
+        if !signed_ids.eq(self.inner.participant_ids.iter()) {
+            return Err(VerificationError::InvalidSignature);
+        }
+        let bytes = self.inner.to_wire();
+        for participant in &self.participants {
+            participant
+                .public_key
+                .verify_strict(&bytes, &participant.signature)
+                .map_err(|_| VerificationError::InvalidSignature)?;
+        }
+        let mut public_keys = self
+            .participants
+            .iter()
+            .map(|participant| participant.public_key.to_bytes())
+            .collect::<Vec<_>>();
+        public_keys.sort_unstable();
+        public_keys.dedup();
+        if public_keys.len() < main.quorum_size() as usize {
+            return Err(VerificationError::TooFewSignatures {
+                required: main.quorum_size(),
+                found: public_keys.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Quorum {
+    /// Like `recover_document`, but also returns a record of the recovery
+    /// signed by each shard of the quorum, which can be printed and archived.
+    pub fn recover_document_with_record(&self) -> Result<(Vec<u8>, RecoveryRecord), String> {
+        let secret = self.recover_document()?;
+        let record = RecoveryRecordBuilder {
+            doc_chksum: self.main_document().checksum(),
+            participant_ids: vec![],
+            software: SOFTWARE_VERSION.into(),
+            recovered_at: now(),
+        }
+        .sign(self.shards());
+        Ok((secret, record))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{test::quorum, Backup};
+
+    #[quickcheck]
+    fn recovery_record(secret: Vec<u8>, sealed: bool) -> bool {
+        let mut backup = match sealed {
+            false => Backup::new(2, &secret).unwrap(),
+            true => Backup::new_sealed(2, &secret).unwrap(),
+        };
+        let main = backup.main_document().clone();
+        let shards = (0..3)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+        let (recovered, record) = quorum(&main, &shards[..2])
+            .recover_document_with_record()
+            .unwrap();
+
+        recovered == secret
+            && record.verify(&main) == Ok(())
+            && record.document_id() == main.id()
+            && record.participant_ids() == [shards[0].id(), shards[1].id()]
+            && record.software() == SOFTWARE_VERSION
+            && record.recovered_at().is_some()
+            && record.signed_by(&shards[0])
+            && record.signed_by(&shards[1])
+            && !record.signed_by(&shards[2])
+    }
+
+    #[quickcheck]
+    fn recovery_record_forged(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let main = backup.main_document().clone();
+        let shards = (0..3)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+        let (_, record) = quorum(&main, &shards[..2])
+            .recover_document_with_record()
+            .unwrap();
+
+        // Claiming that a different shard took part.
+        let mut forged = record.clone();
+        forged.inner.participant_ids[1] = shards[2].id();
+
+        // Dropping a signature, leaving fewer than a quorum.
+        let mut partial = record.clone();
+        partial.inner.participant_ids.pop();
+        partial.participants.pop();
+
+        // Backdating the recovery.
+        let mut backdated = record.clone();
+        backdated.inner.recovered_at = Some(0);
+
+        forged.verify(&main) == Err(VerificationError::InvalidSignature)
+            && partial.verify(&main) == Err(VerificationError::InvalidSignature)
+            && backdated.verify(&main) == Err(VerificationError::InvalidSignature)
+            && !backdated.signed_by(&shards[0])
+            && record.verify(&Backup::new(2, &secret).unwrap().main_document().clone())
+                == Err(VerificationError::ChecksumMismatch)
+    }
+}
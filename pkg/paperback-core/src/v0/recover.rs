@@ -17,21 +17,45 @@
  */
 
 use crate::{
-    shamir::{self, Dealer},
-    v0::{FromWire, KeyShard, KeyShardBuilder, MainDocument, ShardSecret},
+    shamir::{self, Dealer, MacKey},
+    v0::{
+        decoy, shard_mac_key, Bundle, ChaChaPolyKey, Compression, Custodian, FromWire, KeyShard,
+        KeyShardBuilder, MainDocument, RevocationList, RevocationReason, ShardId, ShardSecret,
+        SupersededNotice, Watermark,
+    },
 };
 
+#[cfg(feature = "parallel")]
+use std::iter::once;
 use std::{
     collections::HashMap,
+    fmt,
     hash::{Hash, Hasher},
+    sync::OnceLock,
 };
 
-use aead::{Aead, NewAead, Payload};
-use chacha20poly1305::ChaCha20Poly1305;
+use aead::Payload;
+#[cfg(feature = "parallel")]
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_POINT,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+    traits::{IsIdentity, VartimeMultiscalarMul},
+};
+#[cfg(feature = "parallel")]
+use ed25519_dalek::Signature;
 use ed25519_dalek::{Keypair, PublicKey};
 use multihash::Multihash;
+#[cfg(feature = "parallel")]
+use rand::{rngs::OsRng, Rng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "parallel")]
+use sha2::{Digest, Sha512};
+use zeroize::Zeroize;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
 pub enum Type {
     MainDocument(MainDocument),
     ForgedMainDocument(MainDocument),
@@ -40,14 +64,14 @@ pub enum Type {
 }
 
 impl Type {
-    fn main_document(&self) -> Option<&MainDocument> {
+    pub(super) fn main_document(&self) -> Option<&MainDocument> {
         match self {
             Type::MainDocument(m) => Some(m),
             _ => None,
         }
     }
 
-    fn key_shard(&self) -> Option<&KeyShard> {
+    pub(super) fn key_shard(&self) -> Option<&KeyShard> {
         match self {
             Type::KeyShard(k) => Some(k),
             _ => None,
@@ -81,6 +105,140 @@ impl From<KeyShard> for Type {
     }
 }
 
+// A document whose signature hasn't been checked yet.
+#[derive(Clone)]
+pub(super) enum Untrusted {
+    MainDocument(MainDocument),
+    KeyShard(KeyShard),
+}
+
+impl Untrusted {
+    fn verify(self) -> Type {
+        match self {
+            Untrusted::MainDocument(main) => Type::from(main),
+            Untrusted::KeyShard(shard) => Type::from(shard),
+        }
+    }
+
+    // Verify the signatures of the documents and compute the checksum of the
+    // main document each of them belongs to. Verifying the signatures and
+    // computing the checksums of each document are independent (and
+    // comparatively expensive) operations, so with the "parallel" feature
+    // they are done concurrently. The order of the documents is preserved.
+    pub(super) fn verify_documents(documents: Vec<Self>) -> Vec<(Multihash, Type)> {
+        #[cfg(feature = "parallel")]
+        let documents = Untrusted::verify_all(documents).into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let documents = documents.into_iter().map(Untrusted::verify);
+        documents
+            .map(|document| {
+                let doc_chksum = match &document {
+                    Type::MainDocument(main) | Type::ForgedMainDocument(main) => main.checksum(),
+                    Type::KeyShard(shard) | Type::ForgedKeyShard(shard) => {
+                        shard.inner.doc_chksum.clone()
+                    }
+                };
+                (doc_chksum, document)
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl Untrusted {
+    fn signed(&self) -> (Vec<u8>, &Signature, &PublicKey) {
+        let identity = match self {
+            Untrusted::MainDocument(main) => &main.identity,
+            Untrusted::KeyShard(shard) => &shard.identity,
+        };
+        let id_public_key = &identity.id_public_key;
+        let bytes = match self {
+            Untrusted::MainDocument(main) => main.inner.signable_bytes(id_public_key),
+            Untrusted::KeyShard(shard) => shard.inner.signable_bytes(id_public_key),
+        };
+        (bytes, &identity.id_signature, id_public_key)
+    }
+
+    // The decompressed R and public key of the signature, if they pass the
+    // checks verify_strict does before checking the signature equation.
+    fn points(&self) -> Option<(EdwardsPoint, EdwardsPoint)> {
+        let (_, signature, public_key) = self.signed();
+        let r = CompressedEdwardsY::from_slice(&signature.to_bytes()[..32]).decompress()?;
+        let a = CompressedEdwardsY::from_slice(public_key.as_bytes()).decompress()?;
+        if r.is_small_order() || a.is_small_order() {
+            None
+        } else {
+            Some((r, a))
+        }
+    }
+
+    // Batch verification of the signatures of all of the documents: checks
+    //   [-sum(z_i*s_i)]B + sum([z_i]R_i) + sum([z_i*k_i]A_i) = 0
+    // for random 128-bit z_i (with k_i = H(R_i || A_i || M_i)), which is
+    // about twice as fast as verifying each signature separately.
+    // ed25519_dalek::verify_batch does the same, but can't be built against
+    // the version of rand we use.
+    //
+    // If every signature passes verify_strict then this passes. Otherwise it
+    // fails, except for signatures whose R has a torsion component (which can
+    // only be made by the holder of the identity private key).
+    fn verify_batch(documents: &[Self]) -> bool {
+        let signed = documents
+            .par_iter()
+            .map(|document| {
+                let (r, a) = document.points()?;
+                let (bytes, signature, public_key) = document.signed();
+                let signature = signature.to_bytes();
+                let mut s = [0u8; 32];
+                s.copy_from_slice(&signature[32..]);
+                let s = Scalar::from_canonical_bytes(s)?;
+                let k = Scalar::from_hash(
+                    Sha512::new()
+                        .chain(&signature[..32])
+                        .chain(public_key.as_bytes())
+                        .chain(&bytes),
+                );
+                Some((r, a, s, k))
+            })
+            .collect::<Option<Vec<_>>>();
+        let signed = match signed {
+            Some(signed) => signed,
+            None => return false,
+        };
+
+        let zs = signed
+            .iter()
+            .map(|_| Scalar::from(OsRng.gen::<u128>()))
+            .collect::<Vec<_>>();
+        let b_coefficient: Scalar = signed.iter().zip(&zs).map(|((_, _, s, _), z)| z * s).sum();
+        let scalars = once(-b_coefficient)
+            .chain(zs.iter().cloned())
+            .chain(signed.iter().zip(&zs).map(|((_, _, _, k), z)| z * k));
+        let points = once(ED25519_BASEPOINT_POINT)
+            .chain(signed.iter().map(|(r, _, _, _)| *r))
+            .chain(signed.iter().map(|(_, a, _, _)| *a));
+        EdwardsPoint::vartime_multiscalar_mul(scalars, points).is_identity()
+    }
+
+    // Verify all of the documents, with the same results as Untrusted::verify.
+    // If every signature is valid (the common case) a single batch
+    // verification is enough, otherwise each signature is verified separately
+    // to find the forgeries.
+    fn verify_all(documents: Vec<Self>) -> Vec<Type> {
+        if Untrusted::verify_batch(&documents) {
+            documents
+                .into_iter()
+                .map(|document| match document {
+                    Untrusted::MainDocument(main) => Type::MainDocument(main),
+                    Untrusted::KeyShard(shard) => Type::KeyShard(shard),
+                })
+                .collect()
+        } else {
+            documents.into_par_iter().map(Untrusted::verify).collect()
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Grouping(pub Vec<Vec<Type>>);
 
@@ -88,6 +246,9 @@ pub struct Grouping(pub Vec<Vec<Type>>);
 pub struct UntrustedQuorum {
     untrusted_main_document: Option<MainDocument>,
     untrusted_shards: Vec<KeyShard>,
+    revocation_lists: Vec<RevocationList>,
+    refuse_revoked: bool,
+    superseded_notices: Vec<SupersededNotice>,
 }
 
 #[derive(Debug, Clone, Eq)]
@@ -141,15 +302,30 @@ impl UntrustedQuorum {
         self
     }
 
-    fn group(&self) -> Vec<Vec<Type>> {
-        let documents = self
-            .untrusted_main_document
-            .iter()
-            .cloned()
-            .map(Type::from)
-            .chain(self.untrusted_shards.iter().cloned().map(Type::from))
-            .collect::<Vec<_>>();
+    /// Add a revocation list to check the shards against. Revoked shards are
+    /// reported by `Quorum::revoked_shards` (or rejected entirely, if
+    /// `refuse_revoked` is set).
+    pub fn revocation_list(&mut self, list: RevocationList) -> &mut Self {
+        self.revocation_lists.push(list);
+        self
+    }
 
+    /// Add a notice that the backup (or one of its successors) has been
+    /// superseded. The chain of successors is reported by
+    /// `Quorum::successors`, so that the user can be told that a newer backup
+    /// exists.
+    pub fn superseded_notice(&mut self, notice: SupersededNotice) -> &mut Self {
+        self.superseded_notices.push(notice);
+        self
+    }
+
+    /// Whether `validate` should fail if any of the shards have been revoked.
+    pub fn refuse_revoked(&mut self, refuse: bool) -> &mut Self {
+        self.refuse_revoked = refuse;
+        self
+    }
+
+    fn group(&self) -> Vec<Vec<Type>> {
         #[derive(Clone, Debug, Eq, Hash, PartialEq)]
         struct GroupId {
             // All documents must agree on the paperback version. This could be
@@ -163,22 +339,42 @@ impl UntrustedQuorum {
             id_public_key: HashablePublicKey,
         }
 
+        let documents = self
+            .untrusted_main_document
+            .iter()
+            .cloned()
+            .map(Untrusted::MainDocument)
+            .chain(
+                self.untrusted_shards
+                    .iter()
+                    .cloned()
+                    .map(Untrusted::KeyShard),
+            )
+            .collect::<Vec<_>>();
+
+        let documents = Untrusted::verify_documents(documents)
+            .into_iter()
+            .map(|(doc_chksum, document)| {
+                let group_id = match &document {
+                    Type::MainDocument(main) | Type::ForgedMainDocument(main) => GroupId {
+                        version: main.inner.meta.version,
+                        doc_chksum,
+                        quorum_size: main.quorum_size(),
+                        id_public_key: HashablePublicKey(main.identity.id_public_key),
+                    },
+                    Type::KeyShard(shard) | Type::ForgedKeyShard(shard) => GroupId {
+                        version: shard.inner.version,
+                        doc_chksum,
+                        quorum_size: shard.inner.shard.threshold(),
+                        id_public_key: HashablePublicKey(shard.identity.id_public_key),
+                    },
+                };
+                (group_id, document)
+            })
+            .collect::<Vec<_>>();
+
         let mut groups: HashMap<GroupId, Vec<Type>> = HashMap::new();
-        for document in documents {
-            let group_id = match &document {
-                Type::MainDocument(main) | Type::ForgedMainDocument(main) => GroupId {
-                    version: main.inner.meta.version,
-                    doc_chksum: main.checksum(),
-                    quorum_size: main.quorum_size(),
-                    id_public_key: HashablePublicKey(main.identity.id_public_key.clone()),
-                },
-                Type::KeyShard(shard) | Type::ForgedKeyShard(shard) => GroupId {
-                    version: shard.inner.version,
-                    doc_chksum: shard.inner.doc_chksum.clone(),
-                    quorum_size: shard.inner.shard.threshold(),
-                    id_public_key: HashablePublicKey(shard.identity.id_public_key.clone()),
-                },
-            };
+        for (group_id, document) in documents {
             groups.entry(group_id).or_insert(vec![]).push(document);
         }
         groups.values().cloned().collect::<Vec<_>>()
@@ -245,9 +441,10 @@ impl UntrustedQuorum {
         // TODO: Sanity-check the shards completely.
         assert_eq!(shards.len(), self.untrusted_shards.len());
 
-        // XXX: Should probably support having more shards than needed, and have
-        //      them act as a double-check operation.
-        if main_document.quorum_size() as usize != shards.len() {
+        // Having more shards than needed is fine (only the first quorum_size
+        // unique shards are used for recovery).
+        // XXX: The extra shards should probably act as a double-check.
+        if (main_document.quorum_size() as usize) > shards.len() {
             return Err(InconsistentQuorumError {
                 message: format!(
                     "quorum size required is {} but had {} shards",
@@ -258,6 +455,48 @@ impl UntrustedQuorum {
             });
         }
 
+        // Revocation lists must have been signed by the owner of the main
+        // document, otherwise anyone could deny recovery by revoking shards.
+        if self
+            .revocation_lists
+            .iter()
+            .any(|list| list.verify(&main_document).is_err())
+        {
+            return Err(InconsistentQuorumError {
+                message: "revocation list doesn't match main document".into(),
+                groups: Grouping(groups.clone()),
+            });
+        }
+        let revoked = shards
+            .iter()
+            .filter_map(|shard| {
+                let id = shard.id();
+                self.revocation_lists
+                    .iter()
+                    .find_map(|list| list.reason(&id))
+                    .map(|reason| (id, reason))
+            })
+            .collect::<Vec<_>>();
+        if self.refuse_revoked && !revoked.is_empty() {
+            return Err(InconsistentQuorumError {
+                message: format!(
+                    "quorum contains revoked shard {} ({:?})",
+                    revoked[0].0, revoked[0].1
+                ),
+                groups: Grouping(groups.clone()),
+            });
+        }
+
+        // Superseded notices must form a chain of successors starting at the
+        // main document.
+        let successors =
+            SupersededNotice::chain(self.superseded_notices, &main_document).map_err(|err| {
+                InconsistentQuorumError {
+                    message: format!("superseded notices don't match main document: {}", err),
+                    groups: Grouping(groups.clone()),
+                }
+            })?;
+
         // TODO: Add a sanity-check for these values.
         let id_public_key = main_document.identity.id_public_key;
         let doc_chksum = main_document.checksum();
@@ -269,10 +508,30 @@ impl UntrustedQuorum {
             // grouping checks above would've caused an error.
             id_public_key,
             doc_chksum,
+            revoked,
+            successors,
+            unlocked_doc_key: OnceLock::new(),
         })
     }
 }
 
+// The document key of a time-locked backup, once the lock has been opened.
+#[derive(Clone)]
+struct UnlockedKey(ChaChaPolyKey);
+
+impl Drop for UnlockedKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+// Don't leak the document key through Debug.
+impl fmt::Debug for UnlockedKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("UnlockedKey")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Quorum {
     main_document: MainDocument,
@@ -280,50 +539,125 @@ pub struct Quorum {
     // Cached consensus information.
     id_public_key: PublicKey,
     doc_chksum: Multihash,
+    // Shards in the quorum which were revoked by a revocation list.
+    revoked: Vec<(ShardId, RevocationReason)>,
+    // Chain of notices that the backup has been superseded.
+    successors: Vec<SupersededNotice>,
+    // Opening a time lock is deliberately slow, so it is only done once.
+    unlocked_doc_key: OnceLock<UnlockedKey>,
 }
 
 impl Quorum {
     pub fn recover_document(&self) -> Result<Vec<u8>, String> {
-        let shards = self
-            .shards
-            .iter()
-            .map(|s| s.inner.shard.clone())
-            .collect::<Vec<_>>();
-        let secret = ShardSecret::from_wire(shamir::recover_secret(shards))?;
+        self.with_plaintext(|_, main, _, plaintext| {
+            // Strip any padding added before encryption.
+            let contents = match main.padded() {
+                false => plaintext,
+                true => decoy::unpad(plaintext)?.0,
+            };
+
+            // Undo any compression applied before encryption.
+            match main.compression() {
+                Compression::None => Ok(contents.to_vec()),
+                compression => compression.decompress(contents),
+            }
+        })
+    }
+
+    // Decrypt the main document and pass the plaintext (along with the shard
+    // secret and the AAD of the main document) to `f`. The plaintext is
+    // zeroed afterwards.
+    pub(super) fn with_plaintext<T, F>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&ShardSecret, &MainDocument, &[u8], &[u8]) -> Result<T, String>,
+    {
+        let mut secret = self.shard_secret()?;
+
+        // Open the time lock (if there is one) to unmask the document key.
+        if let Some(lock) = self.main_document.time_lock() {
+            let unlocked = self
+                .unlocked_doc_key
+                .get_or_init(|| UnlockedKey(lock.open(&secret.doc_key)));
+            secret.doc_key = unlocked.0;
+        }
 
         // Double-check that the private key agrees with the quorum's public key
         // choice.
-        if let Some(id_private_key) = secret.id_private_key {
-            if PublicKey::from(&id_private_key) != self.id_public_key {
+        if let Some(id_private_key) = &secret.id_private_key {
+            if PublicKey::from(id_private_key) != self.id_public_key {
                 return Err("private key doesn't match quorum public key")?;
             }
         }
 
         // Decrypt the contents.
-        let aead = ChaCha20Poly1305::new(secret.doc_key);
+        let aad = self.main_document.inner.meta.aad(&self.id_public_key);
         let payload = Payload {
             msg: &self.main_document.inner.ciphertext,
-            aad: &self.main_document.inner.meta.aad(&self.id_public_key),
+            aad: &aad,
         };
-        aead.decrypt(&self.main_document.inner.nonce, payload)
-            .map_err(|err| format!("{:?}", err)) // XXX: Ugly, fix this.
+        let mut plaintext = self.main_document.algorithm().decrypt(
+            &secret.doc_key,
+            &self.main_document.inner.nonce,
+            payload,
+        )?;
+
+        let result = f(&secret, &self.main_document, &aad, &plaintext);
+        plaintext.zeroize();
+        result
     }
 
-    pub fn extend_shards(&self, n: u32) -> Result<Vec<KeyShard>, String> {
-        let shards = self
-            .shards
-            .iter()
-            .map(|s| s.inner.shard.clone())
-            .collect::<Vec<_>>();
+    /// Recover a backup whose secret is a `Bundle` of several files.
+    pub fn recover_bundle(&self) -> Result<Bundle, String> {
+        let mut contents = self.recover_document()?;
+        let bundle = Bundle::from_wire(&contents).map_err(|err| err.to_string());
+        contents.zeroize();
+        bundle
+    }
 
-        // Conduct a complete recovery.
-        let dealer = Dealer::recover(shards);
-        let secret = ShardSecret::from_wire(dealer.secret())?;
+    /// The custodians recorded in the shards of the quorum, in the order the
+    /// shards were added.
+    pub fn custodians(&self) -> Vec<&Custodian> {
+        self.shards.iter().filter_map(KeyShard::custodian).collect()
+    }
 
-        // Get the private key so we can sign the new shards.
-        let id_private_key = secret
-            .id_private_key
-            .ok_or("document is sealed -- no new key shards allowed")?;
+    /// The watermarks embedded in the shards of the quorum, in the order the
+    /// shards were added. The owner of the backup can check them with
+    /// `Watermark::verify` to find out whose copies were used.
+    pub fn watermarks(&self) -> Vec<&Watermark> {
+        self.shards.iter().filter_map(KeyShard::watermark).collect()
+    }
+
+    /// Shards in the quorum which have been revoked (by a revocation list
+    /// passed to `UntrustedQuorum::revocation_list`), and why. Recovery still
+    /// works with revoked shards, but the user should be warned loudly since
+    /// a revoked shard may be in the hands of an attacker.
+    pub fn revoked_shards(&self) -> &[(ShardId, RevocationReason)] {
+        &self.revoked
+    }
+
+    /// The chain of notices (passed to `UntrustedQuorum::superseded_notice`)
+    /// that the backup has been superseded, starting with the notice for this
+    /// backup. If this isn't empty, the user should be told that a newer
+    /// backup (the `new_document_id` of the last notice) exists.
+    pub fn successors(&self) -> &[SupersededNotice] {
+        &self.successors
+    }
+
+    /// Whether the review-by date of the main document (or any of the
+    /// shards) has passed, in which case the backup should be re-tested and
+    /// possibly re-issued.
+    pub fn review_overdue(&self) -> bool {
+        self.main_document.metadata().review_overdue()
+            || self.shards.iter().any(KeyShard::review_overdue)
+    }
+
+    // Get the identity keypair from the recovered shard secret, making sure
+    // that it matches the quorum's public key.
+    pub(super) fn id_keypair(&self, secret: ShardSecret) -> Result<Option<Keypair>, String> {
+        let id_private_key = match secret.id_private_key {
+            Some(key) => key,
+            None => return Ok(None),
+        };
 
         // Make sure the private key matches the expected public key.
         let id_public_key = PublicKey::from(&id_private_key);
@@ -331,11 +665,64 @@ impl Quorum {
             return Err("id_secret_key doesn't match expected id_public_key")?;
         }
 
-        // Create the signing keypair.
-        let id_keypair = Keypair {
+        Ok(Some(Keypair {
             secret: id_private_key,
             public: id_public_key,
+        }))
+    }
+
+    // Recover the shard secret stored in the shards.
+    pub(super) fn shard_secret(&self) -> Result<ShardSecret, String> {
+        let shards = self
+            .shards
+            .iter()
+            .map(|s| s.inner.shard.clone())
+            .collect::<Vec<_>>();
+        let secret = match self.mac_key() {
+            Some(key) => shamir::recover_secret_with_key(shards, &key),
+            None => shamir::recover_secret(shards),
         };
+        ShardSecret::from_wire(secret.map_err(|err| err.to_string())?)
+            .map_err(|err| err.to_string())
+    }
+
+    // Key for the MACs of the shards, or None if none of them have one (key
+    // shards from older versions are only signed). If any shard has a MAC,
+    // every shard must have a valid one.
+    fn mac_key(&self) -> Option<MacKey> {
+        self.shards
+            .iter()
+            .any(|s| s.inner.shard.is_authenticated())
+            .then(|| shard_mac_key(&self.doc_chksum))
+    }
+
+    pub(super) fn main_document(&self) -> &MainDocument {
+        &self.main_document
+    }
+
+    pub(super) fn shards(&self) -> &[KeyShard] {
+        &self.shards
+    }
+
+    pub fn extend_shards(&self, n: u32) -> Result<Vec<KeyShard>, String> {
+        let shards = self
+            .shards
+            .iter()
+            .map(|s| s.inner.shard.clone())
+            .collect::<Vec<_>>();
+
+        // Conduct a complete recovery.
+        let mut dealer = match self.mac_key() {
+            Some(key) => Dealer::recover_with_key(shards, &key),
+            None => Dealer::recover(shards),
+        }
+        .map_err(|err| err.to_string())?;
+        let secret = ShardSecret::from_wire(dealer.secret()).map_err(|err| err.to_string())?;
+
+        // Get the private key so we can sign the new shards.
+        let id_keypair = self
+            .id_keypair(secret)?
+            .ok_or("document is sealed -- no new key shards allowed")?;
 
         // Extend new shards.
         Ok((0..n)
@@ -344,9 +731,69 @@ impl Quorum {
                     version: self.main_document.inner.meta.version,
                     doc_chksum: self.doc_chksum.clone(),
                     shard: dealer.next_shard(),
+                    review_by: self.main_document.metadata().review_by,
+                    custodian: None,
+                    watermark: None,
+                    legacy: false,
                 }
                 .sign(&id_keypair)
             })
             .collect::<Vec<_>>())
     }
 }
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "parallel")]
+    use super::*;
+    use crate::v0::{test::backup_quorum, Backup, BackupOptions, TimeLockParams};
+
+    // The batch verification must give the same results as verifying each
+    // document separately, whether or not some of them are forged.
+    #[quickcheck]
+    #[cfg(feature = "parallel")]
+    fn verify_all_matches_verify(secret: Vec<u8>, forged: Vec<bool>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let mut documents = vec![Untrusted::MainDocument(backup.main_document().clone())];
+        for _ in 1..forged.len() {
+            documents.push(Untrusted::KeyShard(backup.next_shard().unwrap()));
+        }
+        for (document, forged) in documents.iter_mut().zip(&forged) {
+            if *forged {
+                match document {
+                    Untrusted::MainDocument(main) => main.inner.meta.version += 1,
+                    Untrusted::KeyShard(shard) => shard.inner.version += 1,
+                }
+            }
+        }
+
+        let expected = documents
+            .iter()
+            .cloned()
+            .map(Untrusted::verify)
+            .collect::<Vec<_>>();
+        let all_genuine = !forged.iter().take(documents.len()).any(|f| *f);
+
+        Untrusted::verify_batch(&documents) == all_genuine
+            && Untrusted::verify_all(documents) == expected
+    }
+
+    #[test]
+    fn time_lock_opened_once() {
+        let options = BackupOptions {
+            time_lock: Some(TimeLockParams::new(1000)),
+            ..Default::default()
+        };
+        let mut backup = Backup::with_options(2, b"secret", options).unwrap();
+        let quorum = backup_quorum(&mut backup);
+
+        assert!(quorum.unlocked_doc_key.get().is_none());
+        assert_eq!(quorum.recover_document().unwrap(), b"secret");
+        assert!(quorum.unlocked_doc_key.get().is_some());
+        // The cached key is used from now on.
+        assert_eq!(quorum.recover_document().unwrap(), b"secret");
+        assert!(quorum
+            .recover_hidden_document("", Default::default())
+            .is_err());
+    }
+}
@@ -0,0 +1,282 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Signed lists of key shards which should no longer be honoured (because
+//! they were lost or stolen, or their custodian has left). Revocation lists
+//! are passed to `UntrustedQuorum::revocation_list` so that recovery can warn
+//! about (or refuse) revoked shards.
+
+use crate::v0::{
+    document_id, wire::prefixes::*, DocumentId, Identity, MainDocument, Quorum, ShardId, ToWire,
+    VerificationError,
+};
+
+use ed25519_dalek::{Keypair, PublicKey};
+use multihash::Multihash;
+use unsigned_varint::encode;
+
+/// Reasons why a key shard was revoked.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum RevocationReason {
+    /// No reason was given.
+    #[default]
+    Unspecified,
+    /// The shard was lost.
+    Lost,
+    /// The shard was stolen (or otherwise compromised).
+    Stolen,
+    /// The custodian of the shard is no longer trusted to hold it.
+    CustodianLeft,
+}
+
+impl RevocationReason {
+    /// All reasons.
+    pub const ALL: [RevocationReason; 4] = [
+        RevocationReason::Unspecified,
+        RevocationReason::Lost,
+        RevocationReason::Stolen,
+        RevocationReason::CustodianLeft,
+    ];
+
+    pub(crate) fn wire_id(self) -> u32 {
+        match self {
+            RevocationReason::Unspecified => 0,
+            RevocationReason::Lost => 1,
+            RevocationReason::Stolen => 2,
+            RevocationReason::CustodianLeft => 3,
+        }
+    }
+
+    pub(crate) fn from_wire_id(id: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|reason| reason.wire_id() == id)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct RevocationListBuilder {
+    pub(super) doc_chksum: Multihash,
+    pub(super) revoked: Vec<(ShardId, RevocationReason)>,
+}
+
+impl RevocationListBuilder {
+    fn signable_bytes(&self, id_public_key: &PublicKey) -> Vec<u8> {
+        let mut bytes = self.to_wire();
+
+        // Append the Ed25519 public key used for signing.
+        encode::u32(PREFIX_ED25519_PUB, &mut encode::u32_buffer())
+            .iter()
+            .chain(id_public_key.as_bytes())
+            .for_each(|b| bytes.push(*b));
+        bytes
+    }
+
+    pub(super) fn sign(self, id_keypair: &Keypair) -> RevocationList {
+        let bytes = self.signable_bytes(&id_keypair.public);
+        RevocationList {
+            inner: self,
+            identity: Identity {
+                id_public_key: id_keypair.public,
+                id_signature: id_keypair.sign(&bytes),
+            },
+        }
+    }
+}
+
+/// List of key shards of a backup which should no longer be honoured, signed
+/// with the identity key of the backup.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RevocationList {
+    pub(super) inner: RevocationListBuilder,
+    pub(super) identity: Identity,
+}
+
+impl RevocationList {
+    /// Maximum number of shards in a revocation list.
+    pub const MAX_SHARDS: usize = 1 << 16;
+
+    /// Identifier of the main document the revoked shards belong to.
+    pub fn document_id(&self) -> DocumentId {
+        document_id(&self.inner.doc_chksum)
+    }
+
+    /// The revoked shards, and why they were revoked.
+    pub fn revoked(&self) -> &[(ShardId, RevocationReason)] {
+        &self.inner.revoked
+    }
+
+    /// Returns why the shard with the given identifier was revoked, or `None`
+    /// if it hasn't been revoked.
+    pub fn reason(&self, shard_id: &str) -> Option<RevocationReason> {
+        self.inner
+            .revoked
+            .iter()
+            .find(|(id, _)| id == shard_id)
+            .map(|(_, reason)| *reason)
+    }
+
+    /// Verify that this list was signed by the owner of `main`.
+    pub fn verify(&self, main: &MainDocument) -> Result<(), VerificationError> {
+        if self.inner.doc_chksum != main.checksum() {
+            return Err(VerificationError::ChecksumMismatch);
+        }
+        let id_public_key = &self.identity.id_public_key;
+        if id_public_key != &main.identity.id_public_key {
+            return Err(VerificationError::PublicKeyMismatch);
+        }
+        id_public_key
+            .verify_strict(
+                &self.inner.signable_bytes(id_public_key),
+                &self.identity.id_signature,
+            )
+            .map_err(|_| VerificationError::InvalidSignature)
+    }
+}
+
+impl Quorum {
+    /// Create a revocation list for shards of the recovered backup. Sealed
+    /// backups have no identity key to sign the list with, so their shards
+    /// cannot be revoked.
+    pub fn revoke(
+        &self,
+        revoked: Vec<(ShardId, RevocationReason)>,
+    ) -> Result<RevocationList, String> {
+        let id_keypair = self
+            .id_keypair(self.shard_secret()?)?
+            .ok_or("document is sealed -- cannot sign revocation list")?;
+        Ok(RevocationListBuilder {
+            doc_chksum: self.main_document().checksum(),
+            revoked,
+        }
+        .sign(&id_keypair))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{Backup, KeyShard, UntrustedQuorum};
+
+    #[quickcheck]
+    fn revocation_list(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let main = backup.main_document().clone();
+        let shards = (0..3)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+        let list = backup.revoke(vec![(shards[2].id(), RevocationReason::Stolen)]);
+
+        // Shards can also be revoked after recovery.
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(main.clone());
+        quorum
+            .push_shard(shards[0].clone())
+            .push_shard(shards[1].clone());
+        let list2 = quorum
+            .validate()
+            .unwrap()
+            .revoke(vec![(shards[1].id(), RevocationReason::Lost)])
+            .unwrap();
+
+        list.verify(&main) == Ok(())
+            && list2.verify(&main) == Ok(())
+            && list.document_id() == main.id()
+            && list.reason(&shards[2].id()) == Some(RevocationReason::Stolen)
+            && list.reason(&shards[0].id()).is_none()
+            && list2.revoked() == &[(shards[1].id(), RevocationReason::Lost)][..]
+    }
+
+    #[quickcheck]
+    fn revocation_list_forged(main: MainDocument, shard: KeyShard) -> bool {
+        let backup = Backup::new(2, b"secret").unwrap();
+        let list = backup.revoke(vec![(shard.id(), RevocationReason::Unspecified)]);
+
+        // A list for a different document, or signed by a different key.
+        let mut forged = list.clone();
+        forged.inner.doc_chksum = main.checksum();
+
+        // A list whose signature doesn't cover its contents.
+        let mut tampered = list.clone();
+        tampered.inner.revoked.clear();
+
+        list.verify(&main) == Err(VerificationError::ChecksumMismatch)
+            && forged.verify(&main) == Err(VerificationError::PublicKeyMismatch)
+            && tampered.verify(backup.main_document()) == Err(VerificationError::InvalidSignature)
+    }
+
+    #[quickcheck]
+    fn quorum_revoked_shards(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let main = backup.main_document().clone();
+        let shards = (0..3)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+        let list = backup.revoke(vec![(shards[1].id(), RevocationReason::CustodianLeft)]);
+
+        let quorum = |shards: &[KeyShard], refuse: bool| {
+            let mut quorum = UntrustedQuorum::new();
+            quorum
+                .main_document(main.clone())
+                .revocation_list(list.clone())
+                .refuse_revoked(refuse);
+            for shard in shards {
+                quorum.push_shard(shard.clone());
+            }
+            quorum.validate()
+        };
+
+        // Revoked shards are reported, but recovery still works...
+        let warned = quorum(&shards[..2], false).unwrap();
+        // ... unless revoked shards are refused.
+        let refused = quorum(&shards[..2], true);
+        // Quorums without revoked shards are unaffected.
+        let clean = quorum(&[shards[0].clone(), shards[2].clone()], true).unwrap();
+
+        warned.revoked_shards() == &[(shards[1].id(), RevocationReason::CustodianLeft)][..]
+            && warned.recover_document().unwrap() == secret
+            && refused.is_err()
+            && clean.revoked_shards().is_empty()
+    }
+
+    #[quickcheck]
+    fn quorum_forged_revocation_list(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(1, &secret).unwrap();
+        let other = Backup::new(1, &secret).unwrap();
+        let shard = backup.next_shard().unwrap();
+
+        let mut quorum = UntrustedQuorum::new();
+        quorum
+            .main_document(backup.main_document().clone())
+            .push_shard(shard.clone())
+            .revocation_list(other.revoke(vec![(shard.id(), RevocationReason::Stolen)]));
+        quorum.validate().is_err()
+    }
+
+    #[test]
+    fn revoke_sealed_backup() {
+        let mut backup = Backup::new_sealed(1, b"secret").unwrap();
+        let mut quorum = UntrustedQuorum::new();
+        quorum
+            .main_document(backup.main_document().clone())
+            .push_shard(backup.next_shard().unwrap());
+        assert!(quorum.validate().unwrap().revoke(vec![]).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Signatures over documents which have been detached from them, so that the
+//! authenticity of a document can be checked by an auditor who holds only the
+//! document and the signature (and not any of the secret material needed to
+//! recover the backup).
+
+use crate::v0::{Identity, KeyShard, MainDocument};
+
+use multihash::Multihash;
+
+use std::{error, fmt};
+
+/// Kinds of document which can have a detached signature.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SignedKind {
+    /// A `MainDocument`.
+    MainDocument,
+    /// A (decrypted) `KeyShard`.
+    KeyShard,
+}
+
+impl SignedKind {
+    /// All kinds of signed document.
+    pub const ALL: [SignedKind; 2] = [SignedKind::MainDocument, SignedKind::KeyShard];
+
+    pub(crate) fn wire_id(self) -> u32 {
+        match self {
+            SignedKind::MainDocument => 0,
+            SignedKind::KeyShard => 1,
+        }
+    }
+
+    pub(crate) fn from_wire_id(id: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.wire_id() == id)
+    }
+}
+
+impl fmt::Display for SignedKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignedKind::MainDocument => write!(f, "main document"),
+            SignedKind::KeyShard => write!(f, "key shard"),
+        }
+    }
+}
+
+/// Reasons why a `DetachedSignature` does not match a document.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VerificationError {
+    /// The signature is over a different kind of document.
+    WrongKind {
+        expected: SignedKind,
+        found: SignedKind,
+    },
+    /// The signature is over a document with a different checksum.
+    ChecksumMismatch,
+    /// The signature was made with a different key to the one which signed
+    /// the document.
+    PublicKeyMismatch,
+    /// The signature is not a valid signature of the document.
+    InvalidSignature,
+    /// The document was signed by fewer distinct keys than required.
+    TooFewSignatures { required: u32, found: usize },
+    /// The key shard needs a different number of shards for recovery than
+    /// the main document.
+    QuorumSizeMismatch { expected: u32, found: u32 },
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerificationError::WrongKind { expected, found } => write!(
+                f,
+                "signature is over a {} rather than a {}",
+                found, expected
+            ),
+            VerificationError::ChecksumMismatch => {
+                write!(f, "signature is over a different document")
+            }
+            VerificationError::PublicKeyMismatch => {
+                write!(f, "signature was made with a different identity key")
+            }
+            VerificationError::InvalidSignature => write!(f, "signature is invalid"),
+            VerificationError::TooFewSignatures { required, found } => write!(
+                f,
+                "document has {} signatures but needs at least {}",
+                found, required
+            ),
+            VerificationError::QuorumSizeMismatch { expected, found } => write!(
+                f,
+                "key shard has a quorum size of {} rather than {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl error::Error for VerificationError {}
+
+/// The signature over a `MainDocument` or `KeyShard`, detached from the
+/// document it signs.
+///
+/// Verifying a detached signature only requires the signed document, so an
+/// auditor can confirm that a scanned document is authentic without running
+/// a recovery.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DetachedSignature {
+    pub(super) kind: SignedKind,
+    // Checksum of the main document which was signed (or which the signed key
+    // shard belongs to).
+    pub(super) doc_chksum: Multihash,
+    pub(super) identity: Identity,
+}
+
+impl DetachedSignature {
+    /// The kind of document which was signed.
+    pub fn kind(&self) -> SignedKind {
+        self.kind
+    }
+
+    /// Checksum of the main document which was signed, or which the signed
+    /// key shard belongs to.
+    pub fn checksum(&self) -> &Multihash {
+        &self.doc_chksum
+    }
+
+    /// The Ed25519 public key which made the signature.
+    pub fn public_key(&self) -> &[u8; ed25519_dalek::PUBLIC_KEY_LENGTH] {
+        self.identity.id_public_key.as_bytes()
+    }
+
+    fn check(
+        &self,
+        kind: SignedKind,
+        doc_chksum: &Multihash,
+        identity: &Identity,
+        signable_bytes: &[u8],
+    ) -> Result<(), VerificationError> {
+        if self.kind != kind {
+            return Err(VerificationError::WrongKind {
+                expected: kind,
+                found: self.kind,
+            });
+        }
+        if &self.doc_chksum != doc_chksum {
+            return Err(VerificationError::ChecksumMismatch);
+        }
+        if self.identity.id_public_key != identity.id_public_key {
+            return Err(VerificationError::PublicKeyMismatch);
+        }
+        self.identity
+            .id_public_key
+            .verify_strict(signable_bytes, &self.identity.id_signature)
+            .map_err(|_| VerificationError::InvalidSignature)
+    }
+
+    /// Verify that this is a valid signature of `main`.
+    pub fn verify_main_document(&self, main: &MainDocument) -> Result<(), VerificationError> {
+        let id_public_key = &self.identity.id_public_key;
+        self.check(
+            SignedKind::MainDocument,
+            &main.checksum(),
+            &main.identity,
+            &main.inner.signable_bytes(id_public_key),
+        )
+    }
+
+    /// Verify that this is a valid signature of `shard`.
+    pub fn verify_key_shard(&self, shard: &KeyShard) -> Result<(), VerificationError> {
+        let id_public_key = &self.identity.id_public_key;
+        self.check(
+            SignedKind::KeyShard,
+            &shard.inner.doc_chksum,
+            &shard.identity,
+            &shard.inner.signable_bytes(id_public_key),
+        )
+    }
+}
+
+impl MainDocument {
+    /// Export the signature over this document, so that it can be verified
+    /// separately with `DetachedSignature::verify_main_document`.
+    pub fn detached_signature(&self) -> DetachedSignature {
+        DetachedSignature {
+            kind: SignedKind::MainDocument,
+            doc_chksum: self.checksum(),
+            identity: self.identity.clone(),
+        }
+    }
+
+    /// Verify that `shard` is an authentic shard of this (authentic) main
+    /// document, without running a recovery. This lets a user who has
+    /// scanned a main document and a shard confirm that they belong together
+    /// straight away, rather than finding out during recovery.
+    pub fn verify_shard(&self, shard: &KeyShard) -> Result<(), VerificationError> {
+        self.detached_signature().verify_main_document(self)?;
+        if shard.inner.doc_chksum != self.checksum() {
+            return Err(VerificationError::ChecksumMismatch);
+        }
+        if shard.identity.id_public_key != self.identity.id_public_key {
+            return Err(VerificationError::PublicKeyMismatch);
+        }
+        shard.detached_signature().verify_key_shard(shard)?;
+        let found = shard.inner.shard.threshold();
+        if found != self.quorum_size() {
+            return Err(VerificationError::QuorumSizeMismatch {
+                expected: self.quorum_size(),
+                found,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl KeyShard {
+    /// Export the signature over this shard, so that it can be verified
+    /// separately with `DetachedSignature::verify_key_shard`.
+    pub fn detached_signature(&self) -> DetachedSignature {
+        DetachedSignature {
+            kind: SignedKind::KeyShard,
+            doc_chksum: self.inner.doc_chksum.clone(),
+            identity: self.identity.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::Backup;
+
+    #[quickcheck]
+    fn detached_main_document(main: MainDocument, other: MainDocument) -> bool {
+        let signature = main.detached_signature();
+        signature.verify_main_document(&main) == Ok(())
+            && signature.verify_main_document(&other) == Err(VerificationError::ChecksumMismatch)
+    }
+
+    #[quickcheck]
+    fn detached_backup(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let main = backup.main_document().clone();
+        let shard = backup.next_shard().unwrap();
+        let other = Backup::new(2, &secret).unwrap().next_shard().unwrap();
+
+        let main_signature = main.detached_signature();
+        let shard_signature = shard.detached_signature();
+        main_signature.verify_main_document(&main) == Ok(())
+            && shard_signature.verify_key_shard(&shard) == Ok(())
+            && main_signature.checksum() == shard_signature.checksum()
+            && main_signature.public_key() == shard_signature.public_key()
+            && main_signature.verify_key_shard(&shard)
+                == Err(VerificationError::WrongKind {
+                    expected: SignedKind::KeyShard,
+                    found: SignedKind::MainDocument,
+                })
+            && shard_signature.verify_key_shard(&other) == Err(VerificationError::ChecksumMismatch)
+    }
+
+    #[quickcheck]
+    fn main_document_verify_shard(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let main = backup.main_document().clone();
+        let shard = backup.next_shard().unwrap();
+        let other = Backup::new(2, &secret).unwrap();
+
+        // A shard whose signature doesn't cover its contents.
+        let mut forged = shard.clone();
+        forged.identity.id_signature = backup.next_shard().unwrap().identity.id_signature;
+
+        // A main document whose signature doesn't cover its contents.
+        let mut forged_main = main.clone();
+        forged_main.identity.id_signature = other.main_document().identity.id_signature;
+
+        main.verify_shard(&shard) == Ok(())
+            && main.verify_shard(&forged) == Err(VerificationError::InvalidSignature)
+            && forged_main.verify_shard(&shard) == Err(VerificationError::InvalidSignature)
+            && other.main_document().verify_shard(&shard)
+                == Err(VerificationError::ChecksumMismatch)
+    }
+
+    #[quickcheck]
+    fn detached_forged_signature(main: MainDocument, other: MainDocument) -> bool {
+        // A signature from the right key over the wrong contents.
+        let mut signature = main.detached_signature();
+        signature.identity.id_signature = other.identity.id_signature;
+        signature.verify_main_document(&main) == Err(VerificationError::InvalidSignature)
+    }
+
+    #[quickcheck]
+    fn detached_wrong_key(main: MainDocument, other: MainDocument) -> bool {
+        let mut signature = main.detached_signature();
+        signature.identity = other.identity;
+        signature.verify_main_document(&main) == Err(VerificationError::PublicKeyMismatch)
+    }
+}
@@ -0,0 +1,356 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Streaming backups of secrets which are too large to hold in memory (such as
+//! disk images). The secret is read from an `io::Read` and encrypted one chunk
+//! at a time with a fresh key, and the ciphertext is written to an `io::Write`
+//! to be stored digitally. The key is then backed up like any other secret, so
+//! the main document and shards stay small enough to print.
+//!
+//! Chunks are encrypted using the [STREAM][stream] construction -- the nonce
+//! of each chunk includes its index and whether it is the last chunk, so the
+//! chunks of a stream cannot be reordered, dropped or truncated without it
+//! being detected.
+//!
+//! [stream]: https://eprint.iacr.org/2015/189.pdf
+
+use crate::v0::{AeadAlgorithm, Backup, BackupOptions, ChaChaPolyKey, FromWire, Quorum, ToWire};
+
+use std::io::{self, Read, Write};
+
+use aead::Payload;
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroize;
+
+/// Default number of bytes of the secret which are encrypted at a time by
+/// `Backup::new_stream`.
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+// Length of the authentication tag appended to each chunk.
+const TAG_LENGTH: usize = 16;
+
+/// Key of a streamed secret, which is the secret stored in the main document
+/// of a streamed backup.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct StreamKey {
+    pub(super) algorithm: AeadAlgorithm,
+    pub(super) chunk_size: usize,
+    pub(super) key: ChaChaPolyKey,
+    // Each chunk's nonce is this nonce with the chunk index and last-chunk
+    // flag XOR-ed into the last five bytes.
+    pub(super) nonce: Vec<u8>,
+}
+
+impl Drop for StreamKey {
+    fn drop(&mut self) {
+        self.key.as_mut_slice().zeroize();
+    }
+}
+
+impl StreamKey {
+    /// Maximum number of bytes of the secret in each chunk.
+    pub(super) const MAX_CHUNK_SIZE: usize = 1 << 24;
+
+    fn chunk_nonce(&self, index: u32, last: bool) -> Vec<u8> {
+        let mut nonce = self.nonce.clone();
+        let flag = nonce.len() - 1;
+        nonce[flag - 4..flag]
+            .iter_mut()
+            .zip(&index.to_be_bytes())
+            .for_each(|(b, i)| *b ^= i);
+        nonce[flag] ^= last as u8;
+        nonce
+    }
+
+    // Encrypt everything read from reader, writing the chunks to writer.
+    fn encrypt<R, W>(&self, reader: &mut R, writer: &mut W) -> Result<u64, String>
+    where
+        R: Read + ?Sized,
+        W: Write + ?Sized,
+    {
+        let mut current = vec![0u8; self.chunk_size];
+        let mut next = vec![0u8; self.chunk_size];
+        let result = (|| {
+            let mut written = 0u64;
+            let mut length = read_chunk(reader, &mut current)?;
+            for index in 0u32.. {
+                // A stream always has at least one (possibly empty) chunk, and
+                // the last chunk is the one followed by EOF.
+                let next_length = match length {
+                    length if length == self.chunk_size => read_chunk(reader, &mut next)?,
+                    _ => 0,
+                };
+                let last = next_length == 0;
+                let payload = Payload {
+                    msg: &current[..length],
+                    aad: &[],
+                };
+                let ciphertext =
+                    self.algorithm
+                        .encrypt(&self.key, &self.chunk_nonce(index, last), payload)?;
+                writer
+                    .write_all(&ciphertext)
+                    .map_err(|err| err.to_string())?;
+                written += ciphertext.len() as u64;
+                if last {
+                    return Ok(written);
+                }
+                std::mem::swap(&mut current, &mut next);
+                length = next_length;
+            }
+            Err("secret is too large to be streamed".into())
+        })();
+        current.zeroize();
+        next.zeroize();
+        result
+    }
+
+    // Decrypt the chunks read from reader, writing the plaintext to writer.
+    fn decrypt<R, W>(&self, reader: &mut R, writer: &mut W) -> Result<u64, String>
+    where
+        R: Read + ?Sized,
+        W: Write + ?Sized,
+    {
+        let chunk_len = self.chunk_size + TAG_LENGTH;
+        let mut current = vec![0u8; chunk_len];
+        let mut next = vec![0u8; chunk_len];
+        let mut written = 0u64;
+        let mut length = read_chunk(reader, &mut current)?;
+        for index in 0u32.. {
+            let next_length = match length {
+                length if length == chunk_len => read_chunk(reader, &mut next)?,
+                _ => 0,
+            };
+            let last = next_length == 0;
+            let payload = Payload {
+                msg: &current[..length],
+                aad: &[],
+            };
+            let mut plaintext = self
+                .algorithm
+                .decrypt(&self.key, &self.chunk_nonce(index, last), payload)
+                .map_err(|_| "streamed ciphertext is corrupted or truncated")?;
+            let result = writer.write_all(&plaintext);
+            written += plaintext.len() as u64;
+            plaintext.zeroize();
+            result.map_err(|err| err.to_string())?;
+            if last {
+                return Ok(written);
+            }
+            std::mem::swap(&mut current, &mut next);
+            length = next_length;
+        }
+        Err("streamed ciphertext has too many chunks".into())
+    }
+}
+
+// Fill buf from reader (stopping early only at EOF), returning the number of
+// bytes read.
+fn read_chunk<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<usize, String> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.to_string()),
+        }
+    }
+    Ok(filled)
+}
+
+impl Backup {
+    /// Like `new`, but the secret is read from `reader` and encrypted in
+    /// chunks of `DEFAULT_CHUNK_SIZE` bytes (so only two chunks are ever held
+    /// in memory), with the ciphertext written to `writer`. Only the key of
+    /// the ciphertext is stored in the main document, so the ciphertext must
+    /// be kept (digitally) alongside the backup -- see
+    /// `Quorum::recover_stream`.
+    pub fn new_stream<R, W>(
+        quorum_size: u32,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<Self, String>
+    where
+        R: Read + ?Sized,
+        W: Write + ?Sized,
+    {
+        Self::stream_with_options(
+            quorum_size,
+            reader,
+            writer,
+            DEFAULT_CHUNK_SIZE,
+            BackupOptions::default(),
+        )
+    }
+
+    /// Like `new_stream`, but with the given chunk size and options. The
+    /// options apply to the main document, and the ciphertext is encrypted
+    /// with `options.algorithm` but is never compressed or padded.
+    /// Deterministic backups cannot be streamed.
+    pub fn stream_with_options<R, W>(
+        quorum_size: u32,
+        reader: &mut R,
+        writer: &mut W,
+        chunk_size: usize,
+        options: BackupOptions,
+    ) -> Result<Self, String>
+    where
+        R: Read + ?Sized,
+        W: Write + ?Sized,
+    {
+        if options.deterministic {
+            return Err("deterministic backups cannot be streamed".into());
+        }
+        if chunk_size == 0 || chunk_size > StreamKey::MAX_CHUNK_SIZE {
+            return Err(format!(
+                "chunk size must be between 1 and {} bytes",
+                StreamKey::MAX_CHUNK_SIZE
+            ));
+        }
+
+        let mut key = StreamKey {
+            algorithm: options.algorithm,
+            chunk_size,
+            key: ChaChaPolyKey::default(),
+            nonce: options.algorithm.generate_nonce(),
+        };
+        OsRng.fill_bytes(&mut key.key);
+        key.encrypt(reader, writer)?;
+
+        let mut secret = key.to_wire();
+        let backup = Self::with_options(quorum_size, &secret, options);
+        secret.zeroize();
+        backup
+    }
+}
+
+impl Quorum {
+    /// Recover a streamed backup (see `Backup::new_stream`), decrypting the
+    /// ciphertext read from `reader` and writing the secret to `writer`.
+    /// Returns the length of the secret.
+    ///
+    /// Each chunk is authenticated before it is written, but if the
+    /// ciphertext is corrupted (or truncated) the chunks before the damage
+    /// will already have been written when the error is returned.
+    pub fn recover_stream<R, W>(&self, reader: &mut R, writer: &mut W) -> Result<u64, String>
+    where
+        R: Read + ?Sized,
+        W: Write + ?Sized,
+    {
+        let mut contents = self.recover_document()?;
+        let key = StreamKey::from_wire(&contents).map_err(|err| err.to_string());
+        contents.zeroize();
+        key?.decrypt(reader, writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::test::quorum;
+
+    use quickcheck::TestResult;
+
+    fn stream_backup(
+        secret: &[u8],
+        chunk_size: usize,
+        algorithm: AeadAlgorithm,
+    ) -> (Vec<u8>, Quorum) {
+        let mut ciphertext = vec![];
+        let options = BackupOptions {
+            algorithm,
+            ..Default::default()
+        };
+        let mut backup =
+            Backup::stream_with_options(2, &mut &secret[..], &mut ciphertext, chunk_size, options)
+                .unwrap();
+        let main = backup.main_document().clone();
+        let shards = (0..2)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+        (ciphertext, quorum(&main, &shards))
+    }
+
+    #[quickcheck]
+    fn stream_roundtrip(secret: Vec<u8>, chunk_size: u8, algorithm: u8) -> bool {
+        let chunk_size = chunk_size as usize + 1;
+        let algorithm = AeadAlgorithm::ALL[algorithm as usize % AeadAlgorithm::ALL.len()];
+        let (ciphertext, quorum) = stream_backup(&secret, chunk_size, algorithm);
+
+        let chunks = secret.len().div_ceil(chunk_size).max(1);
+        let mut recovered = vec![];
+        ciphertext.len() == secret.len() + chunks * TAG_LENGTH
+            && quorum.recover_stream(&mut &ciphertext[..], &mut recovered)
+                == Ok(secret.len() as u64)
+            && recovered == secret
+    }
+
+    #[quickcheck]
+    fn stream_damaged(secret: Vec<u8>, chunk_size: u8, cut: usize) -> TestResult {
+        let chunk_size = chunk_size as usize + 1;
+        let (ciphertext, quorum) =
+            stream_backup(&secret, chunk_size, AeadAlgorithm::ChaCha20Poly1305);
+
+        // Truncation anywhere (including at a chunk boundary) is detected.
+        let truncated = &ciphertext[..cut % ciphertext.len()];
+        // As is dropping a whole chunk from the middle.
+        let chunk_len = chunk_size + TAG_LENGTH;
+        let mut dropped = ciphertext.clone();
+        if dropped.len() > 2 * chunk_len {
+            dropped.drain(..chunk_len);
+        } else {
+            dropped.push(0);
+        }
+        let mut flipped = ciphertext.clone();
+        let flip = cut % flipped.len();
+        flipped[flip] ^= 1;
+
+        TestResult::from_bool(
+            quorum
+                .recover_stream(&mut &truncated[..], &mut vec![])
+                .is_err()
+                && quorum
+                    .recover_stream(&mut &dropped[..], &mut vec![])
+                    .is_err()
+                && quorum
+                    .recover_stream(&mut &flipped[..], &mut vec![])
+                    .is_err(),
+        )
+    }
+
+    #[test]
+    fn stream_invalid_options() {
+        let options = BackupOptions {
+            deterministic: true,
+            ..Default::default()
+        };
+        assert!(
+            Backup::stream_with_options(2, &mut &b"secret"[..], &mut vec![], 16, options).is_err()
+        );
+        assert!(Backup::stream_with_options(
+            2,
+            &mut &b"secret"[..],
+            &mut vec![],
+            0,
+            Default::default()
+        )
+        .is_err());
+    }
+}
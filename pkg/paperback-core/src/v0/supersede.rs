@@ -0,0 +1,383 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Rotation of a backup to a fresh document key and a new set of shards. The
+//! owner of the old backup signs a notice that it has been superseded by the
+//! new one, so that custodians who still hold shards of the old backup can
+//! tell that those shards have been retired.
+//!
+//! Notices are small enough to be printed as a slip (see `bech32` and
+//! `armor`) and kept alongside the old shards, which can verify them. Each
+//! notice also records the identity key of the new backup, so that a chain of
+//! notices (from backups which were rotated several times) can be verified
+//! without the intermediate main documents. `UntrustedQuorum::superseded_notice`
+//! uses this to tell, during recovery, that a newer backup exists.
+
+use crate::v0::{
+    document_id, now, wire::prefixes::*, Backup, BackupOptions, DocumentId, Identity, KeyShard,
+    MainDocument, Quorum, TimeLock, ToWire, VerificationError,
+};
+
+use ed25519_dalek::{Keypair, PublicKey};
+use multihash::Multihash;
+use unsigned_varint::encode;
+use zeroize::Zeroize;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct SupersededNoticeBuilder {
+    pub(super) old_doc_chksum: Multihash,
+    pub(super) new_doc_chksum: Multihash,
+    pub(super) superseded_at: Option<u64>,
+    pub(super) new_id_public_key: Option<PublicKey>,
+}
+
+impl SupersededNoticeBuilder {
+    fn signable_bytes(&self, id_public_key: &PublicKey) -> Vec<u8> {
+        let mut bytes = self.to_wire();
+
+        // Append the Ed25519 public key used for signing.
+        encode::u32(PREFIX_ED25519_PUB, &mut encode::u32_buffer())
+            .iter()
+            .chain(id_public_key.as_bytes())
+            .for_each(|b| bytes.push(*b));
+        bytes
+    }
+
+    fn sign(self, id_keypair: &Keypair) -> SupersededNotice {
+        let bytes = self.signable_bytes(&id_keypair.public);
+        SupersededNotice {
+            inner: self,
+            identity: Identity {
+                id_public_key: id_keypair.public,
+                id_signature: id_keypair.sign(&bytes),
+            },
+        }
+    }
+}
+
+/// Notice (signed with the identity key of the old backup) that a backup has
+/// been superseded by a new one, created by `Quorum::rotate`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SupersededNotice {
+    pub(super) inner: SupersededNoticeBuilder,
+    pub(super) identity: Identity,
+}
+
+impl SupersededNotice {
+    /// Identifier of the main document which has been superseded.
+    pub fn old_document_id(&self) -> DocumentId {
+        document_id(&self.inner.old_doc_chksum)
+    }
+
+    /// Identifier of the main document which replaces it.
+    pub fn new_document_id(&self) -> DocumentId {
+        document_id(&self.inner.new_doc_chksum)
+    }
+
+    /// When the old backup was superseded (in seconds since the Unix epoch),
+    /// if recorded.
+    pub fn superseded_at(&self) -> Option<u64> {
+        self.inner.superseded_at
+    }
+
+    /// Verify that this notice was signed by the owner of `old`, and that it
+    /// states that `old` was superseded by `new`.
+    pub fn verify(&self, old: &MainDocument, new: &MainDocument) -> Result<(), VerificationError> {
+        if self.inner.old_doc_chksum != old.checksum()
+            || self.inner.new_doc_chksum != new.checksum()
+        {
+            return Err(VerificationError::ChecksumMismatch);
+        }
+        if let Some(new_id_public_key) = &self.inner.new_id_public_key {
+            if new_id_public_key != &new.identity.id_public_key {
+                return Err(VerificationError::PublicKeyMismatch);
+            }
+        }
+        self.verify_signature(&old.identity.id_public_key)
+    }
+
+    /// Verify that this notice was signed by the owner of the backup `shard`
+    /// belongs to (and so that `shard` has been superseded), without needing
+    /// the main document.
+    pub fn verify_shard(&self, shard: &KeyShard) -> Result<(), VerificationError> {
+        if self.inner.old_doc_chksum != shard.inner.doc_chksum {
+            return Err(VerificationError::ChecksumMismatch);
+        }
+        self.verify_signature(&shard.identity.id_public_key)
+    }
+
+    // Verify that this notice was signed by `id_public_key`.
+    fn verify_signature(&self, id_public_key: &PublicKey) -> Result<(), VerificationError> {
+        if id_public_key != &self.identity.id_public_key {
+            return Err(VerificationError::PublicKeyMismatch);
+        }
+        id_public_key
+            .verify_strict(
+                &self.inner.signable_bytes(id_public_key),
+                &self.identity.id_signature,
+            )
+            .map_err(|_| VerificationError::InvalidSignature)
+    }
+
+    // Order the notices into a chain of successors of `main`, verifying each
+    // notice with the identity key of the backup it supersedes. All of the
+    // notices must be part of the chain.
+    pub(super) fn chain(
+        mut notices: Vec<SupersededNotice>,
+        main: &MainDocument,
+    ) -> Result<Vec<SupersededNotice>, VerificationError> {
+        let mut chain = Vec::with_capacity(notices.len());
+        let mut doc_chksum = main.checksum();
+        let mut id_public_key = Some(main.identity.id_public_key);
+        while let Some(idx) = notices
+            .iter()
+            .position(|notice| notice.inner.old_doc_chksum == doc_chksum)
+        {
+            let notice = notices.swap_remove(idx);
+            // Notices which don't record the successor's key can only end a
+            // chain, since the next notice can't be verified.
+            let key = id_public_key.ok_or(VerificationError::PublicKeyMismatch)?;
+            notice.verify_signature(&key)?;
+            doc_chksum = notice.inner.new_doc_chksum.clone();
+            id_public_key = notice.inner.new_id_public_key;
+            chain.push(notice);
+        }
+        match notices.is_empty() {
+            true => Ok(chain),
+            false => Err(VerificationError::ChecksumMismatch),
+        }
+    }
+}
+
+impl Quorum {
+    /// Rotate the backup to a fresh document key, without the secret having
+    /// to be re-entered.
+    ///
+    /// The secret is recovered and backed up again (with the same quorum size)
+    /// as a new `Backup`, from which a new set of shards can be created. Apart
+    /// from the quorum size, nothing is carried over from the old backup: the
+    /// new one is created with exactly the given `options` (so a time lock, for
+    /// instance, has to be requested again). The
+    /// returned notice states that the old main document has been superseded
+    /// by the new one, and is signed with the identity key of the old backup
+    /// (so sealed backups cannot be rotated).
+    pub fn rotate(&self, options: BackupOptions) -> Result<(Backup, SupersededNotice), String> {
+        let id_keypair = self
+            .id_keypair(self.shard_secret()?)?
+            .ok_or("document is sealed -- cannot sign notice of its rotation")?;
+
+        let old = self.main_document();
+        let mut secret = self.recover_document()?;
+        let backup = Backup::with_options(old.quorum_size(), &secret, options);
+        secret.zeroize();
+        let backup = backup?;
+
+        let new = backup.main_document();
+        let notice = SupersededNoticeBuilder {
+            old_doc_chksum: old.checksum(),
+            new_doc_chksum: new.checksum(),
+            superseded_at: now(),
+            new_id_public_key: Some(new.identity.id_public_key),
+        }
+        .sign(&id_keypair);
+
+        Ok((backup, notice))
+    }
+
+    /// Re-seal an unsealed backup, so that no new shards can be created for
+    /// it once it has been distributed.
+    ///
+    /// Since every shard of the old backup contains the identity private key,
+    /// the backup is rotated (see `rotate`) to a new sealed backup with the
+    /// same quorum size, compression, algorithm, metadata, page chunk size
+    /// (see `BackupOptions::chunk_size`) and time lock (with the same number of
+    /// iterations, see `TimeLock::params`). The old shards
+    /// should be destroyed once the new ones have been distributed. Padding is
+    /// not preserved (so the hidden secret of a decoy backup is lost -- create
+    /// a new decoy backup with `Backup::with_decoy` instead). A sealed
+    /// backup can never be unsealed, and so re-sealing an already sealed
+    /// backup is an error.
+    pub fn reseal(&self) -> Result<(Backup, SupersededNotice), String> {
+        if self.id_keypair(self.shard_secret()?)?.is_none() {
+            return Err("document is already sealed".into());
+        }
+
+        let old = self.main_document();
+        self.rotate(BackupOptions {
+            sealed: true,
+            compression: old.compression(),
+            algorithm: old.algorithm(),
+            metadata: old.metadata().clone(),
+            chunk_size: old.chunk_size(),
+            time_lock: old.time_lock().map(TimeLock::params),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{
+        test::backup_quorum, MainDocumentPage, Metadata, TimeLockParams, UntrustedQuorum,
+    };
+
+    #[quickcheck]
+    fn rotate_backup(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let old = backup.main_document().clone();
+        let (mut rotated, notice) = backup_quorum(&mut backup)
+            .rotate(Default::default())
+            .unwrap();
+        let new = rotated.main_document().clone();
+
+        notice.old_document_id() == old.id()
+            && notice.new_document_id() == new.id()
+            && notice.verify(&old, &new) == Ok(())
+            && notice.verify(&new, &old) == Err(VerificationError::ChecksumMismatch)
+            && new.quorum_size() == old.quorum_size()
+            && backup_quorum(&mut rotated).recover_document().as_ref() == Ok(&secret)
+    }
+
+    #[quickcheck]
+    fn rotate_forged_notice(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let old = backup.main_document().clone();
+        let (rotated, notice) = backup_quorum(&mut backup)
+            .rotate(Default::default())
+            .unwrap();
+        let new = rotated.main_document().clone();
+
+        // A notice signed by anyone other than the owner of the old backup.
+        let (_, mut forged) = backup_quorum(&mut Backup::new(2, &secret).unwrap())
+            .rotate(Default::default())
+            .unwrap();
+        forged.inner = notice.inner.clone();
+
+        // A notice whose signature doesn't cover its contents.
+        let mut tampered = notice.clone();
+        tampered.identity.id_signature = forged.identity.id_signature;
+
+        forged.verify(&old, &new) == Err(VerificationError::PublicKeyMismatch)
+            && tampered.verify(&old, &new) == Err(VerificationError::InvalidSignature)
+    }
+
+    #[quickcheck]
+    fn superseded_shard(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let shard = backup.next_shard().unwrap();
+        let (mut rotated, notice) = backup_quorum(&mut backup)
+            .rotate(Default::default())
+            .unwrap();
+
+        notice.superseded_at().is_some()
+            && notice.verify_shard(&shard) == Ok(())
+            && notice.verify_shard(&rotated.next_shard().unwrap())
+                == Err(VerificationError::ChecksumMismatch)
+    }
+
+    #[quickcheck]
+    fn superseded_chain(secret: Vec<u8>) -> bool {
+        let mut backup = Backup::new(1, &secret).unwrap();
+        let shard = backup.next_shard().unwrap();
+        let (mut second, notice1) = backup_quorum(&mut backup)
+            .rotate(Default::default())
+            .unwrap();
+        let (_, notice2) = backup_quorum(&mut second)
+            .rotate(Default::default())
+            .unwrap();
+        let (_, unrelated) = backup_quorum(&mut Backup::new(1, &secret).unwrap())
+            .rotate(Default::default())
+            .unwrap();
+
+        let successors = |notices: &[&SupersededNotice]| {
+            let mut quorum = UntrustedQuorum::new();
+            quorum
+                .main_document(backup.main_document().clone())
+                .push_shard(shard.clone());
+            for notice in notices {
+                quorum.superseded_notice((*notice).clone());
+            }
+            quorum.validate().map(|quorum| quorum.successors().to_vec())
+        };
+
+        // The chain is found regardless of the order of the notices.
+        successors(&[]).unwrap().is_empty()
+            && successors(&[&notice2, &notice1]).unwrap() == vec![notice1.clone(), notice2.clone()]
+            // A notice which isn't part of the chain is an error.
+            && successors(&[&notice2]).is_err()
+            && successors(&[&notice1, &unrelated]).is_err()
+    }
+
+    #[test]
+    fn rotate_sealed_backup() {
+        let mut backup = Backup::new_sealed(2, b"secret").unwrap();
+        assert!(backup_quorum(&mut backup)
+            .rotate(Default::default())
+            .is_err());
+    }
+
+    #[quickcheck]
+    fn reseal_backup(secret: Vec<u8>, metadata: Metadata) -> bool {
+        let options = BackupOptions {
+            metadata,
+            chunk_size: Some(MainDocumentPage::MIN_CHUNK_SIZE * 4),
+            ..Default::default()
+        };
+        let mut backup = Backup::with_options(2, &secret, options).unwrap();
+        let old = backup.main_document().clone();
+        let (mut sealed, notice) = backup_quorum(&mut backup).reseal().unwrap();
+        let new = sealed.main_document().clone();
+        let sealed = backup_quorum(&mut sealed);
+
+        notice.verify(&old, &new) == Ok(())
+            && new.quorum_size() == old.quorum_size()
+            && new.metadata() == old.metadata()
+            && new.chunk_size() == old.chunk_size()
+            && sealed.recover_document().as_ref() == Ok(&secret)
+            && sealed.extend_shards(1).is_err()
+            // Sealed backups cannot be unsealed (or re-sealed).
+            && sealed.reseal().is_err()
+            && sealed.rotate(Default::default()).is_err()
+    }
+
+    #[test]
+    fn reseal_time_locked_backup() {
+        let options = BackupOptions {
+            time_lock: Some(TimeLockParams {
+                iterations: 1000,
+                chains: 4,
+            }),
+            ..Default::default()
+        };
+        let mut backup = Backup::with_options(2, b"secret", options).unwrap();
+        let (mut sealed, _) = backup_quorum(&mut backup).reseal().unwrap();
+
+        // The delay protecting the backup is not removed by resealing it.
+        let lock = sealed.main_document().time_lock().map(TimeLock::params);
+        assert_eq!(
+            lock,
+            backup.main_document().time_lock().map(TimeLock::params)
+        );
+        assert_eq!(
+            backup_quorum(&mut sealed).recover_document().unwrap(),
+            b"secret"
+        );
+    }
+}
@@ -0,0 +1,445 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Time-locked recovery. The document key stored in the key shards of a
+//! time-locked backup is masked with the output of a sequential hash-chain
+//! puzzle stored in the main document, so that even a complete quorum has to
+//! spend a fixed amount of (wall-clock) computation before the secret can be
+//! recovered. This is intended as a deterrent against coerced recovery, not
+//! as a precise clock.
+//!
+//! The puzzle is the concatenation of several hash chains, where each chain
+//! starts from a seed encrypted with the output of the previous chain. Solving
+//! it requires computing the chains one after another, but whoever creates
+//! the puzzle knows every seed up-front and can compute the chains
+//! concurrently, so creating a lock is cheaper than solving it by a factor of
+//! (up to) the number of chains.
+//!
+//! The seed of the first chain is encrypted with a key derived from the
+//! (masked) document key stored in the shards, so the puzzle can only be
+//! started once a quorum has been assembled -- otherwise anyone holding the
+//! main document could solve it ahead of time.
+
+use crate::v0::ChaChaPolyKey;
+
+use std::{
+    num::NonZeroUsize,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroize;
+
+const TIME_LOCK_PERSONAL: &[u8] = b"paperback-tlock";
+const TIME_LOCK_SEED_PERSONAL: &[u8] = b"paperback-tlseed";
+
+/// Length (in bytes) of the seeds and outputs of the hash chains.
+pub(super) const TIME_LOCK_LENGTH: usize = 32;
+
+type ChainValue = [u8; TIME_LOCK_LENGTH];
+
+// Apply the hash function iterations times to state.
+fn iterate(state: &mut ChainValue, iterations: u64) {
+    let params = blake2b_simd::Params::new()
+        .hash_length(TIME_LOCK_LENGTH)
+        .personal(TIME_LOCK_PERSONAL)
+        .clone();
+    for _ in 0..iterations {
+        let hash = params.hash(&state[..]);
+        state.copy_from_slice(hash.as_bytes());
+    }
+}
+
+// Key used to encrypt the seed of the first chain, derived from the masked
+// document key stored in the shards.
+fn seed_key(shard_doc_key: &ChaChaPolyKey) -> ChainValue {
+    let hash = blake2b_simd::Params::new()
+        .hash_length(TIME_LOCK_LENGTH)
+        .key(shard_doc_key)
+        .personal(TIME_LOCK_SEED_PERSONAL)
+        .hash(&[]);
+    let mut key = [0u8; TIME_LOCK_LENGTH];
+    key.copy_from_slice(hash.as_bytes());
+    key
+}
+
+// Number of threads the hash chains can be computed on concurrently.
+fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+// Measure how many iterations of the hash chain this machine computes per
+// second.
+fn hash_rate() -> f64 {
+    const SAMPLE_ITERATIONS: u64 = 1 << 16;
+    let mut state = [0u8; TIME_LOCK_LENGTH];
+    let start = Instant::now();
+    iterate(&mut state, SAMPLE_ITERATIONS);
+    SAMPLE_ITERATIONS as f64 / start.elapsed().as_secs_f64().max(1e-9)
+}
+
+/// Cost parameters of a time lock (see `BackupOptions::time_lock`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TimeLockParams {
+    /// Total number of hash iterations needed to open the lock, all of which
+    /// have to be computed sequentially.
+    pub iterations: u64,
+    /// Number of hash chains the iterations are split between. The chains
+    /// are computed concurrently when the lock is created, so this should be
+    /// (at most) the number of cores available. Must be at least 1.
+    pub chains: u32,
+}
+
+impl TimeLockParams {
+    /// Upper bound on the number of chains of a time lock.
+    pub const MAX_CHAINS: u32 = 1 << 10;
+
+    /// Upper bound on the total number of hash iterations of a time lock
+    /// (several days of computation on a typical machine), so that a crafted
+    /// main document can't make recovery take forever.
+    pub const MAX_ITERATIONS: u64 = 1 << 40;
+
+    /// Parameters for a lock needing `iterations` hash iterations to open,
+    /// split between as many chains as there are cores available.
+    pub fn new(iterations: u64) -> Self {
+        let chains = (available_threads() as u32).clamp(1, Self::MAX_CHAINS);
+        Self { iterations, chains }
+    }
+
+    /// Parameters for a lock taking roughly `duration` to open on this
+    /// machine. Dedicated hardware will be able to open it faster.
+    pub fn for_duration(duration: Duration) -> Self {
+        Self::new((hash_rate() * duration.as_secs_f64()) as u64)
+    }
+
+    // Check that the parameters are usable.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.chains == 0 || self.chains > Self::MAX_CHAINS {
+            Err(format!(
+                "time lock chains must be between 1 and {} not {}",
+                Self::MAX_CHAINS,
+                self.chains
+            ))
+        } else if self.iterations == 0 {
+            Err("time lock iterations must be at least 1".into())
+        } else if self
+            .chain_iterations()
+            .checked_mul(u64::from(self.chains))
+            .is_none_or(|total| total > Self::MAX_ITERATIONS)
+        {
+            Err(format!(
+                "time lock iterations must be at most {} not {}",
+                Self::MAX_ITERATIONS,
+                self.iterations
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Number of iterations of each chain (the total is rounded up to a
+    // multiple of the number of chains).
+    fn chain_iterations(&self) -> u64 {
+        self.iterations.div_ceil(u64::from(self.chains))
+    }
+}
+
+/// The puzzle protecting the document key of a time-locked backup, stored in
+/// its main document (see `MainDocument::time_lock`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TimeLock {
+    pub(super) chain_iterations: u64,
+    // The seed of the first chain, encrypted with seed_key.
+    pub(super) seed: ChainValue,
+    // The seed of each subsequent chain, encrypted with the output of the
+    // previous chain.
+    pub(super) links: Vec<ChainValue>,
+}
+
+impl TimeLock {
+    // Create a new lock for the document key, returning it along with the
+    // masked document key to store in the shards.
+    pub(super) fn create(
+        params: &TimeLockParams,
+        doc_key: &ChaChaPolyKey,
+    ) -> Result<(Self, ChaChaPolyKey), String> {
+        params.validate()?;
+        let chain_iterations = params.chain_iterations();
+
+        let mut seeds = vec![[0u8; TIME_LOCK_LENGTH]; params.chains as usize];
+        seeds.iter_mut().for_each(|seed| OsRng.fill_bytes(seed));
+        let mut outputs = seeds.clone();
+        let per_thread = outputs.len().div_ceil(available_threads());
+        thread::scope(|scope| {
+            for states in outputs.chunks_mut(per_thread) {
+                scope.spawn(move || {
+                    states
+                        .iter_mut()
+                        .for_each(|state| iterate(state, chain_iterations))
+                });
+            }
+        });
+
+        let links = outputs
+            .iter()
+            .zip(&seeds[1..])
+            .map(|(output, seed)| xor(output, seed))
+            .collect();
+        let mut shard_doc_key = *doc_key;
+        apply_mask(&mut shard_doc_key, &outputs[outputs.len() - 1]);
+        let lock = TimeLock {
+            chain_iterations,
+            seed: xor(&seeds[0], &seed_key(&shard_doc_key)),
+            links,
+        };
+
+        seeds.zeroize();
+        outputs.zeroize();
+        Ok((lock, shard_doc_key))
+    }
+
+    // Solve the puzzle (given the masked document key from the shards),
+    // returning the mask it hides. This takes as long as the lock was
+    // configured to take.
+    fn solve(&self, shard_doc_key: &ChaChaPolyKey) -> ChainValue {
+        let mut state = xor(&self.seed, &seed_key(shard_doc_key));
+        iterate(&mut state, self.chain_iterations);
+        for link in &self.links {
+            state = xor(&state, link);
+            iterate(&mut state, self.chain_iterations);
+        }
+        state
+    }
+
+    // Open the lock, unmasking the document key stored in the shards.
+    pub(super) fn open(&self, shard_doc_key: &ChaChaPolyKey) -> ChaChaPolyKey {
+        let mut mask = self.solve(shard_doc_key);
+        let mut doc_key = *shard_doc_key;
+        apply_mask(&mut doc_key, &mask);
+        mask.zeroize();
+        doc_key
+    }
+
+    /// Number of hash chains in the puzzle.
+    pub fn chains(&self) -> u32 {
+        self.links.len() as u32 + 1
+    }
+
+    /// Total number of hash iterations needed to open the lock.
+    pub fn iterations(&self) -> u64 {
+        self.chain_iterations
+            .saturating_mul(u64::from(self.chains()))
+    }
+
+    /// Parameters which create a lock taking as long to open as this one.
+    pub fn params(&self) -> TimeLockParams {
+        TimeLockParams {
+            iterations: self.iterations(),
+            chains: self.chains(),
+        }
+    }
+
+    /// Roughly how long it will take to open the lock on this machine (which
+    /// has to be done to recover the secret).
+    pub fn estimated_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.iterations() as f64 / hash_rate())
+    }
+}
+
+// Mask (or unmask) a document key with the output of the puzzle.
+fn apply_mask(key: &mut ChaChaPolyKey, mask: &ChainValue) {
+    key.iter_mut().zip(mask).for_each(|(k, m)| *k ^= m);
+}
+
+fn xor(a: &ChainValue, b: &ChainValue) -> ChainValue {
+    let mut out = [0u8; TIME_LOCK_LENGTH];
+    out.iter_mut()
+        .zip(a.iter().zip(b))
+        .for_each(|(o, (a, b))| *o = a ^ b);
+    out
+}
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for TimeLock {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let value = |g: &mut G| {
+            let mut value = [0u8; TIME_LOCK_LENGTH];
+            g.fill_bytes(&mut value);
+            value
+        };
+        let seed = value(g);
+        let links = (0..g.next_u32() % 4).map(|_| value(g)).collect();
+        Self {
+            chain_iterations: 1 + u64::from(g.next_u32() % 1000),
+            seed,
+            links,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{Backup, BackupOptions, FromWire, MainDocument, ToWire, UntrustedQuorum};
+
+    #[quickcheck]
+    fn time_lock_open(iterations: u16, chains: u8, key: Vec<u8>) -> bool {
+        let params = TimeLockParams {
+            iterations: 1 + u64::from(iterations),
+            chains: 1 + u32::from(chains % 16),
+        };
+        let mut doc_key = ChaChaPolyKey::default();
+        doc_key
+            .iter_mut()
+            .zip(key.iter().cycle())
+            .for_each(|(k, b)| *k = *b);
+
+        let (lock, shard_doc_key) = TimeLock::create(&params, &doc_key).unwrap();
+        lock.chains() == params.chains
+            && lock.iterations() >= params.iterations
+            && shard_doc_key != doc_key
+            && lock.open(&shard_doc_key) == doc_key
+    }
+
+    #[test]
+    fn time_lock_needs_shards() {
+        let doc_key = ChaChaPolyKey::default();
+        let (lock, shard_doc_key) = TimeLock::create(&TimeLockParams::new(100), &doc_key).unwrap();
+
+        // The puzzle can't be solved from the main document alone, since the
+        // first seed is encrypted with a key derived from the shards.
+        let mut other_key = shard_doc_key;
+        other_key[0] ^= 1;
+        let mut state = lock.seed;
+        iterate(&mut state, lock.chain_iterations);
+        let mut unmasked = shard_doc_key;
+        apply_mask(&mut unmasked, &state);
+
+        assert_ne!(unmasked, doc_key);
+        assert_ne!(lock.open(&other_key), doc_key);
+        assert_eq!(lock.open(&shard_doc_key), doc_key);
+    }
+
+    #[test]
+    fn time_lock_params_validate() {
+        assert!(TimeLockParams::new(1).validate().is_ok());
+        assert!(TimeLockParams {
+            iterations: 0,
+            chains: 1
+        }
+        .validate()
+        .is_err());
+        assert!(TimeLockParams {
+            iterations: 1,
+            chains: 0
+        }
+        .validate()
+        .is_err());
+        assert!(TimeLockParams {
+            iterations: 1,
+            chains: TimeLockParams::MAX_CHAINS + 1
+        }
+        .validate()
+        .is_err());
+        assert!(TimeLockParams {
+            iterations: TimeLockParams::MAX_ITERATIONS,
+            chains: 1
+        }
+        .validate()
+        .is_ok());
+        assert!(TimeLockParams {
+            iterations: TimeLockParams::MAX_ITERATIONS + 1,
+            chains: 1
+        }
+        .validate()
+        .is_err());
+        // The iterations are rounded up to a multiple of the chains.
+        assert!(TimeLockParams {
+            iterations: TimeLockParams::MAX_ITERATIONS,
+            chains: 3
+        }
+        .validate()
+        .is_err());
+        // Rounding up must not overflow.
+        assert!(TimeLockParams {
+            iterations: u64::MAX,
+            chains: 2
+        }
+        .validate()
+        .is_err());
+        assert!(TimeLockParams::for_duration(Duration::MAX)
+            .validate()
+            .is_err());
+    }
+
+    #[quickcheck]
+    fn time_locked_backup(secret: Vec<u8>) -> bool {
+        let options = BackupOptions {
+            time_lock: Some(TimeLockParams {
+                iterations: 1000,
+                chains: 4,
+            }),
+            ..Default::default()
+        };
+        let mut backup = Backup::with_options(2, &secret, options).unwrap();
+        let main_document = backup.main_document().clone();
+        let lock = main_document.time_lock().cloned().unwrap();
+
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(main_document);
+        for _ in 0..2 {
+            quorum.push_shard(backup.next_shard().unwrap());
+        }
+        let quorum = quorum.validate().unwrap();
+
+        // The shards alone don't contain the document key.
+        let shard_doc_key = quorum.shard_secret().unwrap().doc_key;
+        let unmasked = lock.open(&shard_doc_key) != shard_doc_key;
+
+        lock.iterations() == 1000 && unmasked && quorum.recover_document().unwrap() == secret
+    }
+
+    #[test]
+    fn time_lock_too_many_iterations() {
+        let options = BackupOptions {
+            time_lock: Some(TimeLockParams {
+                iterations: 1000,
+                chains: 4,
+            }),
+            ..Default::default()
+        };
+        let backup = Backup::with_options(2, b"secret", options).unwrap();
+        let mut main_document = backup.main_document().clone();
+        let lock = main_document.inner.meta.time_lock.as_mut().unwrap();
+        lock.chain_iterations = TimeLockParams::MAX_ITERATIONS / 4 + 1;
+
+        // A crafted main document can't make recovery take forever.
+        assert!(MainDocument::from_wire(main_document.to_wire()).is_err());
+    }
+
+    #[test]
+    fn deterministic_backup_cannot_be_time_locked() {
+        let options = BackupOptions {
+            deterministic: true,
+            time_lock: Some(TimeLockParams::new(1000)),
+            ..Default::default()
+        };
+        assert!(Backup::with_options(2, b"secret", options).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Unlinkable shard identifiers. The identifiers normally printed on shards
+//! (the document ID and the shard ID) let anyone who collects shards group
+//! them by backup. In privacy mode, shards are instead labelled with an
+//! identifier derived with a keyed PRF from a secret `GroupingHint` kept by
+//! the owner, which reveals nothing about the backup a shard belongs to
+//! unless the hint is known.
+
+use crate::{
+    shamir::encoding,
+    v0::{KeyShard, MainDocument, ShardId},
+};
+
+use std::{fmt, str::FromStr};
+
+use multihash::Multihash;
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroize;
+
+const UNLINKABLE_SALT_PERSONAL: &[u8] = b"paperback-salt";
+const UNLINKABLE_TAG_PERSONAL: &[u8] = b"paperback-unlink";
+
+// Lengths of the salt and the tag of an unlinkable identifier.
+const SALT_LENGTH: usize = 5;
+const TAG_LENGTH: usize = 5;
+
+/// Secret kept by the owner of a backup, needed to tell which backup a shard
+/// labelled with an unlinkable identifier (see `KeyShard::unlinkable_id`)
+/// belongs to. The same hint can be used for several backups.
+#[derive(Clone, Eq, PartialEq)]
+pub struct GroupingHint([u8; GroupingHint::LENGTH]);
+
+impl Drop for GroupingHint {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+// Don't print the hint by accident.
+impl fmt::Debug for GroupingHint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("GroupingHint").field(&"..").finish()
+    }
+}
+
+/// The hint is written down as z-base-32.
+impl fmt::Display for GroupingHint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", zbase32::encode_full_bytes(&self.0))
+    }
+}
+
+impl FromStr for GroupingHint {
+    type Err = String;
+
+    fn from_str(hint: &str) -> Result<Self, Self::Err> {
+        let mut bytes = zbase32::decode_full_bytes_str(&encoding::zbase32_normalize(hint))
+            .map_err(|_| "grouping hint must be z-base-32")?;
+        if bytes.len() != Self::LENGTH {
+            bytes.zeroize();
+            return Err(format!(
+                "grouping hint must be {} bytes",
+                GroupingHint::LENGTH
+            ));
+        }
+        let mut hint = GroupingHint([0u8; Self::LENGTH]);
+        hint.0.copy_from_slice(&bytes);
+        bytes.zeroize();
+        Ok(hint)
+    }
+}
+
+impl GroupingHint {
+    /// Length of a grouping hint.
+    pub const LENGTH: usize = 10;
+
+    /// Generate a new random hint.
+    pub fn generate() -> Self {
+        let mut hint = GroupingHint([0u8; Self::LENGTH]);
+        OsRng.fill_bytes(&mut hint.0);
+        hint
+    }
+
+    // Keyed PRF of the document checksum and a salt.
+    fn tag(&self, doc_chksum: &Multihash, salt: &[u8]) -> [u8; TAG_LENGTH] {
+        let hash = blake2b_simd::Params::new()
+            .hash_length(TAG_LENGTH)
+            .key(&self.0)
+            .personal(UNLINKABLE_TAG_PERSONAL)
+            .to_state()
+            .update(doc_chksum.as_bytes())
+            .update(salt)
+            .finalize();
+        let mut tag = [0u8; TAG_LENGTH];
+        tag.copy_from_slice(hash.as_bytes());
+        tag
+    }
+
+    /// Returns whether the shard labelled with the unlinkable identifier `id`
+    /// belongs to the backup with the main document `main`.
+    pub fn matches(&self, main: &MainDocument, id: &str) -> bool {
+        let id = encoding::zbase32_normalize(id);
+        let bytes = match id
+            .strip_prefix('u')
+            .and_then(|id| zbase32::decode_full_bytes_str(id).ok())
+        {
+            Some(bytes) if bytes.len() == SALT_LENGTH + TAG_LENGTH => bytes,
+            _ => return false,
+        };
+        let (salt, tag) = bytes.split_at(SALT_LENGTH);
+        self.tag(&main.checksum(), salt) == tag
+    }
+}
+
+impl KeyShard {
+    /// An identifier for this shard which (unlike the document ID and `id`)
+    /// doesn't reveal which backup the shard belongs to, unless `hint` is
+    /// known (see `GroupingHint::matches`).
+    pub fn unlinkable_id(&self, hint: &GroupingHint) -> ShardId {
+        // The salt only depends on the shard's own identifier, so the same
+        // shard always gets the same unlinkable identifier.
+        let salt = blake2b_simd::Params::new()
+            .hash_length(SALT_LENGTH)
+            .personal(UNLINKABLE_SALT_PERSONAL)
+            .hash(self.id().as_bytes());
+        let tag = hint.tag(&self.inner.doc_chksum, salt.as_bytes());
+
+        let mut bytes = salt.as_bytes().to_vec();
+        bytes.extend_from_slice(&tag);
+        format!("u{}", zbase32::encode_full_bytes(&bytes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::Backup;
+
+    #[test]
+    fn grouping_hint_roundtrip() {
+        let hint = GroupingHint::generate();
+        let encoded = hint.to_string();
+        assert_eq!(encoded.parse::<GroupingHint>(), Ok(hint.clone()));
+        assert_eq!(encoded.to_uppercase().parse::<GroupingHint>(), Ok(hint));
+        assert!("".parse::<GroupingHint>().is_err());
+        assert!("ybndrfg8".parse::<GroupingHint>().is_err());
+    }
+
+    #[quickcheck]
+    fn unlinkable_ids(secret: Vec<u8>) -> bool {
+        let hint = GroupingHint::generate();
+        let other_hint = GroupingHint::generate();
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let other = Backup::new(2, &secret).unwrap();
+        let shards = (0..3)
+            .map(|_| backup.next_shard().unwrap())
+            .collect::<Vec<_>>();
+        let ids = shards
+            .iter()
+            .map(|shard| shard.unlinkable_id(&hint))
+            .collect::<Vec<_>>();
+
+        let main = backup.main_document();
+        let document_id = main.id();
+        ids.iter().all(|id| {
+            hint.matches(main, id)
+                && !hint.matches(other.main_document(), id)
+                && !other_hint.matches(main, id)
+                // The identifier reveals nothing obvious about the backup.
+                && !id.contains(&document_id)
+        }) && ids[0] == shards[0].unlinkable_id(&hint)
+            && ids[0] != ids[1]
+            && ids[0] != shards[0].unlinkable_id(&other_hint)
+            && !hint.matches(main, &shards[0].id())
+    }
+}
@@ -0,0 +1,241 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Watermarks identifying which custodian's copy of a backup a key shard is,
+//! so that if a shard leaks the owner of the backup can tell whose copy it
+//! was. When handing out shards, the owner numbers the custodians and issues
+//! each shard with `Backup::next_shard_watermarked`, using a `WatermarkKey`
+//! which they keep (privately).
+//!
+//! A watermark contains the custodian's index and a tag computed from the
+//! index and the main document with the watermark key, so custodians can't
+//! forge the watermark of another custodian. The watermark is covered by the
+//! signature of the key shard, and is also stored (unencrypted) in the
+//! encrypted key shard so that it can be printed on the shard and checked
+//! without decrypting it.
+
+use crate::v0::{possession::parse_code, MainDocument};
+
+use std::{fmt, str::FromStr};
+
+use multihash::Multihash;
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroize;
+
+const WATERMARK_TAG_PERSONAL: &[u8] = b"paperback-wmark";
+
+pub(super) const WATERMARK_TAG_LENGTH: usize = 16;
+
+/// Secret key used to create (and check) the watermarks of key shards, which
+/// the owner of a backup should keep privately.
+#[derive(Clone, Eq, PartialEq)]
+pub struct WatermarkKey([u8; WatermarkKey::LENGTH]);
+
+// Don't print the key by accident (it would let custodians forge each other's
+// watermarks).
+impl fmt::Debug for WatermarkKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("WatermarkKey").field(&"..").finish()
+    }
+}
+
+impl Drop for WatermarkKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl WatermarkKey {
+    /// Length of a watermark key.
+    pub const LENGTH: usize = 32;
+
+    /// Generate a new random watermark key.
+    pub fn generate() -> Self {
+        let mut key = [0u8; Self::LENGTH];
+        OsRng.fill_bytes(&mut key);
+        WatermarkKey(key)
+    }
+
+    fn tag(&self, doc_chksum: &Multihash, index: u32) -> blake2b_simd::Hash {
+        blake2b_simd::Params::new()
+            .hash_length(WATERMARK_TAG_LENGTH)
+            .key(&self.0)
+            .personal(WATERMARK_TAG_PERSONAL)
+            .to_state()
+            .update(doc_chksum.as_bytes())
+            .update(&index.to_be_bytes())
+            .finalize()
+    }
+
+    pub(super) fn watermark(&self, doc_chksum: &Multihash, index: u32) -> Watermark {
+        let mut tag = [0u8; WATERMARK_TAG_LENGTH];
+        tag.copy_from_slice(self.tag(doc_chksum, index).as_bytes());
+        Watermark { index, tag }
+    }
+}
+
+/// The key is written down as z-base-32.
+impl fmt::Display for WatermarkKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", zbase32::encode_full_bytes(&self.0))
+    }
+}
+
+impl FromStr for WatermarkKey {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        parse_code(key, "watermark key").map(WatermarkKey)
+    }
+}
+
+/// Watermark embedded in a key shard, identifying the custodian it was issued
+/// to (see `KeyShard::watermark`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Watermark {
+    pub(super) index: u32,
+    pub(super) tag: [u8; WATERMARK_TAG_LENGTH],
+}
+
+impl Watermark {
+    /// Index of the custodian the shard was issued to, as chosen by the owner
+    /// of the backup when issuing it. This is only meaningful once the
+    /// watermark has been checked with `verify`.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns whether this watermark was created with `key` for a shard of
+    /// the backup with the given main document, in which case the shard is
+    /// the copy issued to the custodian with this index.
+    pub fn verify(&self, key: &WatermarkKey, main_document: &MainDocument) -> bool {
+        // blake2b_simd compares hashes in constant time.
+        key.tag(&main_document.checksum(), self.index) == self.tag[..]
+    }
+}
+
+/// Watermarks are printed as the custodian index followed by the tag in
+/// z-base-32.
+impl fmt::Display for Watermark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.index,
+            zbase32::encode_full_bytes(&self.tag)
+        )
+    }
+}
+
+impl FromStr for Watermark {
+    type Err = String;
+
+    fn from_str(watermark: &str) -> Result<Self, Self::Err> {
+        let (index, tag) = watermark
+            .trim()
+            .split_once('-')
+            .ok_or_else(|| String::from("watermark must be of the form <index>-<tag>"))?;
+        Ok(Watermark {
+            index: index
+                .parse()
+                .map_err(|_| String::from("watermark index must be a number"))?,
+            tag: parse_code(tag, "watermark tag")?,
+        })
+    }
+}
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for Watermark {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let mut tag = [0u8; WATERMARK_TAG_LENGTH];
+        g.fill_bytes(&mut tag);
+        Self {
+            index: u32::arbitrary(g),
+            tag,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{Backup, Custodian, EncryptedKeyShard, FromWire, ToWire};
+
+    #[test]
+    fn watermark_codes_roundtrip() {
+        let key = WatermarkKey::generate();
+        assert_eq!(key.to_string().parse(), Ok(key.clone()));
+        assert!("ybndrfg8".parse::<WatermarkKey>().is_err());
+
+        let watermark = key.watermark(&Multihash::from_bytes(vec![0x12, 0]).unwrap(), 7);
+        let printed = watermark.to_string();
+        assert!(printed.starts_with("7-"));
+        assert_eq!(printed.parse(), Ok(watermark.clone()));
+        assert_eq!(printed.to_uppercase().parse(), Ok(watermark));
+        assert!("7".parse::<Watermark>().is_err());
+        assert!("x-ybndrfg8".parse::<Watermark>().is_err());
+    }
+
+    #[quickcheck]
+    fn watermark_identifies_custodian(secret: Vec<u8>, index: u32) -> bool {
+        let key = WatermarkKey::generate();
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let other = Backup::new(2, &secret).unwrap();
+        let custodian = Custodian::new("Alice");
+        let shard = backup
+            .next_shard_watermarked(Some(custodian.clone()), index, &key)
+            .unwrap();
+        let unmarked = backup.next_shard().unwrap();
+        let watermark = shard.watermark().unwrap().clone();
+
+        // The watermark survives encryption, and is visible without the
+        // codewords.
+        let (encrypted, codewords) = shard.clone().encrypt().unwrap();
+        let encrypted = EncryptedKeyShard::from_wire(encrypted.to_wire()).unwrap();
+
+        // A forged watermark (for another custodian) doesn't verify.
+        let forged = Watermark {
+            index: index.wrapping_add(1),
+            ..watermark.clone()
+        };
+
+        watermark.index() == index
+            && watermark.verify(&key, backup.main_document())
+            && !watermark.verify(&WatermarkKey::generate(), backup.main_document())
+            && !watermark.verify(&key, other.main_document())
+            && !forged.verify(&key, backup.main_document())
+            && unmarked.watermark().is_none()
+            && encrypted.watermark() == Some(&watermark)
+            && encrypted.decrypt(&codewords).unwrap().watermark() == Some(&watermark)
+    }
+
+    #[test]
+    fn watermark_tampered() {
+        let key = WatermarkKey::generate();
+        let mut backup = Backup::new(2, b"secret").unwrap();
+        let shard = backup.next_shard_watermarked(None, 1, &key).unwrap();
+        let (mut encrypted, codewords) = shard.encrypt().unwrap();
+
+        // The unencrypted watermark must match the (signed) decrypted one.
+        encrypted.watermark = Some(key.watermark(&backup.main_document().checksum(), 2));
+        assert!(encrypted.clone().decrypt(&codewords).is_err());
+        encrypted.watermark = None;
+        assert!(encrypted.decrypt(&codewords).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::{
+        encoding,
+        header::{self, DocumentKind},
+        WireLimits,
+    },
+    v0::{
+        wire::{FromWire, ToWire, WireError},
+        Bundle, BundleEntry,
+    },
+};
+
+impl Bundle {
+    // Append the wire encoding (without the header) to bytes.
+    fn write_wire_body(&self, bytes: &mut Vec<u8>) {
+        // Encode the number of entries, followed by each entry.
+        encoding::varint(self.entries.len() as u64, bytes);
+        for entry in &self.entries {
+            // Encode name and contents (length-prefixed).
+            encoding::varint(entry.name.len() as u64, bytes);
+            bytes.extend_from_slice(entry.name.as_bytes());
+            encoding::varint(entry.data.len() as u64, bytes);
+            bytes.extend_from_slice(&entry.data);
+        }
+    }
+
+    // Length (in bytes) of the wire encoding, without the header.
+    fn wire_body_len(&self) -> usize {
+        encoding::varint_len(self.entries.len() as u64)
+            + self
+                .entries
+                .iter()
+                .map(|entry| {
+                    encoding::varint_len(entry.name.len() as u64)
+                        + entry.name.len()
+                        + encoding::varint_len(entry.data.len() as u64)
+                        + entry.data.len()
+                })
+                .sum::<usize>()
+    }
+
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::nom_helpers;
+        use nom::{bytes::complete::take, combinator::complete};
+
+        let (mut input, count) = WireError::nom(complete(nom_helpers::usize)(input))?;
+        WireLimits::check("entries", Bundle::MAX_ENTRIES, count)?;
+
+        let mut bundle = Bundle::new();
+        for _ in 0..count {
+            let (remain, name_len) = WireError::nom(complete(nom_helpers::usize)(input))?;
+            WireLimits::check("name", Bundle::MAX_NAME_LENGTH, name_len)?;
+            let (remain, name) = WireError::nom(take(name_len)(remain))?;
+            let (remain, data_len) = WireError::nom(complete(nom_helpers::usize)(remain))?;
+            let (remain, data) = WireError::nom(take(data_len)(remain))?;
+
+            let name = String::from_utf8(name.to_vec()).map_err(|err| format!("{:?}", err))?;
+            bundle.check_name(&name).map_err(|err| err.to_string())?;
+            bundle.entries.push(BundleEntry {
+                name,
+                data: data.to_vec(),
+            });
+            input = remain;
+        }
+
+        Ok((bundle, input))
+    }
+}
+
+impl ToWire for Bundle {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.wire_len());
+        self.to_wire_into(&mut bytes);
+        bytes
+    }
+
+    fn wire_len(&self) -> usize {
+        header::record_len(DocumentKind::Bundle, self.wire_body_len())
+    }
+
+    fn to_wire_into(&self, bytes: &mut Vec<u8>) {
+        header::write_record_into(bytes, DocumentKind::Bundle, self.wire_body_len(), |bytes| {
+            self.write_wire_body(bytes)
+        })
+    }
+}
+
+impl FromWire for Bundle {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(input, DocumentKind::Bundle, Self::from_wire_body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[quickcheck]
+    fn bundle_roundtrip(bundle: Bundle) -> bool {
+        let bytes = bundle.to_wire();
+        bytes.len() == bundle.wire_len()
+            && header::detect(&bytes) == DocumentKind::Bundle
+            && Bundle::from_wire(bytes) == Ok(bundle)
+    }
+
+    #[test]
+    fn bundle_duplicate_names() {
+        let mut bundle = Bundle::new();
+        bundle.add("a", b"1").unwrap().add("b", b"2").unwrap();
+        bundle.entries[1].name = "a".into();
+        assert!(Bundle::from_wire(bundle.to_wire()).is_err());
+    }
+}
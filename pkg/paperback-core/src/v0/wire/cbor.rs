@@ -0,0 +1,390 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    cbor::{FromCbor, MapReader, ToCbor, Value},
+    v0::{
+        timelock::TIME_LOCK_LENGTH,
+        watermark::WATERMARK_TAG_LENGTH,
+        wire::{main_document::PADDING_LENGTH_PREFIXED, WireError},
+        AeadAlgorithm, Argon2Params, ChaChaPolyNonce, Compression, Custodian, EncryptedKeyShard,
+        Identity, MainDocument, MainDocumentBuilder, MainDocumentMeta, MainDocumentPage, Metadata,
+        PassphraseKdf, TimeLock, TimeLockParams, Watermark, ARGON2_SALT_LENGTH,
+    },
+};
+
+use std::convert::TryFrom;
+
+use ed25519_dalek::{PublicKey, Signature};
+
+// Both the main document and key shards are only stored in their encrypted
+// form, so the CBOR encoding contains the same fields as the wire format.
+
+fn take_nonce(map: &mut MapReader) -> Result<ChaChaPolyNonce, WireError> {
+    let nonce = map.take_bytes("nonce")?;
+    let mut buffer = ChaChaPolyNonce::default();
+    if nonce.len() != buffer.len() {
+        return Err(format!("nonce must be {} bytes", buffer.len()).into());
+    }
+    buffer.copy_from_slice(&nonce);
+    Ok(buffer)
+}
+
+fn take_optional_text(
+    map: &mut MapReader,
+    field: &'static str,
+) -> Result<Option<String>, WireError> {
+    match map.take_optional(field) {
+        None => Ok(None),
+        Some(Value::Text(text)) => Ok(Some(text)),
+        Some(_) => Err(format!("cbor field '{}' must be a text string", field).into()),
+    }
+}
+
+fn take_optional_uint(map: &mut MapReader, field: &'static str) -> Result<Option<u64>, WireError> {
+    match map.take_optional(field) {
+        None => Ok(None),
+        Some(Value::Uint(n)) => Ok(Some(n)),
+        Some(_) => Err(format!("cbor field '{}' must be an integer", field).into()),
+    }
+}
+
+impl ToCbor for MainDocument {
+    fn to_cbor(&self) -> Vec<u8> {
+        let mut entries = vec![
+            (
+                "version".into(),
+                Value::Uint(u64::from(self.inner.meta.version)),
+            ),
+            (
+                "quorum_size".into(),
+                Value::Uint(u64::from(self.inner.meta.quorum_size)),
+            ),
+        ];
+        if let Some(id) = self.inner.meta.compression.wire_id() {
+            entries.push(("compression".into(), Value::Uint(u64::from(id))));
+        }
+        if let Some(id) = self.inner.meta.algorithm.wire_id() {
+            entries.push(("algorithm".into(), Value::Uint(u64::from(id))));
+        }
+        let metadata = &self.inner.meta.metadata;
+        if let Some(label) = &metadata.label {
+            entries.push(("label".into(), Value::Text(label.clone())));
+        }
+        if let Some(comment) = &metadata.comment {
+            entries.push(("comment".into(), Value::Text(comment.clone())));
+        }
+        if let Some(created) = metadata.created {
+            entries.push(("created".into(), Value::Uint(created)));
+        }
+        if let Some(review_by) = metadata.review_by {
+            entries.push(("review_by".into(), Value::Uint(review_by)));
+        }
+        if let Some(content_type) = &metadata.content_type {
+            entries.push(("content_type".into(), Value::Text(content_type.clone())));
+        }
+        if self.inner.meta.padded {
+            entries.push((
+                "padding".into(),
+                Value::Uint(u64::from(PADDING_LENGTH_PREFIXED)),
+            ));
+        }
+        if let Some(chunk_size) = self.inner.meta.chunk_size {
+            entries.push(("chunk_size".into(), Value::Uint(u64::from(chunk_size))));
+        }
+        if let Some(lock) = &self.inner.meta.time_lock {
+            entries.push((
+                "time_lock".into(),
+                Value::Map(vec![
+                    ("iterations".into(), Value::Uint(lock.chain_iterations)),
+                    ("seed".into(), Value::Bytes(lock.seed.to_vec())),
+                    (
+                        "links".into(),
+                        Value::Array(
+                            lock.links
+                                .iter()
+                                .map(|link| Value::Bytes(link.to_vec()))
+                                .collect(),
+                        ),
+                    ),
+                ]),
+            ));
+        }
+        entries.extend(vec![
+            ("nonce".into(), Value::Bytes(self.inner.nonce.to_vec())),
+            (
+                "ciphertext".into(),
+                Value::Bytes(self.inner.ciphertext.clone()),
+            ),
+            (
+                "public_key".into(),
+                Value::Bytes(self.identity.id_public_key.as_bytes().to_vec()),
+            ),
+            (
+                "signature".into(),
+                Value::Bytes(self.identity.id_signature.to_bytes().to_vec()),
+            ),
+        ]);
+        Value::Map(entries).encode()
+    }
+}
+
+impl FromCbor for MainDocument {
+    fn from_cbor<B: AsRef<[u8]>>(input: B) -> Result<Self, WireError> {
+        let mut map = MapReader::new(Value::decode(input.as_ref())?)?;
+
+        let version = map.take_u32("version")?;
+        if version != 0 {
+            return Err(WireError::UnsupportedVersion { version });
+        }
+        let quorum_size = map.take_u32("quorum_size")?;
+        let compression = match map.take_optional("compression") {
+            None => Compression::None,
+            Some(Value::Uint(id)) => u32::try_from(id)
+                .ok()
+                .and_then(Compression::from_wire_id)
+                .ok_or_else(|| format!("unknown compression algorithm '{}'", id))?,
+            Some(_) => {
+                return Err(String::from("cbor field 'compression' must be an integer").into())
+            }
+        };
+        let algorithm = match map.take_optional("algorithm") {
+            None => AeadAlgorithm::default(),
+            Some(Value::Uint(id)) => u32::try_from(id)
+                .ok()
+                .and_then(AeadAlgorithm::from_wire_id)
+                .ok_or_else(|| format!("unknown aead algorithm '{}'", id))?,
+            Some(_) => return Err(String::from("cbor field 'algorithm' must be an integer").into()),
+        };
+        let metadata = Metadata {
+            label: take_optional_text(&mut map, "label")?,
+            comment: take_optional_text(&mut map, "comment")?,
+            created: take_optional_uint(&mut map, "created")?,
+            review_by: take_optional_uint(&mut map, "review_by")?,
+            content_type: take_optional_text(&mut map, "content_type")?,
+        };
+        metadata.validate()?;
+        let padded = match take_optional_uint(&mut map, "padding")? {
+            None => false,
+            Some(id) if id == u64::from(PADDING_LENGTH_PREFIXED) => true,
+            Some(id) => return Err(format!("unknown padding scheme '{}'", id).into()),
+        };
+        let chunk_size = match take_optional_uint(&mut map, "chunk_size")? {
+            None => None,
+            Some(chunk_size) => {
+                let chunk_size = u32::try_from(chunk_size)
+                    .map_err(|_| format!("invalid chunk size {}", chunk_size))?;
+                MainDocumentPage::check_chunk_size(chunk_size)?;
+                Some(chunk_size)
+            }
+        };
+        let time_lock = take_time_lock(&mut map)?;
+        let nonce = map.take_bytes("nonce")?;
+        if nonce.len() != algorithm.nonce_len() {
+            return Err(format!("nonce must be {} bytes", algorithm.nonce_len()).into());
+        }
+        let meta = MainDocumentMeta {
+            version,
+            quorum_size,
+            compression,
+            algorithm,
+            metadata,
+            padded,
+            chunk_size,
+            time_lock,
+        };
+        let inner = MainDocumentBuilder {
+            meta,
+            nonce,
+            ciphertext: map.take_bytes("ciphertext")?,
+        };
+        let identity = Identity {
+            id_public_key: PublicKey::from_bytes(&map.take_bytes("public_key")?)
+                .map_err(|err| format!("{:?}", err))?,
+            id_signature: Signature::from_bytes(&map.take_bytes("signature")?)
+                .map_err(|err| format!("{:?}", err))?,
+        };
+        map.finish()?;
+
+        Ok(MainDocument { inner, identity })
+    }
+}
+
+impl ToCbor for EncryptedKeyShard {
+    fn to_cbor(&self) -> Vec<u8> {
+        let mut entries = vec![];
+        if let Some(custodian) = &self.custodian {
+            let mut fields = vec![("name".into(), Value::Text(custodian.name.clone()))];
+            if let Some(contact) = &custodian.contact {
+                fields.push(("contact".into(), Value::Text(contact.clone())));
+            }
+            entries.push(("custodian".into(), Value::Map(fields)));
+        }
+        if let Some(watermark) = &self.watermark {
+            entries.push((
+                "watermark".into(),
+                Value::Map(vec![
+                    ("index".into(), Value::Uint(u64::from(watermark.index))),
+                    ("tag".into(), Value::Bytes(watermark.tag.to_vec())),
+                ]),
+            ));
+        }
+        if let Some(kdf) = &self.kdf {
+            let params = &kdf.params;
+            entries.push((
+                "argon2id".into(),
+                Value::Map(vec![
+                    (
+                        "memory_kib".into(),
+                        Value::Uint(u64::from(params.memory_kib)),
+                    ),
+                    (
+                        "iterations".into(),
+                        Value::Uint(u64::from(params.iterations)),
+                    ),
+                    ("lanes".into(), Value::Uint(u64::from(params.lanes))),
+                    ("salt".into(), Value::Bytes(kdf.salt.to_vec())),
+                ]),
+            ));
+        }
+        entries.extend(vec![
+            ("nonce".into(), Value::Bytes(self.nonce.to_vec())),
+            ("ciphertext".into(), Value::Bytes(self.ciphertext.clone())),
+        ]);
+        Value::Map(entries).encode()
+    }
+}
+
+fn take_kdf(map: &mut MapReader) -> Result<Option<PassphraseKdf>, WireError> {
+    let mut map = match map.take_optional("argon2id") {
+        None => return Ok(None),
+        Some(value) => MapReader::new(value)?,
+    };
+    let params = Argon2Params {
+        memory_kib: map.take_u32("memory_kib")?,
+        iterations: map.take_u32("iterations")?,
+        lanes: map.take_u32("lanes")?,
+    };
+    params.validate()?;
+    let salt = map.take_bytes("salt")?;
+    let salt = <[u8; ARGON2_SALT_LENGTH]>::try_from(salt.as_slice())
+        .map_err(|_| format!("argon2id salt must be {} bytes", ARGON2_SALT_LENGTH))?;
+    map.finish()?;
+    Ok(Some(PassphraseKdf { params, salt }))
+}
+
+fn take_custodian(map: &mut MapReader) -> Result<Option<Custodian>, WireError> {
+    let mut map = match map.take_optional("custodian") {
+        None => return Ok(None),
+        Some(value) => MapReader::new(value)?,
+    };
+    let custodian = Custodian {
+        name: take_optional_text(&mut map, "name")?
+            .ok_or_else(|| String::from("cbor map is missing field 'name'"))?,
+        contact: take_optional_text(&mut map, "contact")?,
+    };
+    custodian.validate()?;
+    map.finish()?;
+    Ok(Some(custodian))
+}
+
+fn take_time_lock(map: &mut MapReader) -> Result<Option<TimeLock>, WireError> {
+    let mut map = match map.take_optional("time_lock") {
+        None => return Ok(None),
+        Some(value) => MapReader::new(value)?,
+    };
+    let value = |bytes: Vec<u8>| {
+        <[u8; TIME_LOCK_LENGTH]>::try_from(bytes.as_slice())
+            .map_err(|_| format!("time lock values must be {} bytes", TIME_LOCK_LENGTH))
+    };
+    let chain_iterations = map.take_uint("iterations")?;
+    let seed = value(map.take_bytes("seed")?)?;
+    let links = match map.take("links")? {
+        Value::Array(links) if links.len() < TimeLockParams::MAX_CHAINS as usize => links
+            .into_iter()
+            .map(|link| match link {
+                Value::Bytes(bytes) => value(bytes),
+                _ => Err("time lock links must be byte strings".into()),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Err(String::from("invalid cbor field 'links'").into()),
+    };
+    map.finish()?;
+    if chain_iterations == 0 {
+        return Err(String::from("time lock iterations must be at least 1").into());
+    }
+    if chain_iterations.saturating_mul(links.len() as u64 + 1) > TimeLockParams::MAX_ITERATIONS {
+        return Err(format!(
+            "time lock iterations must be at most {}",
+            TimeLockParams::MAX_ITERATIONS
+        )
+        .into());
+    }
+    Ok(Some(TimeLock {
+        chain_iterations,
+        seed,
+        links,
+    }))
+}
+
+fn take_watermark(map: &mut MapReader) -> Result<Option<Watermark>, WireError> {
+    let mut map = match map.take_optional("watermark") {
+        None => return Ok(None),
+        Some(value) => MapReader::new(value)?,
+    };
+    let index = map.take_u32("index")?;
+    let tag = map.take_bytes("tag")?;
+    let tag = <[u8; WATERMARK_TAG_LENGTH]>::try_from(tag.as_slice())
+        .map_err(|_| format!("watermark tag must be {} bytes", WATERMARK_TAG_LENGTH))?;
+    map.finish()?;
+    Ok(Some(Watermark { index, tag }))
+}
+
+impl FromCbor for EncryptedKeyShard {
+    fn from_cbor<B: AsRef<[u8]>>(input: B) -> Result<Self, WireError> {
+        let mut map = MapReader::new(Value::decode(input.as_ref())?)?;
+
+        let shard = EncryptedKeyShard {
+            custodian: take_custodian(&mut map)?,
+            watermark: take_watermark(&mut map)?,
+            kdf: take_kdf(&mut map)?,
+            nonce: take_nonce(&mut map)?,
+            ciphertext: map.take_bytes("ciphertext")?,
+        };
+        map.finish()?;
+
+        Ok(shard)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[quickcheck]
+    fn main_document_cbor_roundtrip(main: MainDocument) {
+        let main2 = MainDocument::from_cbor(main.to_cbor()).unwrap();
+        assert_eq!(main, main2);
+    }
+
+    #[quickcheck]
+    fn encrypted_key_shard_cbor_roundtrip(shard: EncryptedKeyShard) {
+        let shard2 = EncryptedKeyShard::from_cbor(shard.to_cbor()).unwrap();
+        assert_eq!(shard, shard2);
+    }
+}
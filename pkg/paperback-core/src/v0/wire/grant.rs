@@ -0,0 +1,184 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::{
+        encoding,
+        header::{self, DocumentKind},
+    },
+    v0::{
+        grant::{ExpansionGrantBuilder, Participant},
+        wire::{
+            helpers::{take_shard_count, take_shard_id},
+            prefixes::*,
+            FromWire, ToWire, WireError,
+        },
+        ExpansionGrant, CHECKSUM_ALGORITHM,
+    },
+};
+
+use multihash::MultihashDigest;
+use unsigned_varint::encode;
+
+// Internal only -- users can't see ExpansionGrantBuilder.
+#[doc(hidden)]
+impl ToWire for ExpansionGrantBuilder {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        // Encode multihash checksum.
+        bytes.extend_from_slice(self.doc_chksum.as_bytes());
+
+        // Encode the identifiers of the new shards (length-prefixed).
+        encoding::varint(self.new_shard_ids.len() as u64, &mut bytes);
+        for id in &self.new_shard_ids {
+            encoding::varint(id.len() as u64, &mut bytes);
+            bytes.extend_from_slice(id.as_bytes());
+        }
+
+        bytes
+    }
+}
+
+// Internal only -- users can't see ExpansionGrantBuilder.
+#[doc(hidden)]
+impl FromWire for ExpansionGrantBuilder {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::v0::wire::helpers::multihash;
+        use nom::combinator::complete;
+
+        let (input, doc_chksum) = WireError::nom(complete(multihash)(input))?;
+        if doc_chksum.algorithm() != CHECKSUM_ALGORITHM.code() {
+            return Err(String::from("document checksum must be Blake2b-256").into());
+        }
+
+        let (count, mut input) = take_shard_count(input, ExpansionGrant::MAX_SHARDS)?;
+        let mut new_shard_ids = Vec::with_capacity(count.min(input.len()));
+        for _ in 0..count {
+            let (id, remain) = take_shard_id(input)?;
+            new_shard_ids.push(id);
+            input = remain;
+        }
+
+        Ok((
+            ExpansionGrantBuilder {
+                doc_chksum: doc_chksum.to_owned(),
+                new_shard_ids,
+            },
+            input,
+        ))
+    }
+}
+
+impl ExpansionGrant {
+    fn wire_body(&self) -> Vec<u8> {
+        let mut buffer = encode::u32_buffer();
+        let mut bytes = self.inner.to_wire();
+
+        // Encode each participating shard's identifier (length-prefixed) and
+        // ed25519 public key and signature (with multicodec prefixes).
+        encoding::varint(self.participants.len() as u64, &mut bytes);
+        for participant in &self.participants {
+            encoding::varint(participant.shard_id.len() as u64, &mut bytes);
+            bytes.extend_from_slice(participant.shard_id.as_bytes());
+            encode::u32(PREFIX_ED25519_PUB, &mut buffer)
+                .iter()
+                .chain(participant.public_key.as_bytes())
+                .for_each(|b| bytes.push(*b));
+            encode::u32(PREFIX_ED25519_SIG, &mut buffer)
+                .iter()
+                .chain(&participant.signature.to_bytes()[..])
+                .for_each(|b| bytes.push(*b));
+        }
+
+        bytes
+    }
+
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::v0::wire::helpers::{take_ed25519_pub, take_ed25519_sig};
+        use nom::{combinator::complete, sequence::pair};
+
+        let (inner, input) = ExpansionGrantBuilder::from_wire_partial(input)?;
+
+        let (count, mut input) = take_shard_count(input, ExpansionGrant::MAX_SHARDS)?;
+        let mut participants = Vec::with_capacity(count.min(input.len()));
+        for _ in 0..count {
+            let (shard_id, remain) = take_shard_id(input)?;
+            let (remain, (public_key, signature)) =
+                WireError::nom(complete(pair(take_ed25519_pub, take_ed25519_sig))(remain))?;
+            participants.push(Participant {
+                shard_id,
+                public_key: public_key.map_err(|err| format!("{:?}", err))?,
+                signature: signature.map_err(|err| format!("{:?}", err))?,
+            });
+            input = remain;
+        }
+
+        Ok((
+            ExpansionGrant {
+                inner,
+                participants,
+            },
+            input,
+        ))
+    }
+}
+
+impl ToWire for ExpansionGrant {
+    fn to_wire(&self) -> Vec<u8> {
+        let body = self.wire_body();
+        let kind = DocumentKind::ExpansionGrant;
+        let mut bytes = Vec::with_capacity(header::record_len(kind, body.len()));
+        header::write_record_into(&mut bytes, kind, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        bytes
+    }
+}
+
+impl FromWire for ExpansionGrant {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(input, DocumentKind::ExpansionGrant, Self::from_wire_body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{Backup, UntrustedQuorum};
+
+    #[quickcheck]
+    fn expansion_grant_roundtrip(secret: Vec<u8>, n: u8) {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(backup.main_document().clone());
+        for _ in 0..2 {
+            quorum.push_shard(backup.next_shard().unwrap());
+        }
+        let (_, grant) = quorum
+            .validate()
+            .unwrap()
+            .extend_shards_with_grant(u32::from(n % 8))
+            .unwrap();
+
+        let bytes = grant.to_wire();
+        assert_eq!(header::detect(&bytes), DocumentKind::ExpansionGrant);
+        assert_eq!(ExpansionGrant::from_wire(bytes), Ok(grant));
+    }
+}
@@ -18,8 +18,11 @@
 
 use crate::{
     nom_helpers,
+    shamir::{WireError, WireLimits},
     v0::{
-        wire::prefixes::*, ChaChaPolyKey, ChaChaPolyNonce, CHACHAPOLY_KEY_LENGTH,
+        timelock::TIME_LOCK_LENGTH, watermark::WATERMARK_TAG_LENGTH, wire::prefixes::*,
+        AeadAlgorithm, Argon2Params, ChaChaPolyKey, ChaChaPolyNonce, Custodian, PassphraseKdf,
+        TimeLock, TimeLockParams, Watermark, ARGON2_SALT_LENGTH, CHACHAPOLY_KEY_LENGTH,
         CHACHAPOLY_NONCE_LENGTH,
     },
 };
@@ -29,9 +32,10 @@ use multihash::{DecodeError, MultihashRef};
 use nom::{
     branch::alt,
     bytes::streaming::{tag, take},
-    combinator::{map, verify},
+    combinator::{map, opt, verify},
     error::ErrorKind,
-    sequence::tuple,
+    multi::count,
+    sequence::{preceded, tuple},
     Err as NomErr, IResult, Needed,
 };
 
@@ -88,6 +92,171 @@ pub(super) fn take_ed25519_sec(
     Ok((input, private_key.map(SecretKey::from_bytes)))
 }
 
+pub(super) fn take_optional_id(prefix: u64) -> impl Fn(&[u8]) -> IResult<&[u8], Option<u32>> {
+    // Optional identifiers are omitted when they have their default value,
+    // which is how documents were encoded before they were added.
+    move |input| {
+        if input.is_empty() {
+            return Ok((input, None));
+        }
+        opt(preceded(
+            verify(nom_helpers::u64, move |x| *x == prefix),
+            nom_helpers::u32,
+        ))(input)
+    }
+}
+
+pub(super) fn take_optional_u64(prefix: u64) -> impl Fn(&[u8]) -> IResult<&[u8], Option<u64>> {
+    move |input| {
+        if input.is_empty() {
+            return Ok((input, None));
+        }
+        opt(preceded(
+            verify(nom_helpers::u64, move |x| *x == prefix),
+            nom_helpers::u64,
+        ))(input)
+    }
+}
+
+pub(super) fn take_optional_text(
+    prefix: u64,
+    max_length: usize,
+) -> impl Fn(&[u8]) -> IResult<&[u8], Option<String>> {
+    move |input| {
+        if input.is_empty() {
+            return Ok((input, None));
+        }
+        let (input, text) = opt(preceded(
+            verify(nom_helpers::u64, move |x| *x == prefix),
+            verify(nom_helpers::usize, move |len| *len <= max_length),
+        ))(input)?;
+        match text {
+            None => Ok((input, None)),
+            Some(length) => {
+                let (remain, text) = take(length)(input)?;
+                match std::str::from_utf8(text) {
+                    Ok(text) => Ok((remain, Some(text.into()))),
+                    Err(_) => Err(NomErr::Failure((input, ErrorKind::Verify))),
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn take_custodian(input: &[u8]) -> IResult<&[u8], Option<Custodian>> {
+    let (input, name) =
+        take_optional_text(PREFIX_CUSTODIAN_NAME, Custodian::MAX_NAME_LENGTH)(input)?;
+    match name {
+        // Contact details are only stored along with a name.
+        None => Ok((input, None)),
+        Some(name) => {
+            let (input, contact) =
+                take_optional_text(PREFIX_CUSTODIAN_CONTACT, Custodian::MAX_CONTACT_LENGTH)(input)?;
+            Ok((input, Some(Custodian { name, contact })))
+        }
+    }
+}
+
+pub(super) fn take_watermark(input: &[u8]) -> IResult<&[u8], Option<Watermark>> {
+    if input.is_empty() {
+        return Ok((input, None));
+    }
+    opt(map(
+        preceded(
+            verify(nom_helpers::u64, |x| *x == PREFIX_WATERMARK),
+            tuple((nom_helpers::u32, take(WATERMARK_TAG_LENGTH))),
+        ),
+        |(index, bytes): (u32, &[u8])| {
+            let mut tag = [0u8; WATERMARK_TAG_LENGTH];
+            tag.copy_from_slice(bytes);
+            Watermark { index, tag }
+        },
+    ))(input)
+}
+
+pub(super) fn take_time_lock(input: &[u8]) -> IResult<&[u8], Option<TimeLock>> {
+    fn value(bytes: &[u8]) -> [u8; TIME_LOCK_LENGTH] {
+        let mut value = [0u8; TIME_LOCK_LENGTH];
+        value.copy_from_slice(bytes);
+        value
+    }
+
+    if input.is_empty() {
+        return Ok((input, None));
+    }
+    let (input, header) = opt(preceded(
+        verify(nom_helpers::u64, |x| *x == PREFIX_TIME_LOCK),
+        verify(
+            tuple((
+                verify(nom_helpers::u64, |n| *n > 0),
+                verify(nom_helpers::u32, |n| {
+                    *n > 0 && *n <= TimeLockParams::MAX_CHAINS
+                }),
+                map(take(TIME_LOCK_LENGTH), value),
+            )),
+            // Bound the work needed to open the lock.
+            |(chain_iterations, chains, _)| {
+                chain_iterations.saturating_mul(u64::from(*chains))
+                    <= TimeLockParams::MAX_ITERATIONS
+            },
+        ),
+    ))(input)?;
+    match header {
+        None => Ok((input, None)),
+        Some((chain_iterations, chains, seed)) => {
+            let (input, links) =
+                count(map(take(TIME_LOCK_LENGTH), value), chains as usize - 1)(input)?;
+            let lock = TimeLock {
+                chain_iterations,
+                seed,
+                links,
+            };
+            Ok((input, Some(lock)))
+        }
+    }
+}
+
+// Shard identifiers are never longer than this.
+const MAX_SHARD_ID_LENGTH: usize = 64;
+
+// Parse a length-prefixed shard identifier.
+pub(super) fn take_shard_id(input: &[u8]) -> Result<(String, &[u8]), WireError> {
+    use nom::{bytes::complete::take, combinator::complete};
+
+    let (input, length) = WireError::nom(complete(nom_helpers::usize)(input))?;
+    WireLimits::check("shard id", MAX_SHARD_ID_LENGTH, length)?;
+    let (input, id) = WireError::nom(take(length)(input))?;
+    let id = String::from_utf8(id.to_vec()).map_err(|err| format!("{:?}", err))?;
+
+    Ok((id, input))
+}
+
+// Parse a count of shards, which must be at most limit.
+pub(super) fn take_shard_count(input: &[u8], limit: usize) -> Result<(usize, &[u8]), WireError> {
+    use nom::combinator::complete;
+
+    let (input, count) = WireError::nom(complete(nom_helpers::usize)(input))?;
+    WireLimits::check("shards", limit, count)?;
+
+    Ok((count, input))
+}
+
+pub(super) fn aead_nonce_prefix(algorithm: AeadAlgorithm) -> u64 {
+    match algorithm {
+        AeadAlgorithm::ChaCha20Poly1305 => PREFIX_CHACHA20POLY1305_NONCE,
+        AeadAlgorithm::XChaCha20Poly1305 => PREFIX_XCHACHA20POLY1305_NONCE,
+        AeadAlgorithm::Aes256Gcm => PREFIX_AES256GCM_NONCE,
+    }
+}
+
+pub(super) fn take_aead_nonce(algorithm: AeadAlgorithm) -> impl Fn(&[u8]) -> IResult<&[u8], &[u8]> {
+    move |input| {
+        let prefix = aead_nonce_prefix(algorithm);
+        let (input, _) = verify(nom_helpers::u64, |x| *x == prefix)(input)?;
+        take(algorithm.nonce_len())(input)
+    }
+}
+
 pub(super) fn take_chachapoly_key(input: &[u8]) -> IResult<&[u8], ChaChaPolyKey> {
     let (input, _) = verify(nom_helpers::u64, |x| *x == PREFIX_CHACHA20POLY1305_KEY)(input)?;
     let (input, key) = take(CHACHAPOLY_KEY_LENGTH)(input)?;
@@ -99,6 +268,32 @@ pub(super) fn take_chachapoly_key(input: &[u8]) -> IResult<&[u8], ChaChaPolyKey>
     }))
 }
 
+pub(super) fn take_argon2id_params(input: &[u8]) -> IResult<&[u8], Option<PassphraseKdf>> {
+    opt(preceded(
+        verify(nom_helpers::u64, |x| *x == PREFIX_ARGON2ID_PARAMS),
+        map(
+            tuple((
+                nom_helpers::u32,
+                nom_helpers::u32,
+                nom_helpers::u32,
+                take(ARGON2_SALT_LENGTH),
+            )),
+            |(memory_kib, iterations, lanes, salt)| {
+                let mut kdf = PassphraseKdf {
+                    params: Argon2Params {
+                        memory_kib,
+                        iterations,
+                        lanes,
+                    },
+                    salt: [0u8; ARGON2_SALT_LENGTH],
+                };
+                kdf.salt.copy_from_slice(salt);
+                kdf
+            },
+        ),
+    ))(input)
+}
+
 pub(super) fn take_chachapoly_nonce(input: &[u8]) -> IResult<&[u8], ChaChaPolyNonce> {
     let (input, _) = verify(nom_helpers::u64, |x| *x == PREFIX_CHACHA20POLY1305_NONCE)(input)?;
     let (input, nonce) = take(CHACHAPOLY_NONCE_LENGTH)(input)?;
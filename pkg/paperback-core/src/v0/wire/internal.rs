@@ -17,7 +17,7 @@
  */
 
 use crate::v0::{
-    wire::{prefixes::*, FromWire, ToWire},
+    wire::{prefixes::*, FromWire, ToWire, WireError},
     ChaChaPolyKey, Identity, ShardSecret,
 };
 
@@ -54,7 +54,7 @@ impl ToWire for Identity {
 
 // Internal only -- users can't see Identity.
 impl FromWire for Identity {
-    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), String> {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
         use crate::v0::wire::helpers::{take_ed25519_pub, take_ed25519_sig};
         use nom::{combinator::complete, IResult};
 
@@ -74,7 +74,7 @@ impl FromWire for Identity {
         }
         let parse = complete(parse);
 
-        let (remain, (public_key, signature)) = parse(input).map_err(|err| format!("{:?}", err))?;
+        let (remain, (public_key, signature)) = WireError::nom(parse(input))?;
 
         Ok((
             Identity {
@@ -119,7 +119,7 @@ impl ToWire for ShardSecret {
 
 // Internal only -- users can't see ShardSecret.
 impl FromWire for ShardSecret {
-    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), String> {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
         use crate::v0::wire::helpers::{take_chachapoly_key, take_ed25519_sec};
         use nom::{combinator::complete, IResult};
 
@@ -133,12 +133,12 @@ impl FromWire for ShardSecret {
         }
         let parse = complete(parse);
 
-        let (remain, (doc_key, private_key)) = parse(input).map_err(|err| format!("{:?}", err))?;
+        let (remain, (doc_key, private_key)) = WireError::nom(parse(input))?;
 
         let id_private_key = match private_key {
             Some(Ok(key)) => Some(key),
             None => None,
-            Some(Err(err)) => return Err(format!("{:?}", err)),
+            Some(Err(err)) => return Err(format!("{:?}", err).into()),
         };
 
         Ok((
@@ -17,17 +17,50 @@
  */
 
 use crate::{
-    shamir::Shard,
+    shamir::{
+        header::{self, DocumentKind},
+        Shard, WireLimits,
+    },
     v0::{
-        wire::{prefixes::*, FromWire, ToWire},
-        ChaChaPolyNonce, EncryptedKeyShard, Identity, KeyShard, KeyShardBuilder,
-        CHACHAPOLY_NONCE_LENGTH, CHECKSUM_ALGORITHM,
+        wire::{prefixes::*, FromWire, ToWire, WireError},
+        ChaChaPolyNonce, Custodian, EncryptedKeyShard, Identity, KeyShard, KeyShardBuilder,
+        PassphraseKdf, Watermark, CHACHAPOLY_NONCE_LENGTH, CHECKSUM_ALGORITHM,
     },
 };
 
 use multihash::{Multihash, MultihashDigest};
 use unsigned_varint::encode;
 
+// Append the encoding of a custodian (if there is one) to bytes.
+fn write_custodian(custodian: &Option<Custodian>, bytes: &mut Vec<u8>) {
+    if let Some(custodian) = custodian {
+        let texts = [
+            (PREFIX_CUSTODIAN_NAME, Some(&custodian.name)),
+            (PREFIX_CUSTODIAN_CONTACT, custodian.contact.as_ref()),
+        ];
+        for (prefix, text) in texts.iter() {
+            if let Some(text) = text {
+                encode::u64(*prefix, &mut encode::u64_buffer())
+                    .iter()
+                    .chain(encode::usize(text.len(), &mut encode::usize_buffer()))
+                    .chain(text.as_bytes())
+                    .for_each(|b| bytes.push(*b));
+            }
+        }
+    }
+}
+
+// Append the encoding of a watermark (if there is one) to bytes.
+fn write_watermark(watermark: &Option<Watermark>, bytes: &mut Vec<u8>) {
+    if let Some(watermark) = watermark {
+        encode::u64(PREFIX_WATERMARK, &mut encode::u64_buffer())
+            .iter()
+            .chain(encode::u32(watermark.index, &mut encode::u32_buffer()))
+            .chain(&watermark.tag)
+            .for_each(|b| bytes.push(*b));
+    }
+}
+
 // Internal only -- users can't see KeyShardBuilder.
 #[doc(hidden)]
 impl ToWire for KeyShardBuilder {
@@ -46,8 +79,28 @@ impl ToWire for KeyShardBuilder {
             .iter()
             .for_each(|b| bytes.push(*b));
 
-        // Encode shard data.
-        bytes.append(&mut self.shard.to_wire());
+        // Encode shard data. Key shards have their own version, so the shard
+        // is stored without a header. Shards decoded from the legacy layout are
+        // written back in that layout, so that their signed bytes are unchanged.
+        match self.legacy {
+            true => self.shard.write_legacy_wire_bytes(&mut bytes),
+            false => self.shard.write_wire_bytes(&mut bytes),
+        }
+
+        // Encode review-by date (only if it is present, so that the signed
+        // bytes of shards without one are unchanged).
+        if let Some(review_by) = self.review_by {
+            encode::u64(PREFIX_REVIEW_BY, &mut encode::u64_buffer())
+                .iter()
+                .chain(encode::u64(review_by, &mut encode::u64_buffer()))
+                .for_each(|b| bytes.push(*b));
+        }
+
+        // Encode custodian (only if there is one).
+        write_custodian(&self.custodian, &mut bytes);
+
+        // Encode watermark (only if there is one).
+        write_watermark(&self.watermark, &mut bytes);
 
         bytes
     }
@@ -56,8 +109,11 @@ impl ToWire for KeyShardBuilder {
 // Internal only -- users can't see KeyShardBuilder.
 #[doc(hidden)]
 impl FromWire for KeyShardBuilder {
-    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), String> {
-        use crate::{nom_helpers, v0::wire::helpers::multihash};
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::{
+            nom_helpers,
+            v0::wire::helpers::{multihash, take_custodian, take_optional_u64, take_watermark},
+        };
         use nom::{combinator::complete, IResult};
 
         fn parse(input: &[u8]) -> IResult<&[u8], (u32, Multihash)> {
@@ -68,14 +124,41 @@ impl FromWire for KeyShardBuilder {
         }
         let parse = complete(parse);
 
-        let (input, (version, doc_chksum)) = parse(input).map_err(|err| format!("{:?}", err))?;
-        let (shard, remain) = Shard::from_wire_partial(input)?;
+        let (input, (version, doc_chksum)) = WireError::nom(parse(input))?;
+
+        // Key shards written before the wire header was added store the shard
+        // in its legacy layout, which is followed directly by the identity of
+        // the key shard (or by nothing at all). The current layout always
+        // continues with a chunk size of at most four bytes, which can never be
+        // mistaken for the identity prefix.
+        let legacy_end = |remain: &[u8]| match nom_helpers::u32(remain) {
+            Ok((_, prefix)) => prefix == PREFIX_ED25519_PUB,
+            Err(_) => remain.is_empty(),
+        };
+        let (shard, input, legacy) = match Shard::from_legacy_wire_body(input, &WireLimits::DEFAULT)
+        {
+            Ok((shard, remain)) if legacy_end(remain) => (shard, remain, true),
+            _ => {
+                let (shard, remain) = Shard::from_wire_body(input, &WireLimits::DEFAULT)?;
+                (shard, remain, false)
+            }
+        };
+        let (input, review_by) = WireError::nom(take_optional_u64(PREFIX_REVIEW_BY)(input))?;
+        let (input, custodian) = WireError::nom(take_custodian(input))?;
+        if let Some(custodian) = &custodian {
+            custodian.validate()?;
+        }
+        let (remain, watermark) = WireError::nom(take_watermark(input))?;
 
         Ok((
             KeyShardBuilder {
                 version,
                 doc_chksum,
                 shard,
+                review_by,
+                custodian,
+                watermark,
+                legacy,
             },
             remain,
         ))
@@ -98,30 +181,47 @@ impl ToWire for KeyShard {
 /// Internal only -- users should use EncryptedKeyShard's FromWire.
 #[doc(hidden)]
 impl FromWire for KeyShard {
-    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), String> {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
         let (inner, input) = KeyShardBuilder::from_wire_partial(input)?;
         let (identity, input) = Identity::from_wire_partial(input)?;
 
         if inner.doc_chksum.algorithm() != CHECKSUM_ALGORITHM.code() {
-            return Err(format!("document checksum must be Blake2b-256",));
+            return Err(String::from("document checksum must be Blake2b-256").into());
         }
 
         if inner.version != 0 {
-            return Err(format!(
-                "key shard version must be '0' not '{}'",
-                inner.version
-            ));
+            return Err(WireError::UnsupportedVersion {
+                version: inner.version,
+            });
         }
 
         Ok((KeyShard { inner, identity }, input))
     }
 }
 
-impl ToWire for EncryptedKeyShard {
-    fn to_wire(&self) -> Vec<u8> {
+impl EncryptedKeyShard {
+    // Wire encoding of the shard without the header (which is how shards were
+    // encoded before the header was added).
+    fn wire_body(&self) -> Vec<u8> {
         let mut buffer = encode::u64_buffer();
         let mut bytes = vec![];
 
+        // Encode custodian and watermark (only if there are any).
+        write_custodian(&self.custodian, &mut bytes);
+        write_watermark(&self.watermark, &mut bytes);
+
+        // Encode Argon2id parameters (only for passphrase-protected shards).
+        if let Some(kdf) = &self.kdf {
+            let params = &kdf.params;
+            encode::u64(PREFIX_ARGON2ID_PARAMS, &mut buffer)
+                .iter()
+                .chain(encode::u32(params.memory_kib, &mut encode::u32_buffer()))
+                .chain(encode::u32(params.iterations, &mut encode::u32_buffer()))
+                .chain(encode::u32(params.lanes, &mut encode::u32_buffer()))
+                .chain(&kdf.salt)
+                .for_each(|b| bytes.push(*b));
+        }
+
         // Encode ChaCha20-Poly1305 nonce.
         encode::u64(PREFIX_CHACHA20POLY1305_NONCE, &mut buffer)
             .iter()
@@ -141,25 +241,47 @@ impl ToWire for EncryptedKeyShard {
 
         bytes
     }
-}
 
-impl FromWire for EncryptedKeyShard {
-    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), String> {
-        use crate::v0::wire::helpers::{take_chachapoly_ciphertext, take_chachapoly_nonce};
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::v0::wire::helpers::{
+            take_argon2id_params, take_chachapoly_ciphertext, take_chachapoly_nonce,
+            take_custodian, take_watermark,
+        };
         use nom::{combinator::complete, IResult};
 
-        fn parse(input: &[u8]) -> IResult<&[u8], (ChaChaPolyNonce, &[u8])> {
+        type Parsed<'a> = (
+            Option<Custodian>,
+            Option<Watermark>,
+            Option<PassphraseKdf>,
+            ChaChaPolyNonce,
+            &'a [u8],
+        );
+
+        fn parse(input: &[u8]) -> IResult<&[u8], Parsed<'_>> {
+            let (input, custodian) = take_custodian(input)?;
+            let (input, watermark) = take_watermark(input)?;
+            let (input, kdf) = take_argon2id_params(input)?;
             let (input, nonce) = take_chachapoly_nonce(input)?;
             let (input, ciphertext) = take_chachapoly_ciphertext(input)?;
 
-            Ok((input, (nonce, ciphertext)))
+            Ok((input, (custodian, watermark, kdf, nonce, ciphertext)))
         }
         let parse = complete(parse);
 
-        let (remain, (nonce, ciphertext)) = parse(input).map_err(|err| format!("{:?}", err))?;
+        let (remain, (custodian, watermark, kdf, nonce, ciphertext)) =
+            WireError::nom(parse(input))?;
+        if let Some(custodian) = &custodian {
+            custodian.validate()?;
+        }
+        if let Some(kdf) = &kdf {
+            kdf.params.validate()?;
+        }
 
         Ok((
             EncryptedKeyShard {
+                custodian,
+                watermark,
+                kdf,
                 nonce,
                 ciphertext: ciphertext.into(),
             },
@@ -168,6 +290,24 @@ impl FromWire for EncryptedKeyShard {
     }
 }
 
+impl ToWire for EncryptedKeyShard {
+    fn to_wire(&self) -> Vec<u8> {
+        let body = self.wire_body();
+        let kind = DocumentKind::EncryptedKeyShard;
+        let mut bytes = Vec::with_capacity(header::record_len(kind, body.len()));
+        header::write_record_into(&mut bytes, kind, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        bytes
+    }
+}
+
+impl FromWire for EncryptedKeyShard {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(input, DocumentKind::EncryptedKeyShard, Self::from_wire_body)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -188,5 +328,13 @@ mod test {
     fn encrypted_key_shard_roundtrip(shard: EncryptedKeyShard) {
         let shard2 = EncryptedKeyShard::from_wire(shard.to_wire()).unwrap();
         assert_eq!(shard, shard2);
+
+        // Shards written before the header was added are still readable.
+        let shard3 = EncryptedKeyShard::from_wire(shard.wire_body()).unwrap();
+        assert_eq!(shard, shard3);
+        assert_eq!(
+            header::detect(shard.to_wire()),
+            DocumentKind::EncryptedKeyShard
+        );
     }
 }
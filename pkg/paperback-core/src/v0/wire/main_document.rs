@@ -16,13 +16,21 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::v0::{
-    wire::{prefixes::*, FromWire, ToWire},
-    ChaChaPolyNonce, Identity, MainDocument, MainDocumentBuilder, MainDocumentMeta,
+use crate::{
+    shamir::header::{self, DocumentKind},
+    v0::{
+        wire::{helpers::aead_nonce_prefix, prefixes::*, FromWire, ToWire, WireError},
+        AeadAlgorithm, Compression, Identity, MainDocument, MainDocumentBuilder, MainDocumentMeta,
+        MainDocumentPage, Metadata, TimeLock,
+    },
 };
 
 use unsigned_varint::encode;
 
+// The only padding scheme: the plaintext is prefixed with the length of the
+// contents, and the padding follows the contents.
+pub(super) const PADDING_LENGTH_PREFIXED: u32 = 1;
+
 // Internal only -- users can't see MainDocumentMeta.
 #[doc(hidden)]
 impl ToWire for MainDocumentMeta {
@@ -40,6 +48,93 @@ impl ToWire for MainDocumentMeta {
             .iter()
             .for_each(|b| bytes.push(*b));
 
+        // Encode compression (only for compressed documents).
+        if let Some(id) = self.compression.wire_id() {
+            encode::u64(PREFIX_COMPRESSION, &mut encode::u64_buffer())
+                .iter()
+                .chain(encode::u32(id, &mut buffer))
+                .for_each(|b| bytes.push(*b));
+        }
+
+        // Encode AEAD algorithm (only if it isn't the default).
+        if let Some(id) = self.algorithm.wire_id() {
+            encode::u64(PREFIX_AEAD_ALGORITHM, &mut encode::u64_buffer())
+                .iter()
+                .chain(encode::u32(id, &mut buffer))
+                .for_each(|b| bytes.push(*b));
+        }
+
+        // Encode label and comment (only if they are present).
+        let texts = [
+            (PREFIX_LABEL, &self.metadata.label),
+            (PREFIX_COMMENT, &self.metadata.comment),
+        ];
+        for (prefix, text) in texts.iter() {
+            if let Some(text) = text {
+                encode::u64(*prefix, &mut encode::u64_buffer())
+                    .iter()
+                    .chain(encode::usize(text.len(), &mut encode::usize_buffer()))
+                    .chain(text.as_bytes())
+                    .for_each(|b| bytes.push(*b));
+            }
+        }
+
+        // Encode creation time and review-by date (only if they are present).
+        let times = [
+            (PREFIX_CREATED, self.metadata.created),
+            (PREFIX_REVIEW_BY, self.metadata.review_by),
+        ];
+        for (prefix, time) in times.iter() {
+            if let Some(time) = time {
+                encode::u64(*prefix, &mut encode::u64_buffer())
+                    .iter()
+                    .chain(encode::u64(*time, &mut encode::u64_buffer()))
+                    .for_each(|b| bytes.push(*b));
+            }
+        }
+
+        // Encode padding scheme (only for padded documents).
+        if self.padded {
+            encode::u64(PREFIX_PADDING, &mut encode::u64_buffer())
+                .iter()
+                .chain(encode::u32(PADDING_LENGTH_PREFIXED, &mut buffer))
+                .for_each(|b| bytes.push(*b));
+        }
+
+        // Encode chunk size (only if one was chosen).
+        if let Some(chunk_size) = self.chunk_size {
+            encode::u64(PREFIX_CHUNK_SIZE, &mut encode::u64_buffer())
+                .iter()
+                .chain(encode::u32(chunk_size, &mut buffer))
+                .for_each(|b| bytes.push(*b));
+        }
+
+        // Encode content type (only if it is present).
+        if let Some(content_type) = &self.metadata.content_type {
+            encode::u64(PREFIX_CONTENT_TYPE, &mut encode::u64_buffer())
+                .iter()
+                .chain(encode::usize(
+                    content_type.len(),
+                    &mut encode::usize_buffer(),
+                ))
+                .chain(content_type.as_bytes())
+                .for_each(|b| bytes.push(*b));
+        }
+
+        // Encode time lock (only for time-locked documents).
+        if let Some(lock) = &self.time_lock {
+            encode::u64(PREFIX_TIME_LOCK, &mut encode::u64_buffer())
+                .iter()
+                .chain(encode::u64(
+                    lock.chain_iterations,
+                    &mut encode::u64_buffer(),
+                ))
+                .chain(encode::u32(lock.chains(), &mut buffer))
+                .chain(&lock.seed)
+                .chain(lock.links.iter().flatten())
+                .for_each(|b| bytes.push(*b));
+        }
+
         bytes
     }
 }
@@ -47,24 +142,108 @@ impl ToWire for MainDocumentMeta {
 // Internal only -- users can't see MainDocumentMeta.
 #[doc(hidden)]
 impl FromWire for MainDocumentMeta {
-    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), String> {
-        use crate::nom_helpers;
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::{
+            nom_helpers,
+            v0::wire::helpers::{
+                take_optional_id, take_optional_text, take_optional_u64, take_time_lock,
+            },
+        };
         use nom::{combinator::complete, IResult};
 
-        fn parse(input: &[u8]) -> IResult<&[u8], MainDocumentMeta> {
+        type Parsed = (
+            u32,
+            u32,
+            Option<u32>,
+            Option<u32>,
+            Metadata,
+            Option<u32>,
+            Option<u32>,
+            Option<TimeLock>,
+        );
+
+        fn parse(input: &[u8]) -> IResult<&[u8], Parsed> {
             let (input, version) = nom_helpers::u32(input)?;
             let (input, quorum_size) = nom_helpers::u32(input)?;
+            let (input, compression) = take_optional_id(PREFIX_COMPRESSION)(input)?;
+            let (input, algorithm) = take_optional_id(PREFIX_AEAD_ALGORITHM)(input)?;
+            let (input, label) =
+                take_optional_text(PREFIX_LABEL, Metadata::MAX_LABEL_LENGTH)(input)?;
+            let (input, comment) =
+                take_optional_text(PREFIX_COMMENT, Metadata::MAX_COMMENT_LENGTH)(input)?;
+            let (input, created) = take_optional_u64(PREFIX_CREATED)(input)?;
+            let (input, review_by) = take_optional_u64(PREFIX_REVIEW_BY)(input)?;
+            let (input, padding) = take_optional_id(PREFIX_PADDING)(input)?;
+            let (input, chunk_size) = take_optional_id(PREFIX_CHUNK_SIZE)(input)?;
+            let (input, content_type) =
+                take_optional_text(PREFIX_CONTENT_TYPE, Metadata::MAX_CONTENT_TYPE_LENGTH)(input)?;
+            let (input, time_lock) = take_time_lock(input)?;
 
-            let meta = MainDocumentMeta {
-                version,
-                quorum_size,
+            let metadata = Metadata {
+                label,
+                comment,
+                created,
+                review_by,
+                content_type,
             };
-
-            Ok((input, meta))
+            Ok((
+                input,
+                (
+                    version,
+                    quorum_size,
+                    compression,
+                    algorithm,
+                    metadata,
+                    padding,
+                    chunk_size,
+                    time_lock,
+                ),
+            ))
         }
         let parse = complete(parse);
 
-        let (remain, meta) = parse(input).map_err(|err| format!("{:?}", err))?;
+        let (
+            remain,
+            (
+                version,
+                quorum_size,
+                compression,
+                algorithm,
+                metadata,
+                padding,
+                chunk_size,
+                time_lock,
+            ),
+        ) = WireError::nom(parse(input))?;
+        metadata.validate()?;
+        let compression = match compression {
+            None => Compression::None,
+            Some(id) => Compression::from_wire_id(id)
+                .ok_or_else(|| format!("unknown compression algorithm '{}'", id))?,
+        };
+        let algorithm = match algorithm {
+            None => AeadAlgorithm::default(),
+            Some(id) => AeadAlgorithm::from_wire_id(id)
+                .ok_or_else(|| format!("unknown aead algorithm '{}'", id))?,
+        };
+        let padded = match padding {
+            None => false,
+            Some(PADDING_LENGTH_PREFIXED) => true,
+            Some(id) => return Err(format!("unknown padding scheme '{}'", id).into()),
+        };
+        if let Some(chunk_size) = chunk_size {
+            MainDocumentPage::check_chunk_size(chunk_size)?;
+        }
+        let meta = MainDocumentMeta {
+            version,
+            quorum_size,
+            compression,
+            algorithm,
+            metadata,
+            padded,
+            chunk_size,
+            time_lock,
+        };
         Ok((meta, remain))
     }
 }
@@ -80,7 +259,7 @@ impl ToWire for MainDocumentBuilder {
         bytes.append(&mut self.meta.to_wire());
 
         // Encode nonce.
-        encode::u64(PREFIX_CHACHA20POLY1305_NONCE, &mut buffer)
+        encode::u64(aead_nonce_prefix(self.meta.algorithm), &mut buffer)
             .iter()
             .chain(&self.nonce)
             .for_each(|b| bytes.push(*b));
@@ -102,25 +281,22 @@ impl ToWire for MainDocumentBuilder {
 // Internal only -- users can't see MainDocumentBuilder.
 #[doc(hidden)]
 impl FromWire for MainDocumentBuilder {
-    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), String> {
-        use crate::v0::wire::helpers::{take_chachapoly_ciphertext, take_chachapoly_nonce};
-        use nom::{combinator::complete, IResult};
-
-        fn parse(input: &[u8]) -> IResult<&[u8], (ChaChaPolyNonce, &[u8])> {
-            let (input, nonce) = take_chachapoly_nonce(input)?;
-            let (input, ciphertext) = take_chachapoly_ciphertext(input)?;
-
-            Ok((input, (nonce, ciphertext)))
-        }
-        let parse = complete(parse);
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::v0::wire::helpers::{take_aead_nonce, take_chachapoly_ciphertext};
+        use nom::{combinator::complete, sequence::pair};
 
         let (meta, input) = MainDocumentMeta::from_wire_partial(input)?;
-        let (remain, (nonce, ciphertext)) = parse(input).map_err(|err| format!("{:?}", err))?;
+        // The nonce depends on the algorithm, which is in the metadata.
+        let parse = complete(pair(
+            take_aead_nonce(meta.algorithm),
+            take_chachapoly_ciphertext,
+        ));
+        let (remain, (nonce, ciphertext)) = WireError::nom(parse(input))?;
 
         Ok((
             MainDocumentBuilder {
                 meta,
-                nonce,
+                nonce: nonce.into(),
                 ciphertext: ciphertext.into(),
             },
             remain,
@@ -128,8 +304,10 @@ impl FromWire for MainDocumentBuilder {
     }
 }
 
-impl ToWire for MainDocument {
-    fn to_wire(&self) -> Vec<u8> {
+impl MainDocument {
+    // Wire encoding of the document without the header (which is how
+    // documents were encoded before the header was added).
+    pub(crate) fn wire_body(&self) -> Vec<u8> {
         let mut bytes = vec![];
 
         bytes.append(&mut self.inner.to_wire());
@@ -137,24 +315,39 @@ impl ToWire for MainDocument {
 
         bytes
     }
-}
 
-impl FromWire for MainDocument {
-    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), String> {
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
         let (inner, input) = MainDocumentBuilder::from_wire_partial(input)?;
         let (identity, input) = Identity::from_wire_partial(input)?;
 
         if inner.meta.version != 0 {
-            return Err(format!(
-                "main document version must be '0' not '{}'",
-                inner.meta.version
-            ));
+            return Err(WireError::UnsupportedVersion {
+                version: inner.meta.version,
+            });
         }
 
         Ok((MainDocument { inner, identity }, input))
     }
 }
 
+impl ToWire for MainDocument {
+    fn to_wire(&self) -> Vec<u8> {
+        let body = self.wire_body();
+        let kind = DocumentKind::MainDocument;
+        let mut bytes = Vec::with_capacity(header::record_len(kind, body.len()));
+        header::write_record_into(&mut bytes, kind, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        bytes
+    }
+}
+
+impl FromWire for MainDocument {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(input, DocumentKind::MainDocument, Self::from_wire_body)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -170,4 +363,111 @@ mod test {
         let meta2 = MainDocumentMeta::from_wire(main.inner.meta.to_wire()).unwrap();
         assert_eq!(main.inner.meta, meta2);
     }
+
+    #[quickcheck]
+    fn main_document_meta_compression(quorum_size: u32) -> bool {
+        let meta = MainDocumentMeta {
+            version: 0,
+            quorum_size,
+            compression: Compression::None,
+            algorithm: AeadAlgorithm::default(),
+            metadata: Metadata::default(),
+            padded: false,
+            chunk_size: None,
+            time_lock: None,
+        };
+        let compressed = MainDocumentMeta {
+            compression: Compression::Deflate,
+            ..meta.clone()
+        };
+
+        // Uncompressed documents are encoded exactly as they were before
+        // compression was supported.
+        let mut legacy = Vec::new();
+        legacy.extend_from_slice(encode::u32(0, &mut encode::u32_buffer()));
+        legacy.extend_from_slice(encode::u32(quorum_size, &mut encode::u32_buffer()));
+
+        meta.to_wire() == legacy
+            && compressed.to_wire() != legacy
+            && MainDocumentMeta::from_wire(compressed.to_wire()) == Ok(compressed)
+    }
+
+    #[quickcheck]
+    fn main_document_meta_metadata(quorum_size: u32, metadata: Metadata) -> bool {
+        let meta = MainDocumentMeta {
+            version: 0,
+            quorum_size,
+            compression: Compression::None,
+            algorithm: AeadAlgorithm::default(),
+            metadata,
+            padded: false,
+            chunk_size: None,
+            time_lock: None,
+        };
+        let oversized = MainDocumentMeta {
+            metadata: Metadata {
+                label: Some("x".repeat(Metadata::MAX_LABEL_LENGTH + 1)),
+                ..Default::default()
+            },
+            ..meta.clone()
+        };
+
+        MainDocumentMeta::from_wire(meta.to_wire()) == Ok(meta)
+            && MainDocumentMeta::from_wire(oversized.to_wire()).is_err()
+    }
+
+    #[quickcheck]
+    fn main_document_kind(main: MainDocument) -> bool {
+        // Documents written before the header was added are still readable,
+        // and have the same checksum.
+        let legacy = main.wire_body();
+        header::detect(main.to_wire()) == DocumentKind::MainDocument
+            && header::detect(&legacy) == DocumentKind::Unknown
+            && MainDocument::from_wire(&legacy).map(|main| main.checksum()) == Ok(main.checksum())
+            && crate::v0::EncryptedKeyShard::from_wire(main.to_wire()).is_err()
+    }
+
+    #[quickcheck]
+    fn main_document_from_reader(main: MainDocument) {
+        let mut stream = main.to_wire();
+        stream.extend(main.to_wire());
+        let mut reader = std::io::Cursor::new(&stream);
+
+        assert_eq!(MainDocument::from_reader(&mut reader).unwrap(), main);
+        assert_eq!(MainDocument::from_reader(&mut reader).unwrap(), main);
+        assert_eq!(
+            MainDocument::from_reader(&mut reader).unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    // Reader which returns at most max bytes from each call to read.
+    struct ShortReader<R> {
+        inner: R,
+        max: usize,
+    }
+
+    impl<R: std::io::Read> std::io::Read for ShortReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.max);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    #[quickcheck]
+    fn main_document_from_short_reader(main: MainDocument, max: usize) {
+        let mut stream = main.to_wire();
+        stream.extend(main.to_wire());
+        let mut reader = ShortReader {
+            inner: std::io::Cursor::new(&stream),
+            max: 1 + max % 16,
+        };
+
+        assert_eq!(MainDocument::from_reader(&mut reader).unwrap(), main);
+        assert_eq!(MainDocument::from_reader(&mut reader).unwrap(), main);
+        assert_eq!(
+            MainDocument::from_reader(&mut reader).unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
 }
@@ -16,10 +16,27 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+mod bundle;
+#[cfg(feature = "cbor")]
+mod cbor;
+mod grant;
 mod helpers;
 mod internal;
 mod key_shard;
 mod main_document;
+mod pages;
+mod possession;
+mod record;
+mod revoke;
+mod signature;
+mod stream;
+mod supersede;
+
+pub use crate::shamir::WireError;
+
+use crate::shamir::header;
+
+use std::io::{self, Read};
 
 pub(crate) mod prefixes {
     /// Prefix for an ed25519 public key.
@@ -45,22 +62,165 @@ pub(crate) mod prefixes {
     // NOTE: Entirely our own creation and not remotely upstreamable.
     pub(super) const PREFIX_CHACHA20POLY1305_NONCE: u64 = 0xfe_caca20_1305;
 
+    /// Prefix for an XChaCha20-Poly1305 nonce.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_XCHACHA20POLY1305_NONCE: u64 = 0xfaca_ca20_1305;
+
+    /// Prefix for an AES-256-GCM nonce.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_AES256GCM_NONCE: u64 = 0xfe_ae52_56c3;
+
     /// Prefix for a ChaCha20-Poly1305 nonce.
     // NOTE: Entirely our own creation and not remotely upstreamable.
     pub(super) const PREFIX_CHACHA20POLY1305_CIPHERTEXT: u64 = 0xfc_caca20_1305;
+
+    /// Prefix for the compression algorithm applied to a document's contents.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_COMPRESSION: u64 = 0xfd_c0de_c0de;
+
+    /// Prefix for the AEAD algorithm used to encrypt a document's contents.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_AEAD_ALGORITHM: u64 = 0xf9_aead;
+
+    /// Prefix for the Argon2id parameters used to derive a key from a passphrase.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_ARGON2ID_PARAMS: u64 = 0xfb_a2901d;
+
+    /// Prefix for the label of a document.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_LABEL: u64 = 0xf8_1abe;
+
+    /// Prefix for the comment of a document.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_COMMENT: u64 = 0xf7_c033;
+
+    /// Prefix for the creation time of a document.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_CREATED: u64 = 0xf6_c7ed;
+
+    /// Prefix for the review-by date of a document or key shard.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_REVIEW_BY: u64 = 0xf5_4e71;
+
+    /// Prefix for the name of the custodian of a key shard.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_CUSTODIAN_NAME: u64 = 0xf4_c057;
+
+    /// Prefix for the contact details of the custodian of a key shard.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_CUSTODIAN_CONTACT: u64 = 0xf3_c057;
+
+    /// Prefix for the padding scheme of a document's plaintext.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_PADDING: u64 = 0xf2_9add;
+
+    /// Prefix for the identity public key of the backup which superseded a
+    /// backup.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_SUCCESSOR_ED25519_PUB: u64 = 0xf1_5cce;
+
+    /// Prefix for the number of bytes of a document stored on each printed
+    /// page.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_CHUNK_SIZE: u64 = 0xf0_c4c4;
+
+    /// Prefix for the watermark identifying the custodian of a key shard.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_WATERMARK: u64 = 0xef_3a7e;
+
+    /// Prefix for the MIME type of the contents of a document.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_CONTENT_TYPE: u64 = 0xee_c7e7;
+
+    /// Prefix for the time lock protecting the document key of a backup.
+    // NOTE: Entirely our own creation and not remotely upstreamable.
+    pub(super) const PREFIX_TIME_LOCK: u64 = 0xed_71c0;
 }
 
 pub trait ToWire {
     fn to_wire(&self) -> Vec<u8>;
+
+    /// Length (in bytes) of the serialised object, so that buffers can be
+    /// allocated up-front for `to_wire_into`.
+    ///
+    /// The default implementation serialises the object to find its length,
+    /// so implementors should override it if the length is cheaper to compute.
+    fn wire_len(&self) -> usize {
+        self.to_wire().len()
+    }
+
+    /// Append the serialised object to `bytes`, allowing many objects to be
+    /// serialised into a single buffer.
+    ///
+    /// The default implementation copies the output of `to_wire`.
+    fn to_wire_into(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_wire())
+    }
 }
 
+/// Deserialise an object from the wire format.
+///
+/// Only canonical encodings are accepted (every varint must be in its shortest
+/// form), so re-serialising a deserialised object with `ToWire` gives back the
+/// same bytes. The one exception is shards written before the wire format
+/// header was added, which are re-serialised with the current header.
 pub trait FromWire: Sized {
-    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), String>;
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError>;
 
-    fn from_wire<B: AsRef<[u8]>>(input: B) -> Result<Self, String> {
+    fn from_wire<B: AsRef<[u8]>>(input: B) -> Result<Self, WireError> {
         match Self::from_wire_partial(input.as_ref())? {
             (ret, []) => Ok(ret),
-            _ => Err("trailing bytes left after deseralisation".into()),
+            (_, remain) => Err(WireError::TrailingData {
+                length: remain.len(),
+            }),
+        }
+    }
+
+    /// Deserialise an object from a stream, reading no more bytes than the
+    /// object occupies (so that objects concatenated in a single stream can
+    /// be read one after another).
+    ///
+    /// The header of the object is read a byte at a time (up to the length of
+    /// the object), and then the rest of the object is read at once and
+    /// parsed. Objects written before the header was added can only be
+    /// delimited by parsing them, so they are read a byte at a time and should
+    /// be buffered (with `std::io::BufReader`, for instance) if reads are
+    /// expensive. If the stream ends before the object is complete, an error
+    /// of kind `UnexpectedEof` is returned. Any other `WireError` is returned
+    /// as an error of kind `InvalidData`.
+    fn from_reader<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        let eof = |err| io::Error::new(io::ErrorKind::UnexpectedEof, err);
+        let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+
+        let mut buffer = vec![];
+        while let Some((field, _, missing)) = header::truncation(&buffer) {
+            // Only read the magic a byte at a time, in case this is a (short)
+            // object without a header.
+            let missing = match field {
+                "magic" => 1,
+                _ => missing,
+            };
+            if Read::take(&mut *reader, missing as u64).read_to_end(&mut buffer)? < missing {
+                return Err(eof(WireError::UnexpectedEof));
+            }
+        }
+        if buffer.starts_with(&header::WIRE_MAGIC) {
+            return Self::from_wire(&buffer).map_err(invalid);
+        }
+
+        loop {
+            match Self::from_wire_partial(&buffer) {
+                Ok((ret, _)) => return Ok(ret),
+                Err(err) if err.cause() == &WireError::UnexpectedEof => {
+                    let mut byte = [0u8];
+                    match reader.read_exact(&mut byte) {
+                        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(eof(err)),
+                        res => res?,
+                    }
+                    buffer.push(byte[0]);
+                }
+                Err(err) => return Err(invalid(err)),
+            }
         }
     }
 }
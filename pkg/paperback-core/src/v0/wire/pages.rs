@@ -0,0 +1,136 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    nom_helpers,
+    shamir::{
+        encoding, erasure_piece_len,
+        header::{self, DocumentKind},
+        WireLimits,
+    },
+    v0::{
+        wire::{FromWire, ToWire, WireError},
+        MainDocumentPage, CHECKSUM_ALGORITHM,
+    },
+};
+
+use multihash::MultihashDigest;
+
+impl MainDocumentPage {
+    fn wire_body(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        // Encode multihash checksum.
+        bytes.extend_from_slice(self.doc_chksum.as_bytes());
+
+        // Encode the page index, threshold, page count and document length.
+        encoding::varint(self.index.into(), &mut bytes);
+        encoding::varint(self.threshold.into(), &mut bytes);
+        encoding::varint(self.page_count.into(), &mut bytes);
+        encoding::varint(self.length as u64, &mut bytes);
+
+        // Encode page data (length-prefixed).
+        encoding::varint(self.data.len() as u64, &mut bytes);
+        bytes.extend_from_slice(&self.data);
+
+        bytes
+    }
+
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::v0::wire::helpers::multihash;
+        use nom::{bytes::complete::take, combinator::complete};
+
+        let (input, doc_chksum) = WireError::nom(complete(multihash)(input))?;
+        if doc_chksum.algorithm() != CHECKSUM_ALGORITHM.code() {
+            return Err(String::from("document checksum must be Blake2b-256").into());
+        }
+
+        let (input, index) = WireError::nom(complete(nom_helpers::u32)(input))?;
+        let (input, threshold) = WireError::nom(complete(nom_helpers::u32)(input))?;
+        let (input, page_count) = WireError::nom(complete(nom_helpers::u32)(input))?;
+        WireLimits::check(
+            "pages",
+            MainDocumentPage::MAX_PAGES as usize,
+            page_count as usize,
+        )?;
+        if threshold == 0 || threshold > page_count {
+            return Err(format!("invalid page threshold {}", threshold).into());
+        }
+        if index == 0 || index > page_count {
+            return Err(format!("invalid page index {}", index).into());
+        }
+
+        let (input, length) = WireError::nom(complete(nom_helpers::usize)(input))?;
+        WireLimits::check(
+            "main document",
+            MainDocumentPage::MAX_DOCUMENT_LENGTH,
+            length,
+        )?;
+        let (input, data_len) = WireError::nom(complete(nom_helpers::usize)(input))?;
+        if data_len != erasure_piece_len(length, threshold) {
+            return Err(String::from("page data has the wrong length").into());
+        }
+        let (input, data) = WireError::nom(take(data_len)(input))?;
+
+        Ok((
+            MainDocumentPage {
+                doc_chksum: doc_chksum.to_owned(),
+                index,
+                threshold,
+                page_count,
+                length,
+                data: data.to_vec(),
+            },
+            input,
+        ))
+    }
+}
+
+impl ToWire for MainDocumentPage {
+    fn to_wire(&self) -> Vec<u8> {
+        let body = self.wire_body();
+        let kind = DocumentKind::MainDocumentPage;
+        let mut bytes = Vec::with_capacity(header::record_len(kind, body.len()));
+        header::write_record_into(&mut bytes, kind, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        bytes
+    }
+}
+
+impl FromWire for MainDocumentPage {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(input, DocumentKind::MainDocumentPage, Self::from_wire_body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::Backup;
+
+    #[quickcheck]
+    fn main_document_page_roundtrip(secret: Vec<u8>) {
+        let backup = Backup::new(2, &secret).unwrap();
+        for page in backup.main_document().to_pages(2, 3).unwrap() {
+            let page2 = MainDocumentPage::from_wire(page.to_wire()).unwrap();
+            assert_eq!(page, page2);
+        }
+    }
+}
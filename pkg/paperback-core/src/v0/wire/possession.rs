@@ -0,0 +1,105 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::header::{self, DocumentKind},
+    v0::{
+        possession::POSSESSION_KEY_LENGTH,
+        wire::{helpers::take_shard_id, FromWire, ToWire, WireError},
+        PossessionVerifier, CHECKSUM_ALGORITHM,
+    },
+};
+
+use multihash::MultihashDigest;
+use unsigned_varint::encode;
+
+impl PossessionVerifier {
+    fn wire_body(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        // Encode multihash checksum.
+        bytes.extend_from_slice(self.doc_chksum.as_bytes());
+
+        // Encode shard identifier (length-prefixed) and possession key.
+        encode::usize(self.shard_id.len(), &mut encode::usize_buffer())
+            .iter()
+            .chain(self.shard_id.as_bytes())
+            .chain(&self.key)
+            .for_each(|b| bytes.push(*b));
+
+        bytes
+    }
+
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::v0::wire::helpers::multihash;
+        use nom::{bytes::complete::take, combinator::complete};
+
+        let (input, doc_chksum) = WireError::nom(complete(multihash)(input))?;
+        if doc_chksum.algorithm() != CHECKSUM_ALGORITHM.code() {
+            return Err(String::from("document checksum must be Blake2b-256").into());
+        }
+        let (shard_id, input) = take_shard_id(input)?;
+        let (input, key) = WireError::nom(take(POSSESSION_KEY_LENGTH)(input))?;
+
+        let mut verifier = PossessionVerifier {
+            doc_chksum: doc_chksum.to_owned(),
+            shard_id,
+            key: [0u8; POSSESSION_KEY_LENGTH],
+        };
+        verifier.key.copy_from_slice(key);
+        Ok((verifier, input))
+    }
+}
+
+impl ToWire for PossessionVerifier {
+    fn to_wire(&self) -> Vec<u8> {
+        let body = self.wire_body();
+        let kind = DocumentKind::PossessionVerifier;
+        let mut bytes = Vec::with_capacity(header::record_len(kind, body.len()));
+        header::write_record_into(&mut bytes, kind, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        bytes
+    }
+}
+
+impl FromWire for PossessionVerifier {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(
+            input,
+            DocumentKind::PossessionVerifier,
+            Self::from_wire_body,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::KeyShard;
+
+    #[quickcheck]
+    fn possession_verifier_roundtrip(shard: KeyShard) {
+        let verifier = shard.possession_verifier();
+
+        let bytes = verifier.to_wire();
+        assert_eq!(header::detect(&bytes), DocumentKind::PossessionVerifier);
+        assert_eq!(PossessionVerifier::from_wire(bytes), Ok(verifier));
+    }
+}
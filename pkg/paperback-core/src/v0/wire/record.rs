@@ -0,0 +1,207 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::{
+        encoding,
+        header::{self, DocumentKind},
+        WireLimits,
+    },
+    v0::{
+        grant::Participant,
+        record::RecoveryRecordBuilder,
+        wire::{
+            helpers::{take_optional_u64, take_shard_count, take_shard_id},
+            prefixes::*,
+            FromWire, ToWire, WireError,
+        },
+        RecoveryRecord, CHECKSUM_ALGORITHM,
+    },
+};
+
+use multihash::MultihashDigest;
+use unsigned_varint::encode;
+
+// Internal only -- users can't see RecoveryRecordBuilder.
+#[doc(hidden)]
+impl ToWire for RecoveryRecordBuilder {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        // Encode multihash checksum.
+        bytes.extend_from_slice(self.doc_chksum.as_bytes());
+
+        // Encode the identifiers of the participating shards (length-prefixed).
+        encoding::varint(self.participant_ids.len() as u64, &mut bytes);
+        for id in &self.participant_ids {
+            encoding::varint(id.len() as u64, &mut bytes);
+            bytes.extend_from_slice(id.as_bytes());
+        }
+
+        // Encode software version (length-prefixed).
+        encoding::varint(self.software.len() as u64, &mut bytes);
+        bytes.extend_from_slice(self.software.as_bytes());
+
+        // Encode recovery time (only if it is known).
+        if let Some(recovered_at) = self.recovered_at {
+            encode::u64(PREFIX_CREATED, &mut encode::u64_buffer())
+                .iter()
+                .chain(encode::u64(recovered_at, &mut encode::u64_buffer()))
+                .for_each(|b| bytes.push(*b));
+        }
+
+        bytes
+    }
+}
+
+// Internal only -- users can't see RecoveryRecordBuilder.
+#[doc(hidden)]
+impl FromWire for RecoveryRecordBuilder {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::{nom_helpers, v0::wire::helpers::multihash};
+        use nom::{bytes::complete::take, combinator::complete};
+
+        let (input, doc_chksum) = WireError::nom(complete(multihash)(input))?;
+        if doc_chksum.algorithm() != CHECKSUM_ALGORITHM.code() {
+            return Err(String::from("document checksum must be Blake2b-256").into());
+        }
+
+        let (count, mut input) = take_shard_count(input, RecoveryRecord::MAX_SHARDS)?;
+        let mut participant_ids = Vec::with_capacity(count.min(input.len()));
+        for _ in 0..count {
+            let (id, remain) = take_shard_id(input)?;
+            participant_ids.push(id);
+            input = remain;
+        }
+
+        let (input, length) = WireError::nom(complete(nom_helpers::usize)(input))?;
+        WireLimits::check(
+            "software version",
+            RecoveryRecord::MAX_SOFTWARE_LENGTH,
+            length,
+        )?;
+        let (input, software) = WireError::nom(take(length)(input))?;
+        let software = String::from_utf8(software.to_vec()).map_err(|err| format!("{:?}", err))?;
+
+        let (input, recovered_at) =
+            WireError::nom(complete(take_optional_u64(PREFIX_CREATED))(input))?;
+
+        Ok((
+            RecoveryRecordBuilder {
+                doc_chksum: doc_chksum.to_owned(),
+                participant_ids,
+                software,
+                recovered_at,
+            },
+            input,
+        ))
+    }
+}
+
+impl RecoveryRecord {
+    fn wire_body(&self) -> Vec<u8> {
+        let mut buffer = encode::u32_buffer();
+        let mut bytes = self.inner.to_wire();
+
+        // Encode each participating shard's ed25519 public key and signature
+        // (with multicodec prefixes), in the same order as their identifiers.
+        for participant in &self.participants {
+            encode::u32(PREFIX_ED25519_PUB, &mut buffer)
+                .iter()
+                .chain(participant.public_key.as_bytes())
+                .for_each(|b| bytes.push(*b));
+            encode::u32(PREFIX_ED25519_SIG, &mut buffer)
+                .iter()
+                .chain(&participant.signature.to_bytes()[..])
+                .for_each(|b| bytes.push(*b));
+        }
+
+        bytes
+    }
+
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::v0::wire::helpers::{take_ed25519_pub, take_ed25519_sig};
+        use nom::{combinator::complete, sequence::pair};
+
+        let (inner, mut input) = RecoveryRecordBuilder::from_wire_partial(input)?;
+
+        let mut participants = Vec::with_capacity(inner.participant_ids.len());
+        for shard_id in &inner.participant_ids {
+            let (remain, (public_key, signature)) =
+                WireError::nom(complete(pair(take_ed25519_pub, take_ed25519_sig))(input))?;
+            participants.push(Participant {
+                shard_id: shard_id.clone(),
+                public_key: public_key.map_err(|err| format!("{:?}", err))?,
+                signature: signature.map_err(|err| format!("{:?}", err))?,
+            });
+            input = remain;
+        }
+
+        Ok((
+            RecoveryRecord {
+                inner,
+                participants,
+            },
+            input,
+        ))
+    }
+}
+
+impl ToWire for RecoveryRecord {
+    fn to_wire(&self) -> Vec<u8> {
+        let body = self.wire_body();
+        let kind = DocumentKind::RecoveryRecord;
+        let mut bytes = Vec::with_capacity(header::record_len(kind, body.len()));
+        header::write_record_into(&mut bytes, kind, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        bytes
+    }
+}
+
+impl FromWire for RecoveryRecord {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(input, DocumentKind::RecoveryRecord, Self::from_wire_body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{Backup, UntrustedQuorum};
+
+    #[quickcheck]
+    fn recovery_record_roundtrip(secret: Vec<u8>) {
+        let mut backup = Backup::new(2, &secret).unwrap();
+        let mut quorum = UntrustedQuorum::new();
+        quorum.main_document(backup.main_document().clone());
+        for _ in 0..2 {
+            quorum.push_shard(backup.next_shard().unwrap());
+        }
+        let (_, record) = quorum
+            .validate()
+            .unwrap()
+            .recover_document_with_record()
+            .unwrap();
+
+        let bytes = record.to_wire();
+        assert_eq!(header::detect(&bytes), DocumentKind::RecoveryRecord);
+        assert_eq!(RecoveryRecord::from_wire(bytes), Ok(record));
+    }
+}
@@ -0,0 +1,150 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::{
+        encoding,
+        header::{self, DocumentKind},
+    },
+    v0::{
+        revoke::RevocationListBuilder,
+        wire::{
+            helpers::{take_shard_count, take_shard_id},
+            FromWire, ToWire, WireError,
+        },
+        Identity, RevocationList, RevocationReason, CHECKSUM_ALGORITHM,
+    },
+};
+
+use multihash::MultihashDigest;
+
+// Internal only -- users can't see RevocationListBuilder.
+#[doc(hidden)]
+impl ToWire for RevocationListBuilder {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        // Encode multihash checksum.
+        bytes.extend_from_slice(self.doc_chksum.as_bytes());
+
+        // Encode each revoked shard's identifier (length-prefixed) and the
+        // reason it was revoked.
+        encoding::varint(self.revoked.len() as u64, &mut bytes);
+        for (id, reason) in &self.revoked {
+            encoding::varint(id.len() as u64, &mut bytes);
+            bytes.extend_from_slice(id.as_bytes());
+            encoding::varint(reason.wire_id().into(), &mut bytes);
+        }
+
+        bytes
+    }
+}
+
+// Internal only -- users can't see RevocationListBuilder.
+#[doc(hidden)]
+impl FromWire for RevocationListBuilder {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::{nom_helpers, v0::wire::helpers::multihash};
+        use nom::combinator::complete;
+
+        let (input, doc_chksum) = WireError::nom(complete(multihash)(input))?;
+        if doc_chksum.algorithm() != CHECKSUM_ALGORITHM.code() {
+            return Err(String::from("document checksum must be Blake2b-256").into());
+        }
+
+        let (count, mut input) = take_shard_count(input, RevocationList::MAX_SHARDS)?;
+        let mut revoked = Vec::with_capacity(count.min(input.len()));
+        for _ in 0..count {
+            let (id, remain) = take_shard_id(input)?;
+            let (remain, reason) = WireError::nom(complete(nom_helpers::u32)(remain))?;
+            let reason = RevocationReason::from_wire_id(reason)
+                .ok_or_else(|| format!("unknown revocation reason '{}'", reason))?;
+            revoked.push((id, reason));
+            input = remain;
+        }
+
+        Ok((
+            RevocationListBuilder {
+                doc_chksum: doc_chksum.to_owned(),
+                revoked,
+            },
+            input,
+        ))
+    }
+}
+
+impl RevocationList {
+    fn wire_body(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        bytes.append(&mut self.inner.to_wire());
+        bytes.append(&mut self.identity.to_wire());
+
+        bytes
+    }
+
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        let (inner, input) = RevocationListBuilder::from_wire_partial(input)?;
+        let (identity, input) = Identity::from_wire_partial(input)?;
+
+        Ok((RevocationList { inner, identity }, input))
+    }
+}
+
+impl ToWire for RevocationList {
+    fn to_wire(&self) -> Vec<u8> {
+        let body = self.wire_body();
+        let kind = DocumentKind::RevocationList;
+        let mut bytes = Vec::with_capacity(header::record_len(kind, body.len()));
+        header::write_record_into(&mut bytes, kind, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        bytes
+    }
+}
+
+impl FromWire for RevocationList {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(input, DocumentKind::RevocationList, Self::from_wire_body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{Backup, KeyShard};
+
+    #[quickcheck]
+    fn revocation_list_roundtrip(shards: Vec<KeyShard>, reasons: Vec<u8>) {
+        let backup = Backup::new(2, b"secret").unwrap();
+        let revoked = shards
+            .iter()
+            .zip(reasons.iter().cycle())
+            .map(|(shard, reason)| {
+                let reasons = &RevocationReason::ALL;
+                (shard.id(), reasons[*reason as usize % reasons.len()])
+            })
+            .collect();
+        let list = backup.revoke(revoked);
+
+        let bytes = list.to_wire();
+        assert_eq!(header::detect(&bytes), DocumentKind::RevocationList);
+        assert_eq!(RevocationList::from_wire(bytes), Ok(list));
+    }
+}
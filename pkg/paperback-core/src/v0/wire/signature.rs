@@ -0,0 +1,124 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::header::{self, DocumentKind},
+    v0::{
+        wire::{FromWire, ToWire, WireError},
+        DetachedSignature, Identity, SignedKind, CHECKSUM_ALGORITHM,
+    },
+};
+
+use multihash::{Multihash, MultihashDigest};
+use unsigned_varint::encode;
+
+impl DetachedSignature {
+    fn wire_body(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        // Encode the kind of document signed.
+        encode::u32(self.kind.wire_id(), &mut encode::u32_buffer())
+            .iter()
+            .for_each(|b| bytes.push(*b));
+
+        // Encode multihash checksum.
+        self.doc_chksum
+            .as_bytes()
+            .iter()
+            .for_each(|b| bytes.push(*b));
+
+        bytes.append(&mut self.identity.to_wire());
+
+        bytes
+    }
+
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::{nom_helpers, v0::wire::helpers::multihash};
+        use nom::{combinator::complete, IResult};
+
+        fn parse(input: &[u8]) -> IResult<&[u8], (u32, Multihash)> {
+            let (input, kind) = nom_helpers::u32(input)?;
+            let (input, doc_chksum) = multihash(input)?;
+
+            Ok((input, (kind, doc_chksum.to_owned())))
+        }
+        let parse = complete(parse);
+
+        let (input, (kind, doc_chksum)) = WireError::nom(parse(input))?;
+        let (identity, input) = Identity::from_wire_partial(input)?;
+
+        let kind = SignedKind::from_wire_id(kind)
+            .ok_or_else(|| format!("unknown kind of signed document {}", kind))?;
+        if doc_chksum.algorithm() != CHECKSUM_ALGORITHM.code() {
+            return Err(String::from("document checksum must be Blake2b-256").into());
+        }
+
+        Ok((
+            DetachedSignature {
+                kind,
+                doc_chksum,
+                identity,
+            },
+            input,
+        ))
+    }
+}
+
+impl ToWire for DetachedSignature {
+    fn to_wire(&self) -> Vec<u8> {
+        let body = self.wire_body();
+        let kind = DocumentKind::DetachedSignature;
+        let mut bytes = Vec::with_capacity(header::record_len(kind, body.len()));
+        header::write_record_into(&mut bytes, kind, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        bytes
+    }
+}
+
+impl FromWire for DetachedSignature {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(input, DocumentKind::DetachedSignature, Self::from_wire_body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{KeyShard, MainDocument};
+
+    #[quickcheck]
+    fn detached_signature_roundtrip(main: MainDocument, shard: KeyShard) {
+        for signature in &[main.detached_signature(), shard.detached_signature()] {
+            let bytes = signature.to_wire();
+            assert_eq!(header::detect(&bytes), DocumentKind::DetachedSignature);
+
+            let signature2 = DetachedSignature::from_wire(bytes).unwrap();
+            assert_eq!(signature, &signature2);
+        }
+        let signature = DetachedSignature::from_wire(main.detached_signature().to_wire()).unwrap();
+        assert_eq!(signature.verify_main_document(&main), Ok(()));
+    }
+
+    #[quickcheck]
+    fn detached_signature_wrong_kind(main: MainDocument) -> bool {
+        DetachedSignature::from_wire(main.to_wire()).is_err()
+            && MainDocument::from_wire(main.detached_signature().to_wire()).is_err()
+    }
+}
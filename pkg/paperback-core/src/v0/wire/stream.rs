@@ -0,0 +1,135 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    nom_helpers,
+    shamir::{
+        header::{self, DocumentKind},
+        WireLimits,
+    },
+    v0::{
+        stream::StreamKey,
+        wire::{helpers::aead_nonce_prefix, prefixes::*, FromWire, ToWire, WireError},
+        AeadAlgorithm,
+    },
+};
+
+use unsigned_varint::encode;
+
+// Internal only -- users can't see StreamKey.
+impl StreamKey {
+    fn wire_body(&self) -> Vec<u8> {
+        let mut buffer = encode::u64_buffer();
+        let mut bytes = vec![];
+
+        // Encode chunk size.
+        encode::usize(self.chunk_size, &mut encode::usize_buffer())
+            .iter()
+            .for_each(|b| bytes.push(*b));
+
+        // Encode ChaCha20-Poly1305 key.
+        encode::u64(PREFIX_CHACHA20POLY1305_KEY, &mut buffer)
+            .iter()
+            .chain(&self.key)
+            .for_each(|b| bytes.push(*b));
+
+        // Encode nonce (whose prefix identifies the algorithm).
+        encode::u64(aead_nonce_prefix(self.algorithm), &mut buffer)
+            .iter()
+            .chain(&self.nonce)
+            .for_each(|b| bytes.push(*b));
+
+        bytes
+    }
+
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::v0::wire::helpers::{take_aead_nonce, take_chachapoly_key};
+        use nom::combinator::complete;
+
+        let (input, chunk_size) = WireError::nom(complete(nom_helpers::usize)(input))?;
+        WireLimits::check("chunk size", StreamKey::MAX_CHUNK_SIZE, chunk_size)?;
+        if chunk_size == 0 {
+            return Err(String::from("chunk size must not be zero").into());
+        }
+        let (input, key) = WireError::nom(complete(take_chachapoly_key)(input))?;
+
+        let (input, (algorithm, nonce)) = AeadAlgorithm::ALL
+            .iter()
+            .find_map(|algorithm| {
+                complete(take_aead_nonce(*algorithm))(input)
+                    .ok()
+                    .map(|(input, nonce)| (input, (*algorithm, nonce)))
+            })
+            .ok_or_else(|| WireError::from(String::from("missing or unknown stream nonce")))?;
+
+        Ok((
+            StreamKey {
+                algorithm,
+                chunk_size,
+                key,
+                nonce: nonce.to_vec(),
+            },
+            input,
+        ))
+    }
+}
+
+// Internal only -- users can't see StreamKey.
+#[doc(hidden)]
+impl ToWire for StreamKey {
+    fn to_wire(&self) -> Vec<u8> {
+        let body = self.wire_body();
+        let kind = DocumentKind::StreamKey;
+        let mut bytes = Vec::with_capacity(header::record_len(kind, body.len()));
+        header::write_record_into(&mut bytes, kind, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        bytes
+    }
+}
+
+// Internal only -- users can't see StreamKey.
+#[doc(hidden)]
+impl FromWire for StreamKey {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(input, DocumentKind::StreamKey, Self::from_wire_body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::ChaChaPolyKey;
+
+    use rand::{rngs::OsRng, RngCore};
+
+    #[quickcheck]
+    fn stream_key_roundtrip(chunk_size: u16, algorithm: u8) {
+        let algorithm = AeadAlgorithm::ALL[algorithm as usize % AeadAlgorithm::ALL.len()];
+        let mut key = StreamKey {
+            algorithm,
+            chunk_size: chunk_size as usize + 1,
+            key: ChaChaPolyKey::default(),
+            nonce: algorithm.generate_nonce(),
+        };
+        OsRng.fill_bytes(&mut key.key);
+        let key2 = StreamKey::from_wire(key.to_wire()).unwrap();
+        assert_eq!(key, key2);
+    }
+}
@@ -0,0 +1,207 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    shamir::header::{self, DocumentKind},
+    v0::{
+        supersede::SupersededNoticeBuilder,
+        wire::{prefixes::*, FromWire, ToWire, WireError},
+        Identity, SupersededNotice, CHECKSUM_ALGORITHM,
+    },
+};
+
+use ed25519_dalek::PublicKey;
+use multihash::{Multihash, MultihashDigest};
+use unsigned_varint::encode;
+
+// Internal only -- users can't see SupersededNoticeBuilder.
+#[doc(hidden)]
+impl ToWire for SupersededNoticeBuilder {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        // Encode multihash checksums of the old and new documents.
+        bytes.extend_from_slice(self.old_doc_chksum.as_bytes());
+        bytes.extend_from_slice(self.new_doc_chksum.as_bytes());
+
+        // Encode the date of the notice (only if it is present).
+        if let Some(superseded_at) = self.superseded_at {
+            encode::u64(PREFIX_CREATED, &mut encode::u64_buffer())
+                .iter()
+                .chain(encode::u64(superseded_at, &mut encode::u64_buffer()))
+                .for_each(|b| bytes.push(*b));
+        }
+
+        // Encode the identity public key of the new document (only if it is
+        // present).
+        if let Some(new_id_public_key) = &self.new_id_public_key {
+            encode::u64(PREFIX_SUCCESSOR_ED25519_PUB, &mut encode::u64_buffer())
+                .iter()
+                .chain(new_id_public_key.as_bytes())
+                .for_each(|b| bytes.push(*b));
+        }
+
+        bytes
+    }
+}
+
+// Internal only -- users can't see SupersededNoticeBuilder.
+#[doc(hidden)]
+impl FromWire for SupersededNoticeBuilder {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        use crate::{
+            nom_helpers,
+            v0::wire::helpers::{multihash, take_optional_u64},
+        };
+        use nom::{
+            bytes::streaming::take,
+            combinator::{complete, opt, verify},
+            sequence::preceded,
+            IResult,
+        };
+
+        type Parsed<'a> = (Multihash, Multihash, Option<u64>, Option<&'a [u8]>);
+
+        fn parse(input: &[u8]) -> IResult<&[u8], Parsed<'_>> {
+            let (input, old_doc_chksum) = multihash(input)?;
+            let (input, new_doc_chksum) = multihash(input)?;
+            let (input, superseded_at) = take_optional_u64(PREFIX_CREATED)(input)?;
+            let (input, new_id_public_key) = match input {
+                [] => (input, None),
+                _ => opt(preceded(
+                    verify(nom_helpers::u64, |x| *x == PREFIX_SUCCESSOR_ED25519_PUB),
+                    take(ed25519_dalek::PUBLIC_KEY_LENGTH),
+                ))(input)?,
+            };
+
+            Ok((
+                input,
+                (
+                    old_doc_chksum.to_owned(),
+                    new_doc_chksum.to_owned(),
+                    superseded_at,
+                    new_id_public_key,
+                ),
+            ))
+        }
+        let parse = complete(parse);
+
+        let (remain, (old_doc_chksum, new_doc_chksum, superseded_at, new_id_public_key)) =
+            WireError::nom(parse(input))?;
+        let new_id_public_key = new_id_public_key
+            .map(PublicKey::from_bytes)
+            .transpose()
+            .map_err(|err| format!("{:?}", err))?;
+        for doc_chksum in &[&old_doc_chksum, &new_doc_chksum] {
+            if doc_chksum.algorithm() != CHECKSUM_ALGORITHM.code() {
+                return Err(String::from("document checksum must be Blake2b-256").into());
+            }
+        }
+
+        Ok((
+            SupersededNoticeBuilder {
+                old_doc_chksum,
+                new_doc_chksum,
+                superseded_at,
+                new_id_public_key,
+            },
+            remain,
+        ))
+    }
+}
+
+impl SupersededNotice {
+    fn wire_body(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        bytes.append(&mut self.inner.to_wire());
+        bytes.append(&mut self.identity.to_wire());
+
+        bytes
+    }
+
+    fn from_wire_body(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        let (inner, input) = SupersededNoticeBuilder::from_wire_partial(input)?;
+        let (identity, input) = Identity::from_wire_partial(input)?;
+
+        Ok((SupersededNotice { inner, identity }, input))
+    }
+}
+
+impl ToWire for SupersededNotice {
+    fn to_wire(&self) -> Vec<u8> {
+        let body = self.wire_body();
+        let kind = DocumentKind::SupersededNotice;
+        let mut bytes = Vec::with_capacity(header::record_len(kind, body.len()));
+        header::write_record_into(&mut bytes, kind, body.len(), |bytes| {
+            bytes.extend_from_slice(&body)
+        });
+        bytes
+    }
+}
+
+impl FromWire for SupersededNotice {
+    fn from_wire_partial(input: &[u8]) -> Result<(Self, &[u8]), WireError> {
+        header::read_record(input, DocumentKind::SupersededNotice, Self::from_wire_body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::v0::{Backup, UntrustedQuorum};
+
+    #[quickcheck]
+    fn superseded_notice_roundtrip(secret: Vec<u8>) {
+        let mut backup = Backup::new(1, &secret).unwrap();
+        let mut quorum = UntrustedQuorum::new();
+        quorum
+            .main_document(backup.main_document().clone())
+            .push_shard(backup.next_shard().unwrap());
+        let (_, notice) = quorum
+            .validate()
+            .unwrap()
+            .rotate(Default::default())
+            .unwrap();
+
+        let bytes = notice.to_wire();
+        assert_eq!(header::detect(&bytes), DocumentKind::SupersededNotice);
+        assert_eq!(SupersededNotice::from_wire(bytes), Ok(notice));
+    }
+
+    #[quickcheck]
+    fn superseded_notice_legacy(secret: Vec<u8>) -> bool {
+        let old = Backup::new(1, &secret).unwrap();
+        let new = Backup::new(1, &secret).unwrap();
+        let notice = SupersededNoticeBuilder {
+            old_doc_chksum: old.main_document().checksum(),
+            new_doc_chksum: new.main_document().checksum(),
+            superseded_at: None,
+            new_id_public_key: None,
+        };
+
+        // Notices without a date or successor key are encoded exactly as they
+        // were before those were added.
+        let mut legacy = Vec::new();
+        legacy.extend_from_slice(notice.old_doc_chksum.as_bytes());
+        legacy.extend_from_slice(notice.new_doc_chksum.as_bytes());
+
+        notice.to_wire() == legacy && SupersededNoticeBuilder::from_wire(legacy) == Ok(notice)
+    }
+}
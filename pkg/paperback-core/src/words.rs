@@ -0,0 +1,312 @@
+/*
+ * paperback: paper backup generator suitable for long-term storage
+ * Copyright (C) 2018-2020 Aleksa Sarai <cyphar@cyphar.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! The data is encoded as words from the [BIP-39][bip39] English word list,
+//! each carrying 11 bits, so that small objects (such as key shards) can be
+//! copied by hand and read back as dictionary words. For example, the bytes
+//! `paperback` are encoded as:
+//!
+//! ```text
+//! annual loan foster slab change cost misery gas regular round
+//! ```
+//!
+//! The data is prefixed with its length (as a varint) and zero-padded to a
+//! whole number of words, followed by `CHECKSUM_WORDS` checksum words derived
+//! from a Blake2b hash of everything before them. Unlike BIP-39 mnemonics,
+//! data of any length can be encoded.
+//!
+//! When decoding, words may be separated by any whitespace and are not
+//! case-sensitive. Since every word in the list is uniquely identified by its
+//! first four letters, any prefix of a word which is at least that long is
+//! accepted as well.
+//!
+//! [bip39]: https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki
+
+use crate::{
+    shamir::encoding,
+    v0::{FromWire, ToWire, WireError},
+};
+
+use std::{error, fmt};
+
+use bip39::Language;
+
+/// Number of bits of data carried by each word.
+pub const WORD_BITS: usize = 11;
+
+/// Number of checksum words at the end of the encoded data.
+pub const CHECKSUM_WORDS: usize = 2;
+
+/// Minimum length of a word prefix which is accepted in place of the word.
+pub const MIN_PREFIX_LENGTH: usize = 4;
+
+const WORD_LANGUAGE: Language = Language::English;
+const CHECKSUM_PERSONAL: &[u8] = b"paperback-words";
+
+/// Reasons why a list of words could not be decoded.
+///
+/// All word positions are counted from 1.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WordError {
+    /// The word at `position` is not in the word list (nor an unambiguous
+    /// prefix of a word in the list).
+    InvalidWord { position: usize },
+    /// There are fewer words than the encoded length requires.
+    Truncated,
+    /// There are more words than the encoded length requires.
+    TrailingWords,
+    /// The padding bits after the data are not zero.
+    InvalidPadding,
+    /// The checksum words do not match (a word was mistyped, swapped or
+    /// skipped).
+    ChecksumMismatch,
+    /// The data could not be deserialised.
+    Wire(WireError),
+}
+
+impl fmt::Display for WordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WordError::InvalidWord { position } => {
+                write!(f, "word {} is not in the word list", position)
+            }
+            WordError::Truncated => write!(f, "missing words at the end"),
+            WordError::TrailingWords => write!(f, "unexpected words at the end"),
+            WordError::InvalidPadding => write!(f, "invalid word padding"),
+            WordError::ChecksumMismatch => {
+                write!(f, "a word was mistyped (checksum mismatch)")
+            }
+            WordError::Wire(err) => write!(f, "failed to deserialise data: {}", err),
+        }
+    }
+}
+
+impl error::Error for WordError {}
+
+impl From<WireError> for WordError {
+    fn from(err: WireError) -> Self {
+        WordError::Wire(err)
+    }
+}
+
+fn wordlist() -> &'static [&'static str] {
+    // Every word has the empty prefix.
+    WORD_LANGUAGE.wordlist().get_words_by_prefix("")
+}
+
+// Index of the word (or unambiguous prefix of a word) in the word list.
+fn word_index(words: &[&str], word: &str) -> Option<usize> {
+    let word = word.to_lowercase();
+    match words.binary_search(&word.as_str()) {
+        Ok(idx) => Some(idx),
+        Err(idx) if word.chars().count() >= MIN_PREFIX_LENGTH => {
+            let matches = words[idx..]
+                .iter()
+                .take_while(|w| w.starts_with(&word))
+                .count();
+            Some(idx).filter(|_| matches == 1)
+        }
+        Err(_) => None,
+    }
+}
+
+fn checksum(values: &[u16]) -> u32 {
+    let bytes = values
+        .iter()
+        .flat_map(|value| value.to_be_bytes().to_vec())
+        .collect::<Vec<_>>();
+    let hash = blake2b_simd::Params::new()
+        .hash_length(4)
+        .personal(CHECKSUM_PERSONAL)
+        .hash(&bytes);
+    let mut checksum = [0u8; 4];
+    checksum.copy_from_slice(hash.as_bytes());
+    u32::from_be_bytes(checksum) >> (32 - CHECKSUM_WORDS * WORD_BITS)
+}
+
+/// Encode `data` as a space-separated list of words.
+pub fn encode(data: &[u8]) -> String {
+    let mut bytes = Vec::with_capacity(encoding::varint_len(data.len() as u64) + data.len());
+    encoding::varint(data.len() as u64, &mut bytes);
+    bytes.extend_from_slice(data);
+
+    let (mut values, mut acc, mut bits) = (vec![], 0u32, 0);
+    for byte in bytes {
+        acc = (acc << 8) | u32::from(byte);
+        bits += 8;
+        if bits >= WORD_BITS {
+            bits -= WORD_BITS;
+            values.push((acc >> bits) as u16 & 0x7ff);
+        }
+    }
+    if bits > 0 {
+        values.push((acc << (WORD_BITS - bits)) as u16 & 0x7ff);
+    }
+    let checksum = checksum(&values);
+    values.extend(
+        (0..CHECKSUM_WORDS)
+            .map(|idx| (checksum >> (WORD_BITS * (CHECKSUM_WORDS - 1 - idx))) as u16 & 0x7ff),
+    );
+
+    let words = wordlist();
+    values
+        .iter()
+        .map(|value| words[usize::from(*value)])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Encode the wire format of `object` as a list of words.
+pub fn encode_wire<T: ToWire + ?Sized>(object: &T) -> String {
+    encode(&object.to_wire())
+}
+
+/// Decode a list of words produced by `encode`.
+pub fn decode(text: &str) -> Result<Vec<u8>, WordError> {
+    let words = wordlist();
+    let mut values = text
+        .split_whitespace()
+        .enumerate()
+        .map(|(idx, word)| {
+            word_index(words, word)
+                .map(|value| value as u16)
+                .ok_or(WordError::InvalidWord { position: idx + 1 })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if values.len() < CHECKSUM_WORDS + 1 {
+        return Err(WordError::Truncated);
+    }
+    let checksum_values = values.split_off(values.len() - CHECKSUM_WORDS);
+    let found = checksum_values
+        .iter()
+        .fold(0u32, |acc, value| (acc << WORD_BITS) | u32::from(*value));
+    if checksum(&values) != found {
+        return Err(WordError::ChecksumMismatch);
+    }
+
+    let (mut bytes, mut acc, mut bits) = (vec![], 0u32, 0);
+    for value in &values {
+        acc = (acc << WORD_BITS) | u32::from(*value);
+        bits += WORD_BITS;
+        while bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+
+    let (length, data) = unsigned_varint::decode::u64(&bytes).map_err(|_| WordError::Truncated)?;
+    let length = length as usize;
+    if data.len() < length {
+        return Err(WordError::Truncated);
+    }
+    let (data, padding) = data.split_at(length);
+    // The padding is less than a whole word, and must be zero.
+    if (padding.len() * 8 + bits) >= WORD_BITS {
+        return Err(WordError::TrailingWords);
+    }
+    if padding.iter().any(|b| *b != 0) || acc & ((1 << bits) - 1) != 0 {
+        return Err(WordError::InvalidPadding);
+    }
+    Ok(data.to_vec())
+}
+
+/// Decode an object from a list of words produced by `encode_wire`.
+pub fn decode_wire<T: FromWire>(text: &str) -> Result<T, WordError> {
+    Ok(T::from_wire(decode(text)?)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{shamir::Shard, v0::EncryptedKeyShard};
+
+    #[test]
+    fn words_wordlist() {
+        let words = wordlist();
+        assert_eq!(words.len(), 1 << WORD_BITS);
+        assert_eq!((words[0], words[2047]), ("abandon", "zoo"));
+        // Every word must be identified by its first four letters.
+        for (idx, word) in words.iter().enumerate() {
+            let prefix = &word[..MIN_PREFIX_LENGTH.min(word.len())];
+            assert_eq!(word_index(words, word), Some(idx));
+            assert_eq!(word_index(words, prefix), Some(idx));
+        }
+    }
+
+    #[quickcheck]
+    fn words_roundtrip(data: Vec<u8>) -> bool {
+        let text = encode(&data);
+        let abbreviated = text
+            .split(' ')
+            .map(|word| &word[..MIN_PREFIX_LENGTH.min(word.len())])
+            .collect::<Vec<_>>()
+            .join("\n");
+        decode(&text) == Ok(data.clone())
+            && decode(&text.to_uppercase()) == Ok(data.clone())
+            && decode(&abbreviated) == Ok(data)
+    }
+
+    #[quickcheck]
+    fn words_shard_roundtrip(shard: Shard) -> bool {
+        decode_wire::<Shard>(&encode_wire(&shard)).as_ref() == Ok(&shard)
+    }
+
+    #[quickcheck]
+    fn words_key_shard_roundtrip(shard: EncryptedKeyShard) -> bool {
+        decode_wire::<EncryptedKeyShard>(&encode_wire(&shard)).as_ref() == Ok(&shard)
+    }
+
+    #[quickcheck]
+    fn words_typo(data: Vec<u8>, pos: usize, delta: usize) -> bool {
+        let text = encode(&data);
+        let mut words = text.split(' ').collect::<Vec<_>>();
+
+        // Replace one word with a different one.
+        let pos = pos % words.len();
+        let old = word_index(wordlist(), words[pos]).unwrap();
+        words[pos] = wordlist()[(old + 1 + delta % 2047) % 2048];
+
+        decode(&words.join(" ")).is_err()
+    }
+
+    #[test]
+    fn words_errors() {
+        let text = encode(b"paperback");
+        let words = text.split(' ').collect::<Vec<_>>();
+        assert_eq!(
+            text,
+            "annual loan foster slab change cost misery gas regular round"
+        );
+
+        assert_eq!(decode(""), Err(WordError::Truncated));
+        assert_eq!(
+            decode(&text.replacen(words[1], "paperback", 1)),
+            Err(WordError::InvalidWord { position: 2 })
+        );
+        // Prefixes must be at least four letters long.
+        assert_eq!(
+            decode(&text.replacen(words[0], &words[0][..3], 1)),
+            Err(WordError::InvalidWord { position: 1 })
+        );
+        assert_eq!(
+            decode(&words[1..].join(" ")),
+            Err(WordError::ChecksumMismatch)
+        );
+    }
+}